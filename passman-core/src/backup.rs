@@ -0,0 +1,522 @@
+//! Automatic local snapshots of a vault, taken before anything that's hard to undo: deleting a password, changing the
+//! master password, keyfile or cipher, removing a team member, importing or pulling passwords, syncing and upgrading an old vault.
+//!
+//! A snapshot is a copy of the whole vault file made with SQLite's online backup API, so it's consistent even while the vault
+//! is in use. It stays encrypted with whatever key the vault had at the time. Snapshots are written to `BACKUP_DIR` next to
+//! the vault file, and old ones are pruned according to the vault's `BackupPolicy`. Vaults that only live in memory aren't snapshotted.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration as StdDuration,
+};
+
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, Utc};
+use rusqlite::{backup::Backup, Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db_ops::{
+        crud::list_password_info,
+        util::{authenticate, create_table, get_metadata, set_metadata, upgrade_legacy_fields},
+    },
+    error::BackendError,
+    password::PasswordField,
+};
+
+/// Key in the `VaultMetadata` table holding the `BackupPolicy`, serialized as JSON.
+pub const BACKUP_POLICY_METADATA: &str = "backup_policy";
+/// The directory snapshots are written to, next to the vault file.
+pub const BACKUP_DIR: &str = "backups";
+
+/// The format of the timestamp in a snapshot's file name.
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+/// Which snapshots are kept. A snapshot is kept if any of the rules keeps it; the rest are deleted after every new snapshot.
+/// A policy that keeps nothing turns snapshots off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupPolicy {
+    /// Keep this many of the newest snapshots.
+    pub keep_last: u32,
+    /// Keep the newest snapshot of each of the last this many days that have one.
+    pub keep_daily: u32,
+    /// Keep the newest snapshot of each of the last this many weeks that have one.
+    pub keep_weekly: u32,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        BackupPolicy {
+            keep_last: 10,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// Whether the policy keeps any snapshots at all.
+    pub fn is_enabled(&self) -> bool {
+        self.keep_last > 0 || self.keep_daily > 0 || self.keep_weekly > 0
+    }
+}
+
+/// A snapshot of a vault, found with `list_snapshots()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub taken_at: DateTime<Utc>,
+    /// What the snapshot was taken before, e.g. `delete`.
+    pub reason: String,
+}
+
+/// Reads the vault's `BackupPolicy`. Vaults without one recorded use `BackupPolicy::default()`.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+///
+pub fn get_backup_policy(connection: &Connection) -> Result<BackupPolicy, BackendError> {
+    get_metadata::<String>(connection, BACKUP_POLICY_METADATA)?.map_or(
+        Ok(BackupPolicy::default()),
+        |policy| {
+            serde_json::from_str(&policy).map_err(|e| BackendError::CorruptMetadata {
+                key: BACKUP_POLICY_METADATA.to_string(),
+                reason: e.to_string(),
+            })
+        },
+    )
+}
+/// Records the vault's `BackupPolicy`. Existing snapshots are pruned the next time one is taken.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `policy` - the new `BackupPolicy`.
+///
+pub fn set_backup_policy(
+    connection: &Connection,
+    policy: &BackupPolicy,
+) -> Result<usize, BackendError> {
+    let policy = serde_json::to_string(policy).map_err(|e| BackendError::CorruptMetadata {
+        key: BACKUP_POLICY_METADATA.to_string(),
+        reason: e.to_string(),
+    })?;
+    Ok(set_metadata(connection, BACKUP_POLICY_METADATA, policy)?)
+}
+
+/// The directory the vault's snapshots are written to, and the prefix of their file names (the vault's file stem).
+/// `None` for vaults that only live in memory.
+fn location(connection: &Connection) -> Option<(PathBuf, String)> {
+    let path = Path::new(connection.path().filter(|path| !path.is_empty())?);
+    let stem = path.file_stem()?.to_string_lossy().into_owned();
+    let dir = path.parent().unwrap_or(Path::new("")).join(BACKUP_DIR);
+    Some((dir, stem))
+}
+
+/// The directory the vault's snapshots are written to, or `None` if the vault only lives in memory.
+pub fn backup_dir(connection: &Connection) -> Option<PathBuf> {
+    location(connection).map(|(dir, _)| dir)
+}
+
+/// Copies the vault into a new snapshot, then prunes old snapshots according to the vault's `BackupPolicy`.
+/// Returns `None` without doing anything if the vault only lives in memory or the policy turns snapshots off.
+///
+/// Call this before starting a transaction, so the snapshot doesn't include half of it.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `reason` - what the snapshot is taken before, e.g. `delete`. Becomes part of the file name.
+///
+pub fn take_snapshot(
+    connection: &Connection,
+    reason: &str,
+) -> Result<Option<Snapshot>, BackendError> {
+    let Some((dir, stem)) = location(connection) else {
+        return Ok(None);
+    };
+    // vaults from before `VaultMetadata` existed are snapshotted before it's created
+    let has_metadata = connection
+        .prepare("select 1 from sqlite_master where type = 'table' and name = 'VaultMetadata'")?
+        .exists(())?;
+    let policy = match has_metadata {
+        true => get_backup_policy(connection)?,
+        false => BackupPolicy::default(),
+    };
+    if !policy.is_enabled() {
+        return Ok(None);
+    }
+    fs::create_dir_all(&dir)?;
+
+    // SQLite would write into an existing file, so every snapshot gets a name of its own
+    let mut taken_at = Utc::now();
+    let mut path = snapshot_path(&dir, &stem, taken_at, reason);
+    while path.exists() {
+        taken_at += Duration::milliseconds(1);
+        path = snapshot_path(&dir, &stem, taken_at, reason);
+    }
+    connection.backup(DatabaseName::Main, &path, None)?;
+
+    prune_snapshots(connection, &policy)?;
+    Ok(Some(Snapshot {
+        path,
+        // the file name only keeps milliseconds
+        taken_at: parse_timestamp(&taken_at.format(TIMESTAMP_FORMAT).to_string())
+            .unwrap_or(taken_at),
+        reason: reason.to_string(),
+    }))
+}
+
+fn snapshot_path(dir: &Path, stem: &str, taken_at: DateTime<Utc>, reason: &str) -> PathBuf {
+    dir.join(format!(
+        "{}-{}-{}.db",
+        stem,
+        taken_at.format(TIMESTAMP_FORMAT),
+        reason
+    ))
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|timestamp| timestamp.and_utc())
+}
+
+/// Lists the vault's snapshots, newest first. Files in the backup directory that belong to other vaults are left out.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+///
+pub fn list_snapshots(connection: &Connection) -> Result<Vec<Snapshot>, BackendError> {
+    let Some((dir, stem)) = location(connection) else {
+        return Ok(Vec::new());
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let parsed = file_name
+            .strip_prefix(stem.as_str())
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(".db"))
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(timestamp, reason)| Some((parse_timestamp(timestamp)?, reason)));
+        // e.g. "vault-old-..." when listing the snapshots of "vault"
+        let Some((taken_at, reason)) = parsed else {
+            continue;
+        };
+        snapshots.push(Snapshot {
+            reason: reason.to_string(),
+            path,
+            taken_at,
+        });
+    }
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.taken_at));
+    Ok(snapshots)
+}
+
+/// Picks the snapshots a `BackupPolicy` doesn't keep.
+/// # Arguments
+///
+/// - `snapshots` - the snapshots, newest first, as returned by `list_snapshots()`.
+/// - `policy` - the `BackupPolicy` to apply.
+pub fn expired_snapshots<'a>(
+    snapshots: &'a [Snapshot],
+    policy: &BackupPolicy,
+) -> Vec<&'a Snapshot> {
+    let mut keep = vec![false; snapshots.len()];
+    for kept in keep.iter_mut().take(policy.keep_last as usize) {
+        *kept = true;
+    }
+    // the newest snapshot of each day or week is the first one seen in it
+    let mut keep_newest_per = |limit: u32, period: &dyn Fn(&Snapshot) -> (i32, u32)| {
+        let mut periods = Vec::new();
+        for (index, snapshot) in snapshots.iter().enumerate() {
+            let period = period(snapshot);
+            if periods.last() != Some(&period) {
+                if periods.len() == limit as usize {
+                    break;
+                }
+                periods.push(period);
+                keep[index] = true;
+            }
+        }
+    };
+    keep_newest_per(policy.keep_daily, &|snapshot| {
+        (snapshot.taken_at.year(), snapshot.taken_at.ordinal())
+    });
+    keep_newest_per(policy.keep_weekly, &|snapshot| {
+        let week = snapshot.taken_at.iso_week();
+        (week.year(), week.week())
+    });
+    snapshots
+        .iter()
+        .zip(keep)
+        .filter(|(_, kept)| !kept)
+        .map(|(snapshot, _)| snapshot)
+        .collect()
+}
+
+/// Deletes the snapshots a `BackupPolicy` doesn't keep. Returns the number of deleted snapshots.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `policy` - the `BackupPolicy` to apply, usually the vault's own.
+///
+pub fn prune_snapshots(
+    connection: &Connection,
+    policy: &BackupPolicy,
+) -> Result<usize, BackendError> {
+    let snapshots = list_snapshots(connection)?;
+    let expired = expired_snapshots(&snapshots, policy);
+    for snapshot in &expired {
+        fs::remove_file(&snapshot.path)?;
+    }
+    Ok(expired.len())
+}
+
+/// Deletes every snapshot of the vault, e.g. after it was wiped. Returns the number of deleted snapshots.
+pub fn delete_snapshots(connection: &Connection) -> Result<usize, BackendError> {
+    let snapshots = list_snapshots(connection)?;
+    for snapshot in &snapshots {
+        fs::remove_file(&snapshot.path)?;
+    }
+    Ok(snapshots.len())
+}
+
+/// Copies a snapshot into memory, upgrades it like `Vault::open()` would, and checks that `master` unlocks it and decrypts
+/// every password. The snapshot itself is never written to.
+fn load_snapshot(path: &Path, master: &str) -> Result<(Connection, usize), BackendError> {
+    let snapshot = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut copy = Connection::open_in_memory()?;
+    Backup::new(&snapshot, &mut copy)?.run_to_completion(100, StdDuration::ZERO, None)?;
+    create_table(&copy)?;
+    upgrade_legacy_fields(&copy)?;
+    if !authenticate(&copy, master, PasswordField::Password)? {
+        return Err(BackendError::SnapshotKeyMismatch);
+    }
+    let passwords = list_password_info(&copy, master)?.len();
+    Ok((copy, passwords))
+}
+
+/// Checks that a snapshot can be restored with a master password: that it's a vault, the master password unlocks it and
+/// every password in it can be decrypted. Returns the number of passwords in it.
+/// Fails with `BackendError::SnapshotKeyMismatch` if the snapshot was encrypted with a different master password (or keyfile).
+/// # Arguments
+///
+/// - `path` - the snapshot.
+/// - `master` - a string slice holding the master password the snapshot was taken with, or its composite key if it required a keyfile.
+///
+pub fn verify_snapshot(path: &Path, master: &str) -> Result<usize, BackendError> {
+    Ok(load_snapshot(path, master)?.1)
+}
+
+/// Replaces the vault with a snapshot. The snapshot is checked with `verify_snapshot()` first, and the vault as it is now is
+/// snapshotted too, so the restore itself can be undone. Returns the number of passwords restored.
+/// # Arguments
+///
+/// - `connection` - a mutable reference to a `rusqlite::Connection` to the vault. No other connection may be using it.
+/// - `path` - the snapshot to restore.
+/// - `master` - a string slice holding the master password the snapshot was taken with, or its composite key if it required a keyfile.
+///
+pub fn restore_snapshot(
+    connection: &mut Connection,
+    path: &Path,
+    master: &str,
+) -> Result<usize, BackendError> {
+    // loaded before the vault is snapshotted, since pruning may delete the snapshot being restored
+    let (snapshot, passwords) = load_snapshot(path, master)?;
+    take_snapshot(connection, "restore")?;
+    Backup::new(&snapshot, connection)?.run_to_completion(100, StdDuration::ZERO, None)?;
+    Ok(passwords)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        expired_snapshots, get_backup_policy, list_snapshots, restore_snapshot, set_backup_policy,
+        take_snapshot, verify_snapshot, BackupPolicy, Snapshot,
+    };
+    use crate::{
+        db_ops::{
+            crud::{change_master_password, delete_password_info, insert_data, read_password_info},
+            util::{create_table, insert_master_record},
+        },
+        envelope::Algorithm,
+        error::BackendError,
+        password::PasswordField,
+        vault::Vault,
+    };
+    use chrono::{TimeZone, Utc};
+    use rusqlite::Connection;
+
+    fn vault(path: &std::path::Path) -> Connection {
+        let connection = Connection::open(path).unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        insert_data(
+            &connection,
+            "mail",
+            "master",
+            PasswordField::Password,
+            "hunter2",
+        )
+        .unwrap();
+        connection
+    }
+
+    #[test]
+    fn snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut connection = vault(&dir.path().join("data.db"));
+        // another vault's snapshots share the directory
+        let other = vault(&dir.path().join("data-old.db"));
+        take_snapshot(&other, "manual").unwrap();
+
+        delete_password_info(&connection, "mail", "master").unwrap();
+        let snapshots = list_snapshots(&connection).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].reason, "delete");
+        assert!(snapshots[0].path.starts_with(dir.path().join("backups")));
+        assert_eq!(verify_snapshot(&snapshots[0].path, "master").unwrap(), 1);
+        assert!(matches!(
+            verify_snapshot(&snapshots[0].path, "wrong"),
+            Err(BackendError::SnapshotKeyMismatch)
+        ));
+
+        // a snapshot from before the master password changed needs the old one
+        change_master_password(&connection, "master", "new master").unwrap();
+        let before_delete = snapshots[0].path.clone();
+        assert!(matches!(
+            restore_snapshot(&mut connection, &before_delete, "new master"),
+            Err(BackendError::SnapshotKeyMismatch)
+        ));
+        assert_eq!(
+            restore_snapshot(&mut connection, &before_delete, "master").unwrap(),
+            1
+        );
+        let mail = read_password_info(&connection, "mail", "master")
+            .unwrap()
+            .unwrap();
+        assert_eq!(mail.password.unwrap(), "hunter2");
+
+        // delete, master password change and restore
+        let reasons = list_snapshots(&connection)
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.reason)
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, ["restore", "master-password", "delete"]);
+    }
+    #[test]
+    fn migration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.db");
+        // a vault from before passman kept any metadata
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "create table PasswordInfo (id INTEGER NOT NULL PRIMARY KEY, name TEXT NOT NULL UNIQUE,
+                username TEXT, email TEXT, password TEXT, notes TEXT)",
+                (),
+            )
+            .unwrap();
+        let vault = Vault::open(&path).unwrap();
+        let snapshots = list_snapshots(vault.connection()).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].reason, "migration");
+
+        // an up to date vault is left alone
+        drop(vault);
+        Vault::open(&path).unwrap();
+        assert_eq!(
+            list_snapshots(&Connection::open(&path).unwrap())
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+    #[test]
+    fn disabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let connection = vault(&dir.path().join("data.db"));
+        let off = BackupPolicy {
+            keep_last: 0,
+            keep_daily: 0,
+            keep_weekly: 0,
+        };
+        assert_eq!(
+            get_backup_policy(&connection).unwrap(),
+            BackupPolicy::default()
+        );
+        set_backup_policy(&connection, &off).unwrap();
+        assert_eq!(get_backup_policy(&connection).unwrap(), off);
+        assert!(take_snapshot(&connection, "manual").unwrap().is_none());
+
+        // nor are vaults in memory snapshotted
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        assert!(take_snapshot(&connection, "manual").unwrap().is_none());
+        assert!(list_snapshots(&connection).unwrap().is_empty());
+    }
+    #[test]
+    fn retention() {
+        // two snapshots a day, from 2026-10-01 (a thursday) to 2026-10-18, newest first
+        let snapshots = (1..=18)
+            .rev()
+            .flat_map(|day| [18, 9].map(|hour| (day, hour)))
+            .map(|(day, hour)| Snapshot {
+                path: format!("{}-{}", day, hour).into(),
+                taken_at: Utc.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap(),
+                reason: "delete".to_string(),
+            })
+            .collect::<Vec<_>>();
+        let kept = |policy: BackupPolicy| {
+            let expired = expired_snapshots(&snapshots, &policy);
+            snapshots
+                .iter()
+                .filter(|snapshot| !expired.contains(snapshot))
+                .map(|snapshot| snapshot.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            kept(BackupPolicy {
+                keep_last: 3,
+                keep_daily: 0,
+                keep_weekly: 0
+            }),
+            ["18-18", "18-9", "17-18"]
+        );
+        assert_eq!(
+            kept(BackupPolicy {
+                keep_last: 1,
+                keep_daily: 3,
+                keep_weekly: 0
+            }),
+            ["18-18", "17-18", "16-18"]
+        );
+        // weeks start on monday, so the 18th, 11th and 4th are the last days of theirs
+        assert_eq!(
+            kept(BackupPolicy {
+                keep_last: 0,
+                keep_daily: 0,
+                keep_weekly: 2
+            }),
+            ["18-18", "11-18"]
+        );
+        assert_eq!(
+            kept(BackupPolicy {
+                keep_last: 0,
+                keep_daily: 0,
+                keep_weekly: 4
+            }),
+            ["18-18", "11-18", "4-18"]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, OsRng},
+    AeadCore, Aes256Gcm, Key, KeyInit,
+};
+
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2_hmac;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use sodiumoxide::crypto::aead::xchacha20poly1305_ietf;
+use typenum::consts::U32;
+
+use crate::{
+    db_ops::MASTER_KEYWORD,
+    envelope::{Algorithm, Envelope, Kdf},
+    error::BackendError,
+};
+
+/// Hashes `text` using `Sha256`.
+///
+/// # Arguments
+///
+/// - `text` - a reference to a `[u8]` to hash.
+pub fn hash(text: &[u8]) -> GenericArray<u8, U32> {
+    let mut hasher = Sha256::new();
+    hasher.update(text);
+    hasher.finalize()
+}
+
+/// Derives an encryption key from a password with the ppbkdf2 algorithm.
+///
+/// # Arguments
+///
+/// - `master_password` - the master password.
+/// - `kdf_salt` - a password to use to generate a key.
+pub fn derive_key(master_password: impl AsRef<[u8]>, password: impl AsRef<[u8]>) -> [u8; 32] {
+    let n = 4096;
+    let mut derived_key = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(
+        master_password.as_ref(),
+        password.as_ref(),
+        n,
+        &mut derived_key,
+    );
+    derived_key
+}
+/// Derives a key for something other than a password from the master password: an HMAC of `context`, keyed with the
+/// master record's key. Keys for different contexts never equal each other, or the key of any password.
+///
+/// # Arguments
+///
+/// - `master` - the master password.
+/// - `context` - what the key is for, with a version, e.g. `b"passman remote settings v1"`.
+pub fn derive_subkey(master: impl AsRef<[u8]>, context: &[u8]) -> [u8; 32] {
+    let mut key = <Hmac<Sha256> as Mac>::new_from_slice(&derive_key(master, MASTER_KEYWORD))
+        .expect("HMAC takes keys of any length");
+    key.update(context);
+    key.finalize().into_bytes().into()
+}
+/// Context mixed into the per-algorithm subkeys of `Kdf::Pbkdf2Sha256Bound`.
+const CIPHER_SUBKEY_CONTEXT: &[u8] = b"passman cipher v1";
+
+/// A symmetric cipher used to encrypt and decrypt password fields.
+/// New data is encrypted with a subkey bound to the algorithm (`Kdf::CURRENT`), so AES and XChaCha never share a key.
+/// Decryption always uses the algorithm and KDF recorded in the `Envelope`, so a vault may safely contain a mix of both.
+pub struct Cipher {
+    algorithm: Algorithm,
+    key: [u8; 32],
+}
+
+impl Cipher {
+    /// Creates a new `Cipher` from an already derived key.
+    ///
+    /// # Arguments
+    /// - `algorithm` - the `Algorithm` to use when encrypting new data.
+    /// - `key` - a 256 bit key, usually from `derive_key()`.
+    pub fn new(algorithm: Algorithm, key: [u8; 32]) -> Self {
+        Cipher { algorithm, key }
+    }
+    /// The key for an envelope written with `algorithm` and `kdf`.
+    fn key(&self, algorithm: Algorithm, kdf: Kdf) -> [u8; 32] {
+        match kdf {
+            Kdf::Pbkdf2Sha256 => self.key,
+            Kdf::Pbkdf2Sha256Bound => {
+                let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+                    .expect("HMAC takes keys of any length");
+                mac.update(CIPHER_SUBKEY_CONTEXT);
+                mac.update(&[algorithm.id()]);
+                mac.finalize().into_bytes().into()
+            }
+        }
+    }
+    /// Encrypts `data` with a fresh random nonce, returning an `Envelope` ready to be stored.
+    pub fn encrypt(&self, data: impl AsRef<[u8]>) -> Result<Envelope, BackendError> {
+        let key = self.key(self.algorithm, Kdf::CURRENT);
+        let (nonce, ciphertext) = match self.algorithm {
+            Algorithm::Aes256Gcm => {
+                let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+                let nonce = Aes256Gcm::generate_nonce(OsRng);
+                let ciphertext = cipher
+                    .encrypt(&nonce, data.as_ref())
+                    .map_err(|_| BackendError::AesError)?;
+                (nonce.to_vec(), ciphertext)
+            }
+            Algorithm::XChaCha20Poly1305 => {
+                let key = xchacha20poly1305_ietf::Key(key);
+                let nonce = xchacha20poly1305_ietf::gen_nonce();
+                let ciphertext = xchacha20poly1305_ietf::seal(data.as_ref(), None, &nonce, &key);
+                (nonce.0.to_vec(), ciphertext)
+            }
+        };
+        Ok(Envelope {
+            algorithm: self.algorithm,
+            kdf: Kdf::CURRENT,
+            nonce,
+            ciphertext,
+        })
+    }
+    /// Decrypts an `Envelope` using the algorithm and KDF recorded in it. May fail with a `BackendError`.
+    pub fn decrypt(&self, envelope: &Envelope) -> Result<Vec<u8>, BackendError> {
+        let key = self.key(envelope.algorithm, envelope.kdf);
+        match envelope.algorithm {
+            Algorithm::Aes256Gcm => {
+                let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+                cipher
+                    .decrypt(
+                        GenericArray::from_slice(&envelope.nonce),
+                        envelope.ciphertext.as_ref(),
+                    )
+                    .map_err(|_| BackendError::AesError)
+            }
+            Algorithm::XChaCha20Poly1305 => {
+                let key = xchacha20poly1305_ietf::Key(key);
+                let nonce = xchacha20poly1305_ietf::Nonce::from_slice(&envelope.nonce)
+                    .ok_or(BackendError::NoMatchingNonce)?;
+                xchacha20poly1305_ietf::open(&envelope.ciphertext, None, &nonce, &key)
+                    .map_err(|_| BackendError::AesError)
+            }
+        }
+    }
+}
+
+/// Decrypts a `Password` field. May fail with a `BackendError`.
+///
+/// # Arguments
+/// - `envelope` - the encrypted password field to decrypt.
+/// - `cipher` - a `Cipher` holding the key for this password.
+///
+pub fn decrypt_password_field(
+    envelope: &Envelope,
+    cipher: &Cipher,
+) -> Result<String, BackendError> {
+    let decrypted = cipher.decrypt(envelope)?;
+    Ok(String::from_utf8(decrypted)?)
+}
+/// Encrypts a `Password` field with a fresh random nonce, returning an `Envelope` ready to be stored.
+///
+/// # Arguments
+/// - `data` - the plaintext to encrypt.
+/// - `cipher` - a `Cipher` holding the key for this password.
+///
+pub fn encrypt_password_field(
+    data: impl AsRef<[u8]>,
+    cipher: &Cipher,
+) -> Result<Envelope, BackendError> {
+    cipher.encrypt(data)
+}
+/// Derives the key for a password and wraps it in a `Cipher`.
+///
+/// # Arguments
+/// - `algorithm` - the `Algorithm` to use when encrypting new data.
+/// - `master` - the master password.
+/// - `password_name` - the name of the password, used as the KDF salt.
+pub fn gen_cipher(
+    algorithm: Algorithm,
+    master: impl AsRef<[u8]>,
+    password_name: impl AsRef<[u8]>,
+) -> Cipher {
+    Cipher::new(algorithm, derive_key(master, password_name))
+}
+
+/// generates a password given a length using randomness from the OS
+pub fn generate_password(length: usize) -> String {
+    let characters: Vec<char> = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ1234567890!@#$%^&*()~`-=_+[]{}\\|;':\",.<>/?".chars().collect();
+    // wouldn't it be lovely if all of my code was this well-written?
+    // this code only looks like this because i didn't write it.
+    (0..length)
+        .map(|_| characters[OsRng.gen_range(0..characters.len())])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::{Algorithm, Envelope, Kdf};
+    use aes_gcm::{aead::Aead, aead::OsRng, AeadCore, Aes256Gcm, Key, KeyInit};
+    #[test]
+    fn sha512() {
+        // the string literal came from an online hasher to compare results to
+        let res = &super::hash(b"test")[..];
+        let expected =
+            hex_literal::hex!("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08");
+        assert_eq!(res, expected);
+    }
+    #[test]
+    fn derive_key() {
+        let res = super::derive_key("mymasterpassword", "salt");
+        let expected =
+            hex::decode("8f21affeb61e304e7b474229ffeb34309ed31beda58d153bc7ad9da6e9b6184c")
+                .unwrap();
+        assert_eq!(res.to_vec(), expected);
+    }
+    #[test]
+    fn derive_subkey() {
+        let key = super::derive_subkey("master", b"one");
+        assert_eq!(key, super::derive_subkey("master", b"one"));
+        assert_ne!(key, super::derive_subkey("master", b"two"));
+        assert_ne!(key, super::derive_subkey("other", b"one"));
+        // a password named after the context still gets a different key
+        assert_ne!(key, super::derive_key("master", "one"));
+        assert_ne!(
+            key,
+            super::derive_key("master", crate::db_ops::MASTER_KEYWORD)
+        );
+    }
+
+    #[test]
+    fn decrypt() {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let key = hex::decode("8f21affeb61e304e7b474229ffeb34309ed31beda58d153bc7ad9da6e9b6184c")
+            .unwrap();
+        // manually creating this key/cipher
+        let aes = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let ciphertext = aes.encrypt(&nonce, b"data".as_ref()).unwrap();
+        let envelope = Envelope {
+            algorithm: Algorithm::Aes256Gcm,
+            kdf: Kdf::Pbkdf2Sha256,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        };
+
+        // here's the function we're testing
+        let cipher = super::Cipher::new(Algorithm::Aes256Gcm, key.try_into().unwrap());
+        let result = super::decrypt_password_field(&envelope, &cipher).unwrap();
+
+        assert_eq!(result, "data");
+    }
+
+    #[test]
+    fn encrypt() {
+        for algorithm in [Algorithm::Aes256Gcm, Algorithm::XChaCha20Poly1305] {
+            let cipher = super::gen_cipher(algorithm, "mymasterpassword", "salt");
+            let envelope = super::encrypt_password_field("data", &cipher).unwrap();
+            assert_eq!(envelope.algorithm, algorithm);
+            assert_eq!(envelope.nonce.len(), algorithm.nonce_len());
+
+            let result = super::decrypt_password_field(&envelope, &cipher).unwrap();
+            assert_eq!(result, "data");
+        }
+    }
+    #[test]
+    fn algorithm_bound_keys() {
+        crate::init().unwrap();
+        let cipher = super::gen_cipher(Algorithm::Aes256Gcm, "mymasterpassword", "salt");
+        let envelope = cipher.encrypt("data").unwrap();
+        assert_eq!(envelope.kdf, Kdf::CURRENT);
+        assert_ne!(
+            cipher.key(Algorithm::Aes256Gcm, Kdf::CURRENT),
+            cipher.key(Algorithm::XChaCha20Poly1305, Kdf::CURRENT)
+        );
+
+        // an envelope claiming the wrong kdf must not decrypt
+        let mut mismatched = envelope.clone();
+        mismatched.kdf = Kdf::Pbkdf2Sha256;
+        assert!(cipher.decrypt(&mismatched).is_err());
+
+        // fields written before keys were bound to the algorithm still decrypt
+        let key = super::derive_key("mymasterpassword", "salt");
+        let nonce = sodiumoxide::crypto::aead::xchacha20poly1305_ietf::gen_nonce();
+        let legacy = Envelope {
+            algorithm: Algorithm::XChaCha20Poly1305,
+            kdf: Kdf::Pbkdf2Sha256,
+            nonce: nonce.0.to_vec(),
+            ciphertext: sodiumoxide::crypto::aead::xchacha20poly1305_ietf::seal(
+                b"data",
+                None,
+                &nonce,
+                &sodiumoxide::crypto::aead::xchacha20poly1305_ietf::Key(key),
+            ),
+        };
+        assert_eq!(
+            super::decrypt_password_field(&legacy, &cipher).unwrap(),
+            "data"
+        );
+    }
+    #[test]
+    fn decrypt_mixed_algorithms() {
+        // a cipher configured for one algorithm must still read envelopes written with the other
+        let aes = super::gen_cipher(Algorithm::Aes256Gcm, "mymasterpassword", "salt");
+        let xchacha = super::gen_cipher(Algorithm::XChaCha20Poly1305, "mymasterpassword", "salt");
+        let envelope = xchacha.encrypt("data").unwrap();
+        assert_eq!(
+            super::decrypt_password_field(&envelope, &aes).unwrap(),
+            "data"
+        );
+    }
+}
@@ -0,0 +1,2613 @@
+pub const MASTER_KEYWORD: &str = ".master";
+/// Key in the `VaultMetadata` table holding the id of the `Algorithm` new data is encrypted with.
+pub const CIPHER_METADATA: &str = "cipher";
+/// The key custom fields are tracked under in the `FieldUpdate` table, next to the standard fields' column names.
+pub const CUSTOM_FIELDS_KEY: &str = "fields";
+/// The key an entry's `Expiry` is tracked under in the `FieldUpdate` table.
+pub const EXPIRY_KEY: &str = "expiry";
+pub mod crud {
+    use crate::{
+        crypto::*,
+        envelope::{Algorithm, Envelope, Kdf},
+        error::*,
+        password::{
+            normalize_folder, DuePassword, EncryptedEntryField, EncryptedPasswordInfo, EntryField,
+            Expiry, PasswordField, PasswordInfo, Timestamps,
+        },
+    };
+    use chrono::{DateTime, Duration, Utc};
+    use rusqlite::{types::ValueRef, Connection, OptionalExtension};
+    use std::collections::BTreeMap;
+
+    use super::{CUSTOM_FIELDS_KEY, EXPIRY_KEY, MASTER_KEYWORD};
+    use crate::store::{
+        list_passwords, read_password, with_transaction, write_password, SqliteStore, VaultStore,
+    };
+    /// Reads an encrypted column as raw bytes. A cell of the wrong type, e.g. a legacy hex TEXT field that was never upgraded,
+    /// is read as raw bytes too, so decrypting it fails with a `CorruptField` naming the password rather than a column type error.
+    fn encrypted_column(
+        row: &rusqlite::Row,
+        index: usize,
+    ) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+        Ok(match row.get_ref(index)? {
+            ValueRef::Null => None,
+            ValueRef::Blob(bytes) | ValueRef::Text(bytes) => Some(bytes.to_vec()),
+            ValueRef::Integer(value) => Some(value.to_string().into_bytes()),
+            ValueRef::Real(value) => Some(value.to_string().into_bytes()),
+        })
+    }
+    /// Maps a row selected with `PASSWORD_INFO_COLUMNS` to an `EncryptedPasswordInfo`, without its custom fields.
+    fn encrypted_password_info_from_row(
+        row: &rusqlite::Row,
+    ) -> Result<EncryptedPasswordInfo, rusqlite::Error> {
+        Ok(EncryptedPasswordInfo {
+            id: row.get(0)?,
+
+            name: row.get(1)?,
+            email: encrypted_column(row, 2)?,
+            username: encrypted_column(row, 3)?,
+            password: encrypted_column(row, 4)?,
+            notes: encrypted_column(row, 5)?,
+            urls: encrypted_column(row, 6)?,
+            tags: encrypted_column(row, 7)?,
+            folder: encrypted_column(row, 8)?,
+            fields: Vec::new(),
+            timestamps: Timestamps {
+                created_at: row.get(9)?,
+                updated_at: row.get(10)?,
+                last_used_at: row.get(11)?,
+                fields: BTreeMap::new(),
+            },
+            expiry: Expiry {
+                expires_at: row.get(12)?,
+                rotation_days: row.get(13)?,
+            },
+        })
+    }
+    /// The columns `encrypted_password_info_from_row()` expects, in order.
+    const PASSWORD_INFO_COLUMNS: &str =
+        "id, name, email, username, password, notes, urls, tags, folder, created_at, updated_at, last_used_at, expires_at, rotation_days";
+
+    /// Reads the custom fields and per-field timestamps belonging to a password, which aren't stored in the `PasswordInfo` table itself.
+    fn with_children(
+        connection: &Connection,
+        mut password: EncryptedPasswordInfo,
+    ) -> Result<EncryptedPasswordInfo, rusqlite::Error> {
+        password.fields = get_entry_fields(connection, password.id)?;
+        let mut stmt = connection
+            .prepare("select field, updated_at from FieldUpdate where password_id = ?")?;
+        password.timestamps.fields = stmt
+            .query_map([password.id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(password)
+    }
+
+    /// Reads a `Password` from the SQLite database. The password should contain encrypted fields.
+    /// This function may fail with `rusqlite::Error`. Otherwise it will return an `Option<EncryptedPasswordInfo>`, being none if no password is found with the given search term.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `search_term` - a string slice that holds the name of the password to search for.
+    ///
+    pub fn get_password_info(
+        connection: &Connection,
+        search_term: &str,
+    ) -> Result<Option<EncryptedPasswordInfo>, rusqlite::Error> {
+        let mut stmt = connection.prepare(&format!(
+            "select {} from PasswordInfo where name = ?",
+            PASSWORD_INFO_COLUMNS
+        ))?;
+        stmt.query_row([search_term], encrypted_password_info_from_row)
+            .optional()?
+            .map(|password| with_children(connection, password))
+            .transpose()
+    }
+    /// Reads every `Password` (except the master record) from the SQLite database, with fields still encrypted.
+    /// This function may fail with `rusqlite::Error`.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    ///
+    pub fn get_all_password_info(
+        connection: &Connection,
+    ) -> Result<Vec<EncryptedPasswordInfo>, rusqlite::Error> {
+        let mut stmt = connection.prepare(&format!(
+            "select {} from PasswordInfo where name != ? order by name",
+            PASSWORD_INFO_COLUMNS
+        ))?;
+        let rows = stmt
+            .query_map([MASTER_KEYWORD], encrypted_password_info_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|password| with_children(connection, password))
+            .collect()
+    }
+    /// Reads the custom fields belonging to a password from the SQLite table `EntryField`, with keys and values still encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_id` - the id of the `PasswordInfo` the fields belong to.
+    ///
+    pub fn get_entry_fields(
+        connection: &Connection,
+        password_id: i32,
+    ) -> Result<Vec<EncryptedEntryField>, rusqlite::Error> {
+        let mut stmt = connection.prepare(
+            "select id, key, value, sensitive, created_at, updated_at from EntryField where password_id = ? order by id",
+        )?;
+        let rows = stmt.query_map([password_id], |row| {
+            Ok(EncryptedEntryField {
+                id: row.get(0)?,
+                key: encrypted_column(row, 1)?.unwrap_or_default(),
+                value: encrypted_column(row, 2)?.unwrap_or_default(),
+                sensitive: row.get(3)?,
+                created_at: row.get(4)?,
+                updated_at: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+    /// Decrypts a `Password`, which is assumed to already contain encrypted data.
+    /// This function will return a result with a `BackendError` if any step in the decryption process fails, including parsing a malformed `Envelope`;
+    /// Otherwise the function will return a `Password` with decrypted fields.
+    ///
+    /// # Arguments
+    ///
+    /// - `password` - An `EncryptedPasswordInfo` with encrypted fields.
+    /// - `master` - a string slice that holds the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `algorithm` - the vault's `Algorithm`. Fields are always decrypted with the algorithm recorded in their `Envelope`.
+    ///
+    pub(crate) fn decrypt_password_info(
+        password: EncryptedPasswordInfo,
+        master: &str,
+        algorithm: Algorithm,
+    ) -> Result<PasswordInfo, BackendError> {
+        // fucking awesome partial struct destructuring
+        let EncryptedPasswordInfo {
+            id,
+            name,
+            timestamps,
+            expiry,
+            .. // and the rest
+        } = password;
+
+        // this is not in the decrypt_field() function because it would involve deriving the key and generating the cipher 4 times
+        // considering the iterations involved in the kdf function it would be extremely inefficient
+        let cipher = gen_cipher(algorithm, master, &name);
+
+        // thank you @seaish for this fucking awesome function
+        // ithis is so cool
+        let f = |column: PasswordField, field: Option<Vec<u8>>| {
+            field
+                .map(|data| {
+                    let envelope = Envelope::from_bytes(&data)?;
+                    decrypt_password_field(&envelope, &cipher)
+                })
+                .transpose() // transpose switches "...the Option of a Result to a Result of an Option." ... that is so cool!!
+                .map_err(|e| e.in_field(&name, column))
+        };
+
+        let email = f(PasswordField::Email, password.email)?;
+        let username = f(PasswordField::Username, password.username)?;
+        let pass = f(PasswordField::Password, password.password)?;
+        let notes = f(PasswordField::Notes, password.notes)?;
+        let urls =
+            f(PasswordField::Urls, password.urls)?.map_or_else(Vec::new, |urls| split_list(&urls));
+        let tags =
+            f(PasswordField::Tags, password.tags)?.map_or_else(Vec::new, |tags| split_list(&tags));
+        let folder = f(PasswordField::Folder, password.folder)?;
+
+        let fields = password
+            .fields
+            .into_iter()
+            .map(|field| {
+                let decrypt = |data: &[u8]| -> Result<String, BackendError> {
+                    Envelope::from_bytes(data)
+                        .map_err(BackendError::from)
+                        .and_then(|envelope| decrypt_password_field(&envelope, &cipher))
+                        .map_err(|e| e.in_field(&name, format!("custom field #{}", field.id)))
+                };
+                Ok(EntryField {
+                    id: field.id,
+                    key: decrypt(&field.key)?,
+                    value: decrypt(&field.value)?,
+                    sensitive: field.sensitive,
+                    created_at: field.created_at,
+                    updated_at: field.updated_at,
+                })
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        Ok(PasswordInfo {
+            id,
+            name,
+            email,
+            username,
+            notes,
+            password: pass,
+            urls,
+            tags,
+            folder,
+            fields,
+            timestamps,
+            expiry,
+        })
+    }
+
+    /// Encrypts every field of a `PasswordInfo`, the reverse of `decrypt_password_info()`. Ids and timestamps are copied as they are.
+    ///
+    /// # Arguments
+    ///
+    /// - `password` - the `PasswordInfo` to encrypt.
+    /// - `master` - a string slice that holds the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `algorithm` - the `Algorithm` to encrypt with.
+    ///
+    pub(crate) fn encrypt_password_info(
+        password: &PasswordInfo,
+        master: &str,
+        algorithm: Algorithm,
+    ) -> Result<EncryptedPasswordInfo, BackendError> {
+        let cipher = gen_cipher(algorithm, master, &password.name);
+        let f = |data: Option<&str>| {
+            data.map(|data| {
+                encrypt_password_field(data, &cipher).map(|envelope| envelope.to_bytes())
+            })
+            .transpose()
+        };
+        let list = |values: &[String]| Some(join_list(values)).filter(|list| !list.is_empty());
+
+        let fields = password
+            .fields
+            .iter()
+            .map(|field| {
+                Ok(EncryptedEntryField {
+                    id: field.id,
+                    key: encrypt_password_field(&field.key, &cipher)?.to_bytes(),
+                    value: encrypt_password_field(&field.value, &cipher)?.to_bytes(),
+                    sensitive: field.sensitive,
+                    created_at: field.created_at,
+                    updated_at: field.updated_at,
+                })
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        Ok(EncryptedPasswordInfo {
+            id: password.id,
+            name: password.name.clone(),
+            email: f(password.email.as_deref())?,
+            username: f(password.username.as_deref())?,
+            notes: f(password.notes.as_deref())?,
+            password: f(password.password.as_deref())?,
+            urls: f(list(&password.urls).as_deref())?,
+            tags: f(list(&password.tags).as_deref())?,
+            folder: f(password.folder.as_deref())?,
+            fields,
+            timestamps: password.timestamps.clone(),
+            expiry: password.expiry,
+        })
+    }
+
+    // the following are functions that implement CRUD (create, read, update, delete)
+
+    /// Reads and decrypts a password from the SQLite table `PasswordInfo`.
+    /// This function will return a result with the `GetPasswordError` enum, which wraps an `Option`;
+    /// If no `Password` name matches the given `search_term`, the function will return `None`.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `search_term` - a string slice that holds the name of the password to search for.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn read_password_info(
+        connection: &Connection,
+        search_term: &str,
+        master: &str,
+    ) -> std::result::Result<std::option::Option<PasswordInfo>, BackendError> {
+        read_password(&SqliteStore::new(connection, master), search_term, master)
+    }
+    /// Reads and decrypts every password in the vault, sorted by name.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn list_password_info(
+        connection: &Connection,
+        master: &str,
+    ) -> Result<Vec<PasswordInfo>, BackendError> {
+        list_passwords(&SqliteStore::new(connection, master), master)
+    }
+    /// Reads and decrypts every password with the given tag. Tags are compared case-insensitively.
+    /// Because tags are encrypted, every password in the vault has to be decrypted to answer this query.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `tag` - the tag to search for.
+    ///
+    pub fn list_by_tag(
+        connection: &Connection,
+        master: &str,
+        tag: &str,
+    ) -> Result<Vec<PasswordInfo>, BackendError> {
+        let mut passwords = list_password_info(connection, master)?;
+        passwords.retain(|password| password.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+        Ok(passwords)
+    }
+    /// Reads and decrypts every password in a folder, including its subfolders.
+    /// Because folders are encrypted, every password in the vault has to be decrypted to answer this query.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `folder` - the folder path to search in, e.g. `Work/Email`.
+    ///
+    pub fn list_by_folder(
+        connection: &Connection,
+        master: &str,
+        folder: &str,
+    ) -> Result<Vec<PasswordInfo>, BackendError> {
+        let folder = normalize_folder(folder).unwrap_or_default();
+        let mut passwords = list_password_info(connection, master)?;
+        passwords.retain(|password| {
+            password.folder.as_deref().is_some_and(|path| {
+                folder.is_empty()
+                    || path == folder
+                    || path
+                        .strip_prefix(&folder)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+        });
+        Ok(passwords)
+    }
+    /// Encrypts and inserts a field into the SQLite table `PasswordInfo`.
+    /// This function makes use of SQLite's `UPSERT` statement, i.e. create an entry with the given value to insert, or update an existing entry.
+    /// (Note: this function serves the purpose of Updating and Creating within the CRUD model)
+    /// The encrypted field is stored as a serialized `Envelope` in a BLOB column, using the vault's `Algorithm`.
+    /// If the function is successful it will return a `usize` of how many entries were updated - should be 1.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password to insert or update into.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `column_name` - a `PasswordField` to insert or update data into.
+    /// - `data` - a string slice holding the data to encrypt and insert into the entry.
+    ///
+    pub fn insert_data(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+        column_name: PasswordField,
+        data: &str,
+    ) -> std::result::Result<usize, BackendError> {
+        check_name(password_name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let cipher = gen_cipher(store.algorithm()?, master, password_name);
+            let data = encrypt_password_field(data, &cipher)?.to_bytes();
+            let mut password = get_or_new(store, password_name)?;
+            set_column(&mut password, column_name, Some(data));
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+
+    /// Fails with `BackendError::ReservedName` if `name` is the master record's name, which must never be written as a password.
+    fn check_name(name: &str) -> Result<(), BackendError> {
+        if name == MASTER_KEYWORD {
+            return Err(BackendError::ReservedName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Reads a password to change it, or starts an empty one if there is no password with this name yet.
+    fn get_or_new(
+        store: &dyn VaultStore,
+        password_name: &str,
+    ) -> Result<EncryptedPasswordInfo, BackendError> {
+        Ok(store
+            .get(password_name)?
+            .unwrap_or_else(|| EncryptedPasswordInfo {
+                name: password_name.to_string(),
+                ..Default::default()
+            }))
+    }
+
+    /// Replaces one encrypted column of a password, or clears it if `data` is `None`, recording when it was changed.
+    fn set_column(
+        password: &mut EncryptedPasswordInfo,
+        column_name: PasswordField,
+        data: Option<Vec<u8>>,
+    ) {
+        let column = match column_name {
+            PasswordField::Email => &mut password.email,
+            PasswordField::Username => &mut password.username,
+            PasswordField::Password => &mut password.password,
+            PasswordField::Notes => &mut password.notes,
+            PasswordField::Urls => &mut password.urls,
+            PasswordField::Tags => &mut password.tags,
+            PasswordField::Folder => &mut password.folder,
+        };
+        *column = data;
+        touch_password(password, &column_name.to_string());
+    }
+
+    /// Records that a field of a password was changed just now, before it's put back. A new password is created now, too.
+    /// `field` is a `PasswordField`'s column name, `CUSTOM_FIELDS_KEY` or `EXPIRY_KEY`.
+    fn touch_password(password: &mut EncryptedPasswordInfo, field: &str) {
+        let now = Utc::now();
+        password.timestamps.created_at.get_or_insert(now);
+        password.timestamps.updated_at = Some(now);
+        password.timestamps.fields.insert(field.to_string(), now);
+    }
+
+    /// Encrypts and writes every field of a `PasswordInfo` in a single transaction, creating the password if it doesn't exist yet.
+    /// Either every field is written or, if anything fails, none of them are - so an entry is never left half-written.
+    ///
+    /// Fields that are `None` (or empty lists) are left as they are, so this can also be used to update some fields of an existing password.
+    /// Fields that already hold the given value aren't written again, so their timestamps and revisions only change when the value does.
+    /// Custom fields with an `id` of 0 are added, and any others update the existing custom field with that `id`.
+    /// The expiry is only written if it has an expiry date or rotation interval.
+    /// Returns the number of fields that were written, including custom fields.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_info` - the `PasswordInfo` to write. Its `name` identifies the password, and its `id` and timestamps are ignored.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn upsert_password_info(
+        connection: &Connection,
+        password_info: &PasswordInfo,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        let name = password_info.name.as_str();
+        check_name(name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let mut password =
+                read_password(store, name, master)?.unwrap_or_else(|| PasswordInfo {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+            let now = Utc::now();
+
+            let mut written = 0;
+            let standard_fields = [
+                (&mut password.email, &password_info.email),
+                (&mut password.username, &password_info.username),
+                (&mut password.notes, &password_info.notes),
+                (&mut password.password, &password_info.password),
+                (&mut password.folder, &password_info.folder),
+            ];
+            for (current, data) in standard_fields {
+                if data.is_some() && *current != *data {
+                    current.clone_from(data);
+                    written += 1;
+                }
+            }
+            let list_fields = [
+                (&mut password.urls, &password_info.urls),
+                (&mut password.tags, &password_info.tags),
+            ];
+            for (current, values) in list_fields {
+                let data = join_list(values);
+                if !data.is_empty() && data != join_list(current) {
+                    *current = split_list(&data);
+                    written += 1;
+                }
+            }
+
+            for field in &password_info.fields {
+                if field.id == 0 {
+                    password.fields.push(EntryField {
+                        created_at: None,
+                        updated_at: None,
+                        ..field.clone()
+                    });
+                } else {
+                    let current = password
+                        .fields
+                        .iter_mut()
+                        .find(|current| current.id == field.id)
+                        .ok_or_else(|| {
+                            BackendError::NoMatchingRecord(format!(
+                                "custom field {} in \"{}\"",
+                                field.id, name
+                            ))
+                        })?;
+                    current.key.clone_from(&field.key);
+                    current.value.clone_from(&field.value);
+                    current.sensitive = field.sensitive;
+                    current.updated_at = Some(now);
+                }
+                written += 1;
+            }
+
+            let expiry = password_info.expiry;
+            if expiry.expires_at.is_some() || expiry.rotation_days.is_some() {
+                password.expiry = expiry;
+            }
+
+            write_password(store, &password, master)?;
+            Ok(written)
+        })
+    }
+
+    /// Writes a password as a whole, so that afterwards it matches `password_info` exactly:
+    /// unlike `upsert_password_info()`, fields that are `None` are cleared and the custom fields are replaced.
+    /// Only fields whose value actually changes are written, so their timestamps and revisions only reflect real changes.
+    /// All changes are made in a single transaction. Returns the number of fields that changed, counting the custom fields and expiry as one each.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_info` - the `PasswordInfo` to write. Its `name` identifies the password, and its `id`, custom field `id`s and timestamps are ignored.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn replace_password_info(
+        connection: &Connection,
+        password_info: &PasswordInfo,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        let name = password_info.name.as_str();
+        check_name(name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let current = read_password(store, name, master)?.unwrap_or_default();
+            let list = |values: &[String]| Some(join_list(values)).filter(|list| !list.is_empty());
+            let standard_fields = [
+                current.email == password_info.email,
+                current.username == password_info.username,
+                current.notes == password_info.notes,
+                current.password == password_info.password,
+                list(&current.urls) == list(&password_info.urls),
+                list(&current.tags) == list(&password_info.tags),
+                current.folder == password_info.folder,
+            ];
+            let mut changed = standard_fields.iter().filter(|&&same| !same).count();
+
+            let contents = |fields: &[EntryField]| -> Vec<(String, String, bool)> {
+                fields
+                    .iter()
+                    .map(|field| (field.key.clone(), field.value.clone(), field.sensitive))
+                    .collect()
+            };
+            let mut password = password_info.clone();
+            if contents(&current.fields) == contents(&password_info.fields) {
+                // nothing to replace, so the custom fields keep their ids
+                password.fields = current.fields.clone();
+            } else {
+                password.fields = password_info
+                    .fields
+                    .iter()
+                    .map(|field| EntryField {
+                        key: field.key.clone(),
+                        value: field.value.clone(),
+                        sensitive: field.sensitive,
+                        ..Default::default()
+                    })
+                    .collect();
+                changed += 1;
+            }
+            if current.expiry != password_info.expiry {
+                changed += 1;
+            }
+
+            write_password(store, &password, master)?;
+            Ok(changed)
+        })
+    }
+
+    /// Clears a field in the SQLite table `PasswordInfo`, setting it back to NULL.
+    /// Returns the number of updated rows, which will be 0 if no password has the given name.
+    /// Fails with `BackendError::ReservedName` if `password_name` is the master record's name.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password to update.
+    /// - `column_name` - the `PasswordField` to clear.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn clear_data(
+        connection: &Connection,
+        password_name: &str,
+        column_name: PasswordField,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        check_name(password_name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let Some(mut password) = store.get(password_name)? else {
+                return Ok(0);
+            };
+            set_column(&mut password, column_name, None);
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+
+    /// Creates an empty password if there is none with this name yet.
+    /// A new password starts after the highest revision in the vault, tombstones included, so `sync` sees it as newer than
+    /// an earlier deletion of the same name, and a renamed password as newer than the name it had before.
+    fn ensure_password_row(
+        connection: &Connection,
+        password_name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), rusqlite::Error> {
+        let created = connection.execute(
+            "insert into PasswordInfo(name, created_at, updated_at, revision)
+            select ?1, ?2, ?2, coalesce(max(revision), 0) + 1
+            from (select revision from PasswordInfo union all select revision from Tombstone)
+            where true on conflict(name) do nothing",
+            (password_name, now),
+        )?;
+        if created > 0 {
+            connection.execute("delete from Tombstone where name = ?", [password_name])?;
+        }
+        Ok(())
+    }
+
+    /// Records that a password was deleted (or renamed), so the deletion can be synced to other vaults.
+    /// Must be called before the password's row is deleted.
+    fn insert_tombstone(
+        connection: &Connection,
+        password_name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<usize, rusqlite::Error> {
+        connection.execute(
+            "insert into Tombstone(name, revision, deleted_at)
+            select name, revision + 1, ?2 from PasswordInfo where name = ?1
+            on conflict(name) do update set revision = excluded.revision, deleted_at = excluded.deleted_at",
+            (password_name, now),
+        )
+    }
+
+    /// Sets when a password expires and/or how often it has to be rotated. Passing `Expiry::default()` removes both.
+    /// Returns the number of updated rows, which will be 0 if no password has the given name.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `expiry` - the new `Expiry`.
+    ///
+    pub fn set_expiry(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+        expiry: &Expiry,
+    ) -> Result<usize, BackendError> {
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let Some(mut password) = store.get(password_name)? else {
+                return Ok(0);
+            };
+            password.expiry = *expiry;
+            touch_password(&mut password, EXPIRY_KEY);
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+    /// Lists passwords that are expired, or will be due within `within` of `now`, soonest first.
+    /// Only plaintext columns are read, so this doesn't require the master password.
+    /// Rotation intervals count from the last change to the password field, falling back to the last change to the entry.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `now` - the current time.
+    /// - `within` - how far ahead to look for passwords that are about to be due.
+    ///
+    pub fn list_due(
+        connection: &Connection,
+        now: DateTime<Utc>,
+        within: Duration,
+    ) -> Result<Vec<DuePassword>, rusqlite::Error> {
+        let mut stmt = connection.prepare(
+            "select p.name, p.expires_at, p.rotation_days, coalesce(f.updated_at, p.updated_at, p.created_at)
+            from PasswordInfo p left join FieldUpdate f on f.password_id = p.id and f.field = 'password'
+            where p.name != ?1 and (p.expires_at is not null or p.rotation_days is not null)",
+        )?;
+        let rows = stmt.query_map([MASTER_KEYWORD], |row| {
+            let expiry = Expiry {
+                expires_at: row.get(1)?,
+                rotation_days: row.get(2)?,
+            };
+            Ok((row.get::<_, String>(0)?, expiry.due_at(row.get(3)?)))
+        })?;
+
+        let mut due = Vec::new();
+        for row in rows {
+            if let (name, Some(due_at)) = row? {
+                if due_at <= now + within {
+                    due.push(DuePassword {
+                        name,
+                        due_at,
+                        expired: due_at <= now,
+                    });
+                }
+            }
+        }
+        due.sort_by(|a, b| a.due_at.cmp(&b.due_at).then_with(|| a.name.cmp(&b.name)));
+        Ok(due)
+    }
+
+    /// Records that a password was just used, e.g. read by the user. Returns the number of updated rows, which will be 0 if no password has the given name.
+    /// Like any write, this fails with `BackendError::ManifestMismatch` or `BackendError::ManifestMissing` until the vault is
+    /// accepted as it is.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password that was used.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn mark_password_used(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let Some(mut password) = store.get(password_name)? else {
+                return Ok(0);
+            };
+            password.timestamps.last_used_at = Some(Utc::now());
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+
+    /// Encrypts and inserts a list of values (e.g. URLs or tags) into a list column of the SQLite table `PasswordInfo`, replacing the previous list.
+    /// Values are trimmed, and empty values are dropped.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password to insert or update into.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `column_name` - either `PasswordField::Urls` or `PasswordField::Tags`.
+    /// - `values` - the values to store.
+    ///
+    pub fn insert_list_data(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+        column_name: PasswordField,
+        values: &[String],
+    ) -> Result<usize, BackendError> {
+        insert_data(
+            connection,
+            password_name,
+            master,
+            column_name,
+            &join_list(values),
+        )
+    }
+
+    /// Joins a list of values into the newline separated form stored in list columns. Values are trimmed, and empty values are dropped.
+    pub(crate) fn join_list(values: &[String]) -> String {
+        values
+            .iter()
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Encrypts and inserts a custom field into the SQLite table `EntryField`, creating the password entry if it doesn't exist yet.
+    /// Both the key and the value are encrypted with the password's key.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password the field belongs to.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `key` - the name of the field, e.g. "PIN".
+    /// - `value` - the data to store in the field.
+    /// - `sensitive` - whether the value should be masked when displayed.
+    ///
+    pub fn insert_entry_field(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+        key: &str,
+        value: &str,
+        sensitive: bool,
+    ) -> Result<usize, BackendError> {
+        check_name(password_name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let cipher = gen_cipher(store.algorithm()?, master, password_name);
+            let now = Utc::now();
+            let mut password = get_or_new(store, password_name)?;
+            password.fields.push(EncryptedEntryField {
+                id: 0,
+                key: encrypt_password_field(key, &cipher)?.to_bytes(),
+                value: encrypt_password_field(value, &cipher)?.to_bytes(),
+                sensitive,
+                created_at: Some(now),
+                updated_at: Some(now),
+            });
+            touch_password(&mut password, CUSTOM_FIELDS_KEY);
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+    /// Re-encrypts and updates an existing custom field, identified by `field.id`. Returns the number of updated rows.
+    /// Fails with `BackendError::NoMatchingRecord` if there is no such field, or it doesn't belong to the given password.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password the field belongs to.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `field` - the `EntryField` with its new key, value and sensitivity.
+    ///
+    pub fn update_entry_field(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+        field: &EntryField,
+    ) -> Result<usize, BackendError> {
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let no_match = || {
+                BackendError::NoMatchingRecord(format!(
+                    "custom field {} in \"{}\"",
+                    field.id, password_name
+                ))
+            };
+            let mut password = store.get(password_name)?.ok_or_else(no_match)?;
+            let cipher = gen_cipher(store.algorithm()?, master, password_name);
+            let current = password
+                .fields
+                .iter_mut()
+                .find(|current| current.id == field.id)
+                .ok_or_else(no_match)?;
+            current.key = encrypt_password_field(&field.key, &cipher)?.to_bytes();
+            current.value = encrypt_password_field(&field.value, &cipher)?.to_bytes();
+            current.sensitive = field.sensitive;
+            current.updated_at = Some(Utc::now());
+            touch_password(&mut password, CUSTOM_FIELDS_KEY);
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+    /// Deletes one custom field from the SQLite table `EntryField`. Returns the number of deleted fields, which will be 0
+    /// if there is no field with this id.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `field_id` - the id of the `EntryField` to delete.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn delete_entry_field(
+        connection: &Connection,
+        field_id: i32,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        let password_name: Option<String> = connection
+            .query_row(
+                "select p.name from EntryField f join PasswordInfo p on p.id = f.password_id where f.id = ?",
+                [field_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(password_name) = password_name else {
+            return Ok(0);
+        };
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let Some(mut password) = store.get(&password_name)? else {
+                return Ok(0);
+            };
+            password.fields.retain(|field| field.id != field_id);
+            touch_password(&mut password, CUSTOM_FIELDS_KEY);
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+
+    /// Re-encrypts every field in the vault with a different `Algorithm`, and records it as the vault's new algorithm.
+    /// All changes are made in a single transaction, so a failure leaves the vault untouched. The vault is snapshotted first.
+    /// Returns the number of fields that were re-encrypted.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `algorithm` - the `Algorithm` to convert the vault to.
+    ///
+    pub fn convert_vault_cipher(
+        connection: &Connection,
+        master: &str,
+        algorithm: Algorithm,
+    ) -> Result<usize, BackendError> {
+        crate::backup::take_snapshot(connection, "cipher")?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let mut converted = 0;
+            for password in store.list()? {
+                let cipher = gen_cipher(algorithm, master, &password.name);
+                let (password, count) = reencrypt_password_info(password, &cipher, &cipher)?;
+                store.put(&password)?;
+                converted += count;
+            }
+            super::util::set_vault_algorithm(connection, algorithm)?;
+            Ok(converted)
+        })
+    }
+
+    /// Re-encrypts every field in the vault with keys derived from a new master password, and replaces the master record's
+    /// hash of it. The recovery phrase is kept. All changes are made in a single transaction, so a failure leaves the vault untouched.
+    /// The vault is snapshotted first.
+    /// Returns the number of fields that were re-encrypted.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the current master password. The master password should be verified/authenticated by the time this function is called.
+    /// - `new_master` - a string slice holding the new master password.
+    ///
+    pub fn change_master_password(
+        connection: &Connection,
+        master: &str,
+        new_master: &str,
+    ) -> Result<usize, BackendError> {
+        crate::backup::take_snapshot(connection, "master-password")?;
+        let transaction = connection.unchecked_transaction()?;
+        let changed = rekey_vault(&transaction, master, new_master)?;
+        transaction.commit()?;
+        Ok(changed)
+    }
+
+    /// Does the work of `change_master_password()` without managing a transaction, for callers that need to change more
+    /// in the same one.
+    pub(crate) fn rekey_vault(
+        connection: &Connection,
+        master: &str,
+        new_master: &str,
+    ) -> Result<usize, BackendError> {
+        // the manifest is resealed with the new master password once everything is re-encrypted
+        let store = SqliteStore::rekeying(connection, master, new_master);
+        with_transaction(&store, |store| {
+            let algorithm = store.algorithm()?;
+            let mut changed = 0;
+            for password in store.list()? {
+                let from = gen_cipher(algorithm, master, &password.name);
+                let to = gen_cipher(algorithm, new_master, &password.name);
+                let (password, count) = reencrypt_password_info(password, &from, &to)?;
+                store.put(&password)?;
+                changed += count;
+            }
+            crate::team::rekey_identity(connection, master, new_master)?;
+            connection.execute(
+                "update PasswordInfo set password = ?1 where name = ?2",
+                (hex::encode(hash(new_master.as_bytes())), MASTER_KEYWORD),
+            )?;
+            Ok(changed)
+        })
+    }
+
+    /// Decrypts every field of an encrypted password with `from` and encrypts it again with `to`.
+    /// This is the building block for changing the cipher, name or key of a password; the result still has to be put.
+    /// Returns the re-encrypted password and the number of fields that were re-encrypted.
+    fn reencrypt_password_info(
+        mut password: EncryptedPasswordInfo,
+        from: &Cipher,
+        to: &Cipher,
+    ) -> Result<(EncryptedPasswordInfo, usize), BackendError> {
+        let name = password.name.clone();
+        let reencrypt = |data: &[u8]| -> Result<Vec<u8>, BackendError> {
+            let envelope = Envelope::from_bytes(data)?;
+            let plaintext = from.decrypt(&envelope)?;
+            Ok(to.encrypt(plaintext)?.to_bytes())
+        };
+        let mut reencrypted = 0;
+        let columns = [
+            (PasswordField::Email, &mut password.email),
+            (PasswordField::Username, &mut password.username),
+            (PasswordField::Password, &mut password.password),
+            (PasswordField::Notes, &mut password.notes),
+            (PasswordField::Urls, &mut password.urls),
+            (PasswordField::Tags, &mut password.tags),
+            (PasswordField::Folder, &mut password.folder),
+        ];
+        for (column, field) in columns {
+            if let Some(data) = field {
+                *data = reencrypt(data).map_err(|e| e.in_field(&name, column))?;
+                reencrypted += 1;
+            }
+        }
+        for field in &mut password.fields {
+            let label = format!("custom field #{}", field.id);
+            field.key = reencrypt(&field.key).map_err(|e| e.in_field(&name, &label))?;
+            field.value = reencrypt(&field.value).map_err(|e| e.in_field(&name, &label))?;
+            reencrypted += 1;
+        }
+        Ok((password, reencrypted))
+    }
+
+    /// Renames a password. Because the password name is the KDF salt, every field is re-encrypted under the new name.
+    /// All changes are made in a single transaction. Returns the number of renamed passwords, which will be 0 if `old_name` doesn't exist.
+    /// Fails with `BackendError::NameTaken` if a password named `new_name` already exists, and with `BackendError::ReservedName`
+    /// if either name is the master record's.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `old_name` - a string slice holding the current name of the password.
+    /// - `new_name` - a string slice holding the new name of the password.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn rename_password_info(
+        connection: &Connection,
+        old_name: &str,
+        new_name: &str,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        check_name(old_name)?;
+        check_name(new_name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let Some(password) = store.get(old_name)? else {
+                return Ok(0);
+            };
+            if store.get(new_name)?.is_some() {
+                return Err(BackendError::NameTaken(new_name.to_string()));
+            }
+
+            let algorithm = store.algorithm()?;
+            let from = gen_cipher(algorithm, master, old_name);
+            let to = gen_cipher(algorithm, master, new_name);
+            let (mut password, _) = reencrypt_password_info(password, &from, &to)?;
+
+            // to other vaults, a rename is a deletion of the old name and a new password
+            store.delete(old_name)?;
+            password.name = new_name.to_string();
+            password.timestamps.updated_at = Some(Utc::now());
+            store.put(&password)?;
+            Ok(1)
+        })
+    }
+
+    /// Copies a password, including its custom fields, expiry and field timestamps, to a new name.
+    /// The copy is re-encrypted under the new name and gets a fresh creation time.
+    /// All changes are made in a single transaction. Returns the number of copied passwords, which will be 0 if `name` doesn't exist.
+    /// Fails with `BackendError::NameTaken` if a password named `new_name` already exists, and with `BackendError::ReservedName`
+    /// if either name is the master record's.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `name` - a string slice holding the name of the password to copy.
+    /// - `new_name` - a string slice holding the name of the copy.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn duplicate_password_info(
+        connection: &Connection,
+        name: &str,
+        new_name: &str,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        check_name(name)?;
+        check_name(new_name)?;
+        let store = SqliteStore::new(connection, master);
+        with_transaction(&store, |store| {
+            let Some(original) = store.get(name)? else {
+                return Ok(0);
+            };
+            if store.get(new_name)?.is_some() {
+                return Err(BackendError::NameTaken(new_name.to_string()));
+            }
+
+            let algorithm = store.algorithm()?;
+            let from = gen_cipher(algorithm, master, name);
+            let to = gen_cipher(algorithm, master, new_name);
+            let (mut copy, _) = reencrypt_password_info(original, &from, &to)?;
+            let now = Utc::now();
+            copy.name = new_name.to_string();
+            copy.timestamps.created_at = Some(now);
+            copy.timestamps.updated_at = Some(now);
+            // the original keeps its custom field ids, so the copy's get new ones
+            store.put(&copy)?;
+            Ok(1)
+        })
+    }
+
+    /// Splits a decrypted list column back into its values.
+    fn split_list(data: &str) -> Vec<String> {
+        data.lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Wraps a legacy `hex(nonce || ciphertext)` field in an `Envelope`.
+    /// Legacy fields were always encrypted with AES 256 GCM and a key derived with PBKDF2 from the password name.
+    /// The result is parsed back before it is returned, so a damaged legacy field is rejected instead of being rewritten as a
+    /// broken envelope.
+    pub(crate) fn legacy_to_envelope(data: &str) -> Result<Envelope, BackendError> {
+        let decoded = hex::decode(data)?;
+        let nonce = decoded.get(..12).ok_or(BackendError::NoMatchingNonce)?;
+        // anything shorter than the 16 byte GCM tag can't have been produced by the old encrypt_password_info()
+        if decoded.len() < 12 + 16 {
+            return Err(EnvelopeError::Truncated.into());
+        }
+        let envelope = Envelope {
+            algorithm: Algorithm::Aes256Gcm,
+            kdf: Kdf::Pbkdf2Sha256,
+            nonce: nonce.to_vec(),
+            ciphertext: decoded[12..].to_vec(),
+        };
+        Ok(Envelope::from_bytes(&envelope.to_bytes())?)
+    }
+
+    /// Deletes one record from the SQLite table `PasswordInfo` Use with caution!.
+    /// A tombstone is left behind, so `sync` can delete the password from other vaults too,
+    /// and the vault is snapshotted first (see `backup`).
+    ///  # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password to insert or update into.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    ///
+    pub fn delete_password_info(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        crate::backup::take_snapshot(connection, "delete")?;
+        remove_password_info(connection, password_name, master)
+    }
+
+    /// Does the work of `delete_password_info()` without taking a snapshot, for callers that took one for a bigger change.
+    pub(crate) fn remove_password_info(
+        connection: &Connection,
+        password_name: &str,
+        master: &str,
+    ) -> Result<usize, BackendError> {
+        Ok(SqliteStore::new(connection, master)
+            .delete(password_name)?
+            .into())
+    }
+
+    /// Deletes a password's rows, leaving a tombstone. Returns the number of deleted passwords.
+    /// This is how `store::SqliteStore` deletes passwords. It doesn't manage a transaction or reseal the manifest itself.
+    pub(crate) fn delete_password_rows(
+        connection: &Connection,
+        password_name: &str,
+    ) -> Result<usize, BackendError> {
+        insert_tombstone(connection, password_name, Utc::now())?;
+        for table in ["EntryField", "FieldUpdate"] {
+            connection.execute(
+                &format!(
+                    "delete from {} where password_id in (select id from PasswordInfo where name = ?)",
+                    table
+                ),
+                [password_name],
+            )?;
+        }
+        Ok(connection.execute("delete from PasswordInfo where name = ?", [password_name])?)
+    }
+
+    /// Writes an encrypted password as a whole, creating it if it doesn't exist yet: every column and custom field is replaced,
+    /// and so are the per-field timestamps that changed. Returns the password's id.
+    /// `password.id` is ignored, since passwords are identified by name. Custom field ids are kept unless another password uses them.
+    /// This is how `store::SqliteStore` puts passwords. It doesn't manage a transaction or reseal the manifest itself.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password` - the `EncryptedPasswordInfo` to write.
+    ///
+    pub(crate) fn put_password_info(
+        connection: &Connection,
+        password: &EncryptedPasswordInfo,
+    ) -> Result<i32, BackendError> {
+        let now = Utc::now();
+        ensure_password_row(connection, &password.name, now)?;
+        connection.execute(
+            "update PasswordInfo set email = ?1, username = ?2, password = ?3, notes = ?4, urls = ?5, tags = ?6, folder = ?7,
+            created_at = coalesce(?8, created_at), updated_at = ?9, last_used_at = ?10, expires_at = ?11, rotation_days = ?12,
+            revision = revision + 1 where name = ?13",
+            rusqlite::params![
+                password.email,
+                password.username,
+                password.password,
+                password.notes,
+                password.urls,
+                password.tags,
+                password.folder,
+                password.timestamps.created_at,
+                password.timestamps.updated_at.unwrap_or(now),
+                password.timestamps.last_used_at,
+                password.expiry.expires_at,
+                password.expiry.rotation_days,
+                password.name,
+            ],
+        )?;
+        let id: i32 = connection.query_row(
+            "select id from PasswordInfo where name = ?",
+            [&password.name],
+            |row| row.get(0),
+        )?;
+
+        connection.execute("delete from EntryField where password_id = ?", [id])?;
+        for field in &password.fields {
+            // a NULL id makes SQLite pick a new one
+            connection.execute(
+                "insert into EntryField (id, password_id, key, value, sensitive, created_at, updated_at)
+                values ((select nullif(?1, 0) where not exists (select 1 from EntryField where id = ?1)), ?2, ?3, ?4, ?5, ?6, ?7)",
+                rusqlite::params![
+                    field.id,
+                    id,
+                    field.key,
+                    field.value,
+                    field.sensitive,
+                    field.created_at,
+                    field.updated_at,
+                ],
+            )?;
+        }
+        // only fields whose timestamp changed count as changed for `sync`
+        for (field, updated_at) in &password.timestamps.fields {
+            connection.execute(
+                "insert into FieldUpdate (password_id, field, updated_at, revision)
+                select id, ?2, ?3, revision from PasswordInfo where id = ?1
+                on conflict(password_id, field) do update set updated_at = excluded.updated_at, revision = excluded.revision
+                where updated_at != excluded.updated_at",
+                (id, field, updated_at),
+            )?;
+        }
+        Ok(id)
+    }
+}
+pub mod util {
+    use crate::{crypto::*, envelope::Algorithm, error::*, password::PasswordField};
+
+    use rusqlite::{
+        types::{FromSql, ToSql},
+        Connection, OptionalExtension,
+    };
+
+    use super::{crud::legacy_to_envelope, CIPHER_METADATA, MASTER_KEYWORD};
+    use std::path::Path;
+    /// Establishes a connection to the SQLite database
+    pub fn establish_connection() -> Result<rusqlite::Connection, rusqlite::Error> {
+        establish_connection_at(Path::new("./data.db"))
+    }
+    /// Establishes a connection to the SQLite database at `path`, creating the file if it doesn't exist.
+    pub fn establish_connection_at(path: &Path) -> Result<rusqlite::Connection, rusqlite::Error> {
+        Connection::open(path)
+    }
+    // I've considered using format!() here to make sure the struct name/fields match this statement
+    // (and potentially other SQLite statement strings), but I think that may just be overengineering.
+
+    /// Creates the SQLite table equivelant of the `Password` struct, its custom `EntryField` and `FieldUpdate` timestamp tables, the `VaultMetadata` key/value table,
+    /// the `Tombstone` and `SyncBase` tables used by `sync`, and the `TeamMember` table of shared vaults.
+    /// Tables from older versions of passman are brought up to date.
+    pub fn create_table(connection: &Connection) -> Result<(), rusqlite::Error> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS VaultMetadata (
+        key TEXT NOT NULL PRIMARY KEY,
+        value BLOB
+      );",
+            (),
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS PasswordInfo (
+        id INTEGER NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        username BLOB DEFAULT NULL,
+        email BLOB DEFAULT NULL,
+        password BLOB DEFAULT NULL,
+        notes BLOB DEFAULT NULL,
+        urls BLOB DEFAULT NULL,
+        tags BLOB DEFAULT NULL,
+        folder BLOB DEFAULT NULL,
+        created_at TEXT DEFAULT NULL,
+        updated_at TEXT DEFAULT NULL,
+        last_used_at TEXT DEFAULT NULL,
+        expires_at TEXT DEFAULT NULL,
+        rotation_days INTEGER DEFAULT NULL
+      );",
+            (),
+        )?;
+        // vaults created by older versions are missing these columns
+        for column in ["urls", "tags", "folder"] {
+            add_column_if_missing(connection, "PasswordInfo", column, "BLOB DEFAULT NULL")?;
+        }
+        for column in ["created_at", "updated_at", "last_used_at", "expires_at"] {
+            add_column_if_missing(connection, "PasswordInfo", column, "TEXT DEFAULT NULL")?;
+        }
+        add_column_if_missing(
+            connection,
+            "PasswordInfo",
+            "rotation_days",
+            "INTEGER DEFAULT NULL",
+        )?;
+        add_column_if_missing(
+            connection,
+            "PasswordInfo",
+            "revision",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS FieldUpdate (
+        password_id INTEGER NOT NULL REFERENCES PasswordInfo(id) ON DELETE CASCADE,
+        field TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (password_id, field)
+      );",
+            (),
+        )?;
+        add_column_if_missing(
+            connection,
+            "FieldUpdate",
+            "revision",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS EntryField (
+        id INTEGER NOT NULL PRIMARY KEY,
+        password_id INTEGER NOT NULL REFERENCES PasswordInfo(id) ON DELETE CASCADE,
+        key BLOB NOT NULL,
+        value BLOB NOT NULL,
+        sensitive INTEGER NOT NULL DEFAULT 0,
+        created_at TEXT DEFAULT NULL,
+        updated_at TEXT DEFAULT NULL
+      );",
+            (),
+        )?;
+        for column in ["created_at", "updated_at"] {
+            add_column_if_missing(connection, "EntryField", column, "TEXT DEFAULT NULL")?;
+        }
+        // deleted passwords, and what was last synced with each other vault; see `sync`
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS Tombstone (
+        name TEXT NOT NULL PRIMARY KEY,
+        revision INTEGER NOT NULL,
+        deleted_at TEXT NOT NULL
+      );",
+            (),
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS SyncBase (
+        peer TEXT NOT NULL,
+        name TEXT NOT NULL,
+        own_revision INTEGER NOT NULL,
+        peer_revision INTEGER NOT NULL,
+        PRIMARY KEY (peer, name)
+      );",
+            (),
+        )?;
+        // who can open a shared vault; see `team`
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS TeamMember (
+        name TEXT NOT NULL PRIMARY KEY,
+        public_key BLOB NOT NULL UNIQUE,
+        sealed_key BLOB NOT NULL,
+        added_at TEXT NOT NULL
+      );",
+            (),
+        )?;
+        Ok(())
+    }
+
+    /// Adds a column to an existing table, unless it is already there.
+    /// Used to bring the schema of vaults created by older versions of passman up to date.
+    fn add_column_if_missing(
+        connection: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), rusqlite::Error> {
+        let exists = connection
+            .prepare(&format!(
+                "select 1 from pragma_table_info('{}') where name = ?",
+                table
+            ))?
+            .exists([column])?;
+        if !exists {
+            connection.execute(
+                &format!("alter table {} add column {} {}", table, column, definition),
+                (),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Whether `create_table()` and `upgrade_legacy_fields()` would change an existing vault, i.e. it was created by an older
+    /// version of passman. New, empty databases don't need upgrading.
+    ///  # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    ///
+    pub fn needs_upgrade(connection: &Connection) -> Result<bool, rusqlite::Error> {
+        let columns = connection
+            .prepare("select name from pragma_table_info('PasswordInfo')")?
+            .query_map((), |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if columns.is_empty() {
+            return Ok(false);
+        }
+        // columns are only ever added, so a vault with the newest one has all the others
+        if !columns.iter().any(|column| column == "revision") {
+            return Ok(true);
+        }
+        connection
+            .prepare(
+                "select 1 from PasswordInfo where name != ?1 and (typeof(email) = 'text' or typeof(username) = 'text'
+                or typeof(password) = 'text' or typeof(notes) = 'text')",
+            )?
+            .exists([MASTER_KEYWORD])
+    }
+
+    /// Converts fields written by older versions of passman, which stored `hex(nonce || ciphertext)` as TEXT,
+    /// into versioned `Envelope` BLOBs. The master record is left untouched. Returns the number of fields converted.
+    ///  # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    ///
+    pub fn upgrade_legacy_fields(connection: &Connection) -> Result<usize, BackendError> {
+        // every field is converted before any row is rewritten, so a damaged field leaves the vault untouched
+        let mut upgrades = Vec::new();
+        for column in [
+            PasswordField::Email,
+            PasswordField::Username,
+            PasswordField::Password,
+            PasswordField::Notes,
+        ] {
+            let mut stmt = connection.prepare(&format!(
+                "select id, name, {} from PasswordInfo where typeof({}) = 'text' and name != ?",
+                column, column
+            ))?;
+            let legacy = stmt
+                .query_map([MASTER_KEYWORD], |row| {
+                    Ok((
+                        row.get::<_, i32>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for (id, name, data) in legacy {
+                let envelope = legacy_to_envelope(&data).map_err(|e| e.in_field(&name, column))?;
+                upgrades.push((column, id, envelope.to_bytes()));
+            }
+        }
+        if upgrades.is_empty() {
+            return Ok(0);
+        }
+
+        let transaction = connection.unchecked_transaction()?;
+        let mut upgraded = 0;
+        for (column, id, bytes) in upgrades {
+            upgraded += transaction.execute(
+                &format!("update PasswordInfo set {} = ?1 where id = ?2", column),
+                (bytes, id),
+            )?;
+        }
+        transaction.commit()?;
+        Ok(upgraded)
+    }
+
+    /// Reads a value from the `VaultMetadata` table. Returns `None` if the key has never been set.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `key` - a string slice holding the metadata key to read.
+    ///
+    pub fn get_metadata<T: FromSql>(
+        connection: &Connection,
+        key: &str,
+    ) -> Result<Option<T>, rusqlite::Error> {
+        connection
+            .query_row(
+                "select value from VaultMetadata where key = ?",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+    /// Inserts or updates a value in the `VaultMetadata` table.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `key` - a string slice holding the metadata key to write.
+    /// - `value` - the value to store.
+    ///
+    pub fn set_metadata(
+        connection: &Connection,
+        key: &str,
+        value: impl ToSql,
+    ) -> Result<usize, rusqlite::Error> {
+        connection.execute(
+            "insert into VaultMetadata (key, value) values (?1, ?2) on conflict(key) do update set value = ?2",
+            (key, value),
+        )
+    }
+    /// Reads the `Algorithm` new data in this vault is encrypted with. Vaults without one recorded use AES 256 GCM.
+    pub fn get_vault_algorithm(connection: &Connection) -> Result<Algorithm, BackendError> {
+        get_metadata::<u8>(connection, CIPHER_METADATA)
+            .map_err(|e| BackendError::CorruptMetadata {
+                key: CIPHER_METADATA.to_string(),
+                reason: e.to_string(),
+            })?
+            .map_or(Ok(Algorithm::default()), |id| {
+                Algorithm::try_from(id).map_err(|e| BackendError::CorruptMetadata {
+                    key: CIPHER_METADATA.to_string(),
+                    reason: e.to_string(),
+                })
+            })
+    }
+    /// Records the `Algorithm` new data in this vault should be encrypted with.
+    /// This does **not** re-encrypt existing data; see `crud::convert_vault_cipher()` for that.
+    pub fn set_vault_algorithm(
+        connection: &Connection,
+        algorithm: Algorithm,
+    ) -> Result<usize, rusqlite::Error> {
+        set_metadata(connection, CIPHER_METADATA, algorithm.id())
+    }
+
+    /// Creates the master record of a new vault, records the `Algorithm` the vault encrypts data with, and seals an empty manifest.
+    /// Only hashes of the master password and recovery phrase are stored. A transaction the caller opened on the connection is
+    /// used rather than a new one.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the new master password.
+    /// - `recovery_phrase` - a string slice holding the phrase that can be used to reset the master password.
+    /// - `algorithm` - the `Algorithm` to encrypt data in this vault with.
+    ///
+    pub fn insert_master_record(
+        connection: &Connection,
+        master: &str,
+        recovery_phrase: &str,
+        algorithm: Algorithm,
+    ) -> Result<(), BackendError> {
+        let transaction = match connection.is_autocommit() {
+            true => Some(connection.unchecked_transaction()?),
+            false => None,
+        };
+        connection.execute(
+            "insert into PasswordInfo (name, password, notes) values (?1, ?2, ?3)",
+            [
+                MASTER_KEYWORD,
+                &hex::encode(hash(master.as_bytes())),
+                &hex::encode(hash(recovery_phrase.as_bytes())),
+            ],
+        )?;
+        set_vault_algorithm(connection, algorithm)?;
+        crate::manifest::seal_manifest(connection, master)?;
+        if let Some(transaction) = transaction {
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Check if a password exists. May fail with `rusqlite::Error`.
+    /// Checks if an `optional()` query `is_some()`, i.e. returns `false` if `None`.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `password_name` - a string slice that holds the name of the password to insert or update into.
+    ///
+    pub fn check_password_info_exists(
+        connection: &Connection,
+        password_name: &str,
+    ) -> Result<bool, rusqlite::Error> {
+        let mut stmt = connection.prepare("select * from PasswordInfo where name = ? ")?;
+        let master_exists = stmt
+            .query_row([password_name], |_| Ok(()))
+            .optional()?
+            .is_some();
+        Ok(master_exists)
+    }
+    /// Check if a password exists. May fail with `rusqlite::Error`.
+    /// Checks if an `optional()` query `is_some()`, i.e. returns `false` if `None`.
+    /// ///  # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice that holds the master password.
+    ///
+    pub fn authenticate(
+        connection: &Connection,
+        master: &str,
+        column: PasswordField,
+    ) -> Result<bool, BackendError> {
+        // the master record stores hex-encoded hashes rather than envelopes, so it's read directly
+        let (password, notes) = connection
+            .query_row(
+                "select password, notes from PasswordInfo where name = ?",
+                [MASTER_KEYWORD],
+                // a NULL or non-text column means the record is damaged, not that the query failed
+                |row| {
+                    let text = |index: usize| -> rusqlite::Result<Option<String>> {
+                        Ok(row.get_ref(index)?.as_str().ok().map(String::from))
+                    };
+                    Ok((text(0)?, text(1)?))
+                },
+            )
+            .optional()?
+            .ok_or(BackendError::MissingMasterRecord)?;
+        let data = match column {
+            PasswordField::Password => Ok(password),
+            PasswordField::Notes => Ok(notes),
+            _ => Err(BackendError::InvalidMasterRecordField),
+        }?;
+        let expected = data
+            .and_then(|data| hex::decode(data).ok())
+            .ok_or(BackendError::CorruptMasterRecord)?;
+
+        Ok(hash(master.as_bytes()).to_vec() == expected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MASTER_KEYWORD;
+    use crate::{
+        crypto::{derive_key, hash},
+        envelope::{Algorithm, Envelope, Kdf},
+        error::{BackendError, EnvelopeError},
+        password::{EntryField, Expiry, PasswordField, PasswordInfo},
+    };
+    use aes_gcm::{
+        aead::{generic_array::GenericArray, Aead, OsRng},
+        AeadCore, Aes256Gcm, Key, KeyInit,
+    };
+    use chrono::{Duration, Utc};
+    use rusqlite::Connection;
+    /// An empty vault, sealed with `master`.
+    fn vault(master: &str) -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+        super::util::insert_master_record(&connection, master, "recovery", Algorithm::Aes256Gcm)
+            .unwrap();
+        connection
+    }
+    fn insert_test_data(connection: &Connection) -> std::result::Result<usize, rusqlite::Error> {
+        connection.execute(
+            "insert into PasswordInfo (name, username, email, password) VALUES (?1, ?2, ?3, ?4)",
+            ("test_name", "cool_user1", "cool_user@usermail.com", "12345"),
+        )
+    }
+
+    #[test]
+    fn establish_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(super::util::establish_connection_at(&dir.path().join("data.db")).is_ok());
+    }
+    #[test]
+    fn create_table() {
+        assert!(super::util::create_table(&Connection::open_in_memory().unwrap()).is_ok());
+    }
+    #[test]
+    fn test_data() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+
+        // just make sure that the insert test data function is working and inserts a row
+        let insert_result = insert_test_data(&connection).unwrap();
+        assert_eq!(insert_result, 1);
+    }
+    #[test]
+    fn read_password() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+
+        let master = "mymasterpassword";
+        let name = "test_name";
+        let password = "coolpassword";
+        let derived = derive_key(master, name);
+        let key = Key::<Aes256Gcm>::from_slice(&derived);
+        let cipher = Aes256Gcm::new(key);
+
+        let nonce: GenericArray<u8, typenum::U12> = Aes256Gcm::generate_nonce(OsRng);
+        let encrypted = cipher.encrypt(&nonce, password.as_bytes()).unwrap();
+
+        let envelope = Envelope {
+            algorithm: Algorithm::Aes256Gcm,
+            kdf: Kdf::Pbkdf2Sha256,
+            nonce: nonce.to_vec(),
+            ciphertext: encrypted,
+        };
+
+        let insert = connection
+            .execute(
+                "insert into PasswordInfo (name, password) VALUES (?1, ?2)",
+                (name, envelope.to_bytes()),
+            )
+            .unwrap();
+        assert_eq!(insert, 1);
+
+        let res = super::crud::read_password_info(&connection, name, master).unwrap();
+
+        assert_eq!(
+            res.expect("no password found")
+                .password
+                .expect("no password field"),
+            password
+        );
+    }
+    #[test]
+    fn upgrade_legacy_fields() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+
+        let master = "mymasterpassword";
+        let name = "test_name";
+        let password = "coolpassword";
+        let derived = derive_key(master, name);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived));
+
+        // this is how fields were stored before envelopes: hex(nonce || ciphertext) as TEXT
+        let nonce: GenericArray<u8, typenum::U12> = Aes256Gcm::generate_nonce(OsRng);
+        let mut n = nonce.to_vec();
+        let mut encrypted = cipher.encrypt(&nonce, password.as_bytes()).unwrap();
+        n.append(&mut encrypted);
+
+        connection
+            .execute(
+                "insert into PasswordInfo (name, password) VALUES (?1, ?2)",
+                (name, hex::encode(n)),
+            )
+            .unwrap();
+
+        assert_eq!(super::util::upgrade_legacy_fields(&connection).unwrap(), 1);
+        // running it again shouldn't touch anything
+        assert_eq!(super::util::upgrade_legacy_fields(&connection).unwrap(), 0);
+
+        let res = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.password.unwrap(), password);
+    }
+    #[test]
+    fn malformed_legacy_fields() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+
+        // a valid looking nonce, but far too short to hold a GCM tag
+        connection
+            .execute(
+                "insert into PasswordInfo (name, email, password) VALUES (?1, ?2, ?3)",
+                ("test_name", hex::encode([0u8; 28]), hex::encode([1u8; 14])),
+            )
+            .unwrap();
+
+        match super::util::upgrade_legacy_fields(&connection) {
+            Err(BackendError::CorruptField { source, .. }) => {
+                assert!(matches!(
+                    *source,
+                    BackendError::EnvelopeError(EnvelopeError::Truncated)
+                ))
+            }
+            other => panic!("expected a corrupt field, got {:?}", other),
+        }
+        // the valid email field must not have been upgraded on its own
+        let kind: String = connection
+            .query_row(
+                "select typeof(email) from PasswordInfo where name = 'test_name'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kind, "text");
+    }
+    #[test]
+    fn malformed_envelope() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+
+        connection
+            .execute(
+                "insert into PasswordInfo (name, password) VALUES (?1, ?2)",
+                ("test_name", vec![1u8, 1, 1, 0]),
+            )
+            .unwrap();
+
+        let res = super::crud::read_password_info(&connection, "test_name", "master");
+        match res {
+            Err(BackendError::CorruptField { source, .. }) => {
+                assert!(matches!(*source, BackendError::EnvelopeError(_)))
+            }
+            other => panic!("expected a corrupt field, got {:?}", other),
+        }
+    }
+    /// Checks that `read_password_info` fails with a `CorruptField` naming the password and field, rather than panicking.
+    fn assert_corrupt_field(connection: &Connection, name: &str, expected_field: &str) {
+        match super::crud::read_password_info(connection, name, "master") {
+            Err(BackendError::CorruptField {
+                password, field, ..
+            }) => {
+                assert_eq!(password, name);
+                assert_eq!(field, expected_field);
+            }
+            other => panic!("expected a corrupt field, got {:?}", other),
+        }
+    }
+    #[test]
+    fn corrupt_rows() {
+        let master = "master";
+        let connection = vault(master);
+        let name = "test_name";
+        super::crud::insert_data(&connection, name, master, PasswordField::Password, "pass")
+            .unwrap();
+        super::crud::insert_data(&connection, name, master, PasswordField::Email, "a@a.com")
+            .unwrap();
+        super::crud::insert_entry_field(&connection, name, master, "PIN", "1234", true).unwrap();
+        let original: Vec<u8> = connection
+            .query_row(
+                "select password from PasswordInfo where name = ?",
+                [name],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let set_password = |data: rusqlite::types::Value| {
+            connection
+                .execute(
+                    "update PasswordInfo set password = ?1 where name = ?2",
+                    (data, name),
+                )
+                .unwrap();
+        };
+
+        // random garbage, a truncated envelope and a flipped ciphertext byte
+        set_password(vec![0xde, 0xad, 0xbe, 0xef].into());
+        assert_corrupt_field(&connection, name, "password");
+        set_password(original[..10].to_vec().into());
+        assert_corrupt_field(&connection, name, "password");
+        let mut tampered = original.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        set_password(tampered.into());
+        assert_corrupt_field(&connection, name, "password");
+        // a legacy hex TEXT value that was never upgraded, or any other cell that isn't a BLOB
+        set_password("not hex".to_string().into());
+        assert_corrupt_field(&connection, name, "password");
+        assert!(matches!(
+            super::crud::read_password_info(&connection, name, master),
+            Err(BackendError::CorruptField { source, .. })
+                if matches!(*source, BackendError::EnvelopeError(_))
+        ));
+        set_password(42.into());
+        assert_corrupt_field(&connection, name, "password");
+
+        // a damaged custom field is reported too
+        set_password(original.into());
+        connection
+            .execute("update EntryField set value = x'00'", [])
+            .unwrap();
+        let field_id: i32 = connection
+            .query_row("select id from EntryField", [], |row| row.get(0))
+            .unwrap();
+        assert_corrupt_field(&connection, name, &format!("custom field #{}", field_id));
+
+        // listing every password fails on the damaged one instead of panicking
+        assert!(matches!(
+            super::crud::list_password_info(&connection, master),
+            Err(BackendError::CorruptField { .. })
+        ));
+    }
+    #[test]
+    fn corrupt_metadata() {
+        let connection = vault("master");
+        super::util::set_metadata(&connection, super::CIPHER_METADATA, 99).unwrap();
+        assert!(matches!(
+            super::util::get_vault_algorithm(&connection),
+            Err(BackendError::CorruptMetadata { .. })
+        ));
+        super::util::set_metadata(&connection, super::CIPHER_METADATA, "aes").unwrap();
+        assert!(matches!(
+            super::crud::insert_data(&connection, "name", "master", PasswordField::Email, "a"),
+            Err(BackendError::CorruptMetadata { .. })
+        ));
+    }
+    #[test]
+    fn corrupt_master_record() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+        assert!(matches!(
+            super::util::authenticate(&connection, "master", PasswordField::Password),
+            Err(BackendError::MissingMasterRecord)
+        ));
+
+        connection
+            .execute(
+                "insert into PasswordInfo (name, password, notes) VALUES (?1, ?2, NULL)",
+                (MASTER_KEYWORD, "zz"),
+            )
+            .unwrap();
+        assert!(matches!(
+            super::util::authenticate(&connection, "master", PasswordField::Password),
+            Err(BackendError::CorruptMasterRecord)
+        ));
+        assert!(matches!(
+            super::util::authenticate(&connection, "master", PasswordField::Notes),
+            Err(BackendError::CorruptMasterRecord)
+        ));
+    }
+    #[test]
+    fn insert_data() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+        let name = "test_name";
+        let password = "coolpassword";
+
+        super::crud::insert_data(&connection, name, master, PasswordField::Password, password)
+            .unwrap();
+
+        let r = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.password.unwrap(), password);
+    }
+    #[test]
+    fn upsert_password_info() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+
+        let mut info = PasswordInfo {
+            name: "test_name".to_string(),
+            email: Some("a@a.com".to_string()),
+            password: Some("pass".to_string()),
+            urls: vec!["https://a.com".to_string(), " ".to_string()],
+            folder: Some("Work".to_string()),
+            fields: vec![EntryField {
+                key: "PIN".to_string(),
+                value: "1234".to_string(),
+                sensitive: true,
+                ..Default::default()
+            }],
+            expiry: Expiry {
+                expires_at: None,
+                rotation_days: Some(90),
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            super::crud::upsert_password_info(&connection, &info, master).unwrap(),
+            5
+        );
+        let r = super::crud::read_password_info(&connection, "test_name", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.email.unwrap(), "a@a.com");
+        assert_eq!(r.password.unwrap(), "pass");
+        assert_eq!(r.urls, vec!["https://a.com"]);
+        assert_eq!(r.folder.unwrap(), "Work");
+        assert_eq!(r.fields[0].value, "1234");
+        assert_eq!(r.expiry.rotation_days, Some(90));
+
+        // only the given fields are changed, and existing custom fields are updated by id
+        let mut field = r.fields[0].clone();
+        field.value = "4321".to_string();
+        info = PasswordInfo {
+            name: "test_name".to_string(),
+            username: Some("user".to_string()),
+            fields: vec![field],
+            ..Default::default()
+        };
+        assert_eq!(
+            super::crud::upsert_password_info(&connection, &info, master).unwrap(),
+            2
+        );
+        let r = super::crud::read_password_info(&connection, "test_name", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.username.unwrap(), "user");
+        assert_eq!(r.email.unwrap(), "a@a.com");
+        assert_eq!(r.fields.len(), 1);
+        assert_eq!(r.fields[0].value, "4321");
+        assert_eq!(r.expiry.rotation_days, Some(90));
+
+        // a custom field id that doesn't exist fails the whole upsert
+        info.username = Some("other".to_string());
+        info.fields[0].id = 9999;
+        assert!(matches!(
+            super::crud::upsert_password_info(&connection, &info, master),
+            Err(BackendError::NoMatchingRecord(_))
+        ));
+        let r = super::crud::read_password_info(&connection, "test_name", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.username.unwrap(), "user");
+    }
+    #[test]
+    fn upsert_password_info_rolls_back() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+        // make writing custom fields fail after the standard fields were written
+        connection.execute("drop table EntryField", []).unwrap();
+
+        let info = PasswordInfo {
+            name: "test_name".to_string(),
+            password: Some("pass".to_string()),
+            fields: vec![EntryField::default()],
+            ..Default::default()
+        };
+        assert!(super::crud::upsert_password_info(&connection, &info, "master").is_err());
+        assert!(super::crud::get_password_info(&connection, "test_name")
+            .unwrap()
+            .is_none());
+    }
+    #[test]
+    fn revisions_and_tombstones() {
+        let master = "master";
+        let connection = vault(master);
+        let revision = |name: &str| -> Option<(u64, bool)> {
+            connection
+                .query_row(
+                    "select revision, 0 from PasswordInfo where name = ?1 union all select revision, 1 from Tombstone where name = ?1",
+                    [name],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .ok()
+        };
+
+        super::crud::insert_data(&connection, "mail", master, PasswordField::Password, "one")
+            .unwrap();
+        let (first, _) = revision("mail").unwrap();
+        // writing the same value again isn't a change
+        let info = PasswordInfo {
+            name: "mail".to_string(),
+            password: Some("one".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            super::crud::upsert_password_info(&connection, &info, master).unwrap(),
+            0
+        );
+        assert_eq!(revision("mail").unwrap().0, first);
+
+        super::crud::set_expiry(
+            &connection,
+            "mail",
+            master,
+            &Expiry {
+                rotation_days: Some(30),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (second, _) = revision("mail").unwrap();
+        assert!(second > first);
+
+        super::crud::delete_password_info(&connection, "mail", master).unwrap();
+        assert_eq!(revision("mail").unwrap(), (second + 1, true));
+
+        // a new password with the same name continues after the tombstone
+        super::crud::insert_data(&connection, "mail", master, PasswordField::Password, "two")
+            .unwrap();
+        let (third, deleted) = revision("mail").unwrap();
+        assert!(third > second + 1 && !deleted);
+
+        // renaming leaves a tombstone for the old name
+        super::crud::rename_password_info(&connection, "mail", "webmail", master).unwrap();
+        assert_eq!(revision("mail").unwrap(), (third + 1, true));
+        assert!(revision("webmail").unwrap().0 > third);
+
+        // replacing clears fields that are `None`
+        let info = PasswordInfo {
+            name: "webmail".to_string(),
+            username: Some("alice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            super::crud::replace_password_info(&connection, &info, master).unwrap(),
+            2
+        );
+        let r = super::crud::read_password_info(&connection, "webmail", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.username.unwrap(), "alice");
+        assert!(r.password.is_none());
+        assert_eq!(
+            super::crud::replace_password_info(&connection, &info, master).unwrap(),
+            0
+        );
+    }
+    #[test]
+    fn delete() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+        let name = "test_name";
+        let password = "coolpassword";
+
+        super::crud::insert_data(&connection, name, master, PasswordField::Password, password)
+            .unwrap();
+
+        super::crud::delete_password_info(&connection, name, master).unwrap();
+        let result = super::crud::read_password_info(&connection, name, master).unwrap();
+        assert!(result.is_none())
+    }
+    #[test]
+    fn entry_fields() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+        let name = "bank";
+
+        // adding a field to a missing password creates it
+        super::crud::insert_entry_field(&connection, name, master, "PIN", "1234", true).unwrap();
+        super::crud::insert_entry_field(&connection, name, master, "Account", "0001", false)
+            .unwrap();
+
+        let info = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.fields.len(), 2);
+        assert_eq!(info.fields[0].key, "PIN");
+        assert_eq!(info.fields[0].value, "1234");
+        assert!(info.fields[0].sensitive);
+        assert!(!info.fields[1].sensitive);
+
+        let updated = EntryField {
+            value: "4321".to_string(),
+            ..info.fields[0].clone()
+        };
+        assert_eq!(
+            super::crud::update_entry_field(&connection, name, master, &updated).unwrap(),
+            1
+        );
+        // a field can't be updated through a different password, or if it doesn't exist
+        assert!(matches!(
+            super::crud::update_entry_field(&connection, "other", master, &updated),
+            Err(BackendError::NoMatchingRecord(_))
+        ));
+        assert!(matches!(
+            super::crud::update_entry_field(
+                &connection,
+                name,
+                master,
+                &EntryField {
+                    id: 9999,
+                    ..updated.clone()
+                }
+            ),
+            Err(BackendError::NoMatchingRecord(_))
+        ));
+
+        super::crud::delete_entry_field(&connection, info.fields[1].id, master).unwrap();
+        let info = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.fields.len(), 1);
+        assert_eq!(info.fields[0].id, updated.id);
+        assert_eq!(info.fields[0].value, updated.value);
+        assert!(info.fields[0].updated_at >= updated.updated_at);
+
+        // deleting the password removes its fields as well
+        super::crud::delete_password_info(&connection, name, master).unwrap();
+        let remaining: i32 = connection
+            .query_row("select count(*) from EntryField", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+    #[test]
+    fn tags_and_folders() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+
+        let insert = |name: &str, tags: &[&str], folder: Option<&str>| {
+            let tags = tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+            super::crud::insert_list_data(&connection, name, master, PasswordField::Tags, &tags)
+                .unwrap();
+            if let Some(folder) = folder {
+                super::crud::insert_data(&connection, name, master, PasswordField::Folder, folder)
+                    .unwrap();
+            }
+        };
+        insert("mail", &["work", " email "], Some("Work/Email"));
+        insert("vpn", &["work"], Some("Work"));
+        insert("bank", &["finance"], None);
+        insert("workshop", &[], Some("Workshop"));
+
+        let urls = vec!["https://a.com".to_string(), "https://b.com".to_string()];
+        super::crud::insert_list_data(&connection, "bank", master, PasswordField::Urls, &urls)
+            .unwrap();
+
+        let names = |passwords: Vec<PasswordInfo>| {
+            passwords.into_iter().map(|p| p.name).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(super::crud::list_by_tag(&connection, master, "WORK").unwrap()),
+            vec!["mail", "vpn"]
+        );
+        // tags are trimmed before they're stored
+        assert_eq!(
+            names(super::crud::list_by_tag(&connection, master, "email").unwrap()),
+            vec!["mail"]
+        );
+        // subfolders are included, but folders sharing a prefix are not
+        assert_eq!(
+            names(super::crud::list_by_folder(&connection, master, "/Work/").unwrap()),
+            vec!["mail", "vpn"]
+        );
+
+        let bank = super::crud::read_password_info(&connection, "bank", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(bank.urls, urls);
+        assert_eq!(bank.tags, vec!["finance"]);
+        assert!(bank.folder.is_none());
+    }
+    #[test]
+    fn upgrade_schema() {
+        let connection = Connection::open_in_memory().unwrap();
+        // the original schema, before urls/tags/folders existed
+        connection
+            .execute(
+                "CREATE TABLE PasswordInfo (
+        id INTEGER NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        username TEXT DEFAULT NULL,
+        email TEXT DEFAULT NULL,
+        password TEXT DEFAULT NULL,
+        notes TEXT DEFAULT NULL
+      );",
+                (),
+            )
+            .unwrap();
+        super::util::create_table(&connection).unwrap();
+        // running it twice must not try to add the columns again
+        super::util::create_table(&connection).unwrap();
+
+        let master = "mymasterpassword";
+        super::util::insert_master_record(&connection, master, "recovery", Algorithm::Aes256Gcm)
+            .unwrap();
+        super::crud::insert_data(&connection, "test", master, PasswordField::Folder, "Work")
+            .unwrap();
+        let res = super::crud::read_password_info(&connection, "test", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.folder.unwrap(), "Work");
+    }
+    #[test]
+    fn timestamps() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+        let name = "test_name";
+
+        super::crud::insert_data(&connection, name, master, PasswordField::Password, "pass")
+            .unwrap();
+        let created = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap()
+            .timestamps;
+        assert!(created.created_at.is_some());
+        assert_eq!(created.created_at, created.updated_at);
+        assert!(created.last_used_at.is_none());
+        assert_eq!(created.fields.keys().collect::<Vec<_>>(), vec!["password"]);
+
+        super::crud::insert_data(&connection, name, master, PasswordField::Email, "a@a.com")
+            .unwrap();
+        super::crud::insert_entry_field(&connection, name, master, "PIN", "1234", true).unwrap();
+        assert_eq!(
+            super::crud::mark_password_used(&connection, name, master).unwrap(),
+            1
+        );
+
+        let info = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap();
+        let timestamps = info.timestamps;
+        // creation time stays the same, everything else moves forward
+        assert_eq!(timestamps.created_at, created.created_at);
+        assert!(timestamps.updated_at >= created.updated_at);
+        assert!(timestamps.last_used_at.is_some());
+        assert!(timestamps.fields["email"] >= timestamps.fields["password"]);
+        assert!(info.fields[0].created_at.is_some());
+        assert_eq!(info.fields[0].created_at, info.fields[0].updated_at);
+
+        // marking a missing password does nothing
+        assert_eq!(
+            super::crud::mark_password_used(&connection, "missing", master).unwrap(),
+            0
+        );
+    }
+    #[test]
+    fn list_due() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+        let now = Utc::now();
+
+        for name in ["expired", "soon", "later", "rotated", "none"] {
+            super::crud::insert_data(&connection, name, master, PasswordField::Password, "pass")
+                .unwrap();
+        }
+        let fixed = |days: i64| Expiry {
+            expires_at: Some(now + Duration::days(days)),
+            rotation_days: None,
+        };
+        super::crud::set_expiry(&connection, "expired", master, &fixed(-1)).unwrap();
+        super::crud::set_expiry(&connection, "soon", master, &fixed(3)).unwrap();
+        super::crud::set_expiry(&connection, "later", master, &fixed(60)).unwrap();
+        super::crud::set_expiry(
+            &connection,
+            "rotated",
+            master,
+            &Expiry {
+                expires_at: None,
+                rotation_days: Some(1),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            super::crud::set_expiry(&connection, "missing", master, &fixed(1)).unwrap(),
+            0
+        );
+
+        let due = super::crud::list_due(&connection, now, Duration::days(14)).unwrap();
+        let names = due.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        // soonest first; "later" is outside the window and "none" has no expiry
+        assert_eq!(names, vec!["expired", "rotated", "soon"]);
+        assert!(due[0].expired);
+        assert!(!due[1].expired);
+
+        // the expiry is also returned with the password
+        let info = super::crud::read_password_info(&connection, "rotated", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.expiry.rotation_days, Some(1));
+
+        // removing the expiry takes it off the list
+        super::crud::set_expiry(&connection, "expired", master, &Expiry::default()).unwrap();
+        let due = super::crud::list_due(&connection, now, Duration::days(14)).unwrap();
+        assert_eq!(due.len(), 2);
+
+        // expiry changes are covered by the manifest like any other write
+        assert!(matches!(
+            crate::manifest::verify_manifest(&connection, master).unwrap(),
+            crate::manifest::ManifestStatus::Valid { .. }
+        ));
+    }
+    #[test]
+    fn rename_and_duplicate() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+
+        super::crud::insert_data(&connection, "old", master, PasswordField::Password, "pass")
+            .unwrap();
+        super::crud::insert_entry_field(&connection, "old", master, "PIN", "1234", true).unwrap();
+        super::crud::insert_data(&connection, "taken", master, PasswordField::Password, "x")
+            .unwrap();
+
+        assert!(matches!(
+            super::crud::rename_password_info(&connection, "old", "taken", master),
+            Err(BackendError::NameTaken(_))
+        ));
+        assert_eq!(
+            super::crud::rename_password_info(&connection, "missing", "new", master).unwrap(),
+            0
+        );
+
+        assert_eq!(
+            super::crud::rename_password_info(&connection, "old", "new", master).unwrap(),
+            1
+        );
+        assert!(super::crud::read_password_info(&connection, "old", master)
+            .unwrap()
+            .is_none());
+        let renamed = super::crud::read_password_info(&connection, "new", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(renamed.password.unwrap(), "pass");
+        assert_eq!(renamed.fields[0].value, "1234");
+
+        assert_eq!(
+            super::crud::duplicate_password_info(&connection, "new", "copy", master).unwrap(),
+            1
+        );
+        let copy = super::crud::read_password_info(&connection, "copy", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(copy.password.unwrap(), "pass");
+        assert_eq!(copy.fields[0].value, "1234");
+        assert_ne!(copy.fields[0].id, renamed.fields[0].id);
+        assert!(copy.timestamps.fields.contains_key("password"));
+
+        // the original is untouched and can still be decrypted
+        let original = super::crud::read_password_info(&connection, "new", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(original.fields.len(), 1);
+        assert!(matches!(
+            super::crud::duplicate_password_info(&connection, "new", "copy", master),
+            Err(BackendError::NameTaken(_))
+        ));
+
+        // the master record can't be renamed, copied or overwritten
+        for (from, to) in [(MASTER_KEYWORD, "stolen"), ("new", MASTER_KEYWORD)] {
+            assert!(matches!(
+                super::crud::rename_password_info(&connection, from, to, master),
+                Err(BackendError::ReservedName(_))
+            ));
+            assert!(matches!(
+                super::crud::duplicate_password_info(&connection, from, to, master),
+                Err(BackendError::ReservedName(_))
+            ));
+        }
+        assert!(matches!(
+            super::crud::insert_data(
+                &connection,
+                MASTER_KEYWORD,
+                master,
+                PasswordField::Password,
+                "pass"
+            ),
+            Err(BackendError::ReservedName(_))
+        ));
+        assert!(super::crud::read_password_info(&connection, "new", master)
+            .unwrap()
+            .is_some());
+    }
+    #[test]
+    fn clear_data() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+        let name = "test_name";
+
+        super::crud::insert_data(&connection, name, master, PasswordField::Password, "pass")
+            .unwrap();
+        super::crud::insert_data(&connection, name, master, PasswordField::Email, "a@a.com")
+            .unwrap();
+
+        assert_eq!(
+            super::crud::clear_data(&connection, name, PasswordField::Email, master).unwrap(),
+            1
+        );
+        let info = super::crud::read_password_info(&connection, name, master)
+            .unwrap()
+            .unwrap();
+        assert!(info.email.is_none());
+        // other fields are untouched
+        assert_eq!(info.password.unwrap(), "pass");
+
+        assert_eq!(
+            super::crud::clear_data(&connection, "missing", PasswordField::Email, master).unwrap(),
+            0
+        );
+        // the master record isn't a password, so none of its columns can be cleared
+        assert!(matches!(
+            super::crud::clear_data(&connection, MASTER_KEYWORD, PasswordField::Password, master),
+            Err(BackendError::ReservedName(_))
+        ));
+    }
+    #[test]
+    fn metadata() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+
+        assert!(super::util::get_metadata::<String>(&connection, "key")
+            .unwrap()
+            .is_none());
+        super::util::set_metadata(&connection, "key", "value").unwrap();
+        super::util::set_metadata(&connection, "key", "new value").unwrap();
+        assert_eq!(
+            super::util::get_metadata::<String>(&connection, "key").unwrap(),
+            Some("new value".to_string())
+        );
+        // vaults without a recorded cipher default to AES
+        assert_eq!(
+            super::util::get_vault_algorithm(&connection).unwrap(),
+            Algorithm::Aes256Gcm
+        );
+    }
+    #[test]
+    fn convert_vault_cipher() {
+        let master = "mymasterpassword";
+        let connection = vault(master);
+
+        super::crud::insert_data(&connection, "a", master, PasswordField::Password, "pass_a")
+            .unwrap();
+        super::crud::insert_data(&connection, "a", master, PasswordField::Email, "a@a.com")
+            .unwrap();
+        super::crud::insert_data(&connection, "b", master, PasswordField::Notes, "note_b").unwrap();
+        super::crud::insert_entry_field(&connection, "b", master, "PIN", "0000", true).unwrap();
+
+        let converted =
+            super::crud::convert_vault_cipher(&connection, master, Algorithm::XChaCha20Poly1305)
+                .unwrap();
+        assert_eq!(converted, 4);
+        assert_eq!(
+            super::util::get_vault_algorithm(&connection).unwrap(),
+            Algorithm::XChaCha20Poly1305
+        );
+
+        // the stored envelopes should now record the new algorithm
+        let stored = super::crud::get_password_info(&connection, "a")
+            .unwrap()
+            .unwrap();
+        let envelope = Envelope::from_bytes(&stored.password.unwrap()).unwrap();
+        assert_eq!(envelope.algorithm, Algorithm::XChaCha20Poly1305);
+
+        let a = super::crud::read_password_info(&connection, "a", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.password.unwrap(), "pass_a");
+        assert_eq!(a.email.unwrap(), "a@a.com");
+        let b = super::crud::read_password_info(&connection, "b", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(b.notes.unwrap(), "note_b");
+        assert_eq!(b.fields[0].value, "0000");
+
+        // and back again
+        super::crud::convert_vault_cipher(&connection, master, Algorithm::Aes256Gcm).unwrap();
+        let a = super::crud::read_password_info(&connection, "a", master)
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.password.unwrap(), "pass_a");
+    }
+    #[test]
+    fn change_master_password() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+        super::util::insert_master_record(&connection, "old", "recovery", Algorithm::Aes256Gcm)
+            .unwrap();
+        super::crud::insert_data(&connection, "a", "old", PasswordField::Password, "pass_a")
+            .unwrap();
+        super::crud::insert_entry_field(&connection, "a", "old", "PIN", "0000", true).unwrap();
+
+        assert_eq!(
+            super::crud::change_master_password(&connection, "old", "new").unwrap(),
+            2
+        );
+        assert!(super::util::authenticate(&connection, "new", PasswordField::Password).unwrap());
+        assert!(!super::util::authenticate(&connection, "old", PasswordField::Password).unwrap());
+        // the recovery phrase still works
+        assert!(super::util::authenticate(&connection, "recovery", PasswordField::Notes).unwrap());
+
+        let a = super::crud::read_password_info(&connection, "a", "new")
+            .unwrap()
+            .unwrap();
+        assert_eq!(a.password.unwrap(), "pass_a");
+        assert_eq!(a.fields[0].value, "0000");
+        assert!(super::crud::read_password_info(&connection, "a", "old").is_err());
+    }
+    #[test]
+    fn check_exists() {
+        let master = "masterpassword";
+        let connection = vault(master);
+        let name = "test";
+        // first, make sure the function returns false if no data exists
+        assert!(!super::util::check_password_info_exists(&connection, name).unwrap());
+        // now lets insert some data
+        super::crud::insert_data(
+            &connection,
+            name,
+            master,
+            PasswordField::Password,
+            "supersecret",
+        )
+        .unwrap();
+        // finally, we'll check one more time to make sure it's returning true since we added data
+        assert!(super::util::check_password_info_exists(&connection, name).unwrap());
+    }
+    #[test]
+    fn authenticate() {
+        let connection = Connection::open_in_memory().unwrap();
+        super::util::create_table(&connection).unwrap();
+        let new_master = "mymasterpassword";
+        let recovery_note = "abcd";
+
+        // ripped from cli.rs
+        let master_password = hex::encode(hash(new_master.as_bytes()));
+        let note = hex::encode(hash(recovery_note.as_bytes()));
+
+        connection
+            .execute(
+                "insert into PasswordInfo (name, password, notes) values (?1, ?2, ?3)",
+                [MASTER_KEYWORD, &master_password, &note],
+            )
+            .unwrap();
+
+        assert!(super::util::authenticate(
+            &connection,
+            "mymasterpassword",
+            PasswordField::Password
+        )
+        .unwrap());
+        assert!(
+            !super::util::authenticate(&connection, "random_guess", PasswordField::Password)
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use core::fmt;
+use std::fmt::Display;
+
+use crate::error::EnvelopeError;
+
+/// The current version of the envelope layout. Bump this whenever the byte layout changes.
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Length of the fixed header: version, algorithm id and KDF id (one byte each).
+const HEADER_LEN: usize = 3;
+
+/// Encryption algorithms that may be recorded in an `Envelope`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    #[default]
+    Aes256Gcm,
+    XChaCha20Poly1305,
+}
+
+impl Algorithm {
+    /// The byte used to identify this algorithm in an envelope.
+    pub fn id(&self) -> u8 {
+        match self {
+            Algorithm::Aes256Gcm => 1,
+            Algorithm::XChaCha20Poly1305 => 2,
+        }
+    }
+    /// Length of the nonce used by this algorithm, in bytes.
+    pub fn nonce_len(&self) -> usize {
+        match self {
+            Algorithm::Aes256Gcm => 12,
+            Algorithm::XChaCha20Poly1305 => 24,
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let str = match self {
+            Algorithm::Aes256Gcm => "AES-256-GCM",
+            Algorithm::XChaCha20Poly1305 => "XChaCha20-Poly1305",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+impl TryFrom<u8> for Algorithm {
+    type Error = EnvelopeError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Algorithm::Aes256Gcm),
+            2 => Ok(Algorithm::XChaCha20Poly1305),
+            other => Err(EnvelopeError::UnknownAlgorithm(other)),
+        }
+    }
+}
+
+/// Key derivation functions that may be recorded in an `Envelope`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    /// PBKDF2-HMAC-SHA256 with the password name as the salt. The same key is used by every algorithm.
+    /// Only kept to read fields written before `Pbkdf2Sha256Bound`.
+    Pbkdf2Sha256,
+    /// PBKDF2-HMAC-SHA256 with the password name as the salt, followed by an HMAC-SHA256 subkey bound to the envelope's
+    /// algorithm, so no two algorithms ever share a key.
+    Pbkdf2Sha256Bound,
+}
+
+impl Kdf {
+    /// The KDF used for newly encrypted data.
+    pub const CURRENT: Kdf = Kdf::Pbkdf2Sha256Bound;
+
+    /// The byte used to identify this KDF in an envelope.
+    pub fn id(&self) -> u8 {
+        match self {
+            Kdf::Pbkdf2Sha256 => 1,
+            Kdf::Pbkdf2Sha256Bound => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for Kdf {
+    type Error = EnvelopeError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Kdf::Pbkdf2Sha256),
+            2 => Ok(Kdf::Pbkdf2Sha256Bound),
+            other => Err(EnvelopeError::UnknownKdf(other)),
+        }
+    }
+}
+
+/// A versioned container for an encrypted field, stored as a BLOB.
+///
+/// Layout: `version (1) || algorithm (1) || kdf (1) || nonce (algorithm dependent) || ciphertext`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub algorithm: Algorithm,
+    pub kdf: Kdf,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Serializes the envelope into its binary representation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.nonce.len() + self.ciphertext.len());
+        bytes.push(ENVELOPE_VERSION);
+        bytes.push(self.algorithm.id());
+        bytes.push(self.kdf.id());
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+    /// Parses an envelope from its binary representation. May fail with an `EnvelopeError`, but never panics.
+    ///
+    /// # Arguments
+    ///
+    /// - `bytes` - the raw bytes of the envelope, usually read from a BLOB column.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        let (&version, rest) = bytes.split_first().ok_or(EnvelopeError::Empty)?;
+        if version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let header = rest.get(..2).ok_or(EnvelopeError::Truncated)?;
+        let algorithm = Algorithm::try_from(header[0])?;
+        let kdf = Kdf::try_from(header[1])?;
+
+        let body = &rest[2..];
+        let nonce = body
+            .get(..algorithm.nonce_len())
+            .ok_or(EnvelopeError::Truncated)?;
+        let ciphertext = &body[algorithm.nonce_len()..];
+        if ciphertext.is_empty() {
+            return Err(EnvelopeError::Truncated);
+        }
+
+        Ok(Envelope {
+            algorithm,
+            kdf,
+            nonce: nonce.to_vec(),
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, Envelope, Kdf, ENVELOPE_VERSION};
+    use crate::error::EnvelopeError;
+
+    fn sample() -> Envelope {
+        Envelope {
+            algorithm: Algorithm::Aes256Gcm,
+            kdf: Kdf::Pbkdf2Sha256,
+            nonce: vec![7; 12],
+            ciphertext: vec![1, 2, 3, 4],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let envelope = sample();
+        let bytes = envelope.to_bytes();
+        assert_eq!(bytes[0], ENVELOPE_VERSION);
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap(), envelope);
+    }
+    #[test]
+    fn empty() {
+        assert!(matches!(
+            Envelope::from_bytes(&[]),
+            Err(EnvelopeError::Empty)
+        ));
+    }
+    #[test]
+    fn bad_header() {
+        let mut bytes = sample().to_bytes();
+        bytes[0] = 99;
+        assert!(matches!(
+            Envelope::from_bytes(&bytes),
+            Err(EnvelopeError::UnsupportedVersion(99))
+        ));
+
+        let mut bytes = sample().to_bytes();
+        bytes[1] = 42;
+        assert!(matches!(
+            Envelope::from_bytes(&bytes),
+            Err(EnvelopeError::UnknownAlgorithm(42))
+        ));
+
+        let mut bytes = sample().to_bytes();
+        bytes[2] = 0;
+        assert!(matches!(
+            Envelope::from_bytes(&bytes),
+            Err(EnvelopeError::UnknownKdf(0))
+        ));
+    }
+    #[test]
+    fn truncated() {
+        let bytes = sample().to_bytes();
+        // every prefix shorter than header + nonce + 1 byte of ciphertext must be rejected
+        for len in 1..(3 + 12 + 1) {
+            assert!(matches!(
+                Envelope::from_bytes(&bytes[..len]),
+                Err(EnvelopeError::Truncated)
+            ));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{fmt::Display, string::FromUtf8Error};
+
+use hex::FromHexError;
+use thiserror::Error;
+#[derive(Error, Debug)]
+/// Custom error `enum` for backend operations.
+/// Contains errors that may occur at *any phase of a backend operation*, e.g. reading a password.
+pub enum BackendError {
+    #[error("error decoding: {0}")]
+    DecodeError(#[from] FromHexError),
+
+    #[error("error converting decrypted data to a string: {0}")]
+    ToStringError(#[from] FromUtf8Error),
+
+    #[error("error occurred during encryption/decryption")]
+    AesError,
+
+    #[error("error getting password from db: {0}")]
+    SQLiteError(#[from] rusqlite::Error),
+
+    #[error("no nonce was found matching the field")]
+    NoMatchingNonce,
+
+    #[error("no {0} was found")]
+    NoMatchingRecord(String),
+
+    #[error("Attempted to authenticate invalid master record field")]
+    InvalidMasterRecordField,
+
+    #[error("error parsing encrypted field: {0}")]
+    EnvelopeError(#[from] EnvelopeError),
+
+    #[error("a password named \"{0}\" already exists")]
+    NameTaken(String),
+
+    #[error("\"{0}\" is reserved for the master record and can't be used as a password name")]
+    ReservedName(String),
+
+    #[error("the {field} field of \"{password}\" is damaged")]
+    CorruptField {
+        password: String,
+        field: String,
+        #[source]
+        source: Box<BackendError>,
+    },
+
+    #[error("no master record was found; the vault has not been set up")]
+    MissingMasterRecord,
+
+    #[error("the master record is damaged and can't be used to log in")]
+    CorruptMasterRecord,
+
+    #[error("vault metadata \"{key}\" is invalid: {reason}")]
+    CorruptMetadata { key: String, reason: String },
+
+    #[error("failed to initialize libsodium")]
+    InitError,
+
+    #[error("the vault is locked")]
+    Locked,
+
+    #[error("couldn't reach the server: {0}")]
+    ServerUnreachable(String),
+
+    #[error("the server didn't accept this username and master password")]
+    Unauthorized,
+
+    #[error("the server returned an error ({status}): {message}")]
+    ServerError { status: u16, message: String },
+
+    #[error("the server sent an invalid response: {0}")]
+    InvalidResponse(String),
+
+    #[error("\"{0}\" was changed while syncing; sync again")]
+    ChangedDuringSync(String),
+
+    #[error("{0} sync conflict(s) haven't been resolved")]
+    UnresolvedConflicts(usize),
+
+    #[error("both vaults have the same id, so one is probably a copy of the other; give the copy a new id first")]
+    SameVault,
+
+    #[error("{0}")]
+    GitError(String),
+
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("the vault has already been set up")]
+    AlreadyInitialized,
+
+    #[error("invalid public key; expected 64 hex digits")]
+    InvalidPublicKey,
+
+    #[error("this identity isn't a member of the shared vault")]
+    NotAMember,
+
+    #[error("\"{0}\" (or their public key) is already a member")]
+    MemberExists(String),
+
+    #[error("the last member of a shared vault can't be removed")]
+    LastMember,
+
+    #[error("not a valid share file: {0}")]
+    InvalidShare(String),
+
+    #[error("the share expired on {0}")]
+    ShareExpired(chrono::DateTime<chrono::Utc>),
+
+    #[error("the vault requires a keyfile")]
+    KeyfileRequired,
+
+    #[error("invalid keyfile: {0}")]
+    InvalidKeyfile(String),
+
+    #[error("the snapshot can't be decrypted with this master password (and keyfile)")]
+    SnapshotKeyMismatch,
+
+    #[error("not a valid vault file: {0}")]
+    InvalidStore(String),
+
+    #[error("the vault doesn't match its manifest, so nothing was changed; check it, and accept it as it is if it's fine")]
+    ManifestMismatch,
+
+    #[error("the vault has no manifest, so nothing was changed; check it, and seal it as it is if it's fine")]
+    ManifestMissing,
+}
+
+impl BackendError {
+    /// Wraps this error with the password and field it occurred on, so a damaged entry can be found and reported.
+    ///
+    /// # Arguments
+    ///
+    /// - `password` - the name of the password the field belongs to.
+    /// - `field` - the field, e.g. a `PasswordField` or the name of a custom field.
+    pub fn in_field(self, password: &str, field: impl Display) -> Self {
+        BackendError::CorruptField {
+            password: password.to_string(),
+            field: field.to_string(),
+            source: Box::new(self),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+/// Errors that may occur while parsing an `Envelope` from raw bytes.
+pub enum EnvelopeError {
+    #[error("envelope is empty")]
+    Empty,
+
+    #[error("envelope is truncated")]
+    Truncated,
+
+    #[error("unsupported envelope version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("unknown algorithm id {0}")]
+    UnknownAlgorithm(u8),
+
+    #[error("unknown KDF id {0}")]
+    UnknownKdf(u8),
+}
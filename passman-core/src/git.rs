@@ -0,0 +1,927 @@
+//! Vaults stored in a git repository, one encrypted file per password, like `pass`.
+//!
+//! Each password is a file in `entries/`, named after the password (see `encode_name()`), holding its revision in plaintext
+//! and the password itself encrypted with the same keys as the local vault. Every write is its own commit.
+//! `.passman` holds the settings shared by every clone, encrypted with the master password.
+//!
+//! `GitVault` is a sync `Replica`, so the local vault is kept in step with the repository through `sync`.
+//! Concurrent changes to the same password are merged field by field by a git merge driver (see `merge_files()`);
+//! whatever it can't merge is left for `GitVault::pull()` to report as conflicts.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crypto::{derive_subkey, gen_cipher, Cipher},
+    db_ops::MASTER_KEYWORD,
+    envelope::{Algorithm, Envelope},
+    error::BackendError,
+    password::{decode_name, encode_name, PasswordInfo},
+    sync::{
+        merge_three_way, next_field_revisions, Conflict, ConflictSide, EntryRevision, Replica,
+        Resolution, SyncEntry, SyncField,
+    },
+};
+
+/// The environment variable the master password is passed to the merge driver in, by `GitVault::pull()`.
+pub const MASTER_ENV: &str = "PASSMAN_GIT_MASTER";
+/// The file holding the vault's encrypted settings, at the root of the repository.
+const SETTINGS_FILE: &str = ".passman";
+/// The context the key for `SETTINGS_FILE` is derived with.
+const SETTINGS_CONTEXT: &[u8] = b"passman git settings v1";
+/// The directory holding one file per password.
+const ENTRIES_DIR: &str = "entries";
+const ENTRY_EXTENSION: &str = "entry";
+/// Entries are merged by passman, and their diffs are meaningless.
+const GIT_ATTRIBUTES: &str = "entries/* merge=passman -diff -text\n";
+
+#[derive(Debug, Serialize, Deserialize)]
+/// Settings shared by every clone, stored encrypted in `SETTINGS_FILE`.
+struct VaultSettings {
+    /// The id of the `Algorithm` entries are encrypted with.
+    algorithm: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// The contents of an entry file. The revision is in plaintext, so listing revisions doesn't have to decrypt anything.
+struct EntryFile {
+    revision: u64,
+    /// A hex encoded `Envelope` holding a `GitRecord`.
+    envelope: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// What's encrypted in an entry file.
+struct GitRecord {
+    entry: PasswordInfo,
+    field_revisions: BTreeMap<String, u64>,
+}
+
+/// A password read from an entry file.
+#[derive(Debug, Clone)]
+struct StoredEntry {
+    revision: u64,
+    entry: SyncEntry,
+}
+
+/// A vault stored in a git repository. See the module documentation for the layout.
+pub struct GitVault {
+    path: PathBuf,
+    master: String,
+    algorithm: Algorithm,
+}
+
+/// Runs git in `dir`, failing with `BackendError::GitError` if it doesn't succeed.
+fn git(dir: &Path, args: &[&str]) -> Result<String, BackendError> {
+    let output = git_output(dir, args, None)?;
+    if !output.status.success() {
+        return Err(BackendError::GitError(format!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runs git in `dir`, passing the master password to the merge driver if it's given.
+fn git_output(dir: &Path, args: &[&str], master: Option<&str>) -> Result<Output, BackendError> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(dir).args(args);
+    if let Some(master) = master {
+        command.env(MASTER_ENV, master);
+    }
+    command
+        .output()
+        .map_err(|e| BackendError::GitError(format!("couldn't run git: {}", e)))
+}
+
+/// The path of a password's file, relative to the root of the repository.
+fn entry_path(name: &str) -> String {
+    format!("{}/{}.{}", ENTRIES_DIR, encode_name(name), ENTRY_EXTENSION)
+}
+
+/// The name of the password stored at `path`, relative to the root of the repository.
+fn entry_name(path: &str) -> Option<String> {
+    let file = path.strip_prefix(ENTRIES_DIR)?.strip_prefix('/')?;
+    decode_name(file.strip_suffix(ENTRY_EXTENSION)?.strip_suffix('.')?)
+}
+
+impl GitVault {
+    /// Sets up a vault in a git repository, creating the repository if needed. If the repository already holds a vault,
+    /// e.g. because it was cloned from another device, it's opened instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - the directory of the repository.
+    /// - `master` - the master password. Every clone has to use the same one.
+    /// - `algorithm` - the `Algorithm` to encrypt entries with, if the vault is new.
+    pub fn init(path: &Path, master: &str, algorithm: Algorithm) -> Result<Self, BackendError> {
+        fs::create_dir_all(path)?;
+        if !path.join(".git").exists() {
+            git(path, &["init", "-q"])?;
+        }
+        if path.join(SETTINGS_FILE).exists() {
+            return GitVault::open(path, master);
+        }
+
+        let vault = GitVault {
+            path: path.to_path_buf(),
+            master: master.to_string(),
+            algorithm,
+        };
+        let settings = serde_json::to_vec(&VaultSettings {
+            algorithm: algorithm.id(),
+        })
+        .map_err(|e| BackendError::GitError(e.to_string()))?;
+        let envelope = vault.settings_cipher().encrypt(settings)?;
+        fs::write(path.join(SETTINGS_FILE), envelope.to_bytes())?;
+        fs::write(path.join(".gitattributes"), GIT_ATTRIBUTES)?;
+        git(path, &["add", SETTINGS_FILE, ".gitattributes"])?;
+        git(path, &["commit", "-q", "-m", "Set up passman vault"])?;
+        Ok(vault)
+    }
+
+    /// Opens a vault in a git repository that was set up with `init()`.
+    /// Fails with `BackendError::AesError` if the vault uses a different master password.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - the directory of the repository.
+    /// - `master` - the master password.
+    pub fn open(path: &Path, master: &str) -> Result<Self, BackendError> {
+        let settings = fs::read(path.join(SETTINGS_FILE)).map_err(|_| {
+            BackendError::GitError(format!("{} doesn't hold a passman vault", path.display()))
+        })?;
+        let mut vault = GitVault {
+            path: path.to_path_buf(),
+            master: master.to_string(),
+            algorithm: Algorithm::default(),
+        };
+        let envelope = Envelope::from_bytes(&settings)?;
+        // vaults set up before the settings had their own key have them encrypted with the master record's key.
+        // They're left that way, since every clone rewriting the file would make them conflict
+        let settings = vault
+            .settings_cipher()
+            .decrypt(&envelope)
+            .or_else(|_| gen_cipher(vault.algorithm, master, MASTER_KEYWORD).decrypt(&envelope))?;
+        let settings: VaultSettings =
+            serde_json::from_slice(&settings).map_err(|e| BackendError::GitError(e.to_string()))?;
+        vault.algorithm = Algorithm::try_from(settings.algorithm)?;
+        Ok(vault)
+    }
+
+    /// Sets the command git runs to merge entry files, in this clone's configuration (it isn't versioned, so every clone
+    /// needs it). The command is called with the paths of the base, ours and theirs versions and the entry's path appended,
+    /// and should call `merge_files()`. Without it, every concurrent change to a password is left for `pull()` to report.
+    ///
+    /// # Arguments
+    ///
+    /// - `command` - e.g. `passman git merge-driver`.
+    pub fn set_merge_driver(&self, command: &str) -> Result<(), BackendError> {
+        git(
+            &self.path,
+            &["config", "merge.passman.name", "passman entries"],
+        )?;
+        git(
+            &self.path,
+            &[
+                "config",
+                "merge.passman.driver",
+                &format!("{} %O %A %B %P", command),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The directory of the repository.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The cipher for `SETTINGS_FILE`.
+    fn settings_cipher(&self) -> Cipher {
+        Cipher::new(
+            self.algorithm,
+            derive_subkey(&self.master, SETTINGS_CONTEXT),
+        )
+    }
+
+    fn decode_entry(&self, name: &str, data: &[u8]) -> Result<StoredEntry, BackendError> {
+        let invalid =
+            |reason: String| BackendError::GitError(format!("{}: {}", entry_path(name), reason));
+        let file: EntryFile = serde_json::from_slice(data).map_err(|e| invalid(e.to_string()))?;
+        let envelope = Envelope::from_bytes(&hex::decode(file.envelope)?)?;
+        let decrypted = gen_cipher(self.algorithm, &self.master, name).decrypt(&envelope)?;
+        let record: GitRecord =
+            serde_json::from_slice(&decrypted).map_err(|e| invalid(e.to_string()))?;
+        // the name is the only thing that could be swapped without breaking the encryption
+        if record.entry.name != name {
+            return Err(invalid(format!("holds \"{}\"", record.entry.name)));
+        }
+        Ok(StoredEntry {
+            revision: file.revision,
+            entry: SyncEntry {
+                password: record.entry,
+                field_revisions: record.field_revisions,
+            },
+        })
+    }
+    fn encode_entry(&self, revision: u64, record: &GitRecord) -> Result<Vec<u8>, BackendError> {
+        let plaintext =
+            serde_json::to_vec(record).map_err(|e| BackendError::GitError(e.to_string()))?;
+        let envelope = gen_cipher(self.algorithm, &self.master, &record.entry.name)
+            .encrypt(plaintext)?
+            .to_bytes();
+        serde_json::to_vec_pretty(&EntryFile {
+            revision,
+            envelope: hex::encode(envelope),
+        })
+        .map_err(|e| BackendError::GitError(e.to_string()))
+    }
+
+    /// Reads a password's file from the working tree.
+    fn read_entry(&self, name: &str) -> Result<Option<StoredEntry>, BackendError> {
+        match fs::read(self.path.join(entry_path(name))) {
+            Ok(data) => Ok(Some(self.decode_entry(name, &data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Writes a password's file to the working tree and stages it.
+    fn stage_entry(&self, revision: u64, record: &GitRecord) -> Result<(), BackendError> {
+        let path = entry_path(&record.entry.name);
+        fs::create_dir_all(self.path.join(ENTRIES_DIR))?;
+        fs::write(self.path.join(&path), self.encode_entry(revision, record)?)?;
+        git(&self.path, &["add", "--", &path])?;
+        Ok(())
+    }
+    fn check_revision(
+        &self,
+        name: &str,
+        current: Option<&StoredEntry>,
+        expected_revision: u64,
+    ) -> Result<(), BackendError> {
+        if current.map_or(0, |current| current.revision) != expected_revision {
+            return Err(BackendError::ChangedDuringSync(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Pushes the repository, passing `args` on to `git push`. Returns git's output.
+    pub fn push(&self, args: &[String]) -> Result<String, BackendError> {
+        let mut command = vec!["push", "-q"];
+        command.extend(args.iter().map(String::as_str));
+        git(&self.path, &command)
+    }
+
+    /// Pulls the repository, passing `args` on to `git pull`. Concurrent changes to the same password are merged by the
+    /// merge driver if it's set up (see `set_merge_driver()`), which is given the master password through `MASTER_ENV`.
+    ///
+    /// Whatever git couldn't merge is returned as a `PendingMerge`, which has to be finished before the vault can be used
+    /// again. If everything was merged, it's empty.
+    pub fn pull(&self, args: &[String]) -> Result<PendingMerge, BackendError> {
+        let mut command = vec!["pull", "-q", "--no-rebase", "--no-edit"];
+        command.extend(args.iter().map(String::as_str));
+        let output = git_output(&self.path, &command, Some(&self.master))?;
+
+        let unmerged = git(
+            &self.path,
+            &["diff", "--name-only", "--diff-filter=U", "-z"],
+        )?;
+        let unmerged: Vec<&str> = unmerged
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .collect();
+        if !output.status.success() && unmerged.is_empty() {
+            return Err(BackendError::GitError(format!(
+                "git pull failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let mut pending = PendingMerge::default();
+        for path in unmerged {
+            let name = entry_name(path).ok_or_else(|| {
+                BackendError::GitError(format!("couldn't merge {}; resolve it with git", path))
+            })?;
+            // stage 1 is the common ancestor, 2 is ours and 3 is theirs; any of them may be missing
+            let stage = |stage: u8| -> Result<Option<StoredEntry>, BackendError> {
+                let output =
+                    git_output(&self.path, &["show", &format!(":{}:{}", stage, path)], None)?;
+                match output.status.success() {
+                    true => Ok(Some(self.decode_entry(&name, &output.stdout)?)),
+                    false => Ok(None),
+                }
+            };
+            let (base, ours, theirs) = (stage(1)?, stage(2)?, stage(3)?);
+            pending.add(name, base, ours, theirs);
+        }
+        Ok(pending)
+    }
+
+    /// Merges three versions of an entry file, for the git merge driver. `ours` is replaced with the result.
+    /// Returns whether the versions could be merged; if not, `ours` is left as it is for `pull()` to report.
+    ///
+    /// # Arguments
+    ///
+    /// - `base` - the version both sides started from. It's empty if the password was added on both sides.
+    /// - `ours` - our version, which is replaced by the merged one.
+    /// - `theirs` - their version.
+    /// - `path` - the path of the entry file in the repository, which the password's name is read from.
+    pub fn merge_files(
+        &self,
+        base: &Path,
+        ours: &Path,
+        theirs: &Path,
+        path: &str,
+    ) -> Result<bool, BackendError> {
+        let name = entry_name(path)
+            .ok_or_else(|| BackendError::GitError(format!("{} isn't an entry", path)))?;
+        let read = |file: &Path| -> Result<Option<StoredEntry>, BackendError> {
+            let data = fs::read(file)?;
+            match data.is_empty() {
+                true => Ok(None),
+                false => Ok(Some(self.decode_entry(&name, &data)?)),
+            }
+        };
+        let (Some(ours_entry), Some(theirs_entry)) = (read(ours)?, read(theirs)?) else {
+            return Ok(false);
+        };
+        let base_entry = read(base)?;
+        let (merged, conflicts) = merge_three_way(
+            base_entry.as_ref().map(|base| &base.entry.password),
+            &ours_entry.entry.password,
+            &theirs_entry.entry.password,
+        );
+        if !conflicts.is_empty() {
+            return Ok(false);
+        }
+        let (revision, record) = merged_record(Some(&ours_entry), Some(&theirs_entry), merged);
+        fs::write(ours, self.encode_entry(revision, &record)?)?;
+        Ok(true)
+    }
+}
+
+/// The file contents for a merged password. It gets a revision newer than both sides, and fields that differ from ours
+/// get that revision too, so syncing with the local vault picks them up.
+fn merged_record(
+    ours: Option<&StoredEntry>,
+    theirs: Option<&StoredEntry>,
+    merged: PasswordInfo,
+) -> (u64, GitRecord) {
+    let revision = ours
+        .map_or(0, |ours| ours.revision)
+        .max(theirs.map_or(0, |theirs| theirs.revision))
+        + 1;
+    let field_revisions = next_field_revisions(ours.map(|ours| &ours.entry), &merged, revision)
+        .unwrap_or_else(|| {
+            ours.map(|ours| ours.entry.field_revisions.clone())
+                .unwrap_or_default()
+        });
+    (
+        revision,
+        GitRecord {
+            entry: merged,
+            field_revisions,
+        },
+    )
+}
+
+#[derive(Debug)]
+struct PendingEntry {
+    name: String,
+    ours: Option<StoredEntry>,
+    theirs: Option<StoredEntry>,
+    /// Merged so far, with conflicting fields taken from ours. `None` if the password was deleted on one side.
+    merged: Option<PasswordInfo>,
+    /// Conflicting fields, and the index of their `Conflict`.
+    conflicts: Vec<(SyncField, usize)>,
+    /// The index of the `Conflict` if the password was deleted on one side.
+    deletion: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+/// Passwords git couldn't merge during `GitVault::pull()`, and the conflicts the user has to resolve.
+/// "Local" is this clone and "remote" is the one that was pulled.
+pub struct PendingMerge {
+    entries: Vec<PendingEntry>,
+    /// Conflicts that have to be resolved before the merge is finished.
+    pub conflicts: Vec<Conflict>,
+}
+
+impl PendingMerge {
+    fn add(
+        &mut self,
+        name: String,
+        base: Option<StoredEntry>,
+        ours: Option<StoredEntry>,
+        theirs: Option<StoredEntry>,
+    ) {
+        let (merged, conflicts, deletion) = match (&ours, &theirs) {
+            (Some(ours), Some(theirs)) => {
+                let (merged, fields) = merge_three_way(
+                    base.as_ref().map(|base| &base.entry.password),
+                    &ours.entry.password,
+                    &theirs.entry.password,
+                );
+                let conflicts = fields
+                    .into_iter()
+                    .map(|field| {
+                        self.conflicts.push(Conflict {
+                            name: name.clone(),
+                            field: Some(field),
+                            local: ConflictSide::new(Some(&ours.entry.password), Some(field)),
+                            remote: ConflictSide::new(Some(&theirs.entry.password), Some(field)),
+                            resolution: None,
+                        });
+                        (field, self.conflicts.len() - 1)
+                    })
+                    .collect();
+                (Some(merged), conflicts, None)
+            }
+            // deleted on one side, changed on the other
+            _ => {
+                let side = |entry: &Option<StoredEntry>| {
+                    ConflictSide::new(entry.as_ref().map(|entry| &entry.entry.password), None)
+                };
+                self.conflicts.push(Conflict {
+                    name: name.clone(),
+                    field: None,
+                    local: side(&ours),
+                    remote: side(&theirs),
+                    resolution: None,
+                });
+                (None, Vec::new(), Some(self.conflicts.len() - 1))
+            }
+        };
+        self.entries.push(PendingEntry {
+            name,
+            ours,
+            theirs,
+            merged,
+            conflicts,
+            deletion,
+        });
+    }
+
+    /// Whether git merged everything by itself.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Writes the resolved passwords and commits the merge.
+    /// Fails with `BackendError::UnresolvedConflicts` if any conflict has no `resolution` yet, before anything is written.
+    pub fn finish(self, vault: &GitVault) -> Result<(), BackendError> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let unresolved = self
+            .conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution.is_none())
+            .count();
+        if unresolved > 0 {
+            return Err(BackendError::UnresolvedConflicts(unresolved));
+        }
+        let keep_remote =
+            |index: usize| self.conflicts[index].resolution == Some(Resolution::KeepRemote);
+
+        for entry in &self.entries {
+            let result = match &entry.merged {
+                Some(merged) => {
+                    let mut merged = merged.clone();
+                    let theirs = &entry
+                        .theirs
+                        .as_ref()
+                        .expect("merged entries exist on both sides");
+                    for &(field, conflict) in &entry.conflicts {
+                        if keep_remote(conflict) {
+                            field.copy(&theirs.entry.password, &mut merged);
+                        }
+                    }
+                    Some(merged)
+                }
+                None => {
+                    let kept = match entry.deletion.is_some_and(keep_remote) {
+                        true => &entry.theirs,
+                        false => &entry.ours,
+                    };
+                    kept.as_ref().map(|kept| kept.entry.password.clone())
+                }
+            };
+            match result {
+                Some(password) => {
+                    let (revision, record) =
+                        merged_record(entry.ours.as_ref(), entry.theirs.as_ref(), password);
+                    vault.stage_entry(revision, &record)?;
+                }
+                None => {
+                    git(
+                        &vault.path,
+                        &[
+                            "rm",
+                            "-q",
+                            "--ignore-unmatch",
+                            "--",
+                            &entry_path(&entry.name),
+                        ],
+                    )?;
+                }
+            }
+        }
+        git(&vault.path, &["commit", "-q", "--no-edit"])?;
+        Ok(())
+    }
+}
+
+impl Replica for GitVault {
+    fn id(&self) -> Result<String, BackendError> {
+        Ok(format!("git:{}", fs::canonicalize(&self.path)?.display()))
+    }
+    fn revisions(&self) -> Result<BTreeMap<String, EntryRevision>, BackendError> {
+        let mut revisions = BTreeMap::new();
+        let dir = match fs::read_dir(self.path.join(ENTRIES_DIR)) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(revisions),
+            Err(e) => return Err(e.into()),
+        };
+        for file in dir {
+            let file = file?;
+            let path = format!("{}/{}", ENTRIES_DIR, file.file_name().to_string_lossy());
+            // deleted passwords have no file, which `sync` treats as deleted
+            let Some(name) = entry_name(&path) else {
+                continue;
+            };
+            let entry: EntryFile = serde_json::from_slice(&fs::read(file.path())?)
+                .map_err(|e| BackendError::GitError(format!("{}: {}", path, e)))?;
+            revisions.insert(
+                name,
+                EntryRevision {
+                    revision: entry.revision,
+                    deleted: false,
+                },
+            );
+        }
+        Ok(revisions)
+    }
+    fn read(&self, name: &str) -> Result<Option<SyncEntry>, BackendError> {
+        Ok(self.read_entry(name)?.map(|stored| stored.entry))
+    }
+    fn write(&self, password: &PasswordInfo, expected_revision: u64) -> Result<u64, BackendError> {
+        let name = password.name.as_str();
+        let current = self.read_entry(name)?;
+        self.check_revision(name, current.as_ref(), expected_revision)?;
+        let revision = expected_revision + 1;
+        let Some(field_revisions) = next_field_revisions(
+            current.as_ref().map(|current| &current.entry),
+            password,
+            revision,
+        ) else {
+            return Ok(expected_revision);
+        };
+        self.stage_entry(
+            revision,
+            &GitRecord {
+                entry: password.clone(),
+                field_revisions,
+            },
+        )?;
+        let message = match current {
+            Some(_) => format!("Update {}", name),
+            None => format!("Add {}", name),
+        };
+        git(
+            &self.path,
+            &["commit", "-q", "-m", &message, "--", &entry_path(name)],
+        )?;
+        Ok(revision)
+    }
+    fn remove(&self, name: &str, expected_revision: u64) -> Result<u64, BackendError> {
+        let current = self.read_entry(name)?;
+        self.check_revision(name, current.as_ref(), expected_revision)?;
+        if current.is_some() {
+            let path = entry_path(name);
+            git(&self.path, &["rm", "-q", "--", &path])?;
+            git(
+                &self.path,
+                &[
+                    "commit",
+                    "-q",
+                    "-m",
+                    &format!("Delete {}", name),
+                    "--",
+                    &path,
+                ],
+            )?;
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entry_name, entry_path, git, GitVault, StoredEntry, SETTINGS_FILE};
+    use crate::{
+        crypto::gen_cipher,
+        db_ops::util::{create_table, insert_master_record},
+        db_ops::MASTER_KEYWORD,
+        envelope::{Algorithm, Envelope},
+        error::BackendError,
+        password::PasswordInfo,
+        sync::{sync, LocalReplica, Replica, Resolution, SyncEntry},
+    };
+    use rusqlite::Connection;
+    use std::{path::Path, sync::Once};
+
+    /// Commits need an identity, which the machine running the tests may not have.
+    fn setup() {
+        static IDENTITY: Once = Once::new();
+        IDENTITY.call_once(|| {
+            for (key, value) in [
+                ("GIT_AUTHOR_NAME", "passman"),
+                ("GIT_AUTHOR_EMAIL", "passman@example.com"),
+                ("GIT_COMMITTER_NAME", "passman"),
+                ("GIT_COMMITTER_EMAIL", "passman@example.com"),
+            ] {
+                std::env::set_var(key, value);
+            }
+        });
+    }
+    fn password(name: &str, username: &str, password: &str) -> PasswordInfo {
+        PasswordInfo {
+            name: name.to_string(),
+            username: Some(username.to_string()),
+            password: Some(password.to_string()),
+            ..Default::default()
+        }
+    }
+    fn log(dir: &Path) -> Vec<String> {
+        git(dir, &["log", "--format=%s"])
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+    fn local_vault() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        connection
+    }
+    fn sync_with(connection: &Connection, vault: &GitVault) {
+        sync(
+            &LocalReplica::new(connection, "master"),
+            vault,
+            |conflict| panic!("unexpected conflict: {:?}", conflict),
+        )
+        .unwrap();
+    }
+    /// A bare repository and two clones of it with a vault, as if on two devices.
+    fn clones() -> (tempfile::TempDir, GitVault, GitVault) {
+        setup();
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "-q", "--bare", "vault.git"]).unwrap();
+        git(dir.path(), &["clone", "-q", "vault.git", "laptop"]).unwrap();
+        let laptop = GitVault::init(
+            &dir.path().join("laptop"),
+            "master",
+            Algorithm::XChaCha20Poly1305,
+        )
+        .unwrap();
+        laptop
+            .push(&["-u".to_string(), "origin".to_string(), "HEAD".to_string()])
+            .unwrap();
+        git(dir.path(), &["clone", "-q", "vault.git", "desktop"]).unwrap();
+        // the vault is already set up, so this opens it
+        let desktop =
+            GitVault::init(&dir.path().join("desktop"), "master", Algorithm::Aes256Gcm).unwrap();
+        (dir, laptop, desktop)
+    }
+
+    #[test]
+    fn names() {
+        for name in ["mail", "work/mail box", ".."] {
+            assert_eq!(entry_name(&entry_path(name)).unwrap(), name);
+        }
+        assert_eq!(entry_path("work/mail"), "entries/work%2Fmail.entry");
+        assert!(entry_name(".passman").is_none());
+        assert!(entry_name("entries/mail.txt").is_none());
+    }
+    #[test]
+    fn entries() {
+        setup();
+        let dir = tempfile::tempdir().unwrap();
+        let vault = GitVault::init(dir.path(), "master", Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(
+            vault
+                .write(&password("mail", "alice", "hunter2"), 0)
+                .unwrap(),
+            1
+        );
+        // writing the same thing again changes nothing
+        assert_eq!(
+            vault
+                .write(&password("mail", "alice", "hunter2"), 1)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            vault.write(&password("mail", "bob", "hunter2"), 1).unwrap(),
+            2
+        );
+        assert!(matches!(
+            vault.write(&password("mail", "carol", "hunter2"), 1),
+            Err(BackendError::ChangedDuringSync(_))
+        ));
+        let SyncEntry {
+            password: mail,
+            field_revisions,
+        } = vault.read("mail").unwrap().unwrap();
+        assert_eq!(mail.username.unwrap(), "bob");
+        assert_eq!(field_revisions["username"], 2);
+        assert_eq!(field_revisions["password"], 1);
+        assert_eq!(vault.revisions().unwrap()["mail"].revision, 2);
+
+        // nothing is stored in plaintext
+        let file = std::fs::read(dir.path().join(entry_path("mail"))).unwrap();
+        for secret in ["hunter2", "bob"] {
+            assert!(!file
+                .windows(secret.len())
+                .any(|window| window == secret.as_bytes()));
+        }
+
+        vault.remove("mail", 2).unwrap();
+        assert!(vault.read("mail").unwrap().is_none());
+        assert!(vault.revisions().unwrap().is_empty());
+        assert_eq!(
+            log(dir.path()),
+            [
+                "Delete mail",
+                "Update mail",
+                "Add mail",
+                "Set up passman vault"
+            ]
+        );
+
+        // the same directory can be opened again, but only with the same master password
+        assert!(GitVault::open(dir.path(), "master").is_ok());
+        assert!(GitVault::open(dir.path(), "wrong").is_err());
+        assert!(GitVault::open(&dir.path().join("entries"), "master").is_err());
+    }
+    #[test]
+    fn legacy_settings() {
+        setup();
+        let dir = tempfile::tempdir().unwrap();
+        GitVault::init(dir.path(), "master", Algorithm::Aes256Gcm).unwrap();
+        let settings = std::fs::read(dir.path().join(SETTINGS_FILE)).unwrap();
+        let envelope = Envelope::from_bytes(&settings).unwrap();
+        // the settings have their own key
+        let legacy = gen_cipher(Algorithm::Aes256Gcm, "master", MASTER_KEYWORD);
+        assert!(legacy.decrypt(&envelope).is_err());
+
+        // but vaults set up before they did can still be opened
+        let settings = legacy.encrypt(br#"{"algorithm":2}"#).unwrap();
+        std::fs::write(dir.path().join(SETTINGS_FILE), settings.to_bytes()).unwrap();
+        let vault = GitVault::open(dir.path(), "master").unwrap();
+        assert_eq!(vault.algorithm, Algorithm::XChaCha20Poly1305);
+        assert!(GitVault::open(dir.path(), "wrong").is_err());
+    }
+    #[test]
+    fn push_and_pull() {
+        let (_dir, laptop, desktop) = clones();
+        let (laptop_db, desktop_db) = (local_vault(), local_vault());
+        crate::db_ops::crud::upsert_password_info(
+            &laptop_db,
+            &password("mail", "alice", "hunter2"),
+            "master",
+        )
+        .unwrap();
+        sync_with(&laptop_db, &laptop);
+        laptop.push(&[]).unwrap();
+        assert!(desktop.pull(&[]).unwrap().is_empty());
+        sync_with(&desktop_db, &desktop);
+
+        // different fields of the same password change on each device
+        let read = |connection| {
+            crate::db_ops::crud::read_password_info(connection, "mail", "master")
+                .unwrap()
+                .unwrap()
+        };
+        let mut mail = read(&laptop_db);
+        mail.username = Some("bob".to_string());
+        crate::db_ops::crud::replace_password_info(&laptop_db, &mail, "master").unwrap();
+        sync_with(&laptop_db, &laptop);
+        let mut mail = read(&desktop_db);
+        mail.password = Some("changed".to_string());
+        crate::db_ops::crud::replace_password_info(&desktop_db, &mail, "master").unwrap();
+        sync_with(&desktop_db, &desktop);
+
+        laptop.push(&[]).unwrap();
+        // without the merge driver git can't merge the file, but passman can, without asking
+        let pending = desktop.pull(&[]).unwrap();
+        assert!(!pending.is_empty() && pending.conflicts.is_empty());
+        pending.finish(&desktop).unwrap();
+        sync_with(&desktop_db, &desktop);
+        desktop.push(&[]).unwrap();
+        assert!(laptop.pull(&[]).unwrap().is_empty());
+        sync_with(&laptop_db, &laptop);
+
+        for connection in [&laptop_db, &desktop_db] {
+            let mail = read(connection);
+            assert_eq!(mail.username.unwrap(), "bob");
+            assert_eq!(mail.password.unwrap(), "changed");
+        }
+    }
+    #[test]
+    fn conflicts() {
+        let (_dir, laptop, desktop) = clones();
+        laptop.write(&password("mail", "alice", "one"), 0).unwrap();
+        laptop.write(&password("bank", "alice", "two"), 0).unwrap();
+        laptop.push(&[]).unwrap();
+        desktop.pull(&[]).unwrap();
+
+        laptop
+            .write(&password("mail", "alice", "laptop"), 1)
+            .unwrap();
+        laptop.remove("bank", 1).unwrap();
+        laptop.push(&[]).unwrap();
+        desktop
+            .write(&password("mail", "alice", "desktop"), 1)
+            .unwrap();
+        desktop.write(&password("bank", "bob", "two"), 1).unwrap();
+
+        let mut pending = desktop.pull(&[]).unwrap();
+        assert_eq!(pending.conflicts.len(), 2);
+        for conflict in &mut pending.conflicts {
+            // "local" is the clone that pulled
+            match conflict.name.as_str() {
+                "bank" => assert!(!conflict.local.deleted && conflict.remote.deleted),
+                _ => assert_eq!(conflict.field.unwrap().to_string(), "password"),
+            }
+            conflict.resolution = Some(Resolution::KeepRemote);
+        }
+        pending.finish(&desktop).unwrap();
+        assert!(desktop.read("bank").unwrap().is_none());
+        assert_eq!(
+            desktop
+                .read("mail")
+                .unwrap()
+                .unwrap()
+                .password
+                .password
+                .unwrap(),
+            "laptop"
+        );
+        // the merge is committed
+        assert!(git(desktop.path(), &["status", "--porcelain"])
+            .unwrap()
+            .is_empty());
+    }
+    #[test]
+    fn merge_files() {
+        setup();
+        let dir = tempfile::tempdir().unwrap();
+        let vault = GitVault::init(dir.path(), "master", Algorithm::Aes256Gcm).unwrap();
+        let file = |name: &str, revision: u64, password: PasswordInfo| {
+            let path = dir.path().join(name);
+            let entry = StoredEntry {
+                revision,
+                entry: SyncEntry {
+                    password,
+                    field_revisions: Default::default(),
+                },
+            };
+            let (_, record) = super::merged_record(None, None, entry.entry.password.clone());
+            std::fs::write(&path, vault.encode_entry(revision, &record).unwrap()).unwrap();
+            path
+        };
+        let base = file("base", 1, password("mail", "alice", "one"));
+        let ours = file("ours", 2, password("mail", "bob", "one"));
+        let theirs = file("theirs", 3, password("mail", "alice", "two"));
+        assert!(vault
+            .merge_files(&base, &ours, &theirs, &entry_path("mail"))
+            .unwrap());
+        let merged = vault
+            .decode_entry("mail", &std::fs::read(&ours).unwrap())
+            .unwrap();
+        assert_eq!(merged.revision, 4);
+        assert_eq!(merged.entry.password.username.unwrap(), "bob");
+        assert_eq!(merged.entry.password.password.unwrap(), "two");
+
+        // the same field changed on both sides is left alone
+        let theirs = file("theirs", 3, password("mail", "carol", "one"));
+        let before = std::fs::read(&ours).unwrap();
+        assert!(!vault
+            .merge_files(&base, &ours, &theirs, &entry_path("mail"))
+            .unwrap());
+        assert_eq!(std::fs::read(&ours).unwrap(), before);
+    }
+}
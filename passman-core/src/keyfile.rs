@@ -0,0 +1,235 @@
+//! Keyfiles, a second factor needed to unlock a vault next to the master password, like KeePass's.
+//!
+//! A vault that requires a keyfile is encrypted with a composite key (see `composite_key()`) rather than the master password,
+//! so it can't be decrypted without both. Everything that takes a master password takes the composite key instead.
+//! Adding, rotating or removing the keyfile re-encrypts the whole vault, after taking a snapshot of it (see `backup`).
+
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use rusqlite::Connection;
+use sodiumoxide::randombytes::randombytes;
+
+use crate::{
+    backup::take_snapshot,
+    crypto::hash,
+    db_ops::{
+        crud::rekey_vault,
+        util::{get_metadata, set_metadata},
+    },
+    error::BackendError,
+};
+
+/// Key in the `VaultMetadata` table recording whether the vault requires a keyfile.
+pub const KEYFILE_METADATA: &str = "keyfile_required";
+
+/// Combines a master password and the contents of a keyfile into the key the vault is encrypted with:
+/// `hex(sha256(sha256(master) || sha256(keyfile)))`. Any file works as a keyfile, as long as it never changes.
+///
+/// # Arguments
+///
+/// - `master` - the master password.
+/// - `keyfile` - the contents of the keyfile.
+pub fn composite_key(master: &str, keyfile: &[u8]) -> String {
+    let mut combined = hash(master.as_bytes()).to_vec();
+    combined.extend_from_slice(&hash(keyfile));
+    hex::encode(hash(&combined))
+}
+
+/// Whether the vault requires a keyfile to unlock.
+pub fn requires_keyfile(connection: &Connection) -> Result<bool, BackendError> {
+    Ok(get_metadata::<bool>(connection, KEYFILE_METADATA)?.unwrap_or(false))
+}
+
+/// Reads a keyfile. Fails with `BackendError::InvalidKeyfile` if it's empty, since an empty file adds no protection.
+pub fn read_keyfile(path: &Path) -> Result<Vec<u8>, BackendError> {
+    let keyfile = std::fs::read(path)?;
+    if keyfile.is_empty() {
+        return Err(BackendError::InvalidKeyfile(format!(
+            "{} is empty",
+            path.display()
+        )));
+    }
+    Ok(keyfile)
+}
+
+/// Writes a new keyfile of 32 random bytes, hex encoded, and returns its contents. Never overwrites an existing file.
+pub fn generate_keyfile(path: &Path) -> Result<Vec<u8>, BackendError> {
+    let keyfile = format!("{}\n", hex::encode(randombytes(32))).into_bytes();
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(&keyfile)?;
+    Ok(keyfile)
+}
+
+/// Starts requiring a keyfile: re-encrypts the vault with the composite key, in a single transaction. Returns the composite key.
+/// Fails with `BackendError::KeyfileRequired` if the vault already requires one; use `rotate_keyfile()` to change it.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+/// - `keyfile` - the contents of the new keyfile.
+///
+pub fn add_keyfile(
+    connection: &Connection,
+    master: &str,
+    keyfile: &[u8],
+) -> Result<String, BackendError> {
+    if requires_keyfile(connection)? {
+        return Err(BackendError::KeyfileRequired);
+    }
+    let key = composite_key(master, keyfile);
+    take_snapshot(connection, "keyfile")?;
+    let transaction = connection.unchecked_transaction()?;
+    rekey_vault(&transaction, master, &key)?;
+    set_metadata(&transaction, KEYFILE_METADATA, true)?;
+    transaction.commit()?;
+    Ok(key)
+}
+
+/// Replaces the keyfile with another one, re-encrypting the vault in a single transaction. Returns the new composite key.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password.
+/// - `keyfile` - the contents of the current keyfile. Together with `master`, it should be verified/authenticated by the time this function is called.
+/// - `new_keyfile` - the contents of the new keyfile.
+///
+pub fn rotate_keyfile(
+    connection: &Connection,
+    master: &str,
+    keyfile: &[u8],
+    new_keyfile: &[u8],
+) -> Result<String, BackendError> {
+    if !requires_keyfile(connection)? {
+        return Err(BackendError::InvalidKeyfile(
+            "the vault doesn't use a keyfile".to_string(),
+        ));
+    }
+    let key = composite_key(master, new_keyfile);
+    take_snapshot(connection, "keyfile")?;
+    let transaction = connection.unchecked_transaction()?;
+    rekey_vault(&transaction, &composite_key(master, keyfile), &key)?;
+    transaction.commit()?;
+    Ok(key)
+}
+
+/// Stops requiring a keyfile, re-encrypting the vault with just the master password in a single transaction.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password.
+/// - `keyfile` - the contents of the current keyfile. Together with `master`, it should be verified/authenticated by the time this function is called.
+///
+pub fn remove_keyfile(
+    connection: &Connection,
+    master: &str,
+    keyfile: &[u8],
+) -> Result<(), BackendError> {
+    if !requires_keyfile(connection)? {
+        return Ok(());
+    }
+    take_snapshot(connection, "keyfile")?;
+    let transaction = connection.unchecked_transaction()?;
+    rekey_vault(&transaction, &composite_key(master, keyfile), master)?;
+    set_metadata(&transaction, KEYFILE_METADATA, false)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_keyfile, composite_key, generate_keyfile, read_keyfile, remove_keyfile,
+        requires_keyfile, rotate_keyfile,
+    };
+    use crate::{
+        db_ops::{
+            crud::{insert_data, read_password_info},
+            util::{authenticate, create_table, insert_master_record},
+        },
+        envelope::Algorithm,
+        error::BackendError,
+        login::LoginAttempt,
+        password::PasswordField,
+        vault::Vault,
+    };
+    use rusqlite::Connection;
+
+    #[test]
+    fn keyfiles() {
+        crate::init().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.key");
+        let keyfile = generate_keyfile(&path).unwrap();
+        assert_eq!(read_keyfile(&path).unwrap(), keyfile);
+        // an existing keyfile is never overwritten
+        assert!(generate_keyfile(&path).is_err());
+        std::fs::write(dir.path().join("empty"), "").unwrap();
+        assert!(matches!(
+            read_keyfile(&dir.path().join("empty")),
+            Err(BackendError::InvalidKeyfile(_))
+        ));
+        assert_ne!(composite_key("master", b"a"), composite_key("master", b"b"));
+        assert_ne!(composite_key("master", b"a"), composite_key("other", b"a"));
+    }
+    #[test]
+    fn add_rotate_remove() {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        insert_data(
+            &connection,
+            "mail",
+            "master",
+            PasswordField::Password,
+            "hunter2",
+        )
+        .unwrap();
+        let read = |key: &str| {
+            read_password_info(&connection, "mail", key).map(|mail| mail.unwrap().password.unwrap())
+        };
+
+        assert!(!requires_keyfile(&connection).unwrap());
+        let key = add_keyfile(&connection, "master", b"first").unwrap();
+        assert!(requires_keyfile(&connection).unwrap());
+        assert!(matches!(
+            add_keyfile(&connection, "master", b"first"),
+            Err(BackendError::KeyfileRequired)
+        ));
+        // the master password alone no longer unlocks or decrypts anything
+        assert!(!authenticate(&connection, "master", PasswordField::Password).unwrap());
+        assert!(read("master").is_err());
+        assert!(authenticate(&connection, &key, PasswordField::Password).unwrap());
+        assert_eq!(read(&key).unwrap(), "hunter2");
+
+        let key = rotate_keyfile(&connection, "master", b"first", b"second").unwrap();
+        assert_eq!(key, composite_key("master", b"second"));
+        assert!(read(&composite_key("master", b"first")).is_err());
+        assert_eq!(read(&key).unwrap(), "hunter2");
+
+        remove_keyfile(&connection, "master", b"second").unwrap();
+        assert!(!requires_keyfile(&connection).unwrap());
+        assert!(authenticate(&connection, "master", PasswordField::Password).unwrap());
+        assert_eq!(read("master").unwrap(), "hunter2");
+    }
+    #[test]
+    fn vault() {
+        let mut vault = Vault::open_in_memory().unwrap();
+        vault
+            .initialize("master", "recovery", Algorithm::Aes256Gcm)
+            .unwrap();
+        add_keyfile(vault.connection(), "master", b"keyfile").unwrap();
+        vault.lock();
+        assert!(matches!(
+            vault.unlock("master"),
+            Err(BackendError::KeyfileRequired)
+        ));
+        assert!(matches!(
+            vault.unlock_with_keyfile("master", b"keyfile").unwrap(),
+            LoginAttempt::Success { .. }
+        ));
+        assert!(vault.is_unlocked());
+    }
+}
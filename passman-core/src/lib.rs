@@ -0,0 +1,47 @@
+//! The backend behind passman: an encrypted SQLite vault, with login throttling and idle sessions.
+//!
+//! Most frontends only need `vault::Vault`. The lower level modules are public for frontends that need more control,
+//! e.g. custom fields, folders or converting the vault's cipher.
+//!
+//! `manifest` authenticates the vault as a whole, so deleted or rolled back passwords are noticed.
+//! `verify` checks a whole vault for damage or tampering.
+//!
+//! `store::VaultStore` is where passwords are kept: the SQLite vault, in memory, or a single encrypted file.
+//!
+//! `backup` snapshots the vault before destructive operations, and restores those snapshots.
+//!
+//! `keyfile` adds a keyfile as a second factor, needed next to the master password to unlock a vault.
+//!
+//! `sync` merges two vaults, e.g. two vault files or (with the `remote` feature) a local vault and a passman-server.
+//!
+//! `git::GitVault` stores a vault in a git repository, one encrypted file per password.
+//!
+//! `team` has shared vaults, which every member opens with the key pair kept in their own vault.
+//! `share` hands a single password to someone else's key pair.
+//!
+//! With the `remote` feature, `remote::RemoteVault` stores entries on a passman-server, encrypted before they leave the client.
+
+pub mod backup;
+pub mod crypto;
+pub mod db_ops;
+pub mod envelope;
+pub mod error;
+pub mod git;
+pub mod keyfile;
+pub mod login;
+pub mod manifest;
+pub mod password;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod session;
+pub mod share;
+pub mod store;
+pub mod sync;
+pub mod team;
+pub mod vault;
+pub mod verify;
+
+/// Initializes the libraries the backend depends on. Call this once, before using anything else in this crate.
+pub fn init() -> Result<(), error::BackendError> {
+    sodiumoxide::init().map_err(|_| error::BackendError::InitError)
+}
@@ -0,0 +1,466 @@
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backup::delete_snapshots,
+    db_ops::util::{
+        authenticate, get_metadata, get_vault_algorithm, insert_master_record, set_metadata,
+    },
+    error::BackendError,
+    manifest::{MANIFEST_METADATA, SEALED_METADATA},
+    password::PasswordField,
+    team::{IDENTITY_PUBLIC_METADATA, IDENTITY_SECRET_METADATA},
+};
+
+/// Key in the `VaultMetadata` table holding the number of failed logins since the last successful one.
+pub const FAILED_LOGINS_METADATA: &str = "failed_logins";
+/// Key in the `VaultMetadata` table holding when the last failed login happened.
+pub const LAST_FAILED_LOGIN_METADATA: &str = "last_failed_login";
+/// Key in the `VaultMetadata` table holding when a lockout ends, if the vault is locked out.
+pub const LOCKED_UNTIL_METADATA: &str = "locked_until";
+/// Key in the `VaultMetadata` table holding the `LoginPolicy`, serialized as JSON.
+pub const LOGIN_POLICY_METADATA: &str = "login_policy";
+
+/// The longest a user has to wait between two login attempts, in seconds.
+pub const MAX_LOGIN_DELAY_SECONDS: i64 = 300;
+
+/// What happens once too many logins in a row have failed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockoutAction {
+    /// Refuse every login for this many minutes.
+    Lockout { minutes: u32 },
+    /// Permanently delete every password in the vault, along with the master record and the vault's snapshots. Use with caution!
+    Wipe,
+    /// Only apply the usual back-off delays.
+    #[default]
+    Nothing,
+}
+
+/// How the vault reacts to failed logins. Stored in the vault metadata, so it can only be changed after logging in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoginPolicy {
+    /// How many failed logins in a row trigger the `action`. `None` means there is no limit.
+    pub max_attempts: Option<u32>,
+    /// What to do once `max_attempts` is reached.
+    pub action: LockoutAction,
+}
+
+/// The result of a login attempt with `attempt_login()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginAttempt {
+    /// The secret was correct. Holds how many logins failed since the last successful one, and when the last of them happened.
+    Success {
+        failed_attempts: u32,
+        last_failed_at: Option<DateTime<Utc>>,
+    },
+    /// The secret was wrong. Holds how many logins have failed in a row, and when the next attempt will be allowed.
+    Failed {
+        failed_attempts: u32,
+        retry_at: DateTime<Utc>,
+    },
+    /// The attempt was made too soon after a failed one, and the secret wasn't checked.
+    Throttled { retry_at: DateTime<Utc> },
+    /// Too many logins failed and the vault is locked until the given time. The secret wasn't checked.
+    LockedOut { until: DateTime<Utc> },
+    /// Too many logins failed and the vault was wiped.
+    Wiped,
+}
+
+/// How long a user has to wait after `failed_attempts` failed logins in a row.
+/// The delay doubles with every failure, starting at one second, up to `MAX_LOGIN_DELAY_SECONDS`.
+pub fn login_delay(failed_attempts: u32) -> Duration {
+    if failed_attempts == 0 {
+        return Duration::zero();
+    }
+    let seconds = 1i64
+        .checked_shl(failed_attempts - 1)
+        .filter(|seconds| *seconds > 0)
+        .map_or(MAX_LOGIN_DELAY_SECONDS, |seconds| {
+            seconds.min(MAX_LOGIN_DELAY_SECONDS)
+        });
+    Duration::seconds(seconds)
+}
+
+/// Reads the vault's `LoginPolicy`. Vaults without one recorded use `LoginPolicy::default()`, which never locks out.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+///
+pub fn get_login_policy(connection: &Connection) -> Result<LoginPolicy, BackendError> {
+    get_metadata::<String>(connection, LOGIN_POLICY_METADATA)?.map_or(
+        Ok(LoginPolicy::default()),
+        |policy| {
+            serde_json::from_str(&policy).map_err(|e| BackendError::CorruptMetadata {
+                key: LOGIN_POLICY_METADATA.to_string(),
+                reason: e.to_string(),
+            })
+        },
+    )
+}
+/// Records the vault's `LoginPolicy`.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `policy` - the new `LoginPolicy`.
+///
+pub fn set_login_policy(
+    connection: &Connection,
+    policy: &LoginPolicy,
+) -> Result<usize, BackendError> {
+    let policy = serde_json::to_string(policy).map_err(|e| BackendError::CorruptMetadata {
+        key: LOGIN_POLICY_METADATA.to_string(),
+        reason: e.to_string(),
+    })?;
+    Ok(set_metadata(connection, LOGIN_POLICY_METADATA, policy)?)
+}
+
+/// Returns when the next login attempt will be allowed, or `None` if the user can log in right away.
+/// Both back-off delays and lockouts are taken into account, so a frontend can wait before prompting for the master password.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `now` - the current time.
+///
+pub fn next_login_at(
+    connection: &Connection,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, BackendError> {
+    if let Some(until) = get_metadata::<DateTime<Utc>>(connection, LOCKED_UNTIL_METADATA)? {
+        if until > now {
+            return Ok(Some(until));
+        }
+    }
+    let failed_attempts = get_metadata::<u32>(connection, FAILED_LOGINS_METADATA)?.unwrap_or(0);
+    let retry_at = get_metadata::<DateTime<Utc>>(connection, LAST_FAILED_LOGIN_METADATA)?
+        .map(|last_failed_at| last_failed_at + login_delay(failed_attempts));
+    Ok(retry_at.filter(|retry_at| *retry_at > now))
+}
+
+/// Checks a master password or recovery phrase against the master record, while enforcing the vault's `LoginPolicy`.
+/// Failed attempts are recorded in the vault metadata, so restarting the program doesn't reset the back-off delay.
+/// Attempts made before `next_login_at()` are refused without checking the secret.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `secret` - the master password or recovery phrase entered by the user.
+/// - `column` - `PasswordField::Password` to check a master password, or `PasswordField::Notes` to check a recovery phrase.
+/// - `now` - the current time.
+///
+pub fn attempt_login(
+    connection: &Connection,
+    secret: &str,
+    column: PasswordField,
+    now: DateTime<Utc>,
+) -> Result<LoginAttempt, BackendError> {
+    if let Some(retry_at) = next_login_at(connection, now)? {
+        let locked = get_metadata::<DateTime<Utc>>(connection, LOCKED_UNTIL_METADATA)?
+            .is_some_and(|until| until == retry_at);
+        return Ok(if locked {
+            LoginAttempt::LockedOut { until: retry_at }
+        } else {
+            LoginAttempt::Throttled { retry_at }
+        });
+    }
+
+    let failed_attempts = get_metadata::<u32>(connection, FAILED_LOGINS_METADATA)?.unwrap_or(0);
+    if authenticate(connection, secret, column)? {
+        let last_failed_at = get_metadata(connection, LAST_FAILED_LOGIN_METADATA)?;
+        reset_failed_logins(connection)?;
+        return Ok(LoginAttempt::Success {
+            failed_attempts,
+            last_failed_at,
+        });
+    }
+
+    let failed_attempts = failed_attempts + 1;
+    let policy = get_login_policy(connection)?;
+    if policy
+        .max_attempts
+        .is_some_and(|max_attempts| failed_attempts >= max_attempts)
+    {
+        match policy.action {
+            LockoutAction::Lockout { minutes } => {
+                let until = now + Duration::minutes(minutes.into());
+                // the lockout replaces the back-off, and the count starts over once it ends
+                reset_failed_logins(connection)?;
+                set_metadata(connection, LOCKED_UNTIL_METADATA, until)?;
+                return Ok(LoginAttempt::LockedOut { until });
+            }
+            LockoutAction::Wipe => {
+                wipe_vault(connection)?;
+                // they're encrypted, but would still give the passwords back to whoever has the master password
+                delete_snapshots(connection)?;
+                return Ok(LoginAttempt::Wiped);
+            }
+            LockoutAction::Nothing => {}
+        }
+    }
+
+    set_metadata(connection, FAILED_LOGINS_METADATA, failed_attempts)?;
+    set_metadata(connection, LAST_FAILED_LOGIN_METADATA, now)?;
+    Ok(LoginAttempt::Failed {
+        failed_attempts,
+        retry_at: now + login_delay(failed_attempts),
+    })
+}
+
+/// Forgets every failed login and lockout.
+fn reset_failed_logins(connection: &Connection) -> Result<usize, rusqlite::Error> {
+    connection.execute(
+        "delete from VaultMetadata where key in (?1, ?2, ?3)",
+        [
+            FAILED_LOGINS_METADATA,
+            LAST_FAILED_LOGIN_METADATA,
+            LOCKED_UNTIL_METADATA,
+        ],
+    )
+}
+
+/// Deletes every password and starts the vault over with a new master password, for a user who lost the old one: every
+/// password is encrypted with keys derived from it, so none of them can be kept, and neither can the team identity.
+/// The recovery phrase and the vault's `Algorithm` stay the same. All changes are made in a single transaction; take a
+/// snapshot first, so the passwords can still be restored if the old master password turns up.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `recovery_phrase` - a string slice holding the recovery phrase, which should be verified/authenticated by the time this function is called.
+/// - `new_master` - a string slice holding the new master password.
+///
+pub fn start_over(
+    connection: &Connection,
+    recovery_phrase: &str,
+    new_master: &str,
+) -> Result<(), BackendError> {
+    let algorithm = get_vault_algorithm(connection)?;
+    let transaction = connection.unchecked_transaction()?;
+    clear_vault(&transaction)?;
+    transaction.execute(
+        "delete from VaultMetadata where key in (?1, ?2)",
+        [IDENTITY_PUBLIC_METADATA, IDENTITY_SECRET_METADATA],
+    )?;
+    insert_master_record(&transaction, new_master, recovery_phrase, algorithm)?;
+    Ok(transaction.commit()?)
+}
+
+/// Deletes every password, custom field and the master record in a single transaction. The vault's metadata is kept,
+/// except for the manifest, which was sealed with the old master password.
+fn wipe_vault(connection: &Connection) -> Result<(), rusqlite::Error> {
+    let transaction = connection.unchecked_transaction()?;
+    clear_vault(&transaction)?;
+    transaction.commit()
+}
+
+/// Does the work of `wipe_vault()` without managing a transaction.
+fn clear_vault(connection: &Connection) -> Result<(), rusqlite::Error> {
+    for table in [
+        "EntryField",
+        "FieldUpdate",
+        "PasswordInfo",
+        "Tombstone",
+        "SyncBase",
+    ] {
+        connection.execute(&format!("delete from {}", table), [])?;
+    }
+    connection.execute(
+        "delete from VaultMetadata where key in (?1, ?2)",
+        [MANIFEST_METADATA, SEALED_METADATA],
+    )?;
+    reset_failed_logins(connection)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use rusqlite::Connection;
+
+    use super::{LockoutAction, LoginAttempt, LoginPolicy, MAX_LOGIN_DELAY_SECONDS};
+    use crate::{
+        db_ops::{
+            crud::{get_password_info, insert_data},
+            util::{check_password_info_exists, create_table, insert_master_record},
+            MASTER_KEYWORD,
+        },
+        envelope::Algorithm,
+        manifest::{verify_manifest, ManifestStatus},
+        password::PasswordField,
+    };
+
+    fn vault() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        connection
+    }
+
+    #[test]
+    fn login_delay() {
+        assert_eq!(super::login_delay(0), Duration::zero());
+        assert_eq!(super::login_delay(1), Duration::seconds(1));
+        assert_eq!(super::login_delay(4), Duration::seconds(8));
+        assert_eq!(
+            super::login_delay(100),
+            Duration::seconds(MAX_LOGIN_DELAY_SECONDS)
+        );
+    }
+    #[test]
+    fn backoff() {
+        let connection = vault();
+        let now = Utc::now();
+        let attempt = |secret: &str, now| {
+            super::attempt_login(&connection, secret, PasswordField::Password, now).unwrap()
+        };
+
+        assert_eq!(
+            attempt("wrong", now),
+            LoginAttempt::Failed {
+                failed_attempts: 1,
+                retry_at: now + Duration::seconds(1)
+            }
+        );
+        // too soon: the correct password isn't even checked, and the attempt isn't counted
+        assert_eq!(
+            attempt("master", now),
+            LoginAttempt::Throttled {
+                retry_at: now + Duration::seconds(1)
+            }
+        );
+        let now = now + Duration::seconds(1);
+        assert_eq!(
+            attempt("wrong", now),
+            LoginAttempt::Failed {
+                failed_attempts: 2,
+                retry_at: now + Duration::seconds(2)
+            }
+        );
+        assert_eq!(
+            super::next_login_at(&connection, now).unwrap(),
+            Some(now + Duration::seconds(2))
+        );
+
+        // a successful login reports the failures in between, then forgets them
+        let later = now + Duration::seconds(2);
+        assert_eq!(
+            attempt("master", later),
+            LoginAttempt::Success {
+                failed_attempts: 2,
+                last_failed_at: Some(now)
+            }
+        );
+        assert_eq!(super::next_login_at(&connection, later).unwrap(), None);
+        assert_eq!(
+            attempt("master", later),
+            LoginAttempt::Success {
+                failed_attempts: 0,
+                last_failed_at: None
+            }
+        );
+        // recovery phrases are throttled the same way
+        assert!(matches!(
+            super::attempt_login(&connection, "wrong", PasswordField::Notes, later).unwrap(),
+            LoginAttempt::Failed { .. }
+        ));
+    }
+    #[test]
+    fn lockout() {
+        let connection = vault();
+        super::set_login_policy(
+            &connection,
+            &LoginPolicy {
+                max_attempts: Some(2),
+                action: LockoutAction::Lockout { minutes: 30 },
+            },
+        )
+        .unwrap();
+        let now = Utc::now();
+        let attempt = |secret: &str, now| {
+            super::attempt_login(&connection, secret, PasswordField::Password, now).unwrap()
+        };
+
+        assert!(matches!(attempt("wrong", now), LoginAttempt::Failed { .. }));
+        let now = now + Duration::seconds(1);
+        let until = now + Duration::minutes(30);
+        assert_eq!(attempt("wrong", now), LoginAttempt::LockedOut { until });
+        assert_eq!(
+            attempt("master", now + Duration::minutes(10)),
+            LoginAttempt::LockedOut { until }
+        );
+        assert!(matches!(
+            attempt("master", until),
+            LoginAttempt::Success { .. }
+        ));
+    }
+    #[test]
+    fn wipe() {
+        let connection = vault();
+        insert_data(
+            &connection,
+            "name",
+            "master",
+            PasswordField::Password,
+            "pass",
+        )
+        .unwrap();
+        super::set_login_policy(
+            &connection,
+            &LoginPolicy {
+                max_attempts: Some(1),
+                action: LockoutAction::Wipe,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::attempt_login(&connection, "wrong", PasswordField::Password, Utc::now())
+                .unwrap(),
+            LoginAttempt::Wiped
+        );
+        assert!(get_password_info(&connection, "name").unwrap().is_none());
+        assert!(!check_password_info_exists(&connection, MASTER_KEYWORD).unwrap());
+    }
+    #[test]
+    fn start_over() {
+        let connection = vault();
+        insert_data(
+            &connection,
+            "name",
+            "master",
+            PasswordField::Password,
+            "pass",
+        )
+        .unwrap();
+
+        super::start_over(&connection, "recovery", "new").unwrap();
+        assert!(get_password_info(&connection, "name").unwrap().is_none());
+        let login = |secret: &str, column| {
+            super::attempt_login(&connection, secret, column, Utc::now()).unwrap()
+        };
+        assert!(matches!(
+            login("new", PasswordField::Password),
+            LoginAttempt::Success { .. }
+        ));
+        assert!(matches!(
+            login("recovery", PasswordField::Notes),
+            LoginAttempt::Success { .. }
+        ));
+        assert!(matches!(
+            verify_manifest(&connection, "new").unwrap(),
+            ManifestStatus::Valid { .. }
+        ));
+    }
+    #[test]
+    fn login_policy() {
+        let connection = vault();
+        assert_eq!(
+            super::get_login_policy(&connection).unwrap(),
+            LoginPolicy::default()
+        );
+        let policy = LoginPolicy {
+            max_attempts: Some(10),
+            action: LockoutAction::Wipe,
+        };
+        super::set_login_policy(&connection, &policy).unwrap();
+        assert_eq!(super::get_login_policy(&connection).unwrap(), policy);
+    }
+}
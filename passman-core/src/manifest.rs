@@ -0,0 +1,441 @@
+//! An authenticated manifest of the whole vault, so deleted or rolled back passwords are noticed.
+//!
+//! Every field is encrypted and authenticated on its own, which can't tell that a whole row was deleted, or that a password
+//! was replaced with an older copy of itself. The manifest is an HMAC, keyed by the master password, over the id, name and
+//! ciphertexts of every password (and its custom fields), sorted by name, and a revision counter that goes up with every write.
+//! Every write through `store::SqliteStore`, which `db_ops::crud` writes with, reseals it, and frontends check it after unlocking.
+//!
+//! Sealing also stores a MAC under `SEALED_METADATA`, marking the vault as sealed. A sealed vault without a manifest doesn't
+//! match it, and a manifest without the mark doesn't match the vault either, so neither can be removed on its own. Removing
+//! both makes the vault look like one from before manifests, which is never sealed without asking the user.
+//!
+//! Writes to a vault that doesn't match its manifest, or whose manifest is damaged, fail with
+//! `BackendError::ManifestMismatch`, and writes to a vault without a manifest with `BackendError::ManifestMissing`. Only
+//! `reseal_manifest()`, e.g. `passman verify --reseal`, accepts such a vault as it is.
+//!
+//! Plaintext columns (timestamps, expiry, revisions) and the vault metadata aren't covered. Neither is rolling back the whole
+//! file, manifest included, which is also how a snapshot is restored (see `backup`).
+
+use hmac::{Hmac, Mac};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    crypto::derive_key,
+    db_ops::{
+        crud::get_all_password_info,
+        util::{get_metadata, set_metadata},
+        MASTER_KEYWORD,
+    },
+    error::BackendError,
+};
+
+/// Key in the `VaultMetadata` table holding the `Manifest`, serialized as JSON.
+pub const MANIFEST_METADATA: &str = "manifest";
+/// Key in the `VaultMetadata` table holding the MAC that marks the vault as sealed, hex encoded.
+pub const SEALED_METADATA: &str = "sealed";
+
+/// The version of the manifest format, which is part of what's authenticated.
+const MANIFEST_CONTEXT: &[u8] = b"passman vault manifest v1";
+
+/// The context of the MAC that marks a vault as sealed.
+const SEALED_CONTEXT: &[u8] = b"passman vault sealed v1";
+
+/// The revision counter and MAC stored in the vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Goes up by one every time the manifest is resealed.
+    pub revision: u64,
+    /// The HMAC, hex encoded.
+    pub mac: String,
+}
+
+/// What `verify_manifest()` found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestStatus {
+    /// The vault matches its manifest.
+    Valid { revision: u64 },
+    /// The vault doesn't have a manifest, and isn't marked as sealed: it was made by an older version of passman, or both
+    /// were removed. Nothing can be written until the user checks the vault and seals it with `reseal_manifest()`.
+    Missing,
+    /// The vault doesn't match its manifest: passwords may have been deleted, added or rolled back outside of passman.
+    /// A sealed vault whose manifest was removed has revision 0.
+    Mismatch { revision: u64 },
+}
+
+/// Derives the HMAC key from the master password, separately from any key used for encryption.
+fn manifest_mac(master: &str) -> Hmac<Sha256> {
+    let mut key = <Hmac<Sha256> as Mac>::new_from_slice(&derive_key(master, MASTER_KEYWORD))
+        .expect("HMAC takes keys of any length");
+    key.update(MANIFEST_CONTEXT);
+    let key = key.finalize().into_bytes();
+    <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC takes keys of any length")
+}
+
+/// Feeds every password's id, name and ciphertexts, and the revision, to the HMAC.
+fn vault_mac(
+    connection: &Connection,
+    master: &str,
+    revision: u64,
+) -> Result<Hmac<Sha256>, BackendError> {
+    let mut mac = manifest_mac(master);
+    // everything is length-prefixed, so no two vaults feed the MAC the same bytes
+    let mut put = |data: &[u8]| {
+        mac.update(&(data.len() as u64).to_be_bytes());
+        mac.update(data);
+    };
+    put(MANIFEST_CONTEXT);
+    put(&revision.to_be_bytes());
+    for password in get_all_password_info(connection)? {
+        put(&password.id.to_be_bytes());
+        put(password.name.as_bytes());
+        for column in [
+            &password.email,
+            &password.username,
+            &password.password,
+            &password.notes,
+            &password.urls,
+            &password.tags,
+            &password.folder,
+        ] {
+            match column {
+                Some(data) => put(data),
+                // ciphertexts are never empty, so this is never mistaken for one
+                None => put(&[]),
+            }
+        }
+        put(&(password.fields.len() as u64).to_be_bytes());
+        for field in &password.fields {
+            put(&field.id.to_be_bytes());
+            put(&field.key);
+            put(&field.value);
+        }
+    }
+    Ok(mac)
+}
+
+/// Reads the vault's `Manifest`, or `None` if it doesn't have one.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+///
+pub fn get_manifest(connection: &Connection) -> Result<Option<Manifest>, BackendError> {
+    get_metadata::<String>(connection, MANIFEST_METADATA)?
+        .map(|manifest| {
+            serde_json::from_str(&manifest).map_err(|e| BackendError::CorruptMetadata {
+                key: MANIFEST_METADATA.to_string(),
+                reason: e.to_string(),
+            })
+        })
+        .transpose()
+}
+
+/// The MAC stored under `SEALED_METADATA` once the vault is sealed.
+fn sealed_mac(master: &str) -> Hmac<Sha256> {
+    let mut mac = manifest_mac(master);
+    mac.update(SEALED_CONTEXT);
+    mac
+}
+
+/// Recomputes the MAC over the vault as it is now, with the next revision, and marks the vault as sealed. Returns the new revision.
+fn write_manifest(
+    connection: &Connection,
+    master: &str,
+    revision: u64,
+) -> Result<u64, BackendError> {
+    let manifest = Manifest {
+        revision,
+        mac: hex::encode(
+            vault_mac(connection, master, revision)?
+                .finalize()
+                .into_bytes(),
+        ),
+    };
+    let manifest = serde_json::to_string(&manifest).map_err(|e| BackendError::CorruptMetadata {
+        key: MANIFEST_METADATA.to_string(),
+        reason: e.to_string(),
+    })?;
+    set_metadata(connection, MANIFEST_METADATA, manifest)?;
+    set_metadata(
+        connection,
+        SEALED_METADATA,
+        hex::encode(sealed_mac(master).finalize().into_bytes()),
+    )?;
+    Ok(revision)
+}
+
+/// Bumps the revision counter and recomputes the MAC over the vault as it is now. Returns the new revision.
+/// Called by `store::SqliteStore` at the end of every transaction that writes passwords, in the same transaction, after
+/// `ensure_manifest()` checked the vault before the first write. A damaged manifest fails with `BackendError::CorruptMetadata`
+/// instead of being replaced.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub(crate) fn seal_manifest(connection: &Connection, master: &str) -> Result<u64, BackendError> {
+    let revision = get_manifest(connection)?.map_or(0, |manifest| manifest.revision) + 1;
+    write_manifest(connection, master, revision)
+}
+
+/// Accepts the vault as it is, e.g. after looking into a `ManifestStatus::Mismatch` or `ManifestStatus::Missing`: the manifest
+/// is resealed even if it doesn't match, is damaged or was removed. Returns the new revision.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub fn reseal_manifest(connection: &Connection, master: &str) -> Result<u64, BackendError> {
+    // a damaged manifest's revision is lost, so it starts over
+    let revision = get_manifest(connection)
+        .ok()
+        .flatten()
+        .map_or(0, |manifest| manifest.revision)
+        + 1;
+    write_manifest(connection, master, revision)
+}
+
+/// Checks the vault against its manifest, without changing anything.
+/// A damaged manifest fails with `BackendError::CorruptMetadata`.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub fn verify_manifest(
+    connection: &Connection,
+    master: &str,
+) -> Result<ManifestStatus, BackendError> {
+    let sealed = get_metadata::<String>(connection, SEALED_METADATA)?;
+    let Some(manifest) = get_manifest(connection)? else {
+        return Ok(match sealed {
+            Some(_) => ManifestStatus::Mismatch { revision: 0 },
+            None => ManifestStatus::Missing,
+        });
+    };
+    let mac = vault_mac(connection, master, manifest.revision)?;
+    let matches = hex::decode(&manifest.mac).is_ok_and(|stored| mac.verify_slice(&stored).is_ok())
+        // a mark that's missing, or wasn't written with this master password, means it was tampered with
+        && sealed
+            .and_then(|sealed| hex::decode(sealed).ok())
+            .is_some_and(|sealed| sealed_mac(master).verify_slice(&sealed).is_ok());
+    Ok(match matches {
+        true => ManifestStatus::Valid {
+            revision: manifest.revision,
+        },
+        false => ManifestStatus::Mismatch {
+            revision: manifest.revision,
+        },
+    })
+}
+
+/// Checks the vault before it's written to: fails with `BackendError::ManifestMismatch` if it doesn't match its manifest,
+/// with `BackendError::ManifestMissing` if it doesn't have one, and with `BackendError::CorruptMetadata` if the manifest
+/// is damaged, so a write never seals over any of them.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub(crate) fn ensure_manifest(connection: &Connection, master: &str) -> Result<(), BackendError> {
+    match verify_manifest(connection, master)? {
+        ManifestStatus::Valid { .. } => Ok(()),
+        ManifestStatus::Missing => Err(BackendError::ManifestMissing),
+        ManifestStatus::Mismatch { .. } => Err(BackendError::ManifestMismatch),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        reseal_manifest, verify_manifest, ManifestStatus, MANIFEST_METADATA, SEALED_METADATA,
+    };
+    use crate::{
+        db_ops::{
+            crud::{delete_password_info, get_password_info, insert_data},
+            util::{create_table, get_metadata, insert_master_record, set_metadata},
+        },
+        envelope::Algorithm,
+        error::BackendError,
+        password::PasswordField,
+    };
+    use rusqlite::Connection;
+
+    fn vault() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        for name in ["bank", "mail"] {
+            insert_data(
+                &connection,
+                name,
+                "master",
+                PasswordField::Password,
+                "hunter2",
+            )
+            .unwrap();
+        }
+        connection
+    }
+
+    #[test]
+    fn writes() {
+        let connection = vault();
+        let Ok(ManifestStatus::Valid { revision }) = verify_manifest(&connection, "master") else {
+            panic!("the manifest should match");
+        };
+        delete_password_info(&connection, "bank", "master").unwrap();
+        assert_eq!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Valid {
+                revision: revision + 1
+            }
+        );
+        // the wrong key doesn't match anything
+        assert!(matches!(
+            verify_manifest(&connection, "wrong").unwrap(),
+            ManifestStatus::Mismatch { .. }
+        ));
+    }
+    #[test]
+    fn tampering() {
+        let connection = vault();
+        let old_password: Vec<u8> = connection
+            .query_row(
+                "select password from PasswordInfo where name = 'mail'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        insert_data(
+            &connection,
+            "mail",
+            "master",
+            PasswordField::Password,
+            "correct horse",
+        )
+        .unwrap();
+
+        // rolling back one password
+        connection
+            .execute(
+                "update PasswordInfo set password = ?1 where name = 'mail'",
+                [&old_password],
+            )
+            .unwrap();
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Mismatch { .. }
+        ));
+        // nothing can be written until the vault is accepted as it is
+        assert!(matches!(
+            insert_data(&connection, "mail", "master", PasswordField::Notes, "note"),
+            Err(BackendError::ManifestMismatch)
+        ));
+        assert_eq!(
+            get_password_info(&connection, "mail")
+                .unwrap()
+                .unwrap()
+                .notes,
+            None
+        );
+        reseal_manifest(&connection, "master").unwrap();
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Valid { .. }
+        ));
+
+        // deleting a row
+        connection
+            .execute("delete from PasswordInfo where name = 'bank'", [])
+            .unwrap();
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Mismatch { .. }
+        ));
+    }
+    #[test]
+    fn missing() {
+        let connection = vault();
+        let remove = |key: &str| {
+            connection
+                .execute("delete from VaultMetadata where key = ?", [key])
+                .unwrap();
+        };
+
+        // a sealed vault whose manifest was removed doesn't match it
+        remove(MANIFEST_METADATA);
+        assert_eq!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Mismatch { revision: 0 }
+        );
+        assert!(matches!(
+            delete_password_info(&connection, "bank", "master"),
+            Err(BackendError::ManifestMismatch)
+        ));
+        reseal_manifest(&connection, "master").unwrap();
+
+        // neither does a manifest without the mark
+        remove(SEALED_METADATA);
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Mismatch { .. }
+        ));
+        reseal_manifest(&connection, "master").unwrap();
+
+        // removing both looks like a vault from before manifests, which isn't sealed until the user accepts it
+        remove(MANIFEST_METADATA);
+        remove(SEALED_METADATA);
+        for _ in 0..2 {
+            assert_eq!(
+                verify_manifest(&connection, "master").unwrap(),
+                ManifestStatus::Missing
+            );
+        }
+        assert!(matches!(
+            delete_password_info(&connection, "bank", "master"),
+            Err(BackendError::ManifestMissing)
+        ));
+        assert!(get_password_info(&connection, "bank").unwrap().is_some());
+        reseal_manifest(&connection, "master").unwrap();
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Valid { .. }
+        ));
+        delete_password_info(&connection, "bank", "master").unwrap();
+    }
+    #[test]
+    fn corrupt() {
+        let connection = vault();
+        set_metadata(&connection, MANIFEST_METADATA, "not a manifest").unwrap();
+        assert!(matches!(
+            verify_manifest(&connection, "master"),
+            Err(BackendError::CorruptMetadata { .. })
+        ));
+        // a write doesn't replace a damaged manifest
+        assert!(matches!(
+            insert_data(&connection, "mail", "master", PasswordField::Notes, "note"),
+            Err(BackendError::CorruptMetadata { .. })
+        ));
+        assert_eq!(reseal_manifest(&connection, "master").unwrap(), 1);
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Valid { revision: 1 }
+        ));
+    }
+    #[test]
+    fn sealed_mark() {
+        let connection = vault();
+        // the mark only counts for the master password it was written with
+        let other = Connection::open_in_memory().unwrap();
+        create_table(&other).unwrap();
+        insert_master_record(&other, "other", "recovery", Algorithm::Aes256Gcm).unwrap();
+        let mark: String = get_metadata(&other, SEALED_METADATA).unwrap().unwrap();
+        set_metadata(&connection, SEALED_METADATA, mark).unwrap();
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Mismatch { .. }
+        ));
+    }
+}
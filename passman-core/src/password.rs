@@ -0,0 +1,392 @@
+use chrono::{DateTime, Duration, Utc};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+/// A struct to represent a password
+pub struct PasswordInfo {
+    /// Password ID, auto-incremented by SQLite database. do not set this yourself!
+    pub id: i32,
+    /// The password name. Must be unique or will fail SQLite constraints.
+    pub name: String,
+    /// Optional email field.
+    pub email: Option<String>,
+    /// Optional username field.
+    pub username: Option<String>,
+    /// Optional notes field.
+    pub notes: Option<String>,
+    /// Optional password field.
+    pub password: Option<String>,
+    /// Websites this password is used on.
+    pub urls: Vec<String>,
+    /// Tags used to group passwords, e.g. "work" or "finance".
+    pub tags: Vec<String>,
+    /// Optional folder path, with levels separated by `/`, e.g. `Work/Email`.
+    pub folder: Option<String>,
+    /// Any number of custom fields, e.g. security questions or PINs.
+    pub fields: Vec<EntryField>,
+    /// When the password was created, changed and last used.
+    pub timestamps: Timestamps,
+    /// When the password has to be changed.
+    pub expiry: Expiry,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+/// The encrypted, on-disk form of a `PasswordInfo`. Each field holds a serialized `Envelope`.
+pub struct EncryptedPasswordInfo {
+    /// Password ID, auto-incremented by SQLite database.
+    pub id: i32,
+    /// The password name, stored in plaintext.
+    pub name: String,
+    /// Optional encrypted email field.
+    pub email: Option<Vec<u8>>,
+    /// Optional encrypted username field.
+    pub username: Option<Vec<u8>>,
+    /// Optional encrypted notes field.
+    pub notes: Option<Vec<u8>>,
+    /// Optional encrypted password field.
+    pub password: Option<Vec<u8>>,
+    /// Optional encrypted, newline separated list of URLs.
+    pub urls: Option<Vec<u8>>,
+    /// Optional encrypted, newline separated list of tags.
+    pub tags: Option<Vec<u8>>,
+    /// Optional encrypted folder path.
+    pub folder: Option<Vec<u8>>,
+    /// Encrypted custom fields.
+    pub fields: Vec<EncryptedEntryField>,
+    /// When the password was created, changed and last used. Stored in plaintext.
+    pub timestamps: Timestamps,
+    /// When the password has to be changed. Stored in plaintext.
+    pub expiry: Expiry,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// Timestamps of a password. These may be `None` for passwords created by older versions of passman.
+pub struct Timestamps {
+    /// When the password was first inserted.
+    pub created_at: Option<DateTime<Utc>>,
+    /// When any field of the password was last changed.
+    pub updated_at: Option<DateTime<Utc>>,
+    /// When the password was last read.
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// When each standard field was last changed, keyed by its column name (see `PasswordField`'s `Display` implementation).
+    /// Changes to custom fields and the expiry are recorded under `CUSTOM_FIELDS_KEY` and `EXPIRY_KEY`.
+    pub fields: BTreeMap<String, DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// When a password has to be changed. This is stored in plaintext, so reminders can be shown without unlocking the vault.
+pub struct Expiry {
+    /// A fixed date after which the password is expired.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// How many days a password may be used before it has to be rotated.
+    pub rotation_days: Option<u32>,
+}
+
+impl Expiry {
+    /// Returns when the password is due, i.e. the earliest of the fixed expiry date and the next rotation.
+    /// Returns `None` if neither applies.
+    ///
+    /// # Arguments
+    ///
+    /// - `last_changed` - when the password was last changed, which rotation intervals count from.
+    pub fn due_at(&self, last_changed: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        let rotation = self
+            .rotation_days
+            .zip(last_changed)
+            .map(|(days, changed)| changed + Duration::days(days.into()));
+        match (self.expires_at, rotation) {
+            (Some(expires_at), Some(rotation)) => Some(expires_at.min(rotation)),
+            (expires_at, rotation) => expires_at.or(rotation),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// A password that is expired or about to be, as listed by `passman due`.
+pub struct DuePassword {
+    /// The password name.
+    pub name: String,
+    /// When the password expires or has to be rotated.
+    pub due_at: DateTime<Utc>,
+    /// Whether `due_at` has already passed.
+    pub expired: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// A custom key/value field on a `PasswordInfo`, such as a PIN, account number or security question.
+pub struct EntryField {
+    /// Field ID, auto-incremented by SQLite database. do not set this yourself!
+    pub id: i32,
+    /// The name of the field.
+    pub key: String,
+    /// The data stored in the field.
+    pub value: String,
+    /// Whether the value should be masked when displayed.
+    pub sensitive: bool,
+    /// When the field was added.
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the field was last changed.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The encrypted, on-disk form of an `EntryField`. Both key and value hold a serialized `Envelope`.
+pub struct EncryptedEntryField {
+    /// Field ID, auto-incremented by SQLite database.
+    pub id: i32,
+    /// The encrypted name of the field.
+    pub key: Vec<u8>,
+    /// The encrypted data stored in the field.
+    pub value: Vec<u8>,
+    /// Whether the value should be masked when displayed. Stored in plaintext.
+    pub sensitive: bool,
+    /// When the field was added. Stored in plaintext.
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the field was last changed. Stored in plaintext.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+/// Enum containing different fields on `PasswordInfo`. Primarily used in inserting or updating data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordField {
+    Email,
+    Username,
+    Notes,
+    Password,
+    Urls,
+    Tags,
+    Folder,
+}
+
+impl PasswordField {
+    /// Every field, in the order they're usually displayed.
+    pub const ALL: [PasswordField; 7] = [
+        PasswordField::Email,
+        PasswordField::Username,
+        PasswordField::Password,
+        PasswordField::Notes,
+        PasswordField::Urls,
+        PasswordField::Tags,
+        PasswordField::Folder,
+    ];
+}
+
+impl Display for PasswordField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let str = match self {
+            PasswordField::Email => "email",
+            PasswordField::Username => "username",
+            PasswordField::Password => "password",
+            PasswordField::Notes => "notes",
+            PasswordField::Urls => "urls",
+            PasswordField::Tags => "tags",
+            PasswordField::Folder => "folder",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Orders in which a list of passwords can be sorted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Name,
+    /// Newest first.
+    Created,
+    /// Most recently changed first.
+    Updated,
+    /// Most recently used first.
+    LastUsed,
+}
+
+/// Sorts passwords in the given `SortOrder`. Passwords without the relevant timestamp go last, sorted by name.
+pub fn sort_passwords(passwords: &mut [PasswordInfo], order: SortOrder) {
+    let key = |password: &PasswordInfo| match order {
+        SortOrder::Name => None,
+        SortOrder::Created => password.timestamps.created_at,
+        SortOrder::Updated => password.timestamps.updated_at,
+        SortOrder::LastUsed => password.timestamps.last_used_at,
+    };
+    // `Option`'s ordering puts `None` first, so reversing it puts both the newest and the missing timestamps where we want them
+    passwords.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name)));
+}
+
+/// Normalizes a folder path, trimming whitespace and removing empty levels, e.g. ` /Work//Email/ ` becomes `Work/Email`.
+/// Returns `None` if nothing is left, i.e. the password isn't in a folder.
+pub fn normalize_folder(path: &str) -> Option<String> {
+    let normalized = path
+        .split('/')
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    (!normalized.is_empty()).then_some(normalized)
+}
+
+/// Percent-encodes a password name so it can be used as a single URL path segment or file name.
+/// Dots are encoded too, so names like `..` can't be mistaken for relative paths.
+pub fn encode_name(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Decodes a name encoded with `encode_name()`. Returns `None` if it isn't valid.
+pub fn decode_name(encoded: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => decoded.push(byte),
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// A folder in the tree of passwords, as shown by the CLI tree view.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FolderNode {
+    /// Subfolders, sorted by name.
+    pub children: BTreeMap<String, FolderNode>,
+    /// Names of the passwords directly inside this folder.
+    pub entries: Vec<String>,
+}
+
+impl FolderNode {
+    /// Builds a folder tree out of a list of passwords. Passwords without a folder end up in the root.
+    pub fn from_passwords<'a>(passwords: impl IntoIterator<Item = &'a PasswordInfo>) -> Self {
+        let mut root = FolderNode::default();
+        for password in passwords {
+            let mut node = &mut root;
+            for level in password.folder.iter().flat_map(|folder| folder.split('/')) {
+                node = node.children.entry(level.to_string()).or_default();
+            }
+            node.entries.push(password.name.clone());
+        }
+        root
+    }
+}
+
+// is this even necessary?
+#[cfg(test)]
+mod tests {
+    use crate::password::{
+        decode_name, encode_name, normalize_folder, sort_passwords, Expiry, FolderNode,
+        PasswordField, PasswordInfo, SortOrder, Timestamps,
+    };
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn test_display() {
+        assert_eq!(PasswordField::Email.to_string(), "email");
+        assert_eq!(PasswordField::Username.to_string(), "username");
+        assert_eq!(PasswordField::Password.to_string(), "password");
+        assert_eq!(PasswordField::Notes.to_string(), "notes");
+        assert_eq!(PasswordField::Urls.to_string(), "urls");
+        assert_eq!(PasswordField::Tags.to_string(), "tags");
+        assert_eq!(PasswordField::Folder.to_string(), "folder");
+    }
+    #[test]
+    fn names() {
+        assert_eq!(encode_name("GitHub"), "GitHub");
+        assert_eq!(encode_name("work/mail box"), "work%2Fmail%20box");
+        assert_eq!(encode_name("..?#"), "%2E%2E%3F%23");
+        assert_eq!(encode_name("ü"), "%C3%BC");
+        for name in ["GitHub", "work/mail box", "..", "ü", "%41"] {
+            assert_eq!(decode_name(&encode_name(name)).unwrap(), name);
+        }
+        assert!(decode_name("%C3").is_none());
+        assert!(decode_name("%zz").is_none());
+        assert!(decode_name("%2").is_none());
+    }
+    #[test]
+    fn test_normalize_folder() {
+        assert_eq!(
+            normalize_folder(" /Work//Email/ "),
+            Some("Work/Email".to_string())
+        );
+        assert_eq!(normalize_folder(" / "), None);
+    }
+    #[test]
+    fn test_folder_tree() {
+        let password = |name: &str, folder: Option<&str>| PasswordInfo {
+            name: name.to_string(),
+            folder: folder.map(String::from),
+            ..Default::default()
+        };
+        let passwords = [
+            password("mail", Some("Work/Email")),
+            password("vpn", Some("Work")),
+            password("bank", None),
+        ];
+        let tree = FolderNode::from_passwords(&passwords);
+
+        assert_eq!(tree.entries, vec!["bank"]);
+        let work = &tree.children["Work"];
+        assert_eq!(work.entries, vec!["vpn"]);
+        assert_eq!(work.children["Email"].entries, vec!["mail"]);
+    }
+    #[test]
+    fn test_sort_passwords() {
+        let password = |name: &str, last_used: Option<i64>| PasswordInfo {
+            name: name.to_string(),
+            timestamps: Timestamps {
+                last_used_at: last_used.map(|secs| Utc.timestamp_opt(secs, 0).unwrap()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut passwords = vec![
+            password("c", Some(10)),
+            password("b", None),
+            password("a", Some(20)),
+            password("d", None),
+        ];
+        let names = |passwords: &[PasswordInfo]| {
+            passwords.iter().map(|p| p.name.clone()).collect::<Vec<_>>()
+        };
+
+        sort_passwords(&mut passwords, SortOrder::LastUsed);
+        assert_eq!(names(&passwords), vec!["a", "c", "b", "d"]);
+
+        sort_passwords(&mut passwords, SortOrder::Name);
+        assert_eq!(names(&passwords), vec!["a", "b", "c", "d"]);
+    }
+    #[test]
+    fn test_expiry_due_at() {
+        let changed = Utc.timestamp_opt(0, 0).unwrap();
+        let fixed = changed + Duration::days(30);
+
+        assert_eq!(Expiry::default().due_at(Some(changed)), None);
+        let rotation = Expiry {
+            rotation_days: Some(90),
+            ..Default::default()
+        };
+        assert_eq!(
+            rotation.due_at(Some(changed)),
+            Some(changed + Duration::days(90))
+        );
+        // rotation can't be computed without knowing when the password last changed
+        assert_eq!(rotation.due_at(None), None);
+        // the earliest date wins
+        let both = Expiry {
+            expires_at: Some(fixed),
+            rotation_days: Some(90),
+        };
+        assert_eq!(both.due_at(Some(changed)), Some(fixed));
+    }
+}
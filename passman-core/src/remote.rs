@@ -0,0 +1,526 @@
+use std::{collections::BTreeMap, io::Read, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    backup::take_snapshot,
+    crypto::{derive_key, derive_subkey, gen_cipher, hash, Cipher},
+    db_ops::{crud::list_password_info, util::get_vault_algorithm, MASTER_KEYWORD},
+    envelope::{Algorithm, Envelope},
+    error::BackendError,
+    password::PasswordInfo,
+    store::{with_transaction, write_password, SqliteStore},
+    sync::{next_field_revisions, EntryRevision, Replica, SyncEntry},
+};
+
+/// Prefixed to the username to derive the key used to log in to the server, so it can't match the key of any entry.
+const AUTH_KEY_CONTEXT: &str = "passman-server:";
+/// The context the key for entry ids is derived with.
+const ENTRY_ID_CONTEXT: &[u8] = b"passman remote entry ids v1";
+/// The context the key for entry names is derived with.
+const ENTRY_NAME_CONTEXT: &[u8] = b"passman remote entry names v1";
+/// The context the key for the vault blob is derived with.
+const SETTINGS_CONTEXT: &[u8] = b"passman remote settings v1";
+/// How long to wait for the server before giving up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// The largest response that will be read from the server, in bytes.
+const MAX_RESPONSE_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+/// An entry stored on the server, as listed by `RemoteVault::list()`. The server only sees an id derived from the name,
+/// and the name encrypted.
+pub struct RemoteEntry {
+    /// The password name.
+    pub name: String,
+    /// How many times the entry was written to the server. Starts at 1.
+    pub revision: u64,
+    /// When the entry was last written to the server.
+    pub updated_at: DateTime<Utc>,
+    /// Whether the entry was deleted. `list()` leaves deleted entries out.
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Deserialize)]
+/// An entry as the server lists it.
+struct ListedEntry {
+    id: String,
+    /// The encrypted name, base64 encoded. Empty for entries uploaded before names were encrypted.
+    label: String,
+    revision: u64,
+    updated_at: DateTime<Utc>,
+    deleted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// Settings shared by every device using a remote vault. These are stored encrypted in the user's vault blob.
+struct VaultSettings {
+    /// The id of the `Algorithm` entries are encrypted with.
+    algorithm: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct Revision {
+    revision: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// What's encrypted in an entry on the server: the password, and the revision each field was last changed in, for `sync`.
+struct RemoteRecord {
+    entry: PasswordInfo,
+    field_revisions: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    Record(RemoteRecord),
+    /// Entries uploaded before field revisions were recorded only hold the password.
+    Legacy(PasswordInfo),
+}
+
+/// A vault stored on a passman-server.
+///
+/// Everything is encrypted on this side with the same keys as the local vault, before it's sent:
+/// the server only ever sees opaque `Envelope`s and entry ids, which are HMACs of the names, never the master password
+/// or any plaintext. Each entry's name is sent encrypted alongside it, so entries can be listed.
+/// It authenticates users with a key derived from their master password, which can't be used to decrypt anything.
+pub struct RemoteVault {
+    agent: ureq::Agent,
+    server: String,
+    username: String,
+    authorization: String,
+    master: String,
+    algorithm: Algorithm,
+    /// The key entry ids are HMACs with.
+    id_key: [u8; 32],
+    /// The key entry names are encrypted with.
+    name_key: [u8; 32],
+}
+
+/// Derives the key a user logs in to the server with. It's a hash of a key derived from the master password,
+/// so the server can't use it to decrypt anything.
+///
+/// # Arguments
+///
+/// - `master` - the master password.
+/// - `username` - the user's name on the server.
+pub fn auth_key(master: &str, username: &str) -> String {
+    let key = derive_key(master, format!("{}{}", AUTH_KEY_CONTEXT, username));
+    hex::encode(hash(&key))
+}
+
+impl RemoteVault {
+    fn new(server: &str, username: &str, master: &str, algorithm: Algorithm) -> Self {
+        let credentials = format!("{}:{}", username, auth_key(master, username));
+        RemoteVault {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            server: server.trim_end_matches('/').to_string(),
+            username: username.to_string(),
+            authorization: format!("Basic {}", STANDARD.encode(credentials)),
+            master: master.to_string(),
+            algorithm,
+            id_key: derive_subkey(master, ENTRY_ID_CONTEXT),
+            name_key: derive_subkey(master, ENTRY_NAME_CONTEXT),
+        }
+    }
+
+    /// Creates an account on the server and sets up an empty remote vault. The server has to allow registration.
+    ///
+    /// # Arguments
+    ///
+    /// - `server` - the URL of the server, e.g. `https://passman.example.com`.
+    /// - `username` - the name to register.
+    /// - `master` - the master password. It's never sent to the server.
+    /// - `algorithm` - the `Algorithm` to encrypt entries with.
+    pub fn register(
+        server: &str,
+        username: &str,
+        master: &str,
+        algorithm: Algorithm,
+    ) -> Result<Self, BackendError> {
+        let remote = RemoteVault::new(server, username, master, algorithm);
+        let body = serde_json::json!({
+            "username": username,
+            "auth_key": auth_key(master, username),
+        });
+        send(
+            remote
+                .agent
+                .post(&format!("{}/v1/register", remote.server))
+                .set("Content-Type", "application/json"),
+            Some(body.to_string().as_bytes()),
+        )?;
+
+        remote.put_settings()?;
+        Ok(remote)
+    }
+
+    /// Logs in to an existing remote vault.
+    /// Fails with `BackendError::Unauthorized` if the username or master password is wrong.
+    ///
+    /// # Arguments
+    ///
+    /// - `server` - the URL of the server, e.g. `https://passman.example.com`.
+    /// - `username` - the user's name on the server.
+    /// - `master` - the master password. It's never sent to the server.
+    pub fn connect(server: &str, username: &str, master: &str) -> Result<Self, BackendError> {
+        let mut remote = RemoteVault::new(server, username, master, Algorithm::default());
+        let blob = found(send(remote.request("GET", "/v1/vault"), None)?)
+            .map(read_body)
+            .transpose()?
+            .ok_or_else(|| {
+                BackendError::InvalidResponse("the remote vault was never set up".to_string())
+            })?;
+        let envelope = Envelope::from_bytes(&blob)?;
+        // vaults set up before the blob had its own key have it encrypted with the master record's key
+        let (settings, legacy) = match remote.settings_cipher().decrypt(&envelope) {
+            Ok(settings) => (settings, false),
+            Err(_) => (
+                gen_cipher(remote.algorithm, master, MASTER_KEYWORD).decrypt(&envelope)?,
+                true,
+            ),
+        };
+        let settings: VaultSettings = serde_json::from_slice(&settings)
+            .map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        remote.algorithm = Algorithm::try_from(settings.algorithm)?;
+        if legacy {
+            remote.put_settings()?;
+        }
+        Ok(remote)
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        self.agent
+            .request(method, &format!("{}{}", self.server, path))
+            .set("Authorization", &self.authorization)
+    }
+    /// The id the server knows an entry by: a hex encoded HMAC of its name, so the server can't tell what the name is.
+    fn entry_id(&self, name: &str) -> String {
+        let mut id = <Hmac<Sha256> as Mac>::new_from_slice(&self.id_key)
+            .expect("HMAC takes keys of any length");
+        id.update(name.as_bytes());
+        hex::encode(id.finalize().into_bytes())
+    }
+    fn entry_path(&self, name: &str) -> String {
+        format!("/v1/entries/{}", self.entry_id(name))
+    }
+    /// Decrypts the name of a listed entry, and checks that it's the name the entry's id was derived from.
+    /// Returns `None` for entries uploaded before names were encrypted, which can't be read under their old ids.
+    fn entry_name(&self, entry: &ListedEntry) -> Result<Option<String>, BackendError> {
+        if entry.label.is_empty() {
+            return Ok(None);
+        }
+        let label = STANDARD
+            .decode(&entry.label)
+            .map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        let name =
+            Cipher::new(self.algorithm, self.name_key).decrypt(&Envelope::from_bytes(&label)?)?;
+        let name =
+            String::from_utf8(name).map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        // the server could otherwise list one entry under another's name
+        if self.entry_id(&name) != entry.id {
+            return Err(BackendError::InvalidResponse(format!(
+                "the entry listed as \"{}\" has a different id",
+                name
+            )));
+        }
+        Ok(Some(name))
+    }
+    /// The cipher for the vault blob.
+    fn settings_cipher(&self) -> Cipher {
+        Cipher::new(
+            self.algorithm,
+            derive_subkey(&self.master, SETTINGS_CONTEXT),
+        )
+    }
+    /// Encrypts the `VaultSettings` and uploads them as the vault blob.
+    fn put_settings(&self) -> Result<(), BackendError> {
+        let settings = serde_json::to_vec(&VaultSettings {
+            algorithm: self.algorithm.id(),
+        })
+        .map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        let blob = self.settings_cipher().encrypt(settings)?.to_bytes();
+        send(self.request("PUT", "/v1/vault"), Some(&blob))?;
+        Ok(())
+    }
+
+    /// Lists the entries stored on the server, sorted by name.
+    pub fn list(&self) -> Result<Vec<RemoteEntry>, BackendError> {
+        let mut entries = self.list_all()?;
+        entries.retain(|entry| !entry.deleted);
+        Ok(entries)
+    }
+    /// Lists the entries stored on the server, including deleted ones.
+    fn list_all(&self) -> Result<Vec<RemoteEntry>, BackendError> {
+        let response = found(send(self.request("GET", "/v1/entries"), None)?)
+            .ok_or_else(|| BackendError::InvalidResponse("no entry list".to_string()))?;
+        let listed: Vec<ListedEntry> = serde_json::from_slice(&read_body(response)?)
+            .map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        let mut entries = Vec::with_capacity(listed.len());
+        for entry in listed {
+            if let Some(name) = self.entry_name(&entry)? {
+                entries.push(RemoteEntry {
+                    name,
+                    revision: entry.revision,
+                    updated_at: entry.updated_at,
+                    deleted: entry.deleted,
+                });
+            }
+        }
+        // the server sorts by id
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+    /// Downloads and decrypts an entry, or returns `None` if the server has no entry with that name.
+    pub fn get(&self, name: &str) -> Result<Option<PasswordInfo>, BackendError> {
+        Ok(self.fetch(name)?.0.map(|entry| entry.password))
+    }
+    /// Downloads and decrypts an entry with its field revisions. Also returns the entry's revision on the server,
+    /// which is known even if the entry was deleted, and 0 if it never existed.
+    fn fetch(&self, name: &str) -> Result<(Option<SyncEntry>, u64), BackendError> {
+        let response = send(self.request("GET", &self.entry_path(name)), None)?;
+        let revision = response
+            .header("X-Revision")
+            .and_then(|revision| revision.parse().ok())
+            .unwrap_or(0);
+        let Some(response) = found(response) else {
+            return Ok((None, revision));
+        };
+        let envelope = Envelope::from_bytes(&read_body(response)?)?;
+        let decrypted = gen_cipher(self.algorithm, &self.master, name).decrypt(&envelope)?;
+        let (password, field_revisions) = match serde_json::from_slice(&decrypted)
+            .map_err(|e| BackendError::InvalidResponse(e.to_string()))?
+        {
+            Payload::Record(record) => (record.entry, record.field_revisions),
+            Payload::Legacy(password) => (password, BTreeMap::new()),
+        };
+        // the name is the only thing the server could have swapped without breaking the encryption
+        if password.name != name {
+            return Err(BackendError::InvalidResponse(format!(
+                "asked for \"{}\" but got \"{}\"",
+                name, password.name
+            )));
+        }
+        Ok((
+            Some(SyncEntry {
+                password,
+                field_revisions,
+            }),
+            revision,
+        ))
+    }
+    /// Encrypts and uploads a whole entry, replacing any copy on the server. Returns the entry's new revision.
+    /// Nothing is uploaded if the server's copy is the same already.
+    pub fn put(&self, password: &PasswordInfo) -> Result<u64, BackendError> {
+        self.upload(password, None)
+    }
+    /// Uploads an entry, recording which fields changed compared to the server's copy.
+    /// The upload fails with `BackendError::ChangedDuringSync` if the entry's revision on the server isn't `expected_revision`,
+    /// or if the entry is written by another device between reading and writing it.
+    fn upload(
+        &self,
+        password: &PasswordInfo,
+        expected_revision: Option<u64>,
+    ) -> Result<u64, BackendError> {
+        let name = password.name.as_str();
+        let (current, revision) = self.fetch(name)?;
+        if expected_revision.is_some_and(|expected| expected != revision) {
+            return Err(BackendError::ChangedDuringSync(name.to_string()));
+        }
+        let Some(field_revisions) = next_field_revisions(current.as_ref(), password, revision + 1)
+        else {
+            return Ok(revision);
+        };
+
+        let plaintext = serde_json::to_vec(&RemoteRecord {
+            entry: password.clone(),
+            field_revisions,
+        })
+        .map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+        let blob = gen_cipher(self.algorithm, &self.master, name)
+            .encrypt(plaintext)?
+            .to_bytes();
+        let label = Cipher::new(self.algorithm, self.name_key)
+            .encrypt(name)?
+            .to_bytes();
+        let request = self
+            .request("PUT", &self.entry_path(name))
+            .set("X-Expected-Revision", &revision.to_string())
+            .set("X-Label", &STANDARD.encode(label));
+        let response = found(changed_during_sync(send(request, Some(&blob)), name)?)
+            .ok_or_else(|| BackendError::InvalidResponse("no revision".to_string()))?;
+        read_revision(response)
+    }
+    /// Deletes an entry from the server, returning whether it existed.
+    pub fn delete(&self, name: &str) -> Result<bool, BackendError> {
+        Ok(found(send(self.request("DELETE", &self.entry_path(name)), None)?).is_some())
+    }
+
+    /// Uploads every password in a local vault, replacing the server's copies. Entries that only exist on the server are kept.
+    /// Returns the number of uploaded passwords.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    ///   The local vault has to use the same master password as the remote one.
+    ///
+    pub fn push(&self, connection: &Connection) -> Result<usize, BackendError> {
+        let passwords = list_password_info(connection, &self.master)?;
+        for password in &passwords {
+            self.put(password)?;
+        }
+        Ok(passwords.len())
+    }
+    /// Downloads every entry on the server into a local vault, replacing local passwords with the same name.
+    /// Passwords that only exist locally are kept. Returns the number of downloaded passwords.
+    ///
+    /// Everything is downloaded and decrypted before the local vault is touched, so a failed download changes nothing,
+    /// and the passwords are written in a single transaction. The local vault is snapshotted first.
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    ///   The local vault has to use the same master password as the remote one.
+    ///
+    pub fn pull(&self, connection: &Connection) -> Result<usize, BackendError> {
+        let mut passwords = Vec::new();
+        for entry in self.list()? {
+            if entry.name == MASTER_KEYWORD {
+                continue;
+            }
+            if let Some(mut password) = self.get(&entry.name)? {
+                // custom field ids belong to the vault the entry was pushed from
+                for field in &mut password.fields {
+                    field.id = 0;
+                }
+                passwords.push(password);
+            }
+        }
+        // fail before taking a snapshot if the local vault can't be written to
+        get_vault_algorithm(connection)?;
+        take_snapshot(connection, "pull")?;
+        with_transaction(&SqliteStore::new(connection, &self.master), |store| {
+            for password in &passwords {
+                write_password(store, password, &self.master)?;
+            }
+            Ok(passwords.len())
+        })
+    }
+}
+
+impl Replica for RemoteVault {
+    fn id(&self) -> Result<String, BackendError> {
+        Ok(format!("{}@{}", self.username, self.server))
+    }
+    fn revisions(&self) -> Result<BTreeMap<String, EntryRevision>, BackendError> {
+        Ok(self
+            .list_all()?
+            .into_iter()
+            .map(|entry| {
+                let revision = EntryRevision {
+                    revision: entry.revision,
+                    deleted: entry.deleted,
+                };
+                (entry.name, revision)
+            })
+            .collect())
+    }
+    fn read(&self, name: &str) -> Result<Option<SyncEntry>, BackendError> {
+        Ok(self.fetch(name)?.0)
+    }
+    fn write(&self, password: &PasswordInfo, expected_revision: u64) -> Result<u64, BackendError> {
+        self.upload(password, Some(expected_revision))
+    }
+    fn remove(&self, name: &str, expected_revision: u64) -> Result<u64, BackendError> {
+        let request = self
+            .request("DELETE", &self.entry_path(name))
+            .set("X-Expected-Revision", &expected_revision.to_string());
+        // a 404 means it was deleted in the meantime
+        let response = found(changed_during_sync(send(request, None), name)?)
+            .ok_or_else(|| BackendError::ChangedDuringSync(name.to_string()))?;
+        read_revision(response)
+    }
+}
+
+/// Sends a request, with `body` if there is one.
+/// Responses with 404 Not Found are returned like successful ones, since they may still have useful headers; see `found()`.
+fn send(request: ureq::Request, body: Option<&[u8]>) -> Result<ureq::Response, BackendError> {
+    let result = match body {
+        Some(body) => request.send_bytes(body),
+        None => request.call(),
+    };
+    match result {
+        Ok(response) => Ok(response),
+        Err(ureq::Error::Status(404, response)) => Ok(response),
+        Err(ureq::Error::Status(401, _)) => Err(BackendError::Unauthorized),
+        Err(ureq::Error::Status(status, response)) => {
+            // the server explains errors as `{ "error": ... }`
+            let message = read_body(response)
+                .ok()
+                .and_then(|body| serde_json::from_slice::<serde_json::Value>(&body).ok())
+                .and_then(|body| body["error"].as_str().map(str::to_string))
+                .unwrap_or_else(|| "no details".to_string());
+            Err(BackendError::ServerError { status, message })
+        }
+        Err(ureq::Error::Transport(transport)) => {
+            Err(BackendError::ServerUnreachable(transport.to_string()))
+        }
+    }
+}
+
+/// Returns `None` for a 404 Not Found response.
+fn found(response: ureq::Response) -> Option<ureq::Response> {
+    (response.status() != 404).then_some(response)
+}
+
+/// Turns a 409 Conflict, which the server responds with if an entry's `X-Expected-Revision` is outdated, into `BackendError::ChangedDuringSync`.
+fn changed_during_sync(
+    result: Result<ureq::Response, BackendError>,
+    name: &str,
+) -> Result<ureq::Response, BackendError> {
+    match result {
+        Err(BackendError::ServerError { status: 409, .. }) => {
+            Err(BackendError::ChangedDuringSync(name.to_string()))
+        }
+        result => result,
+    }
+}
+
+fn read_revision(response: ureq::Response) -> Result<u64, BackendError> {
+    let revision: Revision = serde_json::from_slice(&read_body(response)?)
+        .map_err(|e| BackendError::InvalidResponse(e.to_string()))?;
+    Ok(revision.revision)
+}
+
+fn read_body(response: ureq::Response) -> Result<Vec<u8>, BackendError> {
+    let mut body = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_RESPONSE_SIZE)
+        .read_to_end(&mut body)
+        .map_err(|e| BackendError::ServerUnreachable(e.to_string()))?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::auth_key;
+    use crate::crypto::derive_key;
+
+    #[test]
+    fn auth_keys() {
+        let key = auth_key("master", "alice");
+        assert_eq!(key.len(), 64);
+        assert_ne!(key, auth_key("master", "bob"));
+        assert_ne!(key, auth_key("other", "alice"));
+        // an entry named after the context still gets a different key
+        assert_ne!(
+            key,
+            hex::encode(derive_key("master", "passman-server:alice"))
+        );
+    }
+}
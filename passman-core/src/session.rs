@@ -0,0 +1,203 @@
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use zeroize::Zeroize;
+
+/// How long an unlocked session may sit idle before it locks itself, unless configured otherwise.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How often `watch_idle()` looks at a locked session, so one that's unlocked again is watched from then on.
+const LOCKED_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An unlocked vault, holding the master password for as long as it's being used.
+/// Once it's been idle for longer than its timeout, the session locks itself and forgets the master password,
+/// so the user has to authenticate again.
+///
+/// Every method takes the current time as an `Instant`, so frontends can share this and tests don't need to sleep.
+pub struct Session {
+    master: Option<String>,
+    last_active: Instant,
+    idle_timeout: Duration,
+}
+
+impl Session {
+    /// Starts an unlocked session.
+    ///
+    /// # Arguments
+    ///
+    /// - `master` - the master password. It should be verified/authenticated by the time this function is called.
+    /// - `idle_timeout` - how long the session may be idle before it locks.
+    /// - `now` - the current time.
+    pub fn new(master: String, idle_timeout: Duration, now: Instant) -> Self {
+        Session {
+            master: Some(master),
+            last_active: now,
+            idle_timeout,
+        }
+    }
+    /// Returns the master password if the session is unlocked, and counts this as activity.
+    /// If the session has been idle for too long, it's locked first and `None` is returned.
+    pub fn master(&mut self, now: Instant) -> Option<&str> {
+        if self.is_idle(now) {
+            self.lock();
+        }
+        if self.master.is_some() {
+            self.last_active = now;
+        }
+        self.master.as_deref()
+    }
+    /// Counts as activity without reading the master password, e.g. while the user is busy with a prompt.
+    /// Does nothing if the session is locked.
+    pub fn touch(&mut self, now: Instant) {
+        if self.master.is_some() {
+            self.last_active = now;
+        }
+    }
+    /// Whether the session has been idle for longer than its timeout.
+    pub fn is_idle(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_active) > self.idle_timeout
+    }
+    /// Locks the session right away, wiping the master password from memory.
+    pub fn lock(&mut self) {
+        if let Some(mut master) = self.master.take() {
+            master.zeroize();
+        }
+    }
+    /// Unlocks the session again after the user re-authenticated.
+    ///
+    /// # Arguments
+    ///
+    /// - `master` - the master password. It should be verified/authenticated by the time this function is called.
+    /// - `now` - the current time.
+    pub fn unlock(&mut self, master: String, now: Instant) {
+        self.lock();
+        self.master = Some(master);
+        self.last_active = now;
+    }
+    /// How long the session may be idle before it locks.
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+    /// How long until the session goes idle. If it's already locked, it may be unlocked at any time, so this is only
+    /// as long as `LOCKED_CHECK_INTERVAL` (or the timeout, if that's shorter).
+    fn until_idle(&self, now: Instant) -> Duration {
+        if self.master.is_none() {
+            return self.idle_timeout.min(LOCKED_CHECK_INTERVAL);
+        }
+        self.idle_timeout
+            .saturating_sub(now.saturating_duration_since(self.last_active))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.lock();
+    }
+}
+
+/// Starts a background thread that locks `session` as soon as it goes idle, even while the frontend is blocked,
+/// e.g. on a prompt waiting for input. The thread stops once every other reference to the session is dropped.
+///
+/// # Arguments
+///
+/// - `session` - the shared `Session` to watch.
+pub fn watch_idle(session: &Arc<Mutex<Session>>) -> JoinHandle<()> {
+    let session = Arc::downgrade(session);
+    thread::spawn(move || loop {
+        let wait = {
+            let Some(session) = session.upgrade() else {
+                return;
+            };
+            let mut session = session.lock().unwrap_or_else(PoisonError::into_inner);
+            let now = Instant::now();
+            if session.is_idle(now) {
+                session.lock();
+            }
+            session.until_idle(now)
+        };
+        // is_idle() only counts time past the timeout, so wake up just after it
+        thread::sleep(wait + Duration::from_millis(1));
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{watch_idle, Session, DEFAULT_IDLE_TIMEOUT, LOCKED_CHECK_INTERVAL};
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn idle_lock() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut session = Session::new("master".to_string(), timeout, start);
+
+        assert_eq!(session.master(start + timeout), Some("master"));
+        // using the session resets the idle timer
+        let later = start + timeout * 2;
+        assert!(!session.is_idle(later));
+        assert_eq!(session.master(later), Some("master"));
+
+        let idle = later + timeout + Duration::from_secs(1);
+        assert!(session.is_idle(idle));
+        assert_eq!(session.master(idle), None);
+        // once locked, it stays locked
+        assert_eq!(session.master(idle), None);
+
+        session.touch(idle);
+        assert_eq!(session.master(idle), None);
+
+        session.unlock("master".to_string(), idle);
+        session.touch(idle + timeout);
+        assert_eq!(session.master(idle + timeout * 2), Some("master"));
+        assert_eq!(session.master(idle), Some("master"));
+        session.lock();
+        assert_eq!(session.master(idle + timeout * 2), None);
+    }
+    #[test]
+    fn idle_menu() {
+        // nothing calls master() while a menu is waiting for input, so the watcher has to lock the session by itself
+        let timeout = Duration::from_millis(50);
+        let session = Arc::new(Mutex::new(Session::new(
+            "master".to_string(),
+            timeout,
+            Instant::now(),
+        )));
+        let watcher = watch_idle(&session);
+
+        thread::sleep(timeout * 4);
+        assert!(session.lock().unwrap().master.is_none());
+
+        // a session that was unlocked again is still watched
+        session
+            .lock()
+            .unwrap()
+            .unlock("master".to_string(), Instant::now());
+        assert!(session.lock().unwrap().master.is_some());
+        thread::sleep(timeout * 4);
+        assert!(session.lock().unwrap().master.is_none());
+
+        drop(session);
+        watcher.join().unwrap();
+    }
+    #[test]
+    fn locked_wait() {
+        let now = Instant::now();
+        let mut session = Session::new("master".to_string(), DEFAULT_IDLE_TIMEOUT, now);
+        assert_eq!(session.until_idle(now), DEFAULT_IDLE_TIMEOUT);
+
+        // a locked session could be unlocked right after, so the watcher mustn't sleep through a whole timeout
+        session.lock();
+        assert_eq!(session.until_idle(now), LOCKED_CHECK_INTERVAL);
+        session.unlock("master".to_string(), now + LOCKED_CHECK_INTERVAL);
+        assert_eq!(
+            session.until_idle(now + LOCKED_CHECK_INTERVAL),
+            DEFAULT_IDLE_TIMEOUT
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Handing a single password to someone without giving them a vault.
+//!
+//! A share is a small JSON file holding one decrypted `PasswordInfo`, encrypted with `sodiumoxide::crypto::box_` from the
+//! sender's `Identity` to the recipient's public key. Only the recipient can open it, and it records the sender's public key,
+//! which the box authenticates, so the recipient can check who it came from.
+//!
+//! A share may have an expiry date, after which `open_share()` refuses it. The expiry is only enforced by passman:
+//! it can't stop the recipient from keeping the password once they've opened the share.
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sodiumoxide::crypto::box_::{self, Nonce, PublicKey};
+
+use crate::{
+    backup::take_snapshot,
+    error::BackendError,
+    password::{EntryField, PasswordInfo, Timestamps},
+    store::{with_transaction, write_password, SqliteStore},
+    team::{parse_public_key, Identity},
+};
+
+/// The version of the share file format written by this version of passman.
+pub const SHARE_VERSION: u8 = 1;
+
+/// The file written by `create_share()`.
+#[derive(Serialize, Deserialize)]
+struct ShareFile {
+    version: u8,
+    /// The sender's public key, hex encoded.
+    from: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// What's inside the box.
+#[derive(Serialize, Deserialize)]
+struct ShareContents {
+    password: PasswordInfo,
+    shared_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// A share opened with `open_share()`.
+#[derive(Debug)]
+pub struct ReceivedPassword {
+    pub password: PasswordInfo,
+    /// The sender's public key, hex encoded.
+    pub from: String,
+    pub shared_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Encrypts a password for someone else's public key, returning the contents of the share file.
+///
+/// # Arguments
+///
+/// - `password` - the decrypted password to share. Its `id` and timestamps aren't shared.
+/// - `sender` - the sender's `Identity`.
+/// - `recipient` - the recipient's public key, hex encoded, as printed by `passman identity`.
+/// - `expires_at` - when the share stops being accepted, if ever.
+pub fn create_share(
+    password: &PasswordInfo,
+    sender: &Identity,
+    recipient: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<Vec<u8>, BackendError> {
+    let recipient = parse_public_key(recipient)?;
+    let mut password = password.clone();
+    password.id = 0;
+    password.timestamps = Default::default();
+    for field in &mut password.fields {
+        field.id = 0;
+    }
+    let contents = serde_json::to_vec(&ShareContents {
+        password,
+        shared_at: Utc::now(),
+        expires_at,
+    })
+    .map_err(|e| BackendError::InvalidShare(e.to_string()))?;
+
+    let nonce = box_::gen_nonce();
+    let ciphertext = box_::seal(&contents, &nonce, &recipient, &sender.secret_key);
+    serde_json::to_vec_pretty(&ShareFile {
+        version: SHARE_VERSION,
+        from: sender.public_key_hex(),
+        nonce: hex::encode(nonce.0),
+        ciphertext: hex::encode(ciphertext),
+    })
+    .map_err(|e| BackendError::InvalidShare(e.to_string()))
+}
+
+/// Decrypts a share file with the recipient's identity.
+/// Fails with `BackendError::ShareExpired` if it expired before `now`, and `BackendError::AesError` if it wasn't meant for
+/// this identity or was tampered with.
+///
+/// # Arguments
+///
+/// - `data` - the contents of the share file.
+/// - `recipient` - the recipient's `Identity`.
+/// - `now` - the current time.
+pub fn open_share(
+    data: &[u8],
+    recipient: &Identity,
+    now: DateTime<Utc>,
+) -> Result<ReceivedPassword, BackendError> {
+    let invalid = |reason: &str| BackendError::InvalidShare(reason.to_string());
+    let file: ShareFile = serde_json::from_slice(data).map_err(|_| invalid("it isn't JSON"))?;
+    if file.version != SHARE_VERSION {
+        return Err(invalid(&format!("unsupported version {}", file.version)));
+    }
+    let from: PublicKey = parse_public_key(&file.from)?;
+    let nonce = hex::decode(&file.nonce)
+        .ok()
+        .and_then(|nonce| Nonce::from_slice(&nonce))
+        .ok_or_else(|| invalid("invalid nonce"))?;
+    let contents = box_::open(
+        &hex::decode(&file.ciphertext)?,
+        &nonce,
+        &from,
+        &recipient.secret_key,
+    )
+    .map_err(|_| BackendError::AesError)?;
+    let contents: ShareContents =
+        serde_json::from_slice(&contents).map_err(|e| invalid(&e.to_string()))?;
+
+    if let Some(expires_at) = contents.expires_at.filter(|&expires_at| expires_at <= now) {
+        return Err(BackendError::ShareExpired(expires_at));
+    }
+    Ok(ReceivedPassword {
+        password: contents.password,
+        from: file.from,
+        shared_at: contents.shared_at,
+        expires_at: contents.expires_at,
+    })
+}
+
+/// Imports a received password into a vault under `password.name`, through `store::write_password()`.
+/// All changes are made in a single transaction, after the vault is snapshotted. Returns the number of fields written, including custom fields.
+/// Fails with `BackendError::NameTaken` rather than overwrite an existing password.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `password` - the password to import, e.g. from `open_share()`. Its name may be changed first.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub fn import_shared_password(
+    connection: &Connection,
+    password: &PasswordInfo,
+    master: &str,
+) -> Result<usize, BackendError> {
+    let name = password.name.as_str();
+    take_snapshot(connection, "import")?;
+    let store = SqliteStore::new(connection, master);
+    with_transaction(&store, |store| {
+        if store.get(name)?.is_some() {
+            return Err(BackendError::NameTaken(name.to_string()));
+        }
+
+        // ids and timestamps belong to the sender's vault
+        let imported = PasswordInfo {
+            id: 0,
+            fields: password
+                .fields
+                .iter()
+                .map(|field| EntryField {
+                    key: field.key.clone(),
+                    value: field.value.clone(),
+                    sensitive: field.sensitive,
+                    ..Default::default()
+                })
+                .collect(),
+            timestamps: Timestamps::default(),
+            ..password.clone()
+        };
+        // a password with nothing but a name is still imported
+        write_password(store, &imported, master)?;
+
+        let written = [
+            &password.email,
+            &password.username,
+            &password.password,
+            &password.notes,
+            &password.folder,
+        ]
+        .iter()
+        .filter(|value| value.is_some())
+        .count()
+            + [&password.urls, &password.tags]
+                .iter()
+                .filter(|values| !values.is_empty())
+                .count()
+            + password.fields.len();
+        Ok(written)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_share, import_shared_password, open_share};
+    use crate::{
+        db_ops::{
+            crud::read_password_info,
+            util::{create_table, insert_master_record},
+        },
+        envelope::Algorithm,
+        error::BackendError,
+        password::{EntryField, PasswordInfo},
+        team::Identity,
+    };
+    use chrono::{Duration, Utc};
+    use rusqlite::Connection;
+
+    fn password() -> PasswordInfo {
+        PasswordInfo {
+            id: 7,
+            name: "wifi".to_string(),
+            username: Some("guest".to_string()),
+            password: Some("hunter2".to_string()),
+            tags: vec!["office".to_string()],
+            fields: vec![EntryField {
+                id: 3,
+                key: "PIN".to_string(),
+                value: "0000".to_string(),
+                sensitive: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn share() {
+        crate::init().unwrap();
+        let (alice, bob, eve) = (
+            Identity::generate(),
+            Identity::generate(),
+            Identity::generate(),
+        );
+        let now = Utc::now();
+        let file = create_share(
+            &password(),
+            &alice,
+            &bob.public_key_hex(),
+            Some(now + Duration::days(1)),
+        )
+        .unwrap();
+        // nothing is readable without the recipient's key
+        assert!(!String::from_utf8_lossy(&file).contains("hunter2"));
+
+        let received = open_share(&file, &bob, now).unwrap();
+        assert_eq!(received.from, alice.public_key_hex());
+        assert_eq!(received.password.password.as_deref(), Some("hunter2"));
+        assert_eq!(received.password.id, 0);
+        assert_eq!(received.password.fields[0].id, 0);
+
+        assert!(matches!(
+            open_share(&file, &eve, now),
+            Err(BackendError::AesError)
+        ));
+        assert!(matches!(
+            open_share(&file, &bob, now + Duration::days(2)),
+            Err(BackendError::ShareExpired(_))
+        ));
+        assert!(matches!(
+            open_share(b"{}", &bob, now),
+            Err(BackendError::InvalidShare(_))
+        ));
+        assert!(matches!(
+            create_share(&password(), &alice, "abc", None),
+            Err(BackendError::InvalidPublicKey)
+        ));
+    }
+    #[test]
+    fn import() {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+
+        assert_eq!(
+            import_shared_password(&connection, &password(), "master").unwrap(),
+            4
+        );
+        let wifi = read_password_info(&connection, "wifi", "master")
+            .unwrap()
+            .unwrap();
+        assert_eq!(wifi.username.unwrap(), "guest");
+        assert_eq!(wifi.password.unwrap(), "hunter2");
+        assert_eq!(wifi.tags, ["office"]);
+        assert_eq!(wifi.fields[0].value, "0000");
+        assert!(wifi.fields[0].sensitive);
+
+        assert!(matches!(
+            import_shared_password(&connection, &password(), "master"),
+            Err(BackendError::NameTaken(_))
+        ));
+    }
+}
@@ -0,0 +1,798 @@
+//! Where a vault's passwords are kept, behind the `VaultStore` trait, so frontends aren't tied to SQLite.
+//!
+//! A store holds `EncryptedPasswordInfo`s by name: it never sees plaintext, and the functions in this module do the
+//! encryption on top of any store. There are three stores:
+//!
+//! - `SqliteStore`, the vault file every frontend uses, with the schema from `db_ops`.
+//! - `MemoryStore`, which only lives in memory, e.g. for tests.
+//! - `FileStore`, a single encrypted file holding every password, which also hides their names and timestamps.
+//!
+//! `db_ops::crud` reads and writes passwords through `SqliteStore`, which keeps what's specific to the SQLite schema to
+//! itself: revisions and tombstones for `sync`, and resealing the manifest once its outermost transaction is done.
+//! Syncing with other kinds of storage goes through `sync::Replica`.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    crypto::{decrypt_password_field, derive_key, Cipher},
+    db_ops::{
+        crud::{
+            decrypt_password_info, delete_password_rows, encrypt_password_info,
+            get_all_password_info, get_password_info, join_list, put_password_info,
+        },
+        util::get_vault_algorithm,
+        CUSTOM_FIELDS_KEY, EXPIRY_KEY, MASTER_KEYWORD,
+    },
+    envelope::{Algorithm, Envelope},
+    error::BackendError,
+    manifest::{ensure_manifest, seal_manifest},
+    password::{EncryptedPasswordInfo, PasswordField, PasswordInfo},
+};
+
+/// Storage for encrypted passwords, identified by name.
+pub trait VaultStore {
+    /// The `Algorithm` new data is encrypted with.
+    fn algorithm(&self) -> Result<Algorithm, BackendError>;
+    /// Reads a password, or returns `None` if there is no password with that name.
+    fn get(&self, name: &str) -> Result<Option<EncryptedPasswordInfo>, BackendError>;
+    /// Writes a password as a whole, creating it if it doesn't exist yet. Returns the password's id.
+    /// `password.id` is ignored, since the store assigns ids. Custom field ids are kept unless another password uses them.
+    fn put(&self, password: &EncryptedPasswordInfo) -> Result<i32, BackendError>;
+    /// Deletes a password, returning whether it existed.
+    fn delete(&self, name: &str) -> Result<bool, BackendError>;
+    /// Reads every password, sorted by name.
+    fn list(&self) -> Result<Vec<EncryptedPasswordInfo>, BackendError>;
+    /// Runs `f`, keeping everything it changes only if it succeeds. Transactions may be nested, and a nested one that fails
+    /// only undoes its own changes.
+    /// `f` gets the store back as a `&dyn VaultStore`; use `with_transaction()` to return a value from it.
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn VaultStore) -> Result<(), BackendError>,
+    ) -> Result<(), BackendError>;
+}
+
+/// Runs `f` in a transaction on any store (see `VaultStore::transaction()`), returning what `f` returns.
+/// # Arguments
+///
+/// - `store` - the `VaultStore` to run the transaction on.
+/// - `f` - the changes to make, which get the store back as a `&dyn VaultStore`.
+///
+pub fn with_transaction<S: VaultStore + ?Sized, T>(
+    store: &S,
+    f: impl FnOnce(&dyn VaultStore) -> Result<T, BackendError>,
+) -> Result<T, BackendError> {
+    let mut f = Some(f);
+    let mut result = None;
+    store.transaction(&mut |store| {
+        let f = f.take().expect("a transaction runs its changes once");
+        result = Some(f(store)?);
+        Ok(())
+    })?;
+    Ok(result.expect("the transaction succeeded"))
+}
+
+/// The passwords in a vault file, read and written with the functions in `db_ops`.
+/// Revisions and tombstones are kept up to date on every write. The manifest is checked when the outermost transaction
+/// starts, failing with `BackendError::ManifestMismatch` if the vault doesn't match it, and resealed when it ends, so a
+/// change made through several puts and deletes is sealed once. Nested transactions are savepoints.
+pub struct SqliteStore<'a> {
+    connection: &'a Connection,
+    master: &'a str,
+    /// The master password the manifest is checked with before the first write. Only differs from `master` while rekeying.
+    current_master: &'a str,
+    /// How many transactions are open; the manifest is only resealed when the outermost one ends.
+    depth: Cell<u32>,
+}
+
+impl<'a> SqliteStore<'a> {
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the master password, which the vault's manifest is resealed with after every write
+    ///   (see `manifest`). The master password should be verified/authenticated by the time this function is called.
+    pub fn new(connection: &'a Connection, master: &'a str) -> Self {
+        SqliteStore {
+            connection,
+            master,
+            current_master: master,
+            depth: Cell::new(0),
+        }
+    }
+    /// A store for re-encrypting the vault with a new master password: the manifest is checked with the current one, and
+    /// resealed with the new one.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the current master password.
+    /// - `new_master` - a string slice holding the new master password.
+    pub(crate) fn rekeying(
+        connection: &'a Connection,
+        master: &'a str,
+        new_master: &'a str,
+    ) -> Self {
+        SqliteStore {
+            connection,
+            master: new_master,
+            current_master: master,
+            depth: Cell::new(0),
+        }
+    }
+}
+
+impl VaultStore for SqliteStore<'_> {
+    fn algorithm(&self) -> Result<Algorithm, BackendError> {
+        get_vault_algorithm(self.connection)
+    }
+    fn get(&self, name: &str) -> Result<Option<EncryptedPasswordInfo>, BackendError> {
+        if name == MASTER_KEYWORD {
+            return Ok(None);
+        }
+        Ok(get_password_info(self.connection, name)?)
+    }
+    fn put(&self, password: &EncryptedPasswordInfo) -> Result<i32, BackendError> {
+        if password.name == MASTER_KEYWORD {
+            return Err(BackendError::NameTaken(password.name.clone()));
+        }
+        with_transaction(self, |_| put_password_info(self.connection, password))
+    }
+    fn delete(&self, name: &str) -> Result<bool, BackendError> {
+        if name == MASTER_KEYWORD {
+            return Ok(false);
+        }
+        with_transaction(self, |_| {
+            Ok(delete_password_rows(self.connection, name)? > 0)
+        })
+    }
+    fn list(&self) -> Result<Vec<EncryptedPasswordInfo>, BackendError> {
+        Ok(get_all_password_info(self.connection)?)
+    }
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn VaultStore) -> Result<(), BackendError>,
+    ) -> Result<(), BackendError> {
+        if self.depth.get() > 0 {
+            return self.savepoint(f);
+        }
+        // reuse a transaction the caller opened on the connection, e.g. to take part in a bigger change
+        let transaction = match self.connection.is_autocommit() {
+            true => Some(self.connection.unchecked_transaction()?),
+            false => None,
+        };
+        ensure_manifest(self.connection, self.current_master)?;
+        // a savepoint, so a failure in a transaction the caller opened doesn't leave half of the change behind
+        self.savepoint(f)?;
+        seal_manifest(self.connection, self.master)?;
+        if let Some(transaction) = transaction {
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl SqliteStore<'_> {
+    /// Runs `f` inside a savepoint named after the transaction's depth, rolling back to it if `f` fails.
+    fn savepoint(
+        &self,
+        f: &mut dyn FnMut(&dyn VaultStore) -> Result<(), BackendError>,
+    ) -> Result<(), BackendError> {
+        let depth = self.depth.get() + 1;
+        let name = format!("vault_store_{depth}");
+        self.connection
+            .execute_batch(&format!("savepoint {name}"))?;
+        self.depth.set(depth);
+        let result = f(self);
+        self.depth.set(depth - 1);
+        match result {
+            Ok(()) => {
+                self.connection.execute_batch(&format!("release {name}"))?;
+                Ok(())
+            }
+            Err(e) => {
+                self.connection
+                    .execute_batch(&format!("rollback to {name}; release {name}"))?;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Gives a password that's about to be put in `passwords` its id, and new ids to custom fields whose id is taken.
+fn assign_ids(
+    passwords: &BTreeMap<String, EncryptedPasswordInfo>,
+    password: &mut EncryptedPasswordInfo,
+) {
+    password.id = match passwords.get(&password.name) {
+        Some(existing) => existing.id,
+        None => passwords.values().map(|other| other.id).max().unwrap_or(0) + 1,
+    };
+    let mut taken = passwords
+        .values()
+        .filter(|other| other.name != password.name)
+        .flat_map(|other| other.fields.iter().map(|field| field.id))
+        .collect::<Vec<_>>();
+    let mut next_id = taken
+        .iter()
+        .chain(password.fields.iter().map(|field| &field.id))
+        .max()
+        .copied()
+        .unwrap_or(0);
+    for field in &mut password.fields {
+        if field.id == 0 || taken.contains(&field.id) {
+            next_id += 1;
+            field.id = next_id;
+        }
+        taken.push(field.id);
+    }
+}
+
+/// Passwords that only live in memory, e.g. for tests.
+pub struct MemoryStore {
+    algorithm: Algorithm,
+    passwords: RefCell<BTreeMap<String, EncryptedPasswordInfo>>,
+}
+
+impl MemoryStore {
+    /// # Arguments
+    ///
+    /// - `algorithm` - the `Algorithm` to encrypt new data with.
+    pub fn new(algorithm: Algorithm) -> Self {
+        MemoryStore {
+            algorithm,
+            passwords: RefCell::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl VaultStore for MemoryStore {
+    fn algorithm(&self) -> Result<Algorithm, BackendError> {
+        Ok(self.algorithm)
+    }
+    fn get(&self, name: &str) -> Result<Option<EncryptedPasswordInfo>, BackendError> {
+        Ok(self.passwords.borrow().get(name).cloned())
+    }
+    fn put(&self, password: &EncryptedPasswordInfo) -> Result<i32, BackendError> {
+        let mut passwords = self.passwords.borrow_mut();
+        let mut password = password.clone();
+        assign_ids(&passwords, &mut password);
+        let id = password.id;
+        passwords.insert(password.name.clone(), password);
+        Ok(id)
+    }
+    fn delete(&self, name: &str) -> Result<bool, BackendError> {
+        Ok(self.passwords.borrow_mut().remove(name).is_some())
+    }
+    fn list(&self) -> Result<Vec<EncryptedPasswordInfo>, BackendError> {
+        Ok(self.passwords.borrow().values().cloned().collect())
+    }
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn VaultStore) -> Result<(), BackendError>,
+    ) -> Result<(), BackendError> {
+        let before = self.passwords.borrow().clone();
+        f(self).inspect_err(|_| {
+            self.passwords.replace(before);
+        })
+    }
+}
+
+/// The context a `FileStore`'s key is derived with.
+const FILE_STORE_CONTEXT: &[u8] = b"passman file store v1";
+
+/// Derives the key a `FileStore`'s file is encrypted with from the master password. It's an HMAC of its own context, so it
+/// never equals the master record's key, which the manifest key is derived from.
+fn file_store_key(master: &str) -> [u8; 32] {
+    let mut key = <Hmac<Sha256> as Mac>::new_from_slice(&derive_key(master, MASTER_KEYWORD))
+        .expect("HMAC takes keys of any length");
+    key.update(FILE_STORE_CONTEXT);
+    key.finalize().into_bytes().into()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// What's encrypted in a `FileStore`'s file.
+struct StoreContents {
+    /// The id of the `Algorithm` new data is encrypted with.
+    algorithm: u8,
+    passwords: Vec<EncryptedPasswordInfo>,
+}
+
+/// Every password in a single file, encrypted as a whole on top of each field's own encryption, so not even the names
+/// and timestamps of the passwords can be read without the master password.
+/// The whole file is read when it's opened, and written again (to a temporary file that replaces it) after every change,
+/// or at the end of a transaction.
+pub struct FileStore {
+    path: PathBuf,
+    algorithm: Algorithm,
+    cipher: Cipher,
+    passwords: RefCell<BTreeMap<String, EncryptedPasswordInfo>>,
+    /// How many transactions are open; the file is only written when the outermost one ends.
+    depth: Cell<u32>,
+}
+
+impl FileStore {
+    /// Creates an empty store. Fails with `BackendError::AlreadyInitialized` if the file already exists.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - the file to create.
+    /// - `master` - the master password, which the file is encrypted with.
+    /// - `algorithm` - the `Algorithm` to encrypt with.
+    pub fn create(path: &Path, master: &str, algorithm: Algorithm) -> Result<Self, BackendError> {
+        if path.exists() {
+            return Err(BackendError::AlreadyInitialized);
+        }
+        let store = FileStore {
+            path: path.to_path_buf(),
+            algorithm,
+            cipher: Cipher::new(algorithm, file_store_key(master)),
+            passwords: RefCell::new(BTreeMap::new()),
+            depth: Cell::new(0),
+        };
+        store.save()?;
+        Ok(store)
+    }
+    /// Opens a store made by `create()`. Fails with `BackendError::AesError` if it was made with a different master password.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - the store's file.
+    /// - `master` - the master password.
+    pub fn open(path: &Path, master: &str) -> Result<Self, BackendError> {
+        let envelope = Envelope::from_bytes(&fs::read(path)?)?;
+        let key = file_store_key(master);
+        // decrypting uses the algorithm recorded in the envelope, whichever the cipher was made with
+        let contents = decrypt_password_field(&envelope, &Cipher::new(envelope.algorithm, key))?;
+        let contents: StoreContents = serde_json::from_str(&contents)
+            .map_err(|e| BackendError::InvalidStore(e.to_string()))?;
+        let algorithm = Algorithm::try_from(contents.algorithm)?;
+        Ok(FileStore {
+            path: path.to_path_buf(),
+            algorithm,
+            cipher: Cipher::new(algorithm, key),
+            passwords: RefCell::new(
+                contents
+                    .passwords
+                    .into_iter()
+                    .map(|password| (password.name.clone(), password))
+                    .collect(),
+            ),
+            depth: Cell::new(0),
+        })
+    }
+    /// The store's file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes every password to the file, unless a transaction is open.
+    fn save(&self) -> Result<(), BackendError> {
+        if self.depth.get() > 0 {
+            return Ok(());
+        }
+        let contents = serde_json::to_vec(&StoreContents {
+            algorithm: self.algorithm.id(),
+            passwords: self.passwords.borrow().values().cloned().collect(),
+        })
+        .map_err(|e| BackendError::InvalidStore(e.to_string()))?;
+        let envelope = self.cipher.encrypt(contents)?;
+        // a crash while writing leaves the old file in place
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, envelope.to_bytes())?;
+        fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+    /// Runs a change to the passwords, putting them back as they were if it (or writing the file) fails.
+    fn change<T>(
+        &self,
+        f: impl FnOnce(&mut BTreeMap<String, EncryptedPasswordInfo>) -> T,
+    ) -> Result<T, BackendError> {
+        let before = self.passwords.borrow().clone();
+        let result = f(&mut self.passwords.borrow_mut());
+        self.save().inspect_err(|_| {
+            self.passwords.replace(before);
+        })?;
+        Ok(result)
+    }
+}
+
+impl VaultStore for FileStore {
+    fn algorithm(&self) -> Result<Algorithm, BackendError> {
+        Ok(self.algorithm)
+    }
+    fn get(&self, name: &str) -> Result<Option<EncryptedPasswordInfo>, BackendError> {
+        Ok(self.passwords.borrow().get(name).cloned())
+    }
+    fn put(&self, password: &EncryptedPasswordInfo) -> Result<i32, BackendError> {
+        self.change(|passwords| {
+            let mut password = password.clone();
+            assign_ids(passwords, &mut password);
+            let id = password.id;
+            passwords.insert(password.name.clone(), password);
+            id
+        })
+    }
+    fn delete(&self, name: &str) -> Result<bool, BackendError> {
+        if !self.passwords.borrow().contains_key(name) {
+            return Ok(false);
+        }
+        self.change(|passwords| passwords.remove(name).is_some())
+    }
+    fn list(&self) -> Result<Vec<EncryptedPasswordInfo>, BackendError> {
+        Ok(self.passwords.borrow().values().cloned().collect())
+    }
+    fn transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn VaultStore) -> Result<(), BackendError>,
+    ) -> Result<(), BackendError> {
+        let before = self.passwords.borrow().clone();
+        self.depth.set(self.depth.get() + 1);
+        let result = f(self);
+        self.depth.set(self.depth.get() - 1);
+        result.and_then(|_| self.save()).inspect_err(|_| {
+            self.passwords.replace(before);
+        })
+    }
+}
+
+/// Reads and decrypts a password from any store, or returns `None` if there is no password with that name.
+/// # Arguments
+///
+/// - `store` - the `VaultStore` to read from.
+/// - `name` - a string slice that holds the name of the password.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub fn read_password<S: VaultStore + ?Sized>(
+    store: &S,
+    name: &str,
+    master: &str,
+) -> Result<Option<PasswordInfo>, BackendError> {
+    let algorithm = store.algorithm()?;
+    store
+        .get(name)?
+        .map(|encrypted| decrypt_password_info(encrypted, master, algorithm))
+        .transpose()
+}
+
+/// Reads and decrypts every password in any store, sorted by name.
+/// # Arguments
+///
+/// - `store` - the `VaultStore` to read from.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub fn list_passwords<S: VaultStore + ?Sized>(
+    store: &S,
+    master: &str,
+) -> Result<Vec<PasswordInfo>, BackendError> {
+    let algorithm = store.algorithm()?;
+    store
+        .list()?
+        .into_iter()
+        .map(|encrypted| decrypt_password_info(encrypted, master, algorithm))
+        .collect()
+}
+
+/// Encrypts and writes a password to any store as a whole, like `crud::replace_password_info()`: afterwards it matches
+/// `password` exactly. The timestamps of fields that changed are updated, so expiry reminders keep working, and a password
+/// that doesn't change at all isn't written. Returns the password's id.
+/// # Arguments
+///
+/// - `store` - the `VaultStore` to write to.
+/// - `password` - the `PasswordInfo` to write. Its `name` identifies the password, and its `id` and timestamps are ignored.
+/// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+///
+pub fn write_password<S: VaultStore + ?Sized>(
+    store: &S,
+    password: &PasswordInfo,
+    master: &str,
+) -> Result<i32, BackendError> {
+    with_transaction(store, |store| {
+        let now = Utc::now();
+        let current = read_password(store, &password.name, master)?;
+        let mut timestamps = current
+            .as_ref()
+            .map(|current| current.timestamps.clone())
+            .unwrap_or_default();
+        timestamps.created_at = timestamps.created_at.or(Some(now));
+        timestamps.updated_at = Some(now);
+
+        let exists = current.is_some();
+        let current = current.unwrap_or_default();
+        let list = |values: &[String]| Some(join_list(values)).filter(|list| !list.is_empty());
+        let value = |password: &PasswordInfo, field: PasswordField| match field {
+            PasswordField::Email => password.email.clone(),
+            PasswordField::Username => password.username.clone(),
+            PasswordField::Password => password.password.clone(),
+            PasswordField::Notes => password.notes.clone(),
+            PasswordField::Urls => list(&password.urls),
+            PasswordField::Tags => list(&password.tags),
+            PasswordField::Folder => password.folder.clone(),
+        };
+        let mut changed = false;
+        let mut touch = |field: String| {
+            timestamps.fields.insert(field, now);
+            changed = true;
+        };
+        for field in PasswordField::ALL {
+            if value(&current, field) != value(password, field) {
+                touch(field.to_string());
+            }
+        }
+        let contents = |password: &PasswordInfo| {
+            password
+                .fields
+                .iter()
+                .map(|field| (field.key.clone(), field.value.clone(), field.sensitive))
+                .collect::<Vec<_>>()
+        };
+        if contents(&current) != contents(password) {
+            touch(CUSTOM_FIELDS_KEY.to_string());
+        }
+        if current.expiry != password.expiry {
+            touch(EXPIRY_KEY.to_string());
+        }
+        if exists && !changed {
+            return Ok(current.id);
+        }
+
+        let mut password = password.clone();
+        password.timestamps = timestamps;
+        for field in &mut password.fields {
+            field.created_at = field.created_at.or(Some(now));
+            field.updated_at = field.updated_at.or(Some(now));
+        }
+        store.put(&encrypt_password_info(
+            &password,
+            master,
+            store.algorithm()?,
+        )?)
+    })
+}
+
+/// Deletes a password from any store, returning whether it existed.
+/// # Arguments
+///
+/// - `store` - the `VaultStore` to delete from.
+/// - `name` - a string slice that holds the name of the password.
+///
+pub fn delete_password<S: VaultStore + ?Sized>(
+    store: &S,
+    name: &str,
+) -> Result<bool, BackendError> {
+    store.delete(name)
+}
+
+/// Copies every password from one store to another, still encrypted, in a single transaction. Passwords that are
+/// already in `to` are replaced. Both stores have to use the same master password. Returns the number of copied passwords.
+/// # Arguments
+///
+/// - `from` - the `VaultStore` to copy from, e.g. a `SqliteStore`.
+/// - `to` - the `VaultStore` to copy to, e.g. a `FileStore`.
+///
+pub fn copy_passwords<S: VaultStore + ?Sized, T: VaultStore + ?Sized>(
+    from: &S,
+    to: &T,
+) -> Result<usize, BackendError> {
+    let passwords = from.list()?;
+    with_transaction(to, |to| {
+        for password in &passwords {
+            to.put(password)?;
+        }
+        Ok(passwords.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        copy_passwords, delete_password, list_passwords, read_password, with_transaction,
+        write_password, FileStore, MemoryStore, SqliteStore, VaultStore,
+    };
+    use crate::{
+        crypto::{decrypt_password_field, gen_cipher},
+        db_ops::{
+            crud::read_password_info,
+            util::{create_table, insert_master_record},
+            MASTER_KEYWORD,
+        },
+        envelope::{Algorithm, Envelope},
+        error::BackendError,
+        manifest::{get_manifest, verify_manifest, ManifestStatus},
+        password::{EntryField, PasswordInfo},
+    };
+    use rusqlite::Connection;
+
+    fn password(name: &str, secret: &str) -> PasswordInfo {
+        PasswordInfo {
+            name: name.to_string(),
+            password: Some(secret.to_string()),
+            tags: vec!["work".to_string()],
+            fields: vec![EntryField {
+                key: "PIN".to_string(),
+                value: "0000".to_string(),
+                sensitive: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// What every store has to do the same way, used as a trait object.
+    fn exercise(store: &dyn VaultStore) {
+        write_password(store, &password("mail", "one"), "master").unwrap();
+        write_password(store, &password("bank", "two"), "master").unwrap();
+        let mail = read_password(store, "mail", "master").unwrap().unwrap();
+        assert_eq!(mail.password.unwrap(), "one");
+        assert_eq!(mail.tags, ["work"]);
+        assert_eq!(mail.fields[0].value, "0000");
+        assert!(mail.timestamps.fields.contains_key("password"));
+
+        // rewriting keeps the id, and only touches what changed
+        let id = store.get("mail").unwrap().unwrap().id;
+        let changed_at = mail.timestamps.fields["tags"];
+        let mut changed = password("mail", "three");
+        changed.fields[0].id = mail.fields[0].id;
+        assert_eq!(write_password(store, &changed, "master").unwrap(), id);
+        let mail = read_password(store, "mail", "master").unwrap().unwrap();
+        assert_eq!(mail.password.unwrap(), "three");
+        assert_eq!(mail.timestamps.fields["tags"], changed_at);
+
+        let names = || {
+            list_passwords(store, "master")
+                .unwrap()
+                .into_iter()
+                .map(|password| password.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(), ["bank", "mail"]);
+
+        // a failed transaction changes nothing
+        let result = store.transaction(&mut |store| {
+            delete_password(store, "bank")?;
+            Err(BackendError::Locked)
+        });
+        assert!(matches!(result, Err(BackendError::Locked)));
+        assert_eq!(names(), ["bank", "mail"]);
+
+        // a failed nested transaction only undoes its own changes
+        let mut nested = None;
+        store
+            .transaction(&mut |store| {
+                write_password(store, &password("shop", "four"), "master")?;
+                nested = Some(store.transaction(&mut |store| {
+                    delete_password(store, "bank")?;
+                    Err(BackendError::Locked)
+                }));
+                Ok(())
+            })
+            .unwrap();
+        assert!(matches!(nested, Some(Err(BackendError::Locked))));
+        assert_eq!(names(), ["bank", "mail", "shop"]);
+        assert!(delete_password(store, "shop").unwrap());
+
+        assert!(delete_password(store, "bank").unwrap());
+        assert!(!delete_password(store, "bank").unwrap());
+        assert_eq!(names(), ["mail"]);
+    }
+
+    #[test]
+    fn memory() {
+        exercise(&MemoryStore::new(Algorithm::XChaCha20Poly1305));
+    }
+    #[test]
+    fn sqlite() {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        let store = SqliteStore::new(&connection, "master");
+        exercise(&store);
+
+        // what's put through the store is a normal password, and the manifest still matches
+        assert_eq!(
+            read_password_info(&connection, "mail", "master")
+                .unwrap()
+                .unwrap()
+                .password
+                .unwrap(),
+            "three"
+        );
+        assert!(matches!(
+            verify_manifest(&connection, "master").unwrap(),
+            ManifestStatus::Valid { .. }
+        ));
+        // the master record isn't a password
+        assert!(store.get(MASTER_KEYWORD).unwrap().is_none());
+
+        // the manifest is resealed once per transaction, however many writes it makes
+        let revision = get_manifest(&connection).unwrap().unwrap().revision;
+        with_transaction(&store, |store| {
+            write_password(store, &password("bank", "two"), "master")?;
+            write_password(store, &password("shop", "four"), "master")
+        })
+        .unwrap();
+        assert_eq!(
+            get_manifest(&connection).unwrap().unwrap().revision,
+            revision + 1
+        );
+    }
+    #[test]
+    fn file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.store");
+        let store = FileStore::create(&path, "master", Algorithm::Aes256Gcm).unwrap();
+        exercise(&store);
+        assert!(matches!(
+            FileStore::create(&path, "master", Algorithm::Aes256Gcm),
+            Err(BackendError::AlreadyInitialized)
+        ));
+
+        // everything was written to the file, names included, and nothing is readable without the master password
+        let contents = std::fs::read(&path).unwrap();
+        assert!(!contents.windows(4).any(|window| window == b"mail"));
+        let store = FileStore::open(&path, "master").unwrap();
+        assert_eq!(
+            read_password(&store, "mail", "master")
+                .unwrap()
+                .unwrap()
+                .password
+                .unwrap(),
+            "three"
+        );
+        assert!(matches!(
+            FileStore::open(&path, "wrong"),
+            Err(BackendError::AesError)
+        ));
+        // nor with the master record's key, which the team identity and the manifest key are derived from
+        let envelope = Envelope::from_bytes(&contents).unwrap();
+        assert!(decrypt_password_field(
+            &envelope,
+            &gen_cipher(envelope.algorithm, "master", MASTER_KEYWORD)
+        )
+        .is_err());
+    }
+    #[test]
+    fn copy() {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        let sqlite = SqliteStore::new(&connection, "master");
+        write_password(&sqlite, &password("mail", "one"), "master").unwrap();
+        write_password(&sqlite, &password("bank", "two"), "master").unwrap();
+
+        let memory = MemoryStore::new(Algorithm::Aes256Gcm);
+        write_password(&memory, &password("mail", "old"), "master").unwrap();
+        assert_eq!(copy_passwords(&sqlite, &memory).unwrap(), 2);
+        let copied = list_passwords(&memory, "master").unwrap();
+        assert_eq!(copied.len(), 2);
+        assert_eq!(copied[1].password.as_deref(), Some("one"));
+        // custom fields get ids of their own
+        assert_ne!(copied[0].fields[0].id, copied[1].fields[0].id);
+
+        // and back, into an empty vault
+        let other = Connection::open_in_memory().unwrap();
+        create_table(&other).unwrap();
+        insert_master_record(&other, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        assert_eq!(
+            copy_passwords(&memory, &SqliteStore::new(&other, "master")).unwrap(),
+            2
+        );
+        assert_eq!(
+            read_password_info(&other, "bank", "master")
+                .unwrap()
+                .unwrap()
+                .fields[0]
+                .value,
+            "0000"
+        );
+    }
+}
@@ -0,0 +1,1004 @@
+//! Two-way sync between vaults, e.g. two vault files or a local vault and a passman-server.
+//!
+//! Every password has a revision that goes up whenever it's written, and so does every field, so `sync` can tell what
+//! changed on each side since the last sync. Deleted passwords leave a tombstone with a revision of their own.
+//! For every password, the revisions both sides had after the last sync are recorded (the *base*).
+//!
+//! Passwords that only changed on one side are copied (or deleted) on the other one. Passwords that changed on both
+//! sides are merged field by field, and only fields that were changed to different values on both sides are a `Conflict`,
+//! which the user has to resolve before the sync can be applied.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{
+    backup::take_snapshot,
+    db_ops::{
+        crud::{read_password_info, remove_password_info, replace_password_info},
+        util::{get_metadata, set_metadata},
+        CUSTOM_FIELDS_KEY, EXPIRY_KEY, MASTER_KEYWORD,
+    },
+    error::BackendError,
+    password::{PasswordField, PasswordInfo},
+};
+
+/// Key in the `VaultMetadata` table holding the random id other vaults know this vault by.
+pub const VAULT_ID_METADATA: &str = "vault_id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The current revision of a password, as listed by `Replica::revisions()`.
+pub struct EntryRevision {
+    /// Goes up whenever the password is written or deleted. Passwords that never existed have revision 0.
+    pub revision: u64,
+    /// Whether the password was deleted.
+    pub deleted: bool,
+}
+
+impl EntryRevision {
+    /// The revision of a password that never existed on a replica.
+    const MISSING: EntryRevision = EntryRevision {
+        revision: 0,
+        deleted: true,
+    };
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The revisions of a password on both sides after they were last synced.
+pub struct SyncBase {
+    /// The password's revision on this replica.
+    pub own_revision: u64,
+    /// The password's revision on the other replica.
+    pub peer_revision: u64,
+}
+
+#[derive(Debug, Clone)]
+/// A password as read by `Replica::read()`.
+pub struct SyncEntry {
+    /// The decrypted password.
+    pub password: PasswordInfo,
+    /// The revision each field was last changed in, keyed by `SyncField::key()`. Fields that are missing count as revision 0.
+    pub field_revisions: BTreeMap<String, u64>,
+}
+
+/// A vault that can be synced with another one.
+pub trait Replica {
+    /// A stable id for this replica, used to record what was last synced with it.
+    fn id(&self) -> Result<String, BackendError>;
+    /// The revision of every password, including deleted ones, keyed by name.
+    /// Replicas that don't keep tombstones may leave deleted passwords out; they're treated as deleted with revision 0.
+    fn revisions(&self) -> Result<BTreeMap<String, EntryRevision>, BackendError>;
+    /// Reads a password with its field revisions, or returns `None` if there is no (undeleted) password with that name.
+    fn read(&self, name: &str) -> Result<Option<SyncEntry>, BackendError>;
+    /// Replaces a password as a whole, or recreates it if it was deleted. Fields that don't change keep their revision.
+    /// Returns the password's revision afterwards.
+    ///
+    /// Fails with `BackendError::ChangedDuringSync` if the password's revision isn't `expected_revision` anymore.
+    fn write(&self, password: &PasswordInfo, expected_revision: u64) -> Result<u64, BackendError>;
+    /// Deletes a password, leaving a tombstone. Returns the tombstone's revision.
+    ///
+    /// Fails with `BackendError::ChangedDuringSync` if the password's revision isn't `expected_revision` anymore.
+    fn remove(&self, name: &str, expected_revision: u64) -> Result<u64, BackendError>;
+    /// What was last synced with `peer` for a password. Replicas that don't record this are always synced from scratch,
+    /// unless the other side records it.
+    fn base(&self, _peer: &str, _name: &str) -> Result<Option<SyncBase>, BackendError> {
+        Ok(None)
+    }
+    /// Records what was synced with `peer` for a password.
+    fn set_base(&self, _peer: &str, _name: &str, _base: SyncBase) -> Result<(), BackendError> {
+        Ok(())
+    }
+    /// Saves a copy of the replica before `SyncPlan::apply()` changes it. Replicas that keep their own history don't need to.
+    fn snapshot(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+/// A vault in an SQLite file (or in memory), unlocked with its master password.
+pub struct LocalReplica<'a> {
+    connection: &'a Connection,
+    master: &'a str,
+}
+
+impl<'a> LocalReplica<'a> {
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+    /// - `master` - a string slice holding the master password. The master password should be verified/authenticated by the time this function is called.
+    pub fn new(connection: &'a Connection, master: &'a str) -> Self {
+        LocalReplica { connection, master }
+    }
+
+    /// The current revision of a password, whether it exists or was deleted.
+    fn current_revision(&self, name: &str) -> Result<u64, rusqlite::Error> {
+        Ok(self
+            .connection
+            .query_row(
+                "select revision from PasswordInfo where name = ?1
+                union all select revision from Tombstone where name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0))
+    }
+    fn check_revision(&self, name: &str, expected_revision: u64) -> Result<(), BackendError> {
+        if self.current_revision(name)? != expected_revision {
+            return Err(BackendError::ChangedDuringSync(name.to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Gives a vault a new id, so it's treated as a different vault by `sync`.
+/// Needed after copying a vault file, since the copy has the same id as the original and the two can't be synced otherwise.
+///
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+pub fn reset_vault_id(connection: &Connection) -> Result<String, rusqlite::Error> {
+    let id = hex::encode(rand::random::<[u8; 16]>());
+    set_metadata(connection, VAULT_ID_METADATA, &id)?;
+    Ok(id)
+}
+
+impl Replica for LocalReplica<'_> {
+    fn id(&self) -> Result<String, BackendError> {
+        match get_metadata::<String>(self.connection, VAULT_ID_METADATA)? {
+            Some(id) => Ok(id),
+            None => Ok(reset_vault_id(self.connection)?),
+        }
+    }
+    fn revisions(&self) -> Result<BTreeMap<String, EntryRevision>, BackendError> {
+        let mut revisions = BTreeMap::new();
+        let mut stmt = self
+            .connection
+            .prepare("select name, revision, 1 from Tombstone union all select name, revision, 0 from PasswordInfo where name != ?")?;
+        // passwords come last, so they win if a name somehow has both
+        let rows = stmt.query_map([MASTER_KEYWORD], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                EntryRevision {
+                    revision: row.get(1)?,
+                    deleted: row.get(2)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (name, revision) = row?;
+            revisions.insert(name, revision);
+        }
+        Ok(revisions)
+    }
+    fn read(&self, name: &str) -> Result<Option<SyncEntry>, BackendError> {
+        let Some(password) = read_password_info(self.connection, name, self.master)? else {
+            return Ok(None);
+        };
+        let mut stmt = self.connection.prepare(
+            "select f.field, f.revision from FieldUpdate f join PasswordInfo p on p.id = f.password_id where p.name = ?",
+        )?;
+        let field_revisions = stmt
+            .query_map([name], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(Some(SyncEntry {
+            password,
+            field_revisions,
+        }))
+    }
+    fn write(&self, password: &PasswordInfo, expected_revision: u64) -> Result<u64, BackendError> {
+        self.check_revision(&password.name, expected_revision)?;
+        replace_password_info(self.connection, password, self.master)?;
+        Ok(self.current_revision(&password.name)?)
+    }
+    fn remove(&self, name: &str, expected_revision: u64) -> Result<u64, BackendError> {
+        self.check_revision(name, expected_revision)?;
+        // the whole vault was snapshotted before the sync
+        remove_password_info(self.connection, name, self.master)?;
+        Ok(self.current_revision(name)?)
+    }
+    fn base(&self, peer: &str, name: &str) -> Result<Option<SyncBase>, BackendError> {
+        Ok(self
+            .connection
+            .query_row(
+                "select own_revision, peer_revision from SyncBase where peer = ?1 and name = ?2",
+                (peer, name),
+                |row| {
+                    Ok(SyncBase {
+                        own_revision: row.get(0)?,
+                        peer_revision: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+    fn set_base(&self, peer: &str, name: &str, base: SyncBase) -> Result<(), BackendError> {
+        self.connection.execute(
+            "insert into SyncBase (peer, name, own_revision, peer_revision) values (?1, ?2, ?3, ?4)
+            on conflict(peer, name) do update set own_revision = ?3, peer_revision = ?4",
+            (peer, name, base.own_revision, base.peer_revision),
+        )?;
+        Ok(())
+    }
+    fn snapshot(&self) -> Result<(), BackendError> {
+        take_snapshot(self.connection, "sync")?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The parts of a password that are merged separately.
+pub enum SyncField {
+    Standard(PasswordField),
+    /// All custom fields, as a whole.
+    CustomFields,
+    Expiry,
+}
+
+impl SyncField {
+    /// Every field, in the order they're usually displayed.
+    pub const ALL: [SyncField; 9] = [
+        SyncField::Standard(PasswordField::Email),
+        SyncField::Standard(PasswordField::Username),
+        SyncField::Standard(PasswordField::Password),
+        SyncField::Standard(PasswordField::Notes),
+        SyncField::Standard(PasswordField::Urls),
+        SyncField::Standard(PasswordField::Tags),
+        SyncField::Standard(PasswordField::Folder),
+        SyncField::CustomFields,
+        SyncField::Expiry,
+    ];
+
+    /// The key the field's revision and timestamp are recorded under, e.g. in `Timestamps::fields`.
+    pub fn key(self) -> String {
+        match self {
+            SyncField::Standard(field) => field.to_string(),
+            SyncField::CustomFields => CUSTOM_FIELDS_KEY.to_string(),
+            SyncField::Expiry => EXPIRY_KEY.to_string(),
+        }
+    }
+
+    /// The field's value, in a form that can be compared. `None` if the field is empty.
+    fn value(self, password: &PasswordInfo) -> Option<String> {
+        let list = |values: &[String]| Some(values.join("\n")).filter(|list| !list.is_empty());
+        match self {
+            SyncField::Standard(PasswordField::Email) => password.email.clone(),
+            SyncField::Standard(PasswordField::Username) => password.username.clone(),
+            SyncField::Standard(PasswordField::Password) => password.password.clone(),
+            SyncField::Standard(PasswordField::Notes) => password.notes.clone(),
+            SyncField::Standard(PasswordField::Urls) => list(&password.urls),
+            SyncField::Standard(PasswordField::Tags) => list(&password.tags),
+            SyncField::Standard(PasswordField::Folder) => password.folder.clone(),
+            SyncField::CustomFields => list(
+                &password
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}={}={}", field.key, field.sensitive, field.value))
+                    .collect::<Vec<_>>(),
+            ),
+            SyncField::Expiry => {
+                (password.expiry != Default::default()).then(|| format!("{:?}", password.expiry))
+            }
+        }
+    }
+    /// The field's value, to show the user. Passwords and sensitive custom fields are hidden.
+    fn describe(self, password: &PasswordInfo) -> Option<String> {
+        match self {
+            SyncField::Standard(PasswordField::Password) => password
+                .password
+                .as_ref()
+                .map(|password| format!("hidden, {} characters", password.chars().count())),
+            SyncField::CustomFields if !password.fields.is_empty() => Some(
+                password
+                    .fields
+                    .iter()
+                    .map(|field| match field.sensitive {
+                        true => format!("{}: hidden", field.key),
+                        false => format!("{}: {}", field.key, field.value),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            SyncField::Expiry if password.expiry != Default::default() => {
+                let mut parts = Vec::new();
+                if let Some(expires_at) = password.expiry.expires_at {
+                    parts.push(format!("expires {}", expires_at.format("%Y-%m-%d")));
+                }
+                if let Some(days) = password.expiry.rotation_days {
+                    parts.push(format!("rotate every {} days", days));
+                }
+                Some(parts.join(", "))
+            }
+            _ => self.value(password),
+        }
+    }
+    /// Copies the field from one password to another.
+    pub(crate) fn copy(self, from: &PasswordInfo, to: &mut PasswordInfo) {
+        match self {
+            SyncField::Standard(PasswordField::Email) => to.email = from.email.clone(),
+            SyncField::Standard(PasswordField::Username) => to.username = from.username.clone(),
+            SyncField::Standard(PasswordField::Password) => to.password = from.password.clone(),
+            SyncField::Standard(PasswordField::Notes) => to.notes = from.notes.clone(),
+            SyncField::Standard(PasswordField::Urls) => to.urls = from.urls.clone(),
+            SyncField::Standard(PasswordField::Tags) => to.tags = from.tags.clone(),
+            SyncField::Standard(PasswordField::Folder) => to.folder = from.folder.clone(),
+            SyncField::CustomFields => to.fields = from.fields.clone(),
+            SyncField::Expiry => to.expiry = from.expiry,
+        }
+    }
+}
+
+impl std::fmt::Display for SyncField {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SyncField::Standard(field) => write!(f, "{}", field),
+            SyncField::CustomFields => write!(f, "custom fields"),
+            SyncField::Expiry => write!(f, "expiry"),
+        }
+    }
+}
+
+/// Works out the field revisions for writing `password` as a new revision over `current`, for replicas that store them
+/// with each password: fields that change get the new revision, and the others keep theirs.
+/// Returns `None` if nothing changed, so nothing has to be written.
+///
+/// # Arguments
+///
+/// - `current` - the password as it's stored now, if it exists.
+/// - `password` - the password that's about to be written.
+/// - `revision` - the revision it's going to be written as.
+pub(crate) fn next_field_revisions(
+    current: Option<&SyncEntry>,
+    password: &PasswordInfo,
+    revision: u64,
+) -> Option<BTreeMap<String, u64>> {
+    let mut changed = current.is_none();
+    let field_revisions = SyncField::ALL
+        .into_iter()
+        .map(|field| {
+            let key = field.key();
+            let unchanged = current.and_then(|current| {
+                (field.value(&current.password) == field.value(password))
+                    .then(|| current.field_revisions.get(&key).copied().unwrap_or(0))
+            });
+            changed |= unchanged.is_none();
+            (key, unchanged.unwrap_or(revision))
+        })
+        .collect();
+    changed.then_some(field_revisions)
+}
+
+/// Merges two versions of a password field by field, against the version both started from (if known):
+/// a field that only changed on one side is taken from that side.
+/// Returns the merged password, and the fields that changed to different values on both sides, which are taken from `ours`.
+pub(crate) fn merge_three_way(
+    base: Option<&PasswordInfo>,
+    ours: &PasswordInfo,
+    theirs: &PasswordInfo,
+) -> (PasswordInfo, Vec<SyncField>) {
+    let mut merged = ours.clone();
+    let mut conflicts = Vec::new();
+    for field in SyncField::ALL {
+        let (ours_value, theirs_value) = (field.value(ours), field.value(theirs));
+        if ours_value == theirs_value {
+            continue;
+        }
+        match base.map(|base| field.value(base)) {
+            Some(base_value) if base_value == ours_value => field.copy(theirs, &mut merged),
+            Some(base_value) if base_value == theirs_value => {}
+            _ => conflicts.push(field),
+        }
+    }
+    (merged, conflicts)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One side of a `Conflict`.
+pub struct ConflictSide {
+    /// The field's value, with passwords and sensitive custom fields hidden. `None` if the field is empty,
+    /// or the password was deleted on this side.
+    pub value: Option<String>,
+    /// When the field (or the password, for deletions) was last changed on this side, if known.
+    pub changed_at: Option<DateTime<Utc>>,
+    /// Whether the password was deleted on this side.
+    pub deleted: bool,
+}
+
+impl ConflictSide {
+    pub(crate) fn new(password: Option<&PasswordInfo>, field: Option<SyncField>) -> Self {
+        let Some(password) = password else {
+            return ConflictSide {
+                value: None,
+                changed_at: None,
+                deleted: true,
+            };
+        };
+        let changed_at = match field {
+            Some(field) => password.timestamps.fields.get(&field.key()).copied(),
+            None => password.timestamps.updated_at,
+        };
+        ConflictSide {
+            value: field.and_then(|field| field.describe(password)),
+            changed_at,
+            deleted: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// How to resolve a `Conflict`.
+pub enum Resolution {
+    #[default]
+    KeepLocal,
+    KeepRemote,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A field that was changed to different values on both sides, or a password that was deleted on one side and changed on the other.
+pub struct Conflict {
+    /// The password name.
+    pub name: String,
+    /// The field, or `None` if the password was deleted on one side.
+    pub field: Option<SyncField>,
+    pub local: ConflictSide,
+    pub remote: ConflictSide,
+    /// Has to be set before the `SyncPlan` is applied.
+    pub resolution: Option<Resolution>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// What a sync changed.
+pub struct SyncReport {
+    /// Passwords written to the local side.
+    pub to_local: usize,
+    /// Passwords written to the remote side.
+    pub to_remote: usize,
+    /// Passwords deleted on the local side.
+    pub deleted_local: usize,
+    /// Passwords deleted on the remote side.
+    pub deleted_remote: usize,
+    /// How many conflicts were resolved.
+    pub conflicts: usize,
+}
+
+#[derive(Debug)]
+enum Action {
+    ToRemote(PasswordInfo),
+    ToLocal(PasswordInfo),
+    DeleteRemote,
+    DeleteLocal,
+    /// Changed on both sides. Starts from the local password and takes `from_remote` and any conflicts resolved
+    /// with `KeepRemote` from the remote one.
+    Merge {
+        local: Box<PasswordInfo>,
+        remote: Box<PasswordInfo>,
+        from_remote: Vec<SyncField>,
+        conflicts: Vec<(SyncField, usize)>,
+    },
+    /// Deleted on one side and changed on the other.
+    DeletedOrChanged {
+        kept: PasswordInfo,
+        deleted_locally: bool,
+        conflict: usize,
+    },
+    /// Nothing to write, e.g. deleted on both sides, but the base has to be recorded.
+    Record,
+}
+
+#[derive(Debug)]
+struct Step {
+    name: String,
+    local: EntryRevision,
+    remote: EntryRevision,
+    action: Action,
+}
+
+#[derive(Debug)]
+/// What a sync is going to do, as worked out by `plan_sync()`. Nothing is written until it's applied.
+pub struct SyncPlan {
+    local_id: String,
+    remote_id: String,
+    steps: Vec<Step>,
+    /// Conflicts that have to be resolved before the plan is applied.
+    pub conflicts: Vec<Conflict>,
+}
+
+fn read_live(replica: &dyn Replica, name: &str) -> Result<SyncEntry, BackendError> {
+    replica
+        .read(name)?
+        .ok_or_else(|| BackendError::ChangedDuringSync(name.to_string()))
+}
+
+/// Works out how to sync two replicas. Doesn't write anything.
+///
+/// # Arguments
+///
+/// - `local` - the replica that's considered local, e.g. the user's own vault.
+/// - `remote` - the replica to sync with.
+pub fn plan_sync(local: &dyn Replica, remote: &dyn Replica) -> Result<SyncPlan, BackendError> {
+    let local_id = local.id()?;
+    let remote_id = remote.id()?;
+    if local_id == remote_id {
+        return Err(BackendError::SameVault);
+    }
+    let local_revisions = local.revisions()?;
+    let remote_revisions = remote.revisions()?;
+    let names: BTreeSet<&String> = local_revisions
+        .keys()
+        .chain(remote_revisions.keys())
+        .collect();
+
+    let mut steps = Vec::new();
+    let mut conflicts = Vec::new();
+    for name in names {
+        if name == MASTER_KEYWORD {
+            continue;
+        }
+        let l = *local_revisions.get(name).unwrap_or(&EntryRevision::MISSING);
+        let r = *remote_revisions
+            .get(name)
+            .unwrap_or(&EntryRevision::MISSING);
+        let base = match local.base(&remote_id, name)? {
+            Some(base) => base,
+            None => remote
+                .base(&local_id, name)?
+                .map(|base| SyncBase {
+                    own_revision: base.peer_revision,
+                    peer_revision: base.own_revision,
+                })
+                .unwrap_or_default(),
+        };
+        // not `>`: a password that's gone from a replica without a tombstone has revision 0 again
+        let local_changed = l.revision != base.own_revision;
+        let remote_changed = r.revision != base.peer_revision;
+
+        let action = match (local_changed, remote_changed, l.deleted, r.deleted) {
+            (false, false, _, _) => continue,
+            (_, _, true, true) => Action::Record,
+            (true, false, true, false) => Action::DeleteRemote,
+            (false, true, false, true) => Action::DeleteLocal,
+            (true, false, false, _) => Action::ToRemote(read_live(local, name)?.password),
+            (false, true, _, false) => Action::ToLocal(read_live(remote, name)?.password),
+            (true, true, true, false) | (true, true, false, true) => {
+                let deleted_locally = l.deleted;
+                let kept = match deleted_locally {
+                    true => read_live(remote, name)?.password,
+                    false => read_live(local, name)?.password,
+                };
+                let side = |deleted: bool| ConflictSide::new((!deleted).then_some(&kept), None);
+                conflicts.push(Conflict {
+                    name: name.clone(),
+                    field: None,
+                    local: side(deleted_locally),
+                    remote: side(!deleted_locally),
+                    resolution: None,
+                });
+                Action::DeletedOrChanged {
+                    kept,
+                    deleted_locally,
+                    conflict: conflicts.len() - 1,
+                }
+            }
+            (true, true, false, false) => {
+                let local_entry = read_live(local, name)?;
+                let remote_entry = read_live(remote, name)?;
+                let mut from_remote = Vec::new();
+                let mut field_conflicts = Vec::new();
+                for field in SyncField::ALL {
+                    let local_value = field.value(&local_entry.password);
+                    let remote_value = field.value(&remote_entry.password);
+                    if local_value == remote_value {
+                        continue;
+                    }
+                    let changed = |entry: &SyncEntry, base| {
+                        entry
+                            .field_revisions
+                            .get(&field.key())
+                            .copied()
+                            .unwrap_or(0)
+                            > base
+                    };
+                    match (
+                        changed(&local_entry, base.own_revision),
+                        changed(&remote_entry, base.peer_revision),
+                    ) {
+                        (true, false) => {}
+                        (false, true) => from_remote.push(field),
+                        // changed on both sides, or on neither (e.g. never synced), but different
+                        _ => {
+                            conflicts.push(Conflict {
+                                name: name.clone(),
+                                field: Some(field),
+                                local: ConflictSide::new(Some(&local_entry.password), Some(field)),
+                                remote: ConflictSide::new(
+                                    Some(&remote_entry.password),
+                                    Some(field),
+                                ),
+                                resolution: None,
+                            });
+                            field_conflicts.push((field, conflicts.len() - 1));
+                        }
+                    }
+                }
+                Action::Merge {
+                    local: Box::new(local_entry.password),
+                    remote: Box::new(remote_entry.password),
+                    from_remote,
+                    conflicts: field_conflicts,
+                }
+            }
+        };
+        steps.push(Step {
+            name: name.clone(),
+            local: l,
+            remote: r,
+            action,
+        });
+    }
+    Ok(SyncPlan {
+        local_id,
+        remote_id,
+        steps,
+        conflicts,
+    })
+}
+
+impl SyncPlan {
+    /// Whether the replicas are already in sync.
+    pub fn is_empty(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| matches!(step.action, Action::Record))
+    }
+
+    /// Writes the changes to both replicas, and records what was synced. Both replicas are snapshotted first, if there are any changes.
+    /// Fails with `BackendError::UnresolvedConflicts` if any conflict has no `resolution` yet, before anything is written.
+    /// If either side was changed since the plan was made, this fails with `BackendError::ChangedDuringSync` partway through;
+    /// passwords that were synced by then stay synced, and the rest are picked up by the next sync.
+    ///
+    /// # Arguments
+    ///
+    /// - `local` - the same local replica the plan was made with.
+    /// - `remote` - the same remote replica the plan was made with.
+    pub fn apply(
+        self,
+        local: &dyn Replica,
+        remote: &dyn Replica,
+    ) -> Result<SyncReport, BackendError> {
+        let unresolved = self
+            .conflicts
+            .iter()
+            .filter(|conflict| conflict.resolution.is_none())
+            .count();
+        if unresolved > 0 {
+            return Err(BackendError::UnresolvedConflicts(unresolved));
+        }
+        if !self.is_empty() {
+            local.snapshot()?;
+            remote.snapshot()?;
+        }
+        let keep_remote =
+            |index: usize| self.conflicts[index].resolution == Some(Resolution::KeepRemote);
+
+        let mut report = SyncReport {
+            conflicts: self.conflicts.len(),
+            ..Default::default()
+        };
+        for step in self.steps {
+            let name = step.name.as_str();
+            let (mut local_revision, mut remote_revision) =
+                (step.local.revision, step.remote.revision);
+            let mut write_local = |password: &PasswordInfo| -> Result<(), BackendError> {
+                local_revision = local.write(password, step.local.revision)?;
+                report.to_local += usize::from(local_revision != step.local.revision);
+                Ok(())
+            };
+            let mut write_remote = |password: &PasswordInfo| -> Result<(), BackendError> {
+                remote_revision = remote.write(password, step.remote.revision)?;
+                report.to_remote += usize::from(remote_revision != step.remote.revision);
+                Ok(())
+            };
+            match step.action {
+                Action::ToRemote(password) => write_remote(&password)?,
+                Action::ToLocal(password) => write_local(&password)?,
+                Action::DeleteRemote => {
+                    remote_revision = remote.remove(name, step.remote.revision)?;
+                    report.deleted_remote += 1;
+                }
+                Action::DeleteLocal => {
+                    local_revision = local.remove(name, step.local.revision)?;
+                    report.deleted_local += 1;
+                }
+                Action::Merge {
+                    local: local_password,
+                    remote: remote_password,
+                    from_remote,
+                    conflicts,
+                } => {
+                    let mut merged = *local_password;
+                    for field in from_remote {
+                        field.copy(&remote_password, &mut merged);
+                    }
+                    for (field, index) in conflicts {
+                        if keep_remote(index) {
+                            field.copy(&remote_password, &mut merged);
+                        }
+                    }
+                    write_local(&merged)?;
+                    write_remote(&merged)?;
+                }
+                Action::DeletedOrChanged {
+                    kept,
+                    deleted_locally,
+                    conflict,
+                } => match (deleted_locally, keep_remote(conflict)) {
+                    // keep the deletion
+                    (true, false) => {
+                        remote_revision = remote.remove(name, step.remote.revision)?;
+                        report.deleted_remote += 1;
+                    }
+                    (false, true) => {
+                        local_revision = local.remove(name, step.local.revision)?;
+                        report.deleted_local += 1;
+                    }
+                    // keep the changed password
+                    (true, true) => write_local(&kept)?,
+                    (false, false) => write_remote(&kept)?,
+                },
+                Action::Record => {}
+            }
+            local.set_base(
+                &self.remote_id,
+                name,
+                SyncBase {
+                    own_revision: local_revision,
+                    peer_revision: remote_revision,
+                },
+            )?;
+            remote.set_base(
+                &self.local_id,
+                name,
+                SyncBase {
+                    own_revision: remote_revision,
+                    peer_revision: local_revision,
+                },
+            )?;
+        }
+        Ok(report)
+    }
+}
+
+/// Syncs two replicas in one go, asking `resolve` how to resolve each conflict.
+///
+/// # Arguments
+///
+/// - `local` - the replica that's considered local, e.g. the user's own vault.
+/// - `remote` - the replica to sync with.
+/// - `resolve` - called once for every conflict.
+pub fn sync(
+    local: &dyn Replica,
+    remote: &dyn Replica,
+    mut resolve: impl FnMut(&Conflict) -> Resolution,
+) -> Result<SyncReport, BackendError> {
+    let mut plan = plan_sync(local, remote)?;
+    for conflict in &mut plan.conflicts {
+        conflict.resolution = Some(resolve(conflict));
+    }
+    plan.apply(local, remote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_sync, reset_vault_id, sync, LocalReplica, Resolution, SyncField};
+    use crate::{
+        db_ops::{
+            crud::{
+                delete_password_info, read_password_info, rename_password_info,
+                upsert_password_info,
+            },
+            util::{create_table, insert_master_record},
+        },
+        envelope::Algorithm,
+        error::BackendError,
+        password::{PasswordField, PasswordInfo},
+    };
+    use rusqlite::Connection;
+    use tempfile::TempDir;
+
+    /// Two vault files with the same master password, as if on two devices.
+    fn vaults() -> (TempDir, Connection, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let open = |file: &str, algorithm| {
+            let connection = Connection::open(dir.path().join(file)).unwrap();
+            create_table(&connection).unwrap();
+            insert_master_record(&connection, "master", "recovery", algorithm).unwrap();
+            connection
+        };
+        let laptop = open("laptop.db", Algorithm::XChaCha20Poly1305);
+        let desktop = open("desktop.db", Algorithm::Aes256Gcm);
+        (dir, laptop, desktop)
+    }
+    fn put(connection: &Connection, name: &str, username: &str, password: &str) {
+        let password = PasswordInfo {
+            name: name.to_string(),
+            username: Some(username.to_string()),
+            password: Some(password.to_string()),
+            ..Default::default()
+        };
+        upsert_password_info(connection, &password, "master").unwrap();
+    }
+    fn get(connection: &Connection, name: &str) -> Option<PasswordInfo> {
+        read_password_info(connection, name, "master").unwrap()
+    }
+    fn run(laptop: &Connection, desktop: &Connection) -> super::SyncReport {
+        sync(
+            &LocalReplica::new(laptop, "master"),
+            &LocalReplica::new(desktop, "master"),
+            |conflict| panic!("unexpected conflict: {:?}", conflict),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn copy_and_delete() {
+        let (_dir, laptop, desktop) = vaults();
+        put(&laptop, "mail", "alice", "one");
+        put(&desktop, "bank", "alice", "two");
+
+        let report = run(&laptop, &desktop);
+        assert_eq!((report.to_local, report.to_remote), (1, 1));
+        assert_eq!(get(&desktop, "mail").unwrap().password.unwrap(), "one");
+        assert_eq!(get(&laptop, "bank").unwrap().password.unwrap(), "two");
+
+        // nothing changed since, so there's nothing to do
+        let (local, remote) = (
+            LocalReplica::new(&laptop, "master"),
+            LocalReplica::new(&desktop, "master"),
+        );
+        assert!(plan_sync(&local, &remote).unwrap().is_empty());
+        assert_eq!(run(&desktop, &laptop), Default::default());
+
+        delete_password_info(&desktop, "mail", "master").unwrap();
+        put(&desktop, "bank", "alice", "three");
+        let report = run(&laptop, &desktop);
+        assert_eq!((report.deleted_local, report.to_local), (1, 1));
+        assert!(get(&laptop, "mail").is_none());
+        assert_eq!(get(&laptop, "bank").unwrap().password.unwrap(), "three");
+
+        // a password created again after being deleted is newer than the deletion
+        put(&laptop, "mail", "bob", "four");
+        run(&laptop, &desktop);
+        assert_eq!(get(&desktop, "mail").unwrap().username.unwrap(), "bob");
+        assert!(plan_sync(&remote, &local).unwrap().is_empty());
+    }
+    #[test]
+    fn rename() {
+        let (_dir, laptop, desktop) = vaults();
+        put(&laptop, "mail", "alice", "one");
+        run(&laptop, &desktop);
+
+        rename_password_info(&laptop, "mail", "webmail", "master").unwrap();
+        run(&laptop, &desktop);
+        assert!(get(&desktop, "mail").is_none());
+        assert_eq!(get(&desktop, "webmail").unwrap().password.unwrap(), "one");
+    }
+    #[test]
+    fn merge_fields() {
+        let (_dir, laptop, desktop) = vaults();
+        put(&laptop, "mail", "alice", "one");
+        run(&laptop, &desktop);
+
+        // different fields changed on each side merge without conflicts
+        put(&laptop, "mail", "alice", "two");
+        put(&desktop, "mail", "bob", "one");
+        let report = run(&laptop, &desktop);
+        assert_eq!((report.to_local, report.to_remote), (1, 1));
+        for vault in [&laptop, &desktop] {
+            let password = get(vault, "mail").unwrap();
+            assert_eq!(password.username.unwrap(), "bob");
+            assert_eq!(password.password.unwrap(), "two");
+        }
+        // the same change on both sides isn't a conflict either
+        put(&laptop, "mail", "carol", "two");
+        put(&desktop, "mail", "carol", "two");
+        run(&laptop, &desktop);
+    }
+    #[test]
+    fn conflicts() {
+        let (_dir, laptop, desktop) = vaults();
+        put(&laptop, "mail", "alice", "one");
+        put(&laptop, "bank", "alice", "two");
+        run(&laptop, &desktop);
+
+        put(&laptop, "mail", "alice", "laptop");
+        put(&desktop, "mail", "bob", "desktop!");
+        delete_password_info(&laptop, "bank", "master").unwrap();
+        put(&desktop, "bank", "alice", "changed");
+
+        let (local, remote) = (
+            LocalReplica::new(&laptop, "master"),
+            LocalReplica::new(&desktop, "master"),
+        );
+        let mut plan = plan_sync(&local, &remote).unwrap();
+        assert_eq!(plan.conflicts.len(), 2);
+        let deleted = &plan.conflicts[0];
+        assert_eq!((deleted.name.as_str(), deleted.field), ("bank", None));
+        assert!(deleted.local.deleted && !deleted.remote.deleted);
+        // only the password conflicts; the username was only changed on the desktop
+        let changed = &plan.conflicts[1];
+        assert_eq!(
+            changed.field,
+            Some(SyncField::Standard(PasswordField::Password))
+        );
+        // passwords aren't revealed
+        assert_eq!(changed.local.value.as_deref(), Some("hidden, 6 characters"));
+        assert_eq!(
+            changed.remote.value.as_deref(),
+            Some("hidden, 8 characters")
+        );
+        assert!(changed.local.changed_at.is_some());
+
+        assert!(matches!(
+            plan_sync(&local, &remote).unwrap().apply(&local, &remote),
+            Err(BackendError::UnresolvedConflicts(2))
+        ));
+        plan.conflicts[0].resolution = Some(Resolution::KeepRemote);
+        plan.conflicts[1].resolution = Some(Resolution::KeepLocal);
+        let report = plan.apply(&local, &remote).unwrap();
+        assert_eq!(report.conflicts, 2);
+
+        for vault in [&laptop, &desktop] {
+            assert_eq!(get(vault, "bank").unwrap().password.unwrap(), "changed");
+            let mail = get(vault, "mail").unwrap();
+            assert_eq!(mail.username.unwrap(), "bob");
+            assert_eq!(mail.password.unwrap(), "laptop");
+        }
+        assert!(plan_sync(&local, &remote).unwrap().is_empty());
+    }
+    #[test]
+    fn changed_during_sync() {
+        let (_dir, laptop, desktop) = vaults();
+        put(&laptop, "mail", "alice", "one");
+        let (local, remote) = (
+            LocalReplica::new(&laptop, "master"),
+            LocalReplica::new(&desktop, "master"),
+        );
+        let plan = plan_sync(&local, &remote).unwrap();
+        put(&desktop, "mail", "bob", "two");
+        assert!(matches!(
+            plan.apply(&local, &remote),
+            Err(BackendError::ChangedDuringSync(name)) if name == "mail"
+        ));
+    }
+    #[test]
+    fn copied_vault() {
+        let dir = tempfile::tempdir().unwrap();
+        let laptop = Connection::open(dir.path().join("laptop.db")).unwrap();
+        create_table(&laptop).unwrap();
+        insert_master_record(&laptop, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        put(&laptop, "mail", "alice", "one");
+        let local = LocalReplica::new(&laptop, "master");
+        super::Replica::id(&local).unwrap();
+
+        let copy = dir.path().join("copy.db");
+        laptop
+            .execute("vacuum into ?", [copy.to_str().unwrap()])
+            .unwrap();
+        let copy = Connection::open(copy).unwrap();
+        let remote = LocalReplica::new(&copy, "master");
+        assert!(matches!(
+            plan_sync(&local, &remote),
+            Err(BackendError::SameVault)
+        ));
+
+        reset_vault_id(&copy).unwrap();
+        put(&copy, "mail", "bob", "one");
+        // the copy was never synced, but only the username differs, and it's the same password either way
+        let plan = plan_sync(&local, &remote).unwrap();
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(
+            plan.conflicts[0].field,
+            Some(SyncField::Standard(PasswordField::Username))
+        );
+    }
+}
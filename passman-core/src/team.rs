@@ -0,0 +1,496 @@
+//! Shared vaults, which a team opens with their own master passwords.
+//!
+//! A shared vault is an ordinary vault file, but its "master password" is a random vault key that nobody types.
+//! The key is sealed (`sodiumoxide::crypto::sealedbox`) to the X25519 public key of every member and stored in the
+//! `TeamMember` table, so any member can open it with their `Identity`, which is kept in their personal vault.
+//! Once unlocked, the vault key is passed to the `db_ops` functions like any master password.
+//!
+//! Removing a member rotates the vault key: every field is re-encrypted and the new key is sealed to the remaining members,
+//! so a removed member's copy of the old key can't open anything written afterwards.
+
+use chrono::Utc;
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use sodiumoxide::{
+    crypto::{
+        box_::{self, PublicKey, SecretKey},
+        sealedbox,
+    },
+    randombytes::randombytes,
+};
+
+use crate::{
+    backup::take_snapshot,
+    crypto::{derive_subkey, gen_cipher, generate_password, Cipher},
+    db_ops::{
+        crud::rekey_vault,
+        util::{
+            authenticate, check_password_info_exists, get_metadata, get_vault_algorithm,
+            insert_master_record, set_metadata,
+        },
+        MASTER_KEYWORD,
+    },
+    envelope::{Algorithm, Envelope},
+    error::BackendError,
+    password::PasswordField,
+};
+
+/// Key in a personal vault's `VaultMetadata` table holding the public half of its `Identity`, hex encoded.
+pub const IDENTITY_PUBLIC_METADATA: &str = "identity_public_key";
+/// Key in a personal vault's `VaultMetadata` table holding the secret half of its `Identity`, encrypted with the master password.
+pub const IDENTITY_SECRET_METADATA: &str = "identity_secret_key";
+/// The context the key for the identity's secret key is derived with.
+const IDENTITY_CONTEXT: &[u8] = b"passman team identity v1";
+
+/// A member's key pair, used to open shared vaults.
+pub struct Identity {
+    pub(crate) public_key: PublicKey,
+    pub(crate) secret_key: SecretKey,
+}
+
+impl Identity {
+    /// Generates a new key pair.
+    pub fn generate() -> Self {
+        let (public_key, secret_key) = box_::gen_keypair();
+        Identity {
+            public_key,
+            secret_key,
+        }
+    }
+    /// Reads the identity stored in a personal vault, or returns `None` if it doesn't have one yet.
+    /// A secret key still encrypted with the master record's key, from before identities had their own, is re-encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection` to the personal vault.
+    /// - `master` - the personal vault's master password. It should be verified/authenticated by the time this function is called.
+    pub fn load(connection: &Connection, master: &str) -> Result<Option<Self>, BackendError> {
+        let (Some(public_key), Some(secret_key)) = (
+            get_metadata::<String>(connection, IDENTITY_PUBLIC_METADATA)?,
+            get_metadata::<Vec<u8>>(connection, IDENTITY_SECRET_METADATA)?,
+        ) else {
+            return Ok(None);
+        };
+        let corrupt = |reason: &str| BackendError::CorruptMetadata {
+            key: IDENTITY_SECRET_METADATA.to_string(),
+            reason: reason.to_string(),
+        };
+        let (secret_key, legacy) = decrypt_secret_key(connection, master, &secret_key)
+            .map_err(|_| corrupt("it couldn't be decrypted"))?;
+        if legacy {
+            let encrypted = identity_cipher(connection, master)?.encrypt(&secret_key)?;
+            set_metadata(connection, IDENTITY_SECRET_METADATA, encrypted.to_bytes())?;
+        }
+        Ok(Some(Identity {
+            public_key: parse_public_key(&public_key)?,
+            secret_key: SecretKey::from_slice(&secret_key)
+                .ok_or_else(|| corrupt("wrong length"))?,
+        }))
+    }
+    /// Reads the identity stored in a personal vault, generating and storing one first if it doesn't have one yet.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a reference to a `rusqlite::Connection` to the personal vault.
+    /// - `master` - the personal vault's master password. It should be verified/authenticated by the time this function is called.
+    pub fn load_or_create(connection: &Connection, master: &str) -> Result<Self, BackendError> {
+        if let Some(identity) = Self::load(connection, master)? {
+            return Ok(identity);
+        }
+        let identity = Self::generate();
+        let secret_key = identity_cipher(connection, master)?.encrypt(identity.secret_key.0)?;
+        let transaction = connection.unchecked_transaction()?;
+        set_metadata(
+            &transaction,
+            IDENTITY_PUBLIC_METADATA,
+            identity.public_key_hex(),
+        )?;
+        set_metadata(
+            &transaction,
+            IDENTITY_SECRET_METADATA,
+            secret_key.to_bytes(),
+        )?;
+        transaction.commit()?;
+        Ok(identity)
+    }
+    /// The public key, hex encoded, to give to whoever adds this identity to a shared vault.
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.public_key.0)
+    }
+}
+
+/// Re-encrypts the identity's secret key, if the vault has one, for a new master password. Used by `crud::rekey_vault()`.
+pub(crate) fn rekey_identity(
+    connection: &Connection,
+    master: &str,
+    new_master: &str,
+) -> Result<(), BackendError> {
+    let Some(secret_key) = get_metadata::<Vec<u8>>(connection, IDENTITY_SECRET_METADATA)? else {
+        return Ok(());
+    };
+    let (secret_key, _) = decrypt_secret_key(connection, master, &secret_key).map_err(|e| {
+        BackendError::CorruptMetadata {
+            key: IDENTITY_SECRET_METADATA.to_string(),
+            reason: e.to_string(),
+        }
+    })?;
+    let secret_key = identity_cipher(connection, new_master)?.encrypt(secret_key)?;
+    set_metadata(connection, IDENTITY_SECRET_METADATA, secret_key.to_bytes())?;
+    Ok(())
+}
+
+/// The cipher for the identity's secret key.
+fn identity_cipher(connection: &Connection, master: &str) -> Result<Cipher, BackendError> {
+    Ok(Cipher::new(
+        get_vault_algorithm(connection)?,
+        derive_subkey(master, IDENTITY_CONTEXT),
+    ))
+}
+
+/// Decrypts the identity's secret key. Also returns whether it was encrypted with the master record's key,
+/// as it was before identities had their own.
+fn decrypt_secret_key(
+    connection: &Connection,
+    master: &str,
+    secret_key: &[u8],
+) -> Result<(Vec<u8>, bool), BackendError> {
+    let envelope = Envelope::from_bytes(secret_key)?;
+    if let Ok(secret_key) = identity_cipher(connection, master)?.decrypt(&envelope) {
+        return Ok((secret_key, false));
+    }
+    let legacy = gen_cipher(get_vault_algorithm(connection)?, master, MASTER_KEYWORD);
+    Ok((legacy.decrypt(&envelope)?, true))
+}
+
+/// Parses a hex encoded X25519 public key, as printed by `Identity::public_key_hex()`.
+pub fn parse_public_key(public_key: &str) -> Result<PublicKey, BackendError> {
+    hex::decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| PublicKey::from_slice(&bytes))
+        .ok_or(BackendError::InvalidPublicKey)
+}
+
+/// A member of a shared vault.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Member {
+    pub name: String,
+    /// The member's public key, hex encoded.
+    pub public_key: String,
+    pub added_at: chrono::DateTime<Utc>,
+}
+
+/// Whether a vault is shared, i.e. has any members.
+pub fn is_shared(connection: &Connection) -> Result<bool, BackendError> {
+    Ok(connection.prepare("select 1 from TeamMember")?.exists(())?)
+}
+
+/// Sets up a new shared vault with a random vault key, with `identity` as its first member. Returns the vault key.
+/// Fails with `BackendError::AlreadyInitialized` if the vault has already been set up.
+///
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection` to the new vault, which may be to a file or in memory.
+/// - `name` - what to call the first member, e.g. their username.
+/// - `identity` - the first member's `Identity`.
+/// - `algorithm` - the `Algorithm` to encrypt data in this vault with.
+pub fn create_shared_vault(
+    connection: &Connection,
+    name: &str,
+    identity: &Identity,
+    algorithm: Algorithm,
+) -> Result<String, BackendError> {
+    if check_password_info_exists(connection, MASTER_KEYWORD)? {
+        return Err(BackendError::AlreadyInitialized);
+    }
+    let vault_key = hex::encode(randombytes(32));
+    // nobody can reset a shared vault's key; members who lose access are added again by someone else
+    insert_master_record(connection, &vault_key, &generate_password(32), algorithm)?;
+    insert_member(connection, &vault_key, name, &identity.public_key)?;
+    Ok(vault_key)
+}
+
+/// Opens a shared vault with a member's identity, returning the vault key to pass to `db_ops` functions as the master password.
+/// Fails with `BackendError::NotAMember` if the identity isn't a member.
+///
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection` to the shared vault.
+/// - `identity` - the member's `Identity`, from their personal vault.
+pub fn unlock_shared_vault(
+    connection: &Connection,
+    identity: &Identity,
+) -> Result<String, BackendError> {
+    let sealed_key: Vec<u8> = connection
+        .query_row(
+            "select sealed_key from TeamMember where public_key = ?",
+            [identity.public_key.0.as_slice()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(BackendError::NotAMember)?;
+    let vault_key = sealedbox::open(&sealed_key, &identity.public_key, &identity.secret_key)
+        .map_err(|_| BackendError::AesError)?;
+    let vault_key = String::from_utf8(vault_key)?;
+    // a member whose key wasn't resealed after a rotation has the old one
+    match authenticate(connection, &vault_key, PasswordField::Password)? {
+        true => Ok(vault_key),
+        false => Err(BackendError::NotAMember),
+    }
+}
+
+/// Lists the members of a shared vault, sorted by name. Doesn't require the vault key.
+pub fn list_members(connection: &Connection) -> Result<Vec<Member>, BackendError> {
+    let mut stmt =
+        connection.prepare("select name, public_key, added_at from TeamMember order by name")?;
+    let members = stmt
+        .query_map((), |row| {
+            Ok(Member {
+                name: row.get(0)?,
+                public_key: hex::encode(row.get::<_, Vec<u8>>(1)?),
+                added_at: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(members)
+}
+
+/// Gives someone access to a shared vault by sealing the vault key to their public key.
+/// Fails with `BackendError::MemberExists` if the name or public key is already a member.
+///
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection` to the shared vault.
+/// - `vault_key` - the vault key, from `unlock_shared_vault()`.
+/// - `name` - what to call the new member.
+/// - `public_key` - the new member's public key, hex encoded.
+pub fn add_member(
+    connection: &Connection,
+    vault_key: &str,
+    name: &str,
+    public_key: &str,
+) -> Result<(), BackendError> {
+    let public_key = parse_public_key(public_key)?;
+    insert_member(connection, vault_key, name, &public_key)
+}
+
+fn insert_member(
+    connection: &Connection,
+    vault_key: &str,
+    name: &str,
+    public_key: &PublicKey,
+) -> Result<(), BackendError> {
+    let exists = connection
+        .prepare("select 1 from TeamMember where name = ?1 or public_key = ?2")?
+        .exists((name, public_key.0.as_slice()))?;
+    if exists {
+        return Err(BackendError::MemberExists(name.to_string()));
+    }
+    connection.execute(
+        "insert into TeamMember (name, public_key, sealed_key, added_at) values (?1, ?2, ?3, ?4)",
+        (
+            name,
+            public_key.0.as_slice(),
+            sealedbox::seal(vault_key.as_bytes(), public_key),
+            Utc::now(),
+        ),
+    )?;
+    Ok(())
+}
+
+/// Removes a member from a shared vault and rotates the vault key: every field is re-encrypted with a new key, which is
+/// sealed to the remaining members. All changes are made in a single transaction, after the vault is snapshotted.
+/// Returns the new vault key, or `None` if there was no member called `name`.
+/// Fails with `BackendError::LastMember` rather than leave the vault without members.
+///
+/// The removed member may have kept a copy of the vault and its old key, so passwords they could see should still be changed.
+///
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection` to the shared vault.
+/// - `vault_key` - the current vault key, from `unlock_shared_vault()`.
+/// - `name` - the member to remove.
+pub fn remove_member(
+    connection: &Connection,
+    vault_key: &str,
+    name: &str,
+) -> Result<Option<String>, BackendError> {
+    let exists = connection
+        .prepare("select 1 from TeamMember where name = ?")?
+        .exists([name])?;
+    if !exists {
+        return Ok(None);
+    }
+    take_snapshot(connection, "remove-member")?;
+    let transaction = connection.unchecked_transaction()?;
+    if transaction.execute("delete from TeamMember where name = ?", [name])? == 0 {
+        return Ok(None);
+    }
+    let remaining = transaction
+        .prepare("select public_key from TeamMember")?
+        .query_map((), |row| row.get::<_, Vec<u8>>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if remaining.is_empty() {
+        return Err(BackendError::LastMember);
+    }
+
+    let new_key = hex::encode(randombytes(32));
+    rekey_vault(&transaction, vault_key, &new_key)?;
+    for public_key in remaining {
+        let sealed_key = sealedbox::seal(
+            new_key.as_bytes(),
+            &PublicKey::from_slice(&public_key).ok_or(BackendError::InvalidPublicKey)?,
+        );
+        transaction.execute(
+            "update TeamMember set sealed_key = ?1 where public_key = ?2",
+            (sealed_key, public_key),
+        )?;
+    }
+    transaction.commit()?;
+    Ok(Some(new_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_member, create_shared_vault, is_shared, list_members, remove_member,
+        unlock_shared_vault, Identity, IDENTITY_SECRET_METADATA,
+    };
+    use crate::{
+        crypto::gen_cipher,
+        db_ops::{
+            crud::{change_master_password, read_password_info, upsert_password_info},
+            util::{create_table, get_metadata, insert_master_record, set_metadata},
+            MASTER_KEYWORD,
+        },
+        envelope::{Algorithm, Envelope},
+        error::BackendError,
+        password::PasswordInfo,
+    };
+    use rusqlite::Connection;
+
+    fn vault() -> Connection {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        connection
+    }
+
+    #[test]
+    fn identity() {
+        crate::init().unwrap();
+        let personal = vault();
+        insert_master_record(&personal, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        assert!(Identity::load(&personal, "master").unwrap().is_none());
+
+        let identity = Identity::load_or_create(&personal, "master").unwrap();
+        let loaded = Identity::load(&personal, "master").unwrap().unwrap();
+        assert_eq!(loaded.public_key_hex(), identity.public_key_hex());
+        assert_eq!(loaded.secret_key, identity.secret_key);
+        assert_eq!(
+            Identity::load_or_create(&personal, "master")
+                .unwrap()
+                .public_key_hex(),
+            identity.public_key_hex()
+        );
+        assert!(Identity::load(&personal, "wrong").is_err());
+
+        // the secret key has its own key, but identities created before it did can still be loaded
+        let secret_key: Vec<u8> = get_metadata(&personal, IDENTITY_SECRET_METADATA)
+            .unwrap()
+            .unwrap();
+        let legacy = gen_cipher(Algorithm::Aes256Gcm, "master", MASTER_KEYWORD);
+        assert!(legacy
+            .decrypt(&Envelope::from_bytes(&secret_key).unwrap())
+            .is_err());
+        let encrypted = legacy.encrypt(identity.secret_key.0).unwrap().to_bytes();
+        set_metadata(&personal, IDENTITY_SECRET_METADATA, &encrypted).unwrap();
+        let loaded = Identity::load(&personal, "master").unwrap().unwrap();
+        assert_eq!(loaded.secret_key, identity.secret_key);
+        // and they're moved to their own key
+        let secret_key: Vec<u8> = get_metadata(&personal, IDENTITY_SECRET_METADATA)
+            .unwrap()
+            .unwrap();
+        assert_ne!(secret_key, encrypted);
+
+        // changing the master password keeps the identity
+        change_master_password(&personal, "master", "new").unwrap();
+        assert_eq!(
+            Identity::load(&personal, "new")
+                .unwrap()
+                .unwrap()
+                .public_key_hex(),
+            identity.public_key_hex()
+        );
+    }
+    #[test]
+    fn members() {
+        crate::init().unwrap();
+        let (alice, bob, carol) = (
+            Identity::generate(),
+            Identity::generate(),
+            Identity::generate(),
+        );
+        let shared = vault();
+        assert!(!is_shared(&shared).unwrap());
+        let key =
+            create_shared_vault(&shared, "alice", &alice, Algorithm::XChaCha20Poly1305).unwrap();
+        assert!(is_shared(&shared).unwrap());
+        assert!(matches!(
+            create_shared_vault(&shared, "alice", &alice, Algorithm::Aes256Gcm),
+            Err(BackendError::AlreadyInitialized)
+        ));
+        let password = PasswordInfo {
+            name: "wifi".to_string(),
+            password: Some("hunter2".to_string()),
+            ..Default::default()
+        };
+        upsert_password_info(&shared, &password, &key).unwrap();
+
+        assert!(matches!(
+            unlock_shared_vault(&shared, &bob),
+            Err(BackendError::NotAMember)
+        ));
+        add_member(&shared, &key, "bob", &bob.public_key_hex()).unwrap();
+        add_member(&shared, &key, "carol", &carol.public_key_hex()).unwrap();
+        assert!(matches!(
+            add_member(&shared, &key, "bob", &carol.public_key_hex()),
+            Err(BackendError::MemberExists(_))
+        ));
+        assert!(matches!(
+            add_member(&shared, &key, "dave", "not a key"),
+            Err(BackendError::InvalidPublicKey)
+        ));
+        assert_eq!(unlock_shared_vault(&shared, &bob).unwrap(), key);
+        let names: Vec<String> = list_members(&shared)
+            .unwrap()
+            .into_iter()
+            .map(|member| member.name)
+            .collect();
+        assert_eq!(names, ["alice", "bob", "carol"]);
+
+        // removing bob rotates the key, so the one he had no longer opens anything
+        let new_key = remove_member(&shared, &key, "bob").unwrap().unwrap();
+        assert_ne!(new_key, key);
+        assert!(matches!(
+            unlock_shared_vault(&shared, &bob),
+            Err(BackendError::NotAMember)
+        ));
+        assert!(read_password_info(&shared, "wifi", &key).is_err());
+        for member in [&alice, &carol] {
+            let unlocked = unlock_shared_vault(&shared, member).unwrap();
+            assert_eq!(unlocked, new_key);
+            let wifi = read_password_info(&shared, "wifi", &unlocked)
+                .unwrap()
+                .unwrap();
+            assert_eq!(wifi.password.unwrap(), "hunter2");
+        }
+
+        assert!(remove_member(&shared, &new_key, "bob").unwrap().is_none());
+        remove_member(&shared, &new_key, "carol").unwrap();
+        let last_key = unlock_shared_vault(&shared, &alice).unwrap();
+        assert!(matches!(
+            remove_member(&shared, &last_key, "alice"),
+            Err(BackendError::LastMember)
+        ));
+        assert_eq!(list_members(&shared).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use chrono::Utc;
+use rusqlite::Connection;
+use zeroize::Zeroizing;
+
+use crate::{
+    backup::take_snapshot,
+    db_ops::{
+        crud::{
+            delete_password_info, list_password_info, read_password_info, upsert_password_info,
+        },
+        util::{
+            check_password_info_exists, create_table, insert_master_record, needs_upgrade,
+            upgrade_legacy_fields,
+        },
+        MASTER_KEYWORD,
+    },
+    envelope::Algorithm,
+    error::BackendError,
+    keyfile::{composite_key, requires_keyfile},
+    login::{attempt_login, LoginAttempt},
+    manifest::{verify_manifest, ManifestStatus},
+    password::{PasswordField, PasswordInfo},
+    verify::{verify_vault, VerifyReport},
+};
+
+/// The default location of the vault, relative to the working directory.
+pub const DEFAULT_VAULT_PATH: &str = "./data.db";
+
+/// A passman vault: the stable entry point for frontends.
+///
+/// A vault is opened locked. Once `unlock()` succeeds it holds the master password, so entries can be read and written
+/// without passing it around, until it's locked again.
+/// The underlying `rusqlite::Connection` is available through `connection()` for the lower level `db_ops` functions.
+pub struct Vault {
+    connection: Connection,
+    /// Wiped from memory when the vault is locked or dropped.
+    master: Option<Zeroizing<String>>,
+}
+
+impl Vault {
+    /// Opens (or creates) a vault file, creating any missing tables and upgrading data written by older versions.
+    /// A vault that has to be upgraded is snapshotted first.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - the path of the SQLite file, e.g. `DEFAULT_VAULT_PATH`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BackendError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+    /// Opens an empty vault that only lives in memory. Mostly useful for tests.
+    pub fn open_in_memory() -> Result<Self, BackendError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+    fn from_connection(connection: Connection) -> Result<Self, BackendError> {
+        if needs_upgrade(&connection)? {
+            take_snapshot(&connection, "migration")?;
+        }
+        create_table(&connection)?;
+        upgrade_legacy_fields(&connection)?;
+        Ok(Vault {
+            connection,
+            master: None,
+        })
+    }
+
+    /// The underlying connection, for functions in `db_ops` that aren't covered by `Vault`.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+    /// Whether the vault has a master record yet. New vaults have to be set up with `initialize()` first.
+    pub fn is_initialized(&self) -> Result<bool, BackendError> {
+        Ok(check_password_info_exists(
+            &self.connection,
+            MASTER_KEYWORD,
+        )?)
+    }
+    /// Sets up a new vault with a master password and recovery phrase, and leaves it unlocked.
+    ///
+    /// # Arguments
+    ///
+    /// - `master` - the new master password.
+    /// - `recovery_phrase` - the phrase that can be used to reset the master password.
+    /// - `algorithm` - the `Algorithm` to encrypt data in this vault with.
+    pub fn initialize(
+        &mut self,
+        master: &str,
+        recovery_phrase: &str,
+        algorithm: Algorithm,
+    ) -> Result<(), BackendError> {
+        insert_master_record(&self.connection, master, recovery_phrase, algorithm)?;
+        self.master = Some(Zeroizing::new(master.to_string()));
+        Ok(())
+    }
+
+    /// Tries to unlock the vault with a master password. Failed attempts are throttled according to the vault's `LoginPolicy`.
+    /// The vault is only unlocked if `LoginAttempt::Success` is returned.
+    /// Fails with `BackendError::KeyfileRequired` if the vault requires a keyfile; use `unlock_with_keyfile()` instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `master` - the master password entered by the user.
+    pub fn unlock(&mut self, master: &str) -> Result<LoginAttempt, BackendError> {
+        if requires_keyfile(&self.connection)? {
+            return Err(BackendError::KeyfileRequired);
+        }
+        self.unlock_with_key(master)
+    }
+    /// Tries to unlock a vault that requires a keyfile, like `unlock()`. See `keyfile` for details.
+    ///
+    /// # Arguments
+    ///
+    /// - `master` - the master password entered by the user.
+    /// - `keyfile` - the contents of the keyfile.
+    pub fn unlock_with_keyfile(
+        &mut self,
+        master: &str,
+        keyfile: &[u8],
+    ) -> Result<LoginAttempt, BackendError> {
+        self.unlock_with_key(&Zeroizing::new(composite_key(master, keyfile)))
+    }
+    fn unlock_with_key(&mut self, key: &str) -> Result<LoginAttempt, BackendError> {
+        let attempt = attempt_login(&self.connection, key, PasswordField::Password, Utc::now())?;
+        if let LoginAttempt::Success { .. } = attempt {
+            self.master = Some(Zeroizing::new(key.to_string()));
+        }
+        Ok(attempt)
+    }
+    /// Locks the vault, wiping the master password from memory.
+    pub fn lock(&mut self) {
+        self.master = None;
+    }
+    /// Whether the vault is unlocked.
+    pub fn is_unlocked(&self) -> bool {
+        self.master.is_some()
+    }
+    fn master(&self) -> Result<&str, BackendError> {
+        self.master
+            .as_deref()
+            .map(String::as_str)
+            .ok_or(BackendError::Locked)
+    }
+
+    /// Reads and decrypts a password, or returns `None` if there is no password with that name.
+    /// Fails with `BackendError::Locked` if the vault is locked.
+    pub fn get(&self, name: &str) -> Result<Option<PasswordInfo>, BackendError> {
+        read_password_info(&self.connection, name, self.master()?)
+    }
+    /// Encrypts and writes a password in a single transaction, creating it if it doesn't exist yet.
+    /// Fields that are `None` are left as they are. See `crud::upsert_password_info()` for details.
+    /// Fails with `BackendError::Locked` if the vault is locked.
+    pub fn put(&self, password: &PasswordInfo) -> Result<usize, BackendError> {
+        upsert_password_info(&self.connection, password, self.master()?)
+    }
+    /// Deletes a password, returning whether it existed. Fails with `BackendError::Locked` if the vault is locked.
+    pub fn delete(&self, name: &str) -> Result<bool, BackendError> {
+        Ok(delete_password_info(&self.connection, name, self.master()?)? > 0)
+    }
+    /// Reads and decrypts every password in the vault, sorted by name.
+    /// Fails with `BackendError::Locked` if the vault is locked.
+    pub fn list(&self) -> Result<Vec<PasswordInfo>, BackendError> {
+        list_password_info(&self.connection, self.master()?)
+    }
+    /// Checks the whole vault for damage. See `verify::verify_vault()` for details.
+    /// Fails with `BackendError::Locked` if the vault is locked.
+    pub fn verify(&self) -> Result<VerifyReport, BackendError> {
+        verify_vault(&self.connection, self.master()?)
+    }
+    /// Checks the vault against its manifest, which frontends should do after unlocking it, and warn about a mismatch or
+    /// a missing manifest. See `manifest::verify_manifest()` for details. Fails with `BackendError::Locked` if the vault is locked.
+    pub fn check_manifest(&self) -> Result<ManifestStatus, BackendError> {
+        verify_manifest(&self.connection, self.master()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vault;
+    use crate::{
+        envelope::Algorithm, error::BackendError, login::LoginAttempt, manifest::ManifestStatus,
+        password::PasswordInfo,
+    };
+
+    #[test]
+    fn vault() {
+        let mut vault = Vault::open_in_memory().unwrap();
+        assert!(!vault.is_initialized().unwrap());
+        vault
+            .initialize("master", "recovery", Algorithm::XChaCha20Poly1305)
+            .unwrap();
+        assert!(vault.is_initialized().unwrap());
+        assert!(vault.is_unlocked());
+
+        let password = PasswordInfo {
+            name: "name".to_string(),
+            password: Some("pass".to_string()),
+            ..Default::default()
+        };
+        vault.put(&password).unwrap();
+        assert_eq!(
+            vault.get("name").unwrap().unwrap().password.unwrap(),
+            "pass"
+        );
+        assert_eq!(vault.list().unwrap().len(), 1);
+        assert!(vault.verify().unwrap().is_ok());
+        assert!(matches!(
+            vault.check_manifest().unwrap(),
+            ManifestStatus::Valid { .. }
+        ));
+
+        vault.lock();
+        assert!(matches!(vault.get("name"), Err(BackendError::Locked)));
+        assert!(matches!(vault.put(&password), Err(BackendError::Locked)));
+        assert!(matches!(vault.delete("name"), Err(BackendError::Locked)));
+        assert!(matches!(vault.verify(), Err(BackendError::Locked)));
+        assert!(matches!(vault.check_manifest(), Err(BackendError::Locked)));
+
+        assert!(matches!(
+            vault.unlock("wrong").unwrap(),
+            LoginAttempt::Failed { .. }
+        ));
+        assert!(!vault.is_unlocked());
+    }
+    #[test]
+    fn unlock() {
+        let mut vault = Vault::open_in_memory().unwrap();
+        vault
+            .initialize("master", "recovery", Algorithm::Aes256Gcm)
+            .unwrap();
+        vault.lock();
+        assert!(matches!(
+            vault.unlock("master").unwrap(),
+            LoginAttempt::Success { .. }
+        ));
+        assert!(vault.delete("name").is_ok_and(|existed| !existed));
+    }
+}
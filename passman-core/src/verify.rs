@@ -0,0 +1,324 @@
+//! Checking a whole vault for damage or tampering, rather than finding out when one password fails to decrypt.
+//!
+//! `verify_vault()` runs SQLite's own consistency checks, checks the master record's verifiers and the vault's manifest, and
+//! decrypts every field of every password and the encrypted metadata. Nothing is written, so it's safe to run from a cron job while the vault is in use.
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::{
+    crypto::{decrypt_password_field, gen_cipher},
+    db_ops::{
+        crud::get_all_password_info,
+        util::{authenticate, get_vault_algorithm},
+    },
+    envelope::Envelope,
+    error::BackendError,
+    manifest::{verify_manifest, ManifestStatus},
+    password::PasswordField,
+    team::Identity,
+};
+
+/// A field that couldn't be decrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DamagedField {
+    /// The name of the password the field belongs to.
+    pub password: String,
+    /// The field, e.g. `password` or `custom field #3`.
+    pub field: String,
+    pub error: String,
+}
+
+/// What `verify_vault()` found.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    /// What SQLite's `integrity_check` and `foreign_key_check` found wrong with the file itself.
+    pub integrity_errors: Vec<String>,
+    /// What's wrong with the master record, e.g. that the master password doesn't match its verifier.
+    pub master_record_errors: Vec<String>,
+    /// Encrypted or authenticated metadata that couldn't be read or doesn't match, e.g. the identity used by shared vaults,
+    /// or the manifest (see `manifest`).
+    pub metadata_errors: Vec<String>,
+    /// How many passwords were checked.
+    pub entries: usize,
+    /// How many fields were decrypted, including custom fields' keys and values.
+    pub fields: usize,
+    /// Every field that couldn't be decrypted.
+    pub damaged: Vec<DamagedField>,
+}
+
+impl VerifyReport {
+    /// Whether nothing is wrong with the vault.
+    pub fn is_ok(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.master_record_errors.is_empty()
+            && self.metadata_errors.is_empty()
+            && self.damaged.is_empty()
+    }
+    /// The names of the damaged passwords, in order, each listed once.
+    pub fn damaged_entries(&self) -> Vec<&str> {
+        let mut names = self
+            .damaged
+            .iter()
+            .map(|damaged| damaged.password.as_str())
+            .collect::<Vec<_>>();
+        names.dedup();
+        names
+    }
+}
+
+/// Checks a vault for damage: runs SQLite's `integrity_check` and `foreign_key_check`, checks the master password and recovery
+/// phrase verifiers in the master record, and decrypts every field of every password and the encrypted metadata.
+/// Problems with the vault are collected in the `VerifyReport`, rather than returned as errors.
+///
+/// If the master password doesn't match the master record, nothing is decrypted, since every field would fail.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `master` - a string slice holding the master password, or the key it stands for (see `keyfile` and `team`).
+///
+pub fn verify_vault(connection: &Connection, master: &str) -> Result<VerifyReport, BackendError> {
+    let mut report = VerifyReport::default();
+    for check in ["integrity_check", "foreign_key_check"] {
+        let result = connection
+            .prepare(&format!("pragma {}", check))
+            .and_then(|mut stmt| {
+                stmt.query_map((), |row| {
+                    // foreign_key_check returns the table, rowid, parent table and foreign key index of every orphan
+                    match check {
+                        "integrity_check" => row.get::<_, String>(0),
+                        _ => Ok(format!(
+                            "row {} of {} refers to a missing row in {}",
+                            row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(2)?
+                        )),
+                    }
+                })?
+                .collect::<Result<Vec<_>, _>>()
+            });
+        match result {
+            Ok(rows) => report
+                .integrity_errors
+                .extend(rows.into_iter().filter(|row| row != "ok")),
+            Err(e) => report.integrity_errors.push(e.to_string()),
+        }
+    }
+
+    match authenticate(connection, master, PasswordField::Password) {
+        Ok(true) => {}
+        Ok(false) => report
+            .master_record_errors
+            .push("the master password doesn't match the master record".to_string()),
+        Err(e) => report.master_record_errors.push(e.to_string()),
+    }
+    // only a damaged verifier fails, whatever the phrase
+    if let Err(e) = authenticate(connection, "", PasswordField::Notes) {
+        report
+            .master_record_errors
+            .push(format!("recovery phrase verifier: {}", e));
+    }
+    if !report.master_record_errors.is_empty() {
+        return Ok(report);
+    }
+
+    if let Err(e) = Identity::load(connection, master) {
+        report.metadata_errors.push(e.to_string());
+    }
+    match verify_manifest(connection, master) {
+        Ok(ManifestStatus::Valid { .. }) => {}
+        // an empty vault made by an older version has nothing to lose
+        Ok(ManifestStatus::Missing)
+            if get_all_password_info(connection).is_ok_and(|passwords| passwords.is_empty()) => {}
+        Ok(ManifestStatus::Missing) => report.metadata_errors.push(
+            "the vault has passwords but no manifest: it was made by an older version of passman, or the manifest was removed"
+                .to_string(),
+        ),
+        Ok(ManifestStatus::Mismatch { revision }) => report.metadata_errors.push(format!(
+            "the vault doesn't match its manifest (revision {}): passwords may have been deleted or rolled back",
+            revision
+        )),
+        Err(e) => report.metadata_errors.push(e.to_string()),
+    }
+
+    let algorithm = get_vault_algorithm(connection)?;
+    // a file too damaged to read is already reported by integrity_check
+    let passwords = match get_all_password_info(connection) {
+        Ok(passwords) => passwords,
+        Err(e) => {
+            report.integrity_errors.push(e.to_string());
+            return Ok(report);
+        }
+    };
+    for password in passwords {
+        report.entries += 1;
+        let cipher = gen_cipher(algorithm, master, &password.name);
+        let mut check = |field: String, data: &[u8]| {
+            report.fields += 1;
+            let decrypted = Envelope::from_bytes(data)
+                .map_err(BackendError::from)
+                .and_then(|envelope| decrypt_password_field(&envelope, &cipher));
+            if let Err(e) = decrypted {
+                report.damaged.push(DamagedField {
+                    password: password.name.clone(),
+                    field,
+                    error: e.to_string(),
+                });
+            }
+        };
+        for (column, data) in [
+            (PasswordField::Email, &password.email),
+            (PasswordField::Username, &password.username),
+            (PasswordField::Password, &password.password),
+            (PasswordField::Notes, &password.notes),
+            (PasswordField::Urls, &password.urls),
+            (PasswordField::Tags, &password.tags),
+            (PasswordField::Folder, &password.folder),
+        ] {
+            if let Some(data) = data {
+                check(column.to_string(), data);
+            }
+        }
+        for field in &password.fields {
+            check(format!("custom field #{} (key)", field.id), &field.key);
+            check(format!("custom field #{}", field.id), &field.value);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify_vault;
+    use crate::{
+        db_ops::{
+            crud::{insert_data, insert_entry_field},
+            util::{create_table, insert_master_record},
+            MASTER_KEYWORD,
+        },
+        envelope::Algorithm,
+        manifest::{MANIFEST_METADATA, SEALED_METADATA},
+        password::PasswordField,
+        team::Identity,
+    };
+    use rusqlite::Connection;
+
+    fn vault() -> Connection {
+        crate::init().unwrap();
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, "master", "recovery", Algorithm::Aes256Gcm).unwrap();
+        for name in ["bank", "mail"] {
+            insert_data(
+                &connection,
+                name,
+                "master",
+                PasswordField::Password,
+                "hunter2",
+            )
+            .unwrap();
+            insert_data(&connection, name, "master", PasswordField::Email, "a@b.c").unwrap();
+        }
+        insert_entry_field(&connection, "mail", "master", "PIN", "0000", true).unwrap();
+        Identity::load_or_create(&connection, "master").unwrap();
+        connection
+    }
+
+    #[test]
+    fn intact() {
+        let report = verify_vault(&vault(), "master").unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.entries, 2);
+        assert_eq!(report.fields, 6);
+    }
+    #[test]
+    fn unsealed() {
+        let connection = vault();
+        connection
+            .execute(
+                "delete from VaultMetadata where key in (?1, ?2)",
+                [MANIFEST_METADATA, SEALED_METADATA],
+            )
+            .unwrap();
+        let report = verify_vault(&connection, "master").unwrap();
+        assert_eq!(report.metadata_errors.len(), 1, "{:?}", report);
+
+        // an empty vault has nothing to lose
+        connection
+            .execute("delete from PasswordInfo where name != ?", [MASTER_KEYWORD])
+            .unwrap();
+        assert!(verify_vault(&connection, "master").unwrap().is_ok());
+    }
+    #[test]
+    fn damaged() {
+        let connection = vault();
+        // a flipped bit, and a ciphertext moved from another password
+        let mut email: Vec<u8> = connection
+            .query_row(
+                "select email from PasswordInfo where name = 'bank'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        *email.last_mut().unwrap() ^= 1;
+        connection
+            .execute(
+                "update PasswordInfo set email = ?1 where name = 'bank'",
+                [&email],
+            )
+            .unwrap();
+        connection
+            .execute(
+                "update PasswordInfo set password = (select password from PasswordInfo where name = 'bank') where name = 'mail'",
+                [],
+            )
+            .unwrap();
+        connection
+            .execute("update EntryField set value = x'00'", [])
+            .unwrap();
+
+        let report = verify_vault(&connection, "master").unwrap();
+        assert!(!report.is_ok());
+        assert!(report.integrity_errors.is_empty());
+        // changed outside of passman, so the manifest doesn't match either
+        assert_eq!(report.metadata_errors.len(), 1);
+        assert_eq!(report.damaged_entries(), ["bank", "mail"]);
+        let fields = report
+            .damaged
+            .iter()
+            .map(|damaged| damaged.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, ["email", "password", "custom field #1"]);
+    }
+    #[test]
+    fn master_record() {
+        let connection = vault();
+        let report = verify_vault(&connection, "wrong").unwrap();
+        assert_eq!(report.master_record_errors.len(), 1);
+        // nothing is decrypted with the wrong key
+        assert_eq!(report.fields, 0);
+
+        connection
+            .execute(
+                "update PasswordInfo set notes = 'not hex' where name = ?",
+                [MASTER_KEYWORD],
+            )
+            .unwrap();
+        let report = verify_vault(&connection, "master").unwrap();
+        assert_eq!(report.master_record_errors.len(), 1);
+        assert!(report.master_record_errors[0].starts_with("recovery phrase verifier"));
+    }
+    #[test]
+    fn metadata() {
+        let connection = vault();
+        connection
+            .execute(
+                "update VaultMetadata set value = x'00' where key = 'identity_secret_key'",
+                [],
+            )
+            .unwrap();
+        let report = verify_vault(&connection, "master").unwrap();
+        assert_eq!(report.metadata_errors.len(), 1);
+        assert!(report.damaged.is_empty());
+    }
+}
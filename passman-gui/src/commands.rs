@@ -0,0 +1,279 @@
+use std::{path::Path, time::Instant};
+
+use chrono::{DateTime, Utc};
+use passman_core::{
+    crypto::generate_password,
+    db_ops::MASTER_KEYWORD,
+    envelope::Algorithm,
+    error::BackendError,
+    keyfile::{composite_key, read_keyfile},
+    login::LoginAttempt,
+    manifest::ManifestStatus,
+    password::PasswordInfo,
+};
+use serde::Serialize;
+
+use crate::{error::CommandError, state::AppState};
+
+/// The longest password `generate()` will make.
+pub const MAX_GENERATED_LENGTH: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Whether the vault is set up and unlocked, so the frontend knows which screen to show.
+pub struct Status {
+    /// Whether the vault has a master password yet. If not, `initialize()` has to be called first.
+    pub initialized: bool,
+    /// Whether the vault is unlocked.
+    pub unlocked: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Returned by a successful `unlock()`, so the user can be warned about failed logins they didn't make,
+/// and about passwords deleted or rolled back outside of passman.
+pub struct Unlocked {
+    /// How many logins failed since the last successful one.
+    pub failed_attempts: u32,
+    /// When the last of them happened.
+    pub last_failed_at: Option<DateTime<Utc>>,
+    /// Whether the vault doesn't match its manifest, or the manifest is damaged (see `passman_core::manifest`).
+    /// Nothing can be changed until the vault is accepted as it is.
+    pub manifest_mismatch: bool,
+    /// Whether the vault has no manifest, because it was made by an older version of passman or the manifest was removed.
+    /// Nothing can be changed until it's sealed.
+    pub manifest_missing: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// The parts of a password shown in the list. Secrets (the password, notes and custom fields) are left out,
+/// so they only reach the webview when a single password is opened with `get()`.
+pub struct PasswordSummary {
+    /// The password name.
+    pub name: String,
+    /// The username, if any.
+    pub username: Option<String>,
+    /// The email, if any.
+    pub email: Option<String>,
+    /// Websites this password is used on.
+    pub urls: Vec<String>,
+    /// Tags of the password.
+    pub tags: Vec<String>,
+    /// The folder path, if any.
+    pub folder: Option<String>,
+}
+
+impl From<PasswordInfo> for PasswordSummary {
+    fn from(password: PasswordInfo) -> Self {
+        PasswordSummary {
+            name: password.name,
+            username: password.username,
+            email: password.email,
+            urls: password.urls,
+            tags: password.tags,
+            folder: password.folder,
+        }
+    }
+}
+
+/// Checks that a name the frontend sent can be used for a password.
+fn check_name(name: &str) -> Result<(), CommandError> {
+    if name.trim().is_empty() {
+        return Err(CommandError::InvalidInput(
+            "a password needs a name".to_string(),
+        ));
+    }
+    if name == MASTER_KEYWORD {
+        return Err(CommandError::InvalidInput(format!(
+            "\"{}\" is reserved",
+            MASTER_KEYWORD
+        )));
+    }
+    Ok(())
+}
+
+/// Returns whether the vault is set up and unlocked. Doesn't count as activity.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `now` - the current time.
+pub fn status(state: &AppState, now: Instant) -> Result<Status, CommandError> {
+    Ok(Status {
+        initialized: state.peek(|vault| vault.is_initialized())?,
+        unlocked: state.is_unlocked(now),
+    })
+}
+
+/// Sets up a new vault and leaves it unlocked.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `master` - the new master password.
+/// - `recovery_phrase` - the phrase that can be used to reset the master password.
+/// - `now` - the current time.
+pub fn initialize(
+    state: &AppState,
+    master: &str,
+    recovery_phrase: &str,
+    now: Instant,
+) -> Result<(), CommandError> {
+    if master.is_empty() || recovery_phrase.is_empty() {
+        return Err(CommandError::InvalidInput(
+            "the master password and recovery phrase can't be empty".to_string(),
+        ));
+    }
+    state.unlock_with(master, now, |vault| {
+        if vault.is_initialized()? {
+            return Err(CommandError::AlreadyInitialized);
+        }
+        Ok(vault.initialize(master, recovery_phrase, Algorithm::default())?)
+    })
+}
+
+/// Unlocks the vault with the master password. Failed attempts are throttled like in the CLI.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `master` - the master password entered by the user.
+/// - `keyfile` - the path of the keyfile, for vaults that require one (see `passman_core::keyfile`). Without it, unlocking
+///   such a vault fails with `BackendError::KeyfileRequired`.
+/// - `now` - the current time.
+pub fn unlock(
+    state: &AppState,
+    master: &str,
+    keyfile: Option<&Path>,
+    now: Instant,
+) -> Result<Unlocked, CommandError> {
+    let keyfile = keyfile.map(read_keyfile).transpose()?;
+    // the session holds the key the vault is encrypted with, which is the composite key if there's a keyfile
+    let key = match &keyfile {
+        Some(keyfile) => composite_key(master, keyfile),
+        None => master.to_string(),
+    };
+    state.unlock_with(&key, now, |vault| {
+        if !vault.is_initialized()? {
+            return Err(CommandError::NotInitialized);
+        }
+        let attempt = match &keyfile {
+            Some(keyfile) => vault.unlock_with_keyfile(master, keyfile)?,
+            None => vault.unlock(master)?,
+        };
+        match attempt {
+            LoginAttempt::Success {
+                failed_attempts,
+                last_failed_at,
+            } => {
+                // a damaged manifest is reported like a mismatch, rather than keeping the user out
+                let status = match vault.check_manifest() {
+                    Ok(status) => Some(status),
+                    Err(BackendError::CorruptMetadata { .. }) => None,
+                    Err(e) => return Err(e.into()),
+                };
+                Ok(Unlocked {
+                    failed_attempts,
+                    last_failed_at,
+                    manifest_mismatch: !matches!(
+                        status,
+                        Some(ManifestStatus::Valid { .. } | ManifestStatus::Missing)
+                    ),
+                    manifest_missing: status == Some(ManifestStatus::Missing),
+                })
+            }
+            LoginAttempt::Failed {
+                failed_attempts,
+                retry_at,
+            } => Err(CommandError::WrongPassword {
+                failed_attempts,
+                retry_at,
+            }),
+            LoginAttempt::Throttled { retry_at } => Err(CommandError::Throttled { retry_at }),
+            LoginAttempt::LockedOut { until } => Err(CommandError::LockedOut { until }),
+            LoginAttempt::Wiped => Err(CommandError::Wiped),
+        }
+    })
+}
+
+/// Locks the vault right away.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+pub fn lock(state: &AppState) {
+    state.lock();
+}
+
+/// Lists every password in the vault, sorted by name, without its secrets.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `now` - the current time.
+pub fn list(state: &AppState, now: Instant) -> Result<Vec<PasswordSummary>, CommandError> {
+    state.with_vault(now, |vault| {
+        Ok(vault
+            .list()?
+            .into_iter()
+            .map(PasswordSummary::from)
+            .collect())
+    })
+}
+
+/// Reads and decrypts a single password.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `name` - the name of the password.
+/// - `now` - the current time.
+pub fn get(state: &AppState, name: &str, now: Instant) -> Result<PasswordInfo, CommandError> {
+    check_name(name)?;
+    state.with_vault(now, |vault| {
+        vault
+            .get(name)?
+            .ok_or_else(|| CommandError::NotFound(name.to_string()))
+    })
+}
+
+/// Creates or updates a password. Fields that are `null` are left as they are. Returns the number of fields written.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `password` - the password to write. Its `id` and `timestamps` are ignored.
+/// - `now` - the current time.
+pub fn upsert(
+    state: &AppState,
+    password: &PasswordInfo,
+    now: Instant,
+) -> Result<usize, CommandError> {
+    check_name(&password.name)?;
+    state.with_vault(now, |vault| Ok(vault.put(password)?))
+}
+
+/// Deletes a password, returning whether it existed.
+///
+/// # Arguments
+///
+/// - `state` - the shared `AppState`.
+/// - `name` - the name of the password.
+/// - `now` - the current time.
+pub fn delete(state: &AppState, name: &str, now: Instant) -> Result<bool, CommandError> {
+    check_name(name)?;
+    state.with_vault(now, |vault| Ok(vault.delete(name)?))
+}
+
+/// Generates a random password. This doesn't touch the vault, so it works while it's locked.
+///
+/// # Arguments
+///
+/// - `length` - the length of the password, between 1 and `MAX_GENERATED_LENGTH`.
+pub fn generate(length: usize) -> Result<String, CommandError> {
+    if !(1..=MAX_GENERATED_LENGTH).contains(&length) {
+        return Err(CommandError::InvalidInput(format!(
+            "the length has to be between 1 and {}",
+            MAX_GENERATED_LENGTH
+        )));
+    }
+    Ok(generate_password(length))
+}
@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use passman_core::error::BackendError;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use thiserror::Error;
+
+/// Errors returned by the commands in `commands`.
+///
+/// These are sent to the webview as `{ "kind": ..., "message": ... }`, so the frontend can react to specific errors
+/// (e.g. show the unlock screen on `locked`) and display the rest as they are.
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("the vault is locked")]
+    Locked,
+    #[error("the vault hasn't been set up yet")]
+    NotInitialized,
+    #[error("the vault has already been set up")]
+    AlreadyInitialized,
+    #[error(
+        "wrong master password ({failed_attempts} failed attempts), try again after {retry_at}"
+    )]
+    WrongPassword {
+        failed_attempts: u32,
+        retry_at: DateTime<Utc>,
+    },
+    #[error("too many attempts, try again after {retry_at}")]
+    Throttled { retry_at: DateTime<Utc> },
+    #[error("too many failed logins, the vault is locked until {until}")]
+    LockedOut { until: DateTime<Utc> },
+    #[error("too many failed logins, the vault has been wiped")]
+    Wiped,
+    #[error("there is no password named \"{0}\"")]
+    NotFound(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+}
+
+impl CommandError {
+    /// A short, stable name for the error, for the frontend to match on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CommandError::Locked | CommandError::Backend(BackendError::Locked) => "locked",
+            CommandError::Backend(BackendError::KeyfileRequired) => "keyfile_required",
+            CommandError::NotInitialized => "not_initialized",
+            CommandError::AlreadyInitialized => "already_initialized",
+            CommandError::WrongPassword { .. } => "wrong_password",
+            CommandError::Throttled { .. } => "throttled",
+            CommandError::LockedOut { .. } => "locked_out",
+            CommandError::Wiped => "wiped",
+            CommandError::NotFound(_) => "not_found",
+            CommandError::InvalidInput(_) => "invalid_input",
+            CommandError::Backend(_) => "backend",
+        }
+    }
+}
+
+impl Serialize for CommandError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("CommandError", 2)?;
+        error.serialize_field("kind", self.kind())?;
+        // include the causes the message doesn't already mention, e.g. why a field is damaged
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(cause) = source {
+            let cause_message = cause.to_string();
+            if !message.contains(&cause_message) {
+                message.push_str(&format!(": {}", cause_message));
+            }
+            source = cause.source();
+        }
+        error.serialize_field("message", &message)?;
+        error.end()
+    }
+}
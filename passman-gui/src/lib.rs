@@ -0,0 +1,9 @@
+//! The desktop frontend for passman.
+//!
+//! The Tauri app (`src/main.rs`, built with `--features tauri`) is a thin shell: every command it exposes to the webview
+//! is a function in `commands`, which only depends on `passman-core`. That keeps the command layer testable without a
+//! display, a webview or the Tauri runtime.
+
+pub mod commands;
+pub mod error;
+pub mod state;
@@ -0,0 +1,130 @@
+// no console window next to the app on Windows release builds
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+
+#[cfg(feature = "tauri")]
+mod app {
+    use std::{
+        path::PathBuf,
+        process::ExitCode,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use passman_core::{
+        password::PasswordInfo,
+        session::DEFAULT_IDLE_TIMEOUT,
+        vault::{Vault, DEFAULT_VAULT_PATH},
+    };
+    use passman_gui::{
+        commands::{self, PasswordSummary, Status, Unlocked},
+        error::CommandError,
+        state::AppState,
+    };
+    use tauri::{Emitter, Manager, State};
+
+    /// How often the vault is checked for idleness.
+    const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    #[tauri::command]
+    fn status(state: State<AppState>) -> Result<Status, CommandError> {
+        commands::status(&state, Instant::now())
+    }
+    #[tauri::command]
+    fn initialize(
+        state: State<AppState>,
+        master: String,
+        recovery_phrase: String,
+    ) -> Result<(), CommandError> {
+        commands::initialize(&state, &master, &recovery_phrase, Instant::now())
+    }
+    #[tauri::command]
+    fn unlock(
+        state: State<AppState>,
+        master: String,
+        keyfile: Option<PathBuf>,
+    ) -> Result<Unlocked, CommandError> {
+        commands::unlock(&state, &master, keyfile.as_deref(), Instant::now())
+    }
+    #[tauri::command]
+    fn lock(state: State<AppState>) {
+        commands::lock(&state)
+    }
+    #[tauri::command]
+    fn list(state: State<AppState>) -> Result<Vec<PasswordSummary>, CommandError> {
+        commands::list(&state, Instant::now())
+    }
+    #[tauri::command]
+    fn get(state: State<AppState>, name: String) -> Result<PasswordInfo, CommandError> {
+        commands::get(&state, &name, Instant::now())
+    }
+    #[tauri::command]
+    fn upsert(state: State<AppState>, password: PasswordInfo) -> Result<usize, CommandError> {
+        commands::upsert(&state, &password, Instant::now())
+    }
+    #[tauri::command]
+    fn delete(state: State<AppState>, name: String) -> Result<bool, CommandError> {
+        commands::delete(&state, &name, Instant::now())
+    }
+    #[tauri::command]
+    fn generate(length: usize) -> Result<String, CommandError> {
+        commands::generate(length)
+    }
+
+    /// Runs the app. If the vault can't be opened there is nothing to show, so the error is printed and the app exits
+    /// with a failure code, like the CLI does.
+    pub fn run() -> ExitCode {
+        if let Err(e) = passman_core::init() {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+        let vault = match Vault::open(DEFAULT_VAULT_PATH) {
+            Ok(vault) => vault,
+            Err(e) => {
+                eprintln!(
+                    "error: couldn't open the vault at {}: {}",
+                    DEFAULT_VAULT_PATH, e
+                );
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let result = tauri::Builder::default()
+            .manage(AppState::new(vault, DEFAULT_IDLE_TIMEOUT))
+            .setup(|app| {
+                // the webview is told when the vault locks itself, so it can go back to the unlock screen
+                // without waiting for the next command to fail
+                let handle = app.handle().clone();
+                thread::spawn(move || loop {
+                    thread::sleep(IDLE_CHECK_INTERVAL);
+                    if handle.state::<AppState>().lock_if_idle(Instant::now()) {
+                        let _ = handle.emit("vault-locked", ());
+                    }
+                });
+                Ok(())
+            })
+            .invoke_handler(tauri::generate_handler![
+                status, initialize, unlock, lock, list, get, upsert, delete, generate
+            ])
+            .run(tauri::generate_context!());
+        match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tauri")]
+fn main() -> std::process::ExitCode {
+    app::run()
+}
+
+#[cfg(not(feature = "tauri"))]
+fn main() {
+    eprintln!(
+        "passman-gui was built without the `tauri` feature; rebuild it with `--features tauri`."
+    );
+    std::process::exit(1);
+}
@@ -0,0 +1,130 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use passman_core::{session::Session, vault::Vault};
+
+use crate::error::CommandError;
+
+/// The state shared by every command: the vault, and the session that keeps it unlocked.
+///
+/// The vault is only usable while the session is; once the session has been idle for longer than its timeout,
+/// the vault is locked again and commands fail with `CommandError::Locked` until the user unlocks it.
+/// Tauri calls commands from several threads, so everything lives behind a `Mutex`.
+pub struct AppState {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    vault: Vault,
+    session: Option<Session>,
+    idle_timeout: Duration,
+}
+
+impl Inner {
+    /// Locks the vault if the session has been idle for too long. Returns whether the vault is unlocked afterwards,
+    /// and counts as activity if it is.
+    fn refresh(&mut self, now: Instant) -> bool {
+        let active = self
+            .session
+            .as_mut()
+            .is_some_and(|session| session.master(now).is_some());
+        if !active {
+            self.session = None;
+            self.vault.lock();
+        }
+        active
+    }
+}
+
+impl AppState {
+    /// Wraps a (locked) vault.
+    ///
+    /// # Arguments
+    ///
+    /// - `vault` - the `Vault` the commands work on.
+    /// - `idle_timeout` - how long the vault may be idle before it locks, e.g. `session::DEFAULT_IDLE_TIMEOUT`.
+    pub fn new(vault: Vault, idle_timeout: Duration) -> Self {
+        AppState {
+            inner: Mutex::new(Inner {
+                vault,
+                session: None,
+                idle_timeout,
+            }),
+        }
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        // a command that panicked can't have left the vault half-written (writes are transactions),
+        // so there's no reason to refuse every later command
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs `f` with the unlocked vault, counting it as activity.
+    /// Fails with `CommandError::Locked` if the vault is locked or has been idle for too long.
+    pub(crate) fn with_vault<T>(
+        &self,
+        now: Instant,
+        f: impl FnOnce(&Vault) -> Result<T, CommandError>,
+    ) -> Result<T, CommandError> {
+        let mut inner = self.inner();
+        if !inner.refresh(now) {
+            return Err(CommandError::Locked);
+        }
+        f(&inner.vault)
+    }
+    /// Runs `f` with the vault whether it's locked or not, without counting as activity.
+    pub(crate) fn peek<T>(&self, f: impl FnOnce(&Vault) -> T) -> T {
+        f(&self.inner().vault)
+    }
+    /// Runs `f`, which is expected to unlock the vault with `master`, and starts a new session if it did.
+    pub(crate) fn unlock_with<T>(
+        &self,
+        master: &str,
+        now: Instant,
+        f: impl FnOnce(&mut Vault) -> Result<T, CommandError>,
+    ) -> Result<T, CommandError> {
+        let mut inner = self.inner();
+        let value = f(&mut inner.vault)?;
+        if inner.vault.is_unlocked() {
+            inner.session = Some(Session::new(master.to_string(), inner.idle_timeout, now));
+        }
+        Ok(value)
+    }
+
+    /// Whether the vault is unlocked. This does not count as activity, but does lock an idle vault.
+    pub fn is_unlocked(&self, now: Instant) -> bool {
+        let mut inner = self.inner();
+        match &inner.session {
+            Some(session) if !session.is_idle(now) => true,
+            _ => {
+                inner.session = None;
+                inner.vault.lock();
+                false
+            }
+        }
+    }
+    /// Locks the vault if it's been idle for too long. Returns `true` if it was locked by this call,
+    /// so the frontend can be told about it. Meant to be called periodically.
+    pub fn lock_if_idle(&self, now: Instant) -> bool {
+        let mut inner = self.inner();
+        let idle = inner
+            .session
+            .as_ref()
+            .is_some_and(|session| session.is_idle(now));
+        if idle {
+            inner.session = None;
+            inner.vault.lock();
+        }
+        idle
+    }
+    /// Locks the vault right away.
+    pub fn lock(&self) {
+        let mut inner = self.inner();
+        inner.session = None;
+        inner.vault.lock();
+    }
+}
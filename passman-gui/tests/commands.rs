@@ -0,0 +1,264 @@
+//! Tests for the commands behind the Tauri app. These call the command layer directly,
+//! so they don't need a display, a webview or the Tauri runtime.
+
+use std::time::{Duration, Instant};
+
+use passman_core::{
+    envelope::Algorithm,
+    keyfile::{add_keyfile, generate_keyfile},
+    password::PasswordInfo,
+    vault::Vault,
+};
+use passman_gui::{
+    commands::{self, Status},
+    error::CommandError,
+    state::AppState,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn state() -> AppState {
+    passman_core::init().unwrap();
+    AppState::new(Vault::open_in_memory().unwrap(), TIMEOUT)
+}
+
+fn unlocked_state(now: Instant) -> AppState {
+    let state = state();
+    commands::initialize(&state, "master", "recovery", now).unwrap();
+    state
+}
+
+fn password(name: &str, password: &str) -> PasswordInfo {
+    PasswordInfo {
+        name: name.to_string(),
+        username: Some("user".to_string()),
+        password: Some(password.to_string()),
+        tags: vec!["work".to_string()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn setup_and_unlock() {
+    let now = Instant::now();
+    let state = state();
+    assert_eq!(
+        commands::status(&state, now).unwrap(),
+        Status {
+            initialized: false,
+            unlocked: false
+        }
+    );
+    assert!(matches!(
+        commands::unlock(&state, "master", None, now),
+        Err(CommandError::NotInitialized)
+    ));
+
+    commands::initialize(&state, "master", "recovery", now).unwrap();
+    assert_eq!(
+        commands::status(&state, now).unwrap(),
+        Status {
+            initialized: true,
+            unlocked: true
+        }
+    );
+    assert!(matches!(
+        commands::initialize(&state, "other", "recovery", now),
+        Err(CommandError::AlreadyInitialized)
+    ));
+
+    commands::lock(&state);
+    assert!(!commands::status(&state, now).unwrap().unlocked);
+    assert!(matches!(
+        commands::unlock(&state, "wrong", None, now),
+        Err(CommandError::WrongPassword {
+            failed_attempts: 1,
+            ..
+        })
+    ));
+    // the next attempt comes too soon after the failed one
+    assert!(matches!(
+        commands::unlock(&state, "master", None, now),
+        Err(CommandError::Throttled { .. })
+    ));
+    assert!(!commands::status(&state, now).unwrap().unlocked);
+}
+
+#[test]
+fn crud() {
+    let now = Instant::now();
+    let state = unlocked_state(now);
+
+    assert_eq!(
+        commands::upsert(&state, &password("b", "secret"), now).unwrap(),
+        3
+    );
+    commands::upsert(&state, &password("a", "hunter2"), now).unwrap();
+
+    let list = commands::list(&state, now).unwrap();
+    let names: Vec<_> = list.iter().map(|summary| summary.name.as_str()).collect();
+    assert_eq!(names, ["a", "b"]);
+    assert_eq!(list[0].username.as_deref(), Some("user"));
+    assert_eq!(list[0].tags, ["work"]);
+
+    let update = PasswordInfo {
+        name: "a".to_string(),
+        password: Some("changed".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(commands::upsert(&state, &update, now).unwrap(), 1);
+    let a = commands::get(&state, "a", now).unwrap();
+    assert_eq!(a.password.as_deref(), Some("changed"));
+    // fields that weren't sent are left alone
+    assert_eq!(a.username.as_deref(), Some("user"));
+
+    assert!(commands::delete(&state, "a", now).unwrap());
+    assert!(!commands::delete(&state, "a", now).unwrap());
+    assert!(matches!(
+        commands::get(&state, "a", now),
+        Err(CommandError::NotFound(name)) if name == "a"
+    ));
+}
+
+#[test]
+fn reserved_names() {
+    let now = Instant::now();
+    let state = unlocked_state(now);
+    for name in ["", "  ", ".master"] {
+        assert!(matches!(
+            commands::upsert(&state, &password(name, "x"), now),
+            Err(CommandError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            commands::delete(&state, name, now),
+            Err(CommandError::InvalidInput(_))
+        ));
+    }
+    // the master record is untouched
+    commands::lock(&state);
+    commands::unlock(&state, "master", None, now).unwrap();
+}
+
+#[test]
+fn keyfile() {
+    let now = Instant::now();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("vault.db");
+    let keyfile_path = dir.path().join("vault.key");
+    {
+        passman_core::init().unwrap();
+        let mut vault = Vault::open(&path).unwrap();
+        vault
+            .initialize("master", "recovery", Algorithm::default())
+            .unwrap();
+        let keyfile = generate_keyfile(&keyfile_path).unwrap();
+        add_keyfile(vault.connection(), "master", &keyfile).unwrap();
+    }
+    let state = AppState::new(Vault::open(&path).unwrap(), TIMEOUT);
+
+    let error = commands::unlock(&state, "master", None, now).unwrap_err();
+    assert_eq!(error.kind(), "keyfile_required");
+    assert!(matches!(
+        commands::unlock(&state, "master", Some(&dir.path().join("missing.key")), now),
+        Err(CommandError::Backend(_))
+    ));
+    assert!(!commands::status(&state, now).unwrap().unlocked);
+
+    commands::unlock(&state, "master", Some(&keyfile_path), now).unwrap();
+    commands::upsert(&state, &password("a", "secret"), now).unwrap();
+    assert_eq!(
+        commands::get(&state, "a", now).unwrap().password.as_deref(),
+        Some("secret")
+    );
+}
+
+#[test]
+fn locked() {
+    let now = Instant::now();
+    let state = unlocked_state(now);
+    commands::upsert(&state, &password("a", "secret"), now).unwrap();
+    commands::lock(&state);
+
+    assert!(matches!(
+        commands::list(&state, now),
+        Err(CommandError::Locked)
+    ));
+    assert!(matches!(
+        commands::get(&state, "a", now),
+        Err(CommandError::Locked)
+    ));
+    assert!(matches!(
+        commands::upsert(&state, &password("b", "secret"), now),
+        Err(CommandError::Locked)
+    ));
+    assert!(matches!(
+        commands::delete(&state, "a", now),
+        Err(CommandError::Locked)
+    ));
+    // generating doesn't need the vault
+    assert_eq!(commands::generate(16).unwrap().chars().count(), 16);
+
+    commands::unlock(&state, "master", None, now).unwrap();
+    assert_eq!(commands::list(&state, now).unwrap().len(), 1);
+}
+
+#[test]
+fn auto_lock() {
+    let start = Instant::now();
+    let state = unlocked_state(start);
+
+    // using the vault keeps it unlocked
+    let later = start + TIMEOUT;
+    assert!(commands::list(&state, later).is_ok());
+    assert!(!state.lock_if_idle(later + TIMEOUT));
+    // checking the status doesn't count as activity
+    assert!(commands::status(&state, later + TIMEOUT).unwrap().unlocked);
+
+    let idle = later + TIMEOUT + Duration::from_secs(1);
+    assert!(state.lock_if_idle(idle));
+    // it's already locked, so it isn't locked again
+    assert!(!state.lock_if_idle(idle));
+    assert!(matches!(
+        commands::list(&state, idle),
+        Err(CommandError::Locked)
+    ));
+
+    commands::unlock(&state, "master", None, idle).unwrap();
+    // an idle vault is also locked by the next command, without waiting for `lock_if_idle()`
+    let idle = idle + TIMEOUT * 2;
+    assert!(matches!(
+        commands::get(&state, "a", idle),
+        Err(CommandError::Locked)
+    ));
+    assert!(!commands::status(&state, idle).unwrap().unlocked);
+}
+
+#[test]
+fn generate() {
+    let password = commands::generate(32).unwrap();
+    assert_eq!(password.chars().count(), 32);
+    assert!(matches!(
+        commands::generate(0),
+        Err(CommandError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        commands::generate(commands::MAX_GENERATED_LENGTH + 1),
+        Err(CommandError::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn serialized_errors() {
+    let state = state();
+    let error = commands::list(&state, Instant::now()).unwrap_err();
+    assert_eq!(
+        serde_json::to_value(&error).unwrap(),
+        serde_json::json!({ "kind": "locked", "message": "the vault is locked" })
+    );
+
+    // passwords sent by the webview may leave out any field but the name
+    let password: PasswordInfo =
+        serde_json::from_value(serde_json::json!({ "name": "a", "password": "secret" })).unwrap();
+    assert_eq!(password.password.as_deref(), Some("secret"));
+    assert!(password.username.is_none() && password.urls.is_empty());
+}
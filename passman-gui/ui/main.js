@@ -0,0 +1,141 @@
+const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
+
+const $ = (id) => document.getElementById(id);
+const list = (value) => value.split(",").map((item) => item.trim()).filter(Boolean);
+const orNull = (value) => (value === "" ? null : value);
+
+let entries = [];
+
+function show(screen) {
+  for (const id of ["setup", "unlock", "vault"]) {
+    $(id).hidden = id !== screen;
+  }
+}
+
+function showError(error) {
+  $("error").textContent = error?.message ?? String(error);
+  $("error").hidden = false;
+}
+
+// every command goes through here, so a vault that locked itself sends the user back to the unlock screen
+async function call(command, args) {
+  $("error").hidden = true;
+  try {
+    return await invoke(command, args);
+  } catch (error) {
+    if (error?.kind === "locked") {
+      show("unlock");
+    }
+    showError(error);
+    throw error;
+  }
+}
+
+async function refresh() {
+  const status = await call("status");
+  if (!status.initialized) {
+    show("setup");
+  } else if (!status.unlocked) {
+    show("unlock");
+  } else {
+    show("vault");
+    entries = await call("list");
+    renderEntries();
+  }
+}
+
+function renderEntries() {
+  const query = $("search").value.toLowerCase();
+  $("entries").replaceChildren(
+    ...entries
+      .filter((entry) => entry.name.toLowerCase().includes(query))
+      .map((entry) => {
+        const item = document.createElement("li");
+        item.textContent = entry.name;
+        item.addEventListener("click", () => open(entry.name));
+        return item;
+      }),
+  );
+}
+
+async function open(name) {
+  const password = await call("get", { name });
+  $("name").value = password.name;
+  for (const field of ["username", "email", "password", "folder", "notes"]) {
+    $(field).value = password[field] ?? "";
+  }
+  $("urls").value = password.urls.join(", ");
+  $("tags").value = password.tags.join(", ");
+  $("password").type = "password";
+}
+
+$("setup").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  await call("initialize", {
+    master: $("setup-master").value,
+    recoveryPhrase: $("setup-recovery").value,
+  });
+  await refresh();
+});
+
+$("unlock").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  const unlocked = await call("unlock", {
+    master: $("unlock-master").value,
+    keyfile: $("unlock-keyfile").value || null,
+  });
+  $("unlock-master").value = "";
+  await refresh();
+  if (unlocked.manifest_mismatch) {
+    showError("This vault doesn't match its manifest: passwords may have been deleted or rolled back outside of passman. Run `passman verify` to check it.");
+  } else if (unlocked.manifest_missing) {
+    showError("This vault has no manifest: it was made by an older version of passman, or the manifest was removed. Check your passwords, and run `passman verify --reseal` to seal it.");
+  } else if (unlocked.failed_attempts > 0) {
+    showError(`${unlocked.failed_attempts} failed login(s) since you last logged in.`);
+  }
+});
+
+$("entry").addEventListener("submit", async (event) => {
+  event.preventDefault();
+  await call("upsert", {
+    password: {
+      name: $("name").value,
+      username: orNull($("username").value),
+      email: orNull($("email").value),
+      password: orNull($("password").value),
+      notes: orNull($("notes").value),
+      folder: orNull($("folder").value),
+      urls: list($("urls").value),
+      tags: list($("tags").value),
+    },
+  });
+  await refresh();
+});
+
+$("delete").addEventListener("click", async () => {
+  await call("delete", { name: $("name").value });
+  $("entry").reset();
+  await refresh();
+});
+
+$("reveal").addEventListener("click", () => {
+  $("password").type = $("password").type === "password" ? "text" : "password";
+});
+$("generate").addEventListener("click", async () => {
+  $("password").value = await call("generate", { length: 20 });
+});
+$("new").addEventListener("click", () => $("entry").reset());
+$("lock").addEventListener("click", async () => {
+  await call("lock");
+  show("unlock");
+});
+$("search").addEventListener("input", renderEntries);
+
+listen("vault-locked", () => {
+  $("entry").reset();
+  entries = [];
+  show("unlock");
+});
+
+refresh();
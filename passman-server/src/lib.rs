@@ -0,0 +1,25 @@
+//! A self-hosted server for passman vaults.
+//!
+//! The server stores, per user, one vault blob and any number of entries. Clients encrypt everything before it's sent
+//! (see `passman_core::remote`), so the server only ever sees usernames, entry ids and opaque blobs. Clients derive
+//! entry ids from the password names, and send the names themselves encrypted, as labels.
+//!
+//! # API
+//!
+//! Every endpoint except registration needs HTTP Basic authentication with the username and the user's auth key.
+//! After too many failed logins in a row, the user's logins are refused with 429 for a while.
+//! Errors are returned as `{ "error": "..." }`.
+//!
+//! - `POST /v1/register` - creates a user from `{ "username": ..., "auth_key": ... }`, if registration is allowed.
+//! - `GET`/`PUT /v1/vault` - reads or replaces the user's vault blob.
+//! - `GET /v1/entries` - lists the user's entries as
+//!   `[{ "id": ..., "label": ..., "revision": ..., "updated_at": ..., "deleted": ... }]`.
+//!   Deleted entries are kept, without their data, so other devices can sync the deletion.
+//! - `GET`/`PUT`/`DELETE /v1/entries/{id}` - reads, replaces or deletes an entry.
+//!   `PUT` needs the entry's label in an `X-Label` header, base64 encoded.
+//!   `GET` sends the entry's revision in an `X-Revision` header, also with the 404 for deleted entries.
+//!   `PUT` and `DELETE` return `{ "revision": ... }` with the new revision. If they're sent with an `X-Expected-Revision`
+//!   header (0 for entries that don't exist), they fail with 409 if the entry has a different revision by now.
+
+pub mod server;
+pub mod store;
@@ -0,0 +1,37 @@
+use anyhow::Context;
+use clap::Parser;
+use rusqlite::Connection;
+
+use passman_server::{server::Server, store::Store};
+
+/// Command line arguments.
+#[derive(Parser)]
+#[command(
+    name = "passman-server",
+    version,
+    about = "A self-hosted server for passman vaults"
+)]
+struct Args {
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:8750")]
+    listen: String,
+    /// The database file to keep users and their encrypted entries in
+    #[arg(long, default_value = "./server.db")]
+    db: String,
+    /// Let anyone who can reach the server create a user
+    #[arg(long)]
+    allow_registration: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let connection = Connection::open(&args.db).context("could not open the database")?;
+    let store = Store::new(connection).context("could not set up the database")?;
+    let server = Server::bind(&args.listen, store, args.allow_registration)?;
+    match server.addr() {
+        Some(addr) => println!("passman-server listening on http://{}", addr),
+        None => println!("passman-server listening on {}", args.listen),
+    }
+    server.run();
+    Ok(())
+}
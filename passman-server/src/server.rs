@@ -0,0 +1,358 @@
+use std::{io::Read, net::SocketAddr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::store::{LoginOutcome, Store, WriteOutcome};
+
+/// The largest request body the server accepts, in bytes.
+pub const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+/// Errors that may occur while starting the server.
+pub enum ServerError {
+    #[error("couldn't listen on {addr}: {reason}")]
+    Bind { addr: String, reason: String },
+
+    #[error("error opening the database: {0}")]
+    SQLiteError(#[from] rusqlite::Error),
+}
+
+#[derive(Deserialize)]
+struct Registration {
+    username: String,
+    auth_key: String,
+}
+
+/// A response before it's sent, so handlers don't have to deal with `tiny_http` directly.
+struct Reply {
+    status: u16,
+    body: Vec<u8>,
+    content_type: &'static str,
+    /// The entry's revision, sent as `X-Revision`.
+    revision: Option<u64>,
+}
+
+impl Reply {
+    fn empty(status: u16) -> Self {
+        Reply {
+            status,
+            body: Vec::new(),
+            content_type: "text/plain",
+            revision: None,
+        }
+    }
+    fn bytes(body: Vec<u8>) -> Self {
+        Reply {
+            status: 200,
+            body,
+            content_type: "application/octet-stream",
+            revision: None,
+        }
+    }
+    fn json(status: u16, body: &impl serde::Serialize) -> Self {
+        Reply {
+            status,
+            body: serde_json::to_vec(body).unwrap_or_default(),
+            content_type: "application/json",
+            revision: None,
+        }
+    }
+    fn error(status: u16, message: &str) -> Self {
+        Reply::json(status, &serde_json::json!({ "error": message }))
+    }
+    fn with_revision(self, revision: Option<u64>) -> Self {
+        Reply { revision, ..self }
+    }
+}
+
+/// A passman server, serving the API described in the crate documentation over HTTP.
+///
+/// The server doesn't do TLS itself; put it behind a reverse proxy that does if it's reachable from other machines.
+pub struct Server {
+    http: tiny_http::Server,
+    store: Store,
+    allow_registration: bool,
+}
+
+/// Checks that a username is reasonable: 1 to 64 letters, digits or any of `-_.@`.
+fn valid_username(username: &str) -> bool {
+    (1..=64).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c))
+}
+
+/// Decodes a percent-encoded path segment. Returns `None` if it isn't valid UTF-8 once decoded.
+fn decode_segment(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = segment.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Reads the username and auth key from an `Authorization: Basic ...` header.
+fn credentials(request: &Request) -> Option<(String, String)> {
+    let header = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))?;
+    let encoded = header.value.as_str().strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, auth_key) = decoded.split_once(':')?;
+    Some((username.to_string(), auth_key.to_string()))
+}
+
+/// Reads the optional `X-Expected-Revision` header.
+fn expected_revision(request: &Request) -> Result<Option<u64>, Reply> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("X-Expected-Revision"))
+        .map(|header| {
+            header
+                .value
+                .as_str()
+                .trim()
+                .parse()
+                .map_err(|_| Reply::error(400, "invalid X-Expected-Revision header"))
+        })
+        .transpose()
+}
+
+/// Reads the `X-Label` header every written entry needs: the encrypted entry name, base64 encoded.
+fn label(request: &Request) -> Result<String, Reply> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("X-Label"))
+        .map(|header| header.value.as_str().trim().to_string())
+        .filter(|label| !label.is_empty() && STANDARD.decode(label).is_ok())
+        .ok_or_else(|| Reply::error(400, "missing or invalid X-Label header"))
+}
+
+fn read_body(request: &mut Request) -> Result<Vec<u8>, Reply> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|_| Reply::error(400, "couldn't read the request body"))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(Reply::error(413, "the request body is too large"));
+    }
+    Ok(body)
+}
+
+impl Server {
+    /// Starts listening, but doesn't handle any requests until `run()` is called.
+    ///
+    /// # Arguments
+    ///
+    /// - `addr` - the address to listen on, e.g. `127.0.0.1:8750`. Use port 0 to pick a free port.
+    /// - `store` - the `Store` to keep data in.
+    /// - `allow_registration` - whether anyone who can reach the server may create a user.
+    pub fn bind(addr: &str, store: Store, allow_registration: bool) -> Result<Self, ServerError> {
+        let http = tiny_http::Server::http(addr).map_err(|e| ServerError::Bind {
+            addr: addr.to_string(),
+            reason: e.to_string(),
+        })?;
+        Ok(Server {
+            http,
+            store,
+            allow_registration,
+        })
+    }
+    /// The address the server is listening on.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Handles requests, one at a time, until the process exits.
+    pub fn run(&self) {
+        for mut request in self.http.incoming_requests() {
+            let reply = self.handle(&mut request).unwrap_or_else(|reply| reply);
+            let content_type = Header::from_bytes("Content-Type", reply.content_type)
+                .expect("content types are valid headers");
+            let mut response = Response::from_data(reply.body)
+                .with_status_code(reply.status)
+                .with_header(content_type);
+            if let Some(revision) = reply.revision {
+                response.add_header(
+                    Header::from_bytes("X-Revision", revision.to_string())
+                        .expect("numbers are valid headers"),
+                );
+            }
+            if let Err(e) = request.respond(response) {
+                eprintln!("error sending a response: {}", e);
+            }
+        }
+    }
+
+    fn handle(&self, request: &mut Request) -> Result<Reply, Reply> {
+        // copied, because the request has to be borrowed mutably to read the body
+        let url = request.url().to_string();
+        let method = request.method().clone();
+        let path = url.split('?').next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+        if let (Method::Post, ["v1", "register"]) = (&method, segments.as_slice()) {
+            return self.register(request);
+        }
+
+        let user_id = self.authenticate(request)?;
+        match (&method, segments.as_slice()) {
+            (Method::Get, ["v1", "vault"]) => match self.store.get_vault(user_id) {
+                Ok(Some(data)) => Ok(Reply::bytes(data)),
+                Ok(None) => Err(Reply::error(404, "the vault hasn't been set up")),
+                Err(e) => Err(internal_error(e)),
+            },
+            (Method::Put, ["v1", "vault"]) => {
+                let body = read_body(request)?;
+                self.store
+                    .put_vault(user_id, &body)
+                    .map_err(internal_error)?;
+                Ok(Reply::empty(204))
+            }
+            (Method::Get, ["v1", "entries"]) => {
+                let entries = self.store.list_entries(user_id).map_err(internal_error)?;
+                Ok(Reply::json(200, &entries))
+            }
+            (_, ["v1", "entries", id]) => {
+                let id = decode_segment(id).ok_or_else(|| Reply::error(400, "invalid entry id"))?;
+                match method {
+                    Method::Get => match self.store.get_entry(user_id, &id) {
+                        Ok((Some(data), revision)) => {
+                            Ok(Reply::bytes(data).with_revision(revision))
+                        }
+                        // deleted entries still have a revision, for clients that want to overwrite them
+                        Ok((None, revision)) => {
+                            Err(Reply::error(404, "no such entry").with_revision(revision))
+                        }
+                        Err(e) => Err(internal_error(e)),
+                    },
+                    Method::Put => {
+                        let expected = expected_revision(request)?;
+                        let label = label(request)?;
+                        let body = read_body(request)?;
+                        let outcome = self
+                            .store
+                            .put_entry(user_id, &id, &label, &body, expected)
+                            .map_err(internal_error)?;
+                        write_reply(outcome)
+                    }
+                    Method::Delete => {
+                        let expected = expected_revision(request)?;
+                        let outcome = self
+                            .store
+                            .delete_entry(user_id, &id, expected)
+                            .map_err(internal_error)?;
+                        write_reply(outcome)
+                    }
+                    _ => Err(Reply::error(405, "method not allowed")),
+                }
+            }
+            _ => Err(Reply::error(404, "not found")),
+        }
+    }
+
+    fn register(&self, request: &mut Request) -> Result<Reply, Reply> {
+        if !self.allow_registration {
+            return Err(Reply::error(403, "registration is disabled on this server"));
+        }
+        let body = read_body(request)?;
+        let registration: Registration = serde_json::from_slice(&body)
+            .map_err(|_| Reply::error(400, "expected a username and auth_key"))?;
+        if !valid_username(&registration.username) {
+            return Err(Reply::error(400, "invalid username"));
+        }
+        if registration.auth_key.len() != 64
+            || !registration.auth_key.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(Reply::error(400, "invalid auth key"));
+        }
+        match self
+            .store
+            .register(&registration.username, &registration.auth_key)
+        {
+            Ok(true) => Ok(Reply::empty(201)),
+            Ok(false) => Err(Reply::error(409, "that username is taken")),
+            Err(e) => Err(internal_error(e)),
+        }
+    }
+
+    fn authenticate(&self, request: &Request) -> Result<i64, Reply> {
+        let unauthorized = || Reply::error(401, "wrong username or auth key");
+        let (username, auth_key) = credentials(request).ok_or_else(unauthorized)?;
+        match self
+            .store
+            .authenticate(&username, &auth_key)
+            .map_err(internal_error)?
+        {
+            LoginOutcome::LoggedIn(user_id) => Ok(user_id),
+            LoginOutcome::WrongCredentials => Err(unauthorized()),
+            LoginOutcome::TooManyAttempts => {
+                Err(Reply::error(429, "too many failed logins, try again later"))
+            }
+        }
+    }
+}
+
+fn write_reply(outcome: WriteOutcome) -> Result<Reply, Reply> {
+    match outcome {
+        WriteOutcome::Written(revision) => Ok(Reply::json(
+            200,
+            &serde_json::json!({ "revision": revision }),
+        )),
+        WriteOutcome::NotFound => Err(Reply::error(404, "no such entry")),
+        WriteOutcome::Conflict(revision) => Err(Reply::error(
+            409,
+            "the entry was changed by someone else in the meantime",
+        )
+        .with_revision(Some(revision))),
+    }
+}
+
+fn internal_error(error: rusqlite::Error) -> Reply {
+    eprintln!("database error: {}", error);
+    Reply::error(500, "internal server error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_segment, valid_username};
+
+    #[test]
+    fn segments() {
+        assert_eq!(decode_segment("GitHub").unwrap(), "GitHub");
+        assert_eq!(
+            decode_segment("work%2Fmail%20box").unwrap(),
+            "work/mail box"
+        );
+        assert_eq!(decode_segment("%C3%BC").unwrap(), "ü");
+        assert!(decode_segment("%C3").is_none());
+        assert!(decode_segment("%zz").is_none());
+        assert!(decode_segment("%2").is_none());
+    }
+    #[test]
+    fn usernames() {
+        assert!(valid_username("alice"));
+        assert!(valid_username("alice.smith@example.com"));
+        assert!(!valid_username(""));
+        assert!(!valid_username("al ice"));
+        assert!(!valid_username("alice:bob"));
+        assert!(!valid_username(&"a".repeat(65)));
+    }
+}
@@ -0,0 +1,610 @@
+use chrono::{DateTime, Duration, Utc};
+use pbkdf2::pbkdf2_hmac;
+use rand::{rngs::OsRng, RngCore};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// How many times in a row a user can fail to log in before logins are refused for `LOCKOUT_SECONDS`.
+pub const MAX_FAILED_LOGINS: u32 = 5;
+/// How long logins are refused for after `MAX_FAILED_LOGINS` failed ones, in seconds.
+pub const LOCKOUT_SECONDS: i64 = 60;
+/// How many PBKDF2 rounds auth keys are hashed with. Auth keys are derived from the master password by the client already,
+/// so this only has to make a leaked database slow to check guesses against.
+const AUTH_HASH_ROUNDS: u32 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// An entry as listed by `GET /v1/entries`.
+pub struct EntryInfo {
+    /// The id the client chose for the entry. Clients derive it from the password name, which the server never sees.
+    pub id: String,
+    /// The encrypted password name, base64 encoded, for the client to list entries with.
+    /// Empty for entries written before clients sent one.
+    pub label: String,
+    /// How many times the entry was written. Starts at 1.
+    pub revision: u64,
+    /// When the entry was last written.
+    pub updated_at: DateTime<Utc>,
+    /// Whether the entry was deleted. Deleted entries are kept without their data, so other devices can sync the deletion.
+    pub deleted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The result of logging in.
+pub enum LoginOutcome {
+    /// The username and auth key match. Holds the id of the user.
+    LoggedIn(i64),
+    /// There's no such user, or the auth key is wrong.
+    WrongCredentials,
+    /// The user failed to log in too many times in a row, and has to wait before trying again.
+    TooManyAttempts,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The result of writing or deleting an entry.
+pub enum WriteOutcome {
+    /// The entry was written, and has this revision now.
+    Written(u64),
+    /// There is no such entry to delete.
+    NotFound,
+    /// The client expected a different revision, because someone else wrote the entry in the meantime. Holds the actual revision.
+    Conflict(u64),
+}
+
+/// The server's database: users, their vault blobs and their entries. None of the data can be decrypted by the server.
+pub struct Store {
+    connection: Connection,
+}
+
+/// Hashes an auth key with a new random salt for storage, so a leaked database can't be used to log in.
+/// The result is `pbkdf2-sha256$<rounds>$<salt>$<hash>`, with the salt and hash hex encoded.
+fn hash_auth_key(auth_key: &str) -> String {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let mut hash = [0u8; 32];
+    pbkdf2_hmac::<Sha256>(auth_key.as_bytes(), &salt, AUTH_HASH_ROUNDS, &mut hash);
+    format!(
+        "pbkdf2-sha256${}${}${}",
+        AUTH_HASH_ROUNDS,
+        hex::encode(salt),
+        hex::encode(hash)
+    )
+}
+
+/// Checks an auth key against a hash from `hash_auth_key()`, or against a plain SHA-256 hash,
+/// which is how auth keys were stored before they were salted. The hashes are compared in constant time.
+fn verify_auth_key(auth_key: &str, auth_hash: &str) -> bool {
+    let (expected, actual) = match auth_hash.split('$').collect::<Vec<_>>().as_slice() {
+        ["pbkdf2-sha256", rounds, salt, hash] => {
+            let (Ok(rounds), Ok(salt), Ok(hash)) =
+                (rounds.parse(), hex::decode(salt), hex::decode(hash))
+            else {
+                return false;
+            };
+            let mut actual = vec![0u8; hash.len()];
+            pbkdf2_hmac::<Sha256>(auth_key.as_bytes(), &salt, rounds, &mut actual);
+            (hash, actual)
+        }
+        [legacy] => {
+            let Ok(hash) = hex::decode(legacy) else {
+                return false;
+            };
+            (hash, Sha256::digest(auth_key.as_bytes()).to_vec())
+        }
+        _ => return false,
+    };
+    expected.ct_eq(&actual).into()
+}
+
+/// Whether an auth hash was stored before auth keys were salted.
+fn is_legacy_hash(auth_hash: &str) -> bool {
+    !auth_hash.contains('$')
+}
+
+impl Store {
+    /// Opens (or creates) the server's database.
+    ///
+    /// # Arguments
+    ///
+    /// - `connection` - a `rusqlite::Connection`, which may be to a file or in memory.
+    pub fn new(connection: Connection) -> Result<Self, rusqlite::Error> {
+        connection.execute_batch(
+            "create table if not exists Users (
+                id integer primary key,
+                username text unique not null,
+                auth_hash text not null,
+                created_at text not null,
+                failed_logins integer not null default 0,
+                locked_until text
+            );
+            create table if not exists Vaults (
+                user_id integer primary key references Users(id),
+                data blob not null,
+                updated_at text not null
+            );
+            create table if not exists Entries (
+                user_id integer not null references Users(id),
+                id text not null,
+                label text not null,
+                data blob not null,
+                revision integer not null,
+                updated_at text not null,
+                primary key (user_id, id)
+            );",
+        )?;
+        // stores created when clients still sent plaintext names
+        let has_label = connection
+            .prepare("select 1 from pragma_table_info('Entries') where name = 'label'")?
+            .exists([])?;
+        if !has_label {
+            connection.execute_batch(
+                "alter table Entries rename column name to id;
+                alter table Entries add column label text not null default '';",
+            )?;
+        }
+        // stores created before failed logins were limited
+        let has_failed_logins = connection
+            .prepare("select 1 from pragma_table_info('Users') where name = 'failed_logins'")?
+            .exists([])?;
+        if !has_failed_logins {
+            connection.execute_batch(
+                "alter table Users add column failed_logins integer not null default 0;
+                alter table Users add column locked_until text;",
+            )?;
+        }
+        // stores created before deleted entries were kept
+        let has_deleted = connection
+            .prepare("select 1 from pragma_table_info('Entries') where name = 'deleted'")?
+            .exists([])?;
+        if !has_deleted {
+            connection.execute(
+                "alter table Entries add column deleted integer not null default 0",
+                [],
+            )?;
+        }
+        Ok(Store { connection })
+    }
+
+    /// Creates a user. Returns `false` if the username is taken.
+    ///
+    /// # Arguments
+    ///
+    /// - `username` - the name of the new user.
+    /// - `auth_key` - the key the user will log in with.
+    pub fn register(&self, username: &str, auth_key: &str) -> Result<bool, rusqlite::Error> {
+        let inserted = self.connection.execute(
+            "insert into Users(username, auth_hash, created_at) values (?1, ?2, ?3) on conflict(username) do nothing",
+            (username, hash_auth_key(auth_key), Utc::now()),
+        )?;
+        Ok(inserted > 0)
+    }
+    /// Checks a user's username and auth key.
+    /// After `MAX_FAILED_LOGINS` failed logins in a row, the user can't log in for `LOCKOUT_SECONDS`, even with the right key.
+    /// Hashes stored before auth keys were salted are replaced with salted ones on the first successful login.
+    ///
+    /// # Arguments
+    ///
+    /// - `username` - the name of the user.
+    /// - `auth_key` - the key the user logged in with.
+    pub fn authenticate(
+        &self,
+        username: &str,
+        auth_key: &str,
+    ) -> Result<LoginOutcome, rusqlite::Error> {
+        let user = self
+            .connection
+            .query_row(
+                "select id, auth_hash, failed_logins, locked_until from Users where username = ?",
+                [username],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u32>(2)?,
+                        row.get::<_, Option<DateTime<Utc>>>(3)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let Some((id, auth_hash, failed_logins, locked_until)) = user else {
+            // hash anyway, so unknown usernames can't be told apart by how long they take
+            verify_auth_key(auth_key, &hash_auth_key(""));
+            return Ok(LoginOutcome::WrongCredentials);
+        };
+        let now = Utc::now();
+        if locked_until.is_some_and(|locked_until| locked_until > now) {
+            return Ok(LoginOutcome::TooManyAttempts);
+        }
+        if !verify_auth_key(auth_key, &auth_hash) {
+            let failed_logins = failed_logins + 1;
+            let locked_until = (failed_logins >= MAX_FAILED_LOGINS)
+                .then(|| now + Duration::seconds(LOCKOUT_SECONDS));
+            // the count starts over once the lockout is over
+            self.connection.execute(
+                "update Users set failed_logins = ?2, locked_until = ?3 where id = ?1",
+                (
+                    id,
+                    if locked_until.is_some() {
+                        0
+                    } else {
+                        failed_logins
+                    },
+                    locked_until,
+                ),
+            )?;
+            return Ok(LoginOutcome::WrongCredentials);
+        }
+        if is_legacy_hash(&auth_hash) {
+            self.connection.execute(
+                "update Users set auth_hash = ?2 where id = ?1",
+                (id, hash_auth_key(auth_key)),
+            )?;
+        }
+        if failed_logins > 0 || locked_until.is_some() {
+            self.connection.execute(
+                "update Users set failed_logins = 0, locked_until = null where id = ?1",
+                [id],
+            )?;
+        }
+        Ok(LoginOutcome::LoggedIn(id))
+    }
+
+    /// Reads a user's vault blob, if it was ever written.
+    pub fn get_vault(&self, user_id: i64) -> Result<Option<Vec<u8>>, rusqlite::Error> {
+        self.connection
+            .query_row(
+                "select data from Vaults where user_id = ?",
+                [user_id],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+    /// Replaces a user's vault blob.
+    pub fn put_vault(&self, user_id: i64, data: &[u8]) -> Result<(), rusqlite::Error> {
+        self.connection.execute(
+            "insert into Vaults(user_id, data, updated_at) values (?1, ?2, ?3)
+            on conflict(user_id) do update set data = ?2, updated_at = ?3",
+            (user_id, data, Utc::now()),
+        )?;
+        Ok(())
+    }
+
+    /// Lists a user's entries, including deleted ones, sorted by id.
+    pub fn list_entries(&self, user_id: i64) -> Result<Vec<EntryInfo>, rusqlite::Error> {
+        let mut stmt = self.connection.prepare(
+            "select id, label, revision, updated_at, deleted from Entries where user_id = ? order by id",
+        )?;
+        let entries = stmt
+            .query_map([user_id], |row| {
+                Ok(EntryInfo {
+                    id: row.get(0)?,
+                    label: row.get(1)?,
+                    revision: row.get(2)?,
+                    updated_at: row.get(3)?,
+                    deleted: row.get(4)?,
+                })
+            })?
+            .collect();
+        entries
+    }
+    /// Reads one of a user's entries and its revision. Deleted entries have a revision but no data,
+    /// and entries that never existed have neither.
+    pub fn get_entry(
+        &self,
+        user_id: i64,
+        id: &str,
+    ) -> Result<(Option<Vec<u8>>, Option<u64>), rusqlite::Error> {
+        let entry = self
+            .connection
+            .query_row(
+                "select data, revision, deleted from Entries where user_id = ?1 and id = ?2",
+                (user_id, id),
+                |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, u64>(1)?,
+                        row.get::<_, bool>(2)?,
+                    ))
+                },
+            )
+            .optional()?;
+        Ok(match entry {
+            Some((data, revision, deleted)) => ((!deleted).then_some(data), Some(revision)),
+            None => (None, None),
+        })
+    }
+    /// Creates or replaces one of a user's entries, including deleted ones.
+    ///
+    /// # Arguments
+    ///
+    /// - `user_id` - the id of the user, from `authenticate()`.
+    /// - `id` - the id of the entry.
+    /// - `label` - the encrypted name of the entry, base64 encoded.
+    /// - `data` - the encrypted entry.
+    /// - `expected_revision` - if given, the entry is only written if this is its current revision (0 if it doesn't exist).
+    pub fn put_entry(
+        &self,
+        user_id: i64,
+        id: &str,
+        label: &str,
+        data: &[u8],
+        expected_revision: Option<u64>,
+    ) -> Result<WriteOutcome, rusqlite::Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let (_, current) = self.get_entry(user_id, id)?;
+        let current = current.unwrap_or(0);
+        if expected_revision.is_some_and(|expected| expected != current) {
+            return Ok(WriteOutcome::Conflict(current));
+        }
+        transaction.execute(
+            "insert into Entries(user_id, id, label, data, revision, updated_at, deleted) values (?1, ?2, ?3, ?4, ?5, ?6, 0)
+            on conflict(user_id, id) do update set label = ?3, data = ?4, revision = ?5, updated_at = ?6, deleted = 0",
+            (user_id, id, label, data, current + 1, Utc::now()),
+        )?;
+        transaction.commit()?;
+        Ok(WriteOutcome::Written(current + 1))
+    }
+    /// Deletes one of a user's entries, leaving a tombstone behind.
+    ///
+    /// # Arguments
+    ///
+    /// - `user_id` - the id of the user, from `authenticate()`.
+    /// - `id` - the id of the entry.
+    /// - `expected_revision` - if given, the entry is only deleted if this is its current revision.
+    pub fn delete_entry(
+        &self,
+        user_id: i64,
+        id: &str,
+        expected_revision: Option<u64>,
+    ) -> Result<WriteOutcome, rusqlite::Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        let (data, current) = self.get_entry(user_id, id)?;
+        let (Some(_), Some(current)) = (data, current) else {
+            return Ok(WriteOutcome::NotFound);
+        };
+        if expected_revision.is_some_and(|expected| expected != current) {
+            return Ok(WriteOutcome::Conflict(current));
+        }
+        transaction.execute(
+            "update Entries set data = x'', revision = ?3, updated_at = ?4, deleted = 1 where user_id = ?1 and id = ?2",
+            (user_id, id, current + 1, Utc::now()),
+        )?;
+        transaction.commit()?;
+        Ok(WriteOutcome::Written(current + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoginOutcome, Store, WriteOutcome, MAX_FAILED_LOGINS};
+    use rusqlite::Connection;
+    use sha2::{Digest, Sha256};
+
+    /// Logs in, and returns the id of the user.
+    fn log_in(store: &Store, username: &str, auth_key: &str) -> i64 {
+        match store.authenticate(username, auth_key).unwrap() {
+            LoginOutcome::LoggedIn(id) => id,
+            outcome => panic!("couldn't log in: {:?}", outcome),
+        }
+    }
+    fn auth_hash(store: &Store, username: &str) -> String {
+        store
+            .connection
+            .query_row(
+                "select auth_hash from Users where username = ?",
+                [username],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn users() {
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        assert!(store.register("alice", "key").unwrap());
+        assert!(!store.register("alice", "other").unwrap());
+        log_in(&store, "alice", "key");
+        assert_eq!(
+            store.authenticate("alice", "other").unwrap(),
+            LoginOutcome::WrongCredentials
+        );
+        assert_eq!(
+            store.authenticate("bob", "key").unwrap(),
+            LoginOutcome::WrongCredentials
+        );
+
+        // only a salted hash of the key is stored
+        store.register("bob", "key").unwrap();
+        assert!(!auth_hash(&store, "alice").contains("key"));
+        assert_ne!(auth_hash(&store, "alice"), auth_hash(&store, "bob"));
+    }
+    #[test]
+    fn legacy_hashes() {
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        store
+            .connection
+            .execute(
+                "insert into Users(username, auth_hash, created_at) values ('alice', ?, '2024-01-01T00:00:00Z')",
+                [hex::encode(Sha256::digest(b"key"))],
+            )
+            .unwrap();
+        assert_eq!(
+            store.authenticate("alice", "other").unwrap(),
+            LoginOutcome::WrongCredentials
+        );
+        let id = log_in(&store, "alice", "key");
+
+        // the unsalted hash is replaced on the first login
+        assert!(auth_hash(&store, "alice").starts_with("pbkdf2-sha256$"));
+        assert_eq!(log_in(&store, "alice", "key"), id);
+    }
+    #[test]
+    fn failed_logins() {
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        store.register("alice", "key").unwrap();
+        store.register("bob", "key").unwrap();
+
+        // a successful login starts the count over
+        for _ in 1..MAX_FAILED_LOGINS {
+            store.authenticate("alice", "wrong").unwrap();
+        }
+        log_in(&store, "alice", "key");
+        for _ in 1..MAX_FAILED_LOGINS {
+            store.authenticate("alice", "wrong").unwrap();
+        }
+        log_in(&store, "alice", "key");
+
+        for _ in 0..MAX_FAILED_LOGINS {
+            assert_eq!(
+                store.authenticate("alice", "wrong").unwrap(),
+                LoginOutcome::WrongCredentials
+            );
+        }
+        // even the right key is refused for a while
+        assert_eq!(
+            store.authenticate("alice", "key").unwrap(),
+            LoginOutcome::TooManyAttempts
+        );
+        log_in(&store, "bob", "key");
+
+        store
+            .connection
+            .execute("update Users set locked_until = '2024-01-01T00:00:00Z'", [])
+            .unwrap();
+        log_in(&store, "alice", "key");
+    }
+    #[test]
+    fn entries() {
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        store.register("alice", "a").unwrap();
+        store.register("bob", "b").unwrap();
+        let alice = log_in(&store, "alice", "a");
+        let bob = log_in(&store, "bob", "b");
+
+        let written = |revision| WriteOutcome::Written(revision);
+        assert_eq!(
+            store
+                .put_entry(alice, "mail", "mail label", b"one", None)
+                .unwrap(),
+            written(1)
+        );
+        assert_eq!(
+            store
+                .put_entry(alice, "mail", "mail label", b"two", Some(1))
+                .unwrap(),
+            written(2)
+        );
+        assert_eq!(
+            store
+                .put_entry(alice, "bank", "bank label", b"three", None)
+                .unwrap(),
+            written(1)
+        );
+        assert_eq!(
+            store.get_entry(alice, "mail").unwrap(),
+            (Some(b"two".to_vec()), Some(2))
+        );
+
+        let names: Vec<_> = store
+            .list_entries(alice)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.id, entry.label))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("bank".to_string(), "bank label".to_string()),
+                ("mail".to_string(), "mail label".to_string())
+            ]
+        );
+
+        // users can't see each other's entries
+        assert_eq!(store.get_entry(bob, "mail").unwrap(), (None, None));
+        assert!(store.list_entries(bob).unwrap().is_empty());
+        assert_eq!(
+            store.delete_entry(bob, "mail", None).unwrap(),
+            WriteOutcome::NotFound
+        );
+
+        // deleting leaves a tombstone with the next revision
+        assert_eq!(
+            store.delete_entry(alice, "mail", Some(2)).unwrap(),
+            written(3)
+        );
+        assert_eq!(store.get_entry(alice, "mail").unwrap(), (None, Some(3)));
+        assert!(store.list_entries(alice).unwrap()[1].deleted);
+        assert_eq!(
+            store.delete_entry(alice, "mail", None).unwrap(),
+            WriteOutcome::NotFound
+        );
+        assert_eq!(
+            store
+                .put_entry(alice, "mail", "mail label", b"back", Some(3))
+                .unwrap(),
+            written(4)
+        );
+
+        assert!(store.get_vault(alice).unwrap().is_none());
+        store.put_vault(alice, b"vault").unwrap();
+        store.put_vault(alice, b"vault 2").unwrap();
+        assert_eq!(store.get_vault(alice).unwrap().unwrap(), b"vault 2");
+    }
+    #[test]
+    fn legacy_entries() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "create table Entries (
+                    user_id integer not null,
+                    name text not null,
+                    data blob not null,
+                    revision integer not null,
+                    updated_at text not null,
+                    primary key (user_id, name)
+                );
+                insert into Entries values (1, 'mail', x'00', 1, '2024-01-01T00:00:00Z');",
+            )
+            .unwrap();
+        let store = Store::new(connection).unwrap();
+        let entries = store.list_entries(1).unwrap();
+        assert_eq!(entries[0].id, "mail");
+        // clients can tell they don't know the entry's name
+        assert_eq!(entries[0].label, "");
+        assert!(!entries[0].deleted);
+    }
+    #[test]
+    fn expected_revisions() {
+        let store = Store::new(Connection::open_in_memory().unwrap()).unwrap();
+        store.register("alice", "a").unwrap();
+        let alice = log_in(&store, "alice", "a");
+
+        // 0 means "doesn't exist yet"
+        assert_eq!(
+            store
+                .put_entry(alice, "mail", "mail label", b"one", Some(1))
+                .unwrap(),
+            WriteOutcome::Conflict(0)
+        );
+        store
+            .put_entry(alice, "mail", "mail label", b"one", Some(0))
+            .unwrap();
+        store
+            .put_entry(alice, "mail", "mail label", b"two", None)
+            .unwrap();
+
+        // another device wrote revision 2 in the meantime
+        assert_eq!(
+            store
+                .put_entry(alice, "mail", "mail label", b"stale", Some(1))
+                .unwrap(),
+            WriteOutcome::Conflict(2)
+        );
+        assert_eq!(
+            store.delete_entry(alice, "mail", Some(1)).unwrap(),
+            WriteOutcome::Conflict(2)
+        );
+        assert_eq!(store.get_entry(alice, "mail").unwrap().0.unwrap(), b"two");
+    }
+}
@@ -0,0 +1,309 @@
+//! Runs a server in-process on localhost and talks to it with passman-core's `RemoteVault`.
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use passman_core::{
+    crypto::gen_cipher,
+    db_ops::MASTER_KEYWORD,
+    envelope::{Algorithm, Envelope},
+    error::BackendError,
+    password::{EntryField, PasswordInfo},
+    remote::RemoteVault,
+    sync::{plan_sync, sync, LocalReplica, Resolution},
+    vault::Vault,
+};
+use passman_server::{server::Server, store::Store};
+use rusqlite::Connection;
+
+/// Starts a server on a free port and returns its URL, along with a connection to its database.
+fn start(allow_registration: bool) -> (String, Connection) {
+    passman_core::init().unwrap();
+    // a shared in-memory database, so the test can look at what the server stored
+    static SERVERS: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "file:server-{}?mode=memory&cache=shared",
+        SERVERS.fetch_add(1, Ordering::Relaxed)
+    );
+    let inspect = Connection::open(&name).unwrap();
+    let store = Store::new(Connection::open(&name).unwrap()).unwrap();
+    let server = Server::bind("127.0.0.1:0", store, allow_registration).unwrap();
+    let url = format!("http://{}", server.addr().unwrap());
+    thread::spawn(move || server.run());
+    (url, inspect)
+}
+
+fn password(name: &str, secret: &str) -> PasswordInfo {
+    PasswordInfo {
+        name: name.to_string(),
+        username: Some("alice".to_string()),
+        password: Some(secret.to_string()),
+        urls: vec!["https://example.com".to_string()],
+        fields: vec![EntryField {
+            key: "PIN".to_string(),
+            value: "1234".to_string(),
+            sensitive: true,
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn entries() {
+    let (url, _) = start(true);
+    let remote =
+        RemoteVault::register(&url, "alice", "master", Algorithm::XChaCha20Poly1305).unwrap();
+
+    assert_eq!(remote.put(&password("mail", "hunter2")).unwrap(), 1);
+    assert_eq!(remote.put(&password("mail", "changed")).unwrap(), 2);
+    remote.put(&password("work/mail box", "secret")).unwrap();
+
+    let entries = remote.list().unwrap();
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, ["mail", "work/mail box"]);
+    assert_eq!(entries[0].revision, 2);
+
+    let mail = remote.get("mail").unwrap().unwrap();
+    assert_eq!(mail.password.as_deref(), Some("changed"));
+    assert_eq!(mail.fields[0].value, "1234");
+    assert!(remote.get("missing").unwrap().is_none());
+
+    assert!(remote.delete("mail").unwrap());
+    assert!(!remote.delete("mail").unwrap());
+    assert!(remote.get("mail").unwrap().is_none());
+}
+
+#[test]
+fn login() {
+    let (url, _) = start(true);
+    RemoteVault::register(&url, "alice", "master", Algorithm::XChaCha20Poly1305)
+        .unwrap()
+        .put(&password("mail", "hunter2"))
+        .unwrap();
+
+    // another device logs in with the same master password
+    let remote = RemoteVault::connect(&url, "alice", "master").unwrap();
+    assert_eq!(
+        remote.get("mail").unwrap().unwrap().password.as_deref(),
+        Some("hunter2")
+    );
+
+    assert!(matches!(
+        RemoteVault::connect(&url, "alice", "wrong"),
+        Err(BackendError::Unauthorized)
+    ));
+    assert!(matches!(
+        RemoteVault::connect(&url, "bob", "master"),
+        Err(BackendError::Unauthorized)
+    ));
+    assert!(matches!(
+        RemoteVault::register(&url, "alice", "other", Algorithm::Aes256Gcm),
+        Err(BackendError::ServerError { status: 409, .. })
+    ));
+
+    // users can't see each other's entries, even with the same master password
+    let bob = RemoteVault::register(&url, "bob", "master", Algorithm::Aes256Gcm).unwrap();
+    assert!(bob.list().unwrap().is_empty());
+    assert!(bob.get("mail").unwrap().is_none());
+}
+
+#[test]
+fn legacy_settings() {
+    let (url, inspect) = start(true);
+    RemoteVault::register(&url, "alice", "master", Algorithm::Aes256Gcm).unwrap();
+    // vaults used to have their settings encrypted with the master record's key
+    let legacy = gen_cipher(Algorithm::Aes256Gcm, "master", MASTER_KEYWORD)
+        .encrypt(br#"{"algorithm":1}"#)
+        .unwrap()
+        .to_bytes();
+    inspect
+        .execute("update Vaults set data = ?", [&legacy])
+        .unwrap();
+
+    RemoteVault::connect(&url, "alice", "master").unwrap();
+    let data: Vec<u8> = inspect
+        .query_row("select data from Vaults", [], |row| row.get(0))
+        .unwrap();
+    assert_ne!(data, legacy);
+    // moved to the blob's own key
+    let envelope = Envelope::from_bytes(&data).unwrap();
+    assert!(gen_cipher(Algorithm::Aes256Gcm, "master", MASTER_KEYWORD)
+        .decrypt(&envelope)
+        .is_err());
+    RemoteVault::connect(&url, "alice", "master").unwrap();
+}
+
+#[test]
+fn registration_disabled() {
+    let (url, _) = start(false);
+    assert!(matches!(
+        RemoteVault::register(&url, "alice", "master", Algorithm::Aes256Gcm),
+        Err(BackendError::ServerError { status: 403, .. })
+    ));
+}
+
+#[test]
+fn unreachable() {
+    // nothing listens on the discard port
+    assert!(matches!(
+        RemoteVault::connect("http://127.0.0.1:9", "alice", "master"),
+        Err(BackendError::ServerUnreachable(_))
+    ));
+}
+
+#[test]
+fn server_never_sees_plaintext() {
+    let (url, inspect) = start(true);
+    let remote = RemoteVault::register(&url, "alice", "master", Algorithm::Aes256Gcm).unwrap();
+    remote.put(&password("mail", "hunter2")).unwrap();
+
+    let mut stored: Vec<Vec<u8>> = Vec::new();
+    for query in [
+        "select data from Entries",
+        "select cast(id as blob) from Entries",
+        "select cast(label as blob) from Entries",
+        "select data from Vaults",
+        "select cast(auth_hash as blob) from Users",
+    ] {
+        let mut stmt = inspect.prepare(query).unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        stored.extend(rows.map(Result::unwrap));
+    }
+    assert_eq!(stored.len(), 5);
+    for data in &stored {
+        for secret in ["hunter2", "alice", "mail", "1234", "example.com", "master"] {
+            assert!(
+                !data
+                    .windows(secret.len())
+                    .any(|window| window == secret.as_bytes()),
+                "\"{}\" was stored in plaintext",
+                secret
+            );
+        }
+    }
+}
+
+#[test]
+fn push_and_pull() {
+    let (url, _) = start(true);
+
+    let mut laptop = Vault::open_in_memory().unwrap();
+    laptop
+        .initialize("master", "recovery", Algorithm::XChaCha20Poly1305)
+        .unwrap();
+    laptop.put(&password("mail", "hunter2")).unwrap();
+    laptop.put(&password("bank", "secret")).unwrap();
+
+    let remote =
+        RemoteVault::register(&url, "alice", "master", Algorithm::XChaCha20Poly1305).unwrap();
+    assert_eq!(remote.push(laptop.connection()).unwrap(), 2);
+
+    // a second device with the same master password, and an entry of its own
+    let mut workstation = Vault::open_in_memory().unwrap();
+    workstation
+        .initialize("master", "recovery", Algorithm::Aes256Gcm)
+        .unwrap();
+    workstation.put(&password("local only", "x")).unwrap();
+    let mut stale = password("mail", "old");
+    stale.notes = Some("stale notes".to_string());
+    workstation.put(&stale).unwrap();
+
+    assert_eq!(remote.pull(workstation.connection()).unwrap(), 2);
+    let mail = workstation.get("mail").unwrap().unwrap();
+    assert_eq!(mail.password.as_deref(), Some("hunter2"));
+    // pulled entries replace local ones entirely
+    assert!(mail.notes.is_none());
+    assert_eq!(mail.fields.len(), 1);
+    assert!(workstation.get("local only").unwrap().is_some());
+
+    // pulling again doesn't duplicate custom fields
+    remote.pull(workstation.connection()).unwrap();
+    assert_eq!(workstation.get("mail").unwrap().unwrap().fields.len(), 1);
+    // passwords are replaced in place, not deleted and written again
+    let tombstones: i64 = workstation
+        .connection()
+        .query_row("select count(*) from Tombstone", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tombstones, 0);
+}
+
+#[test]
+fn sync_devices() {
+    let (url, _) = start(true);
+    let remote =
+        RemoteVault::register(&url, "alice", "master", Algorithm::XChaCha20Poly1305).unwrap();
+    let device = |algorithm| {
+        let mut vault = Vault::open_in_memory().unwrap();
+        vault.initialize("master", "recovery", algorithm).unwrap();
+        vault
+    };
+    let laptop = device(Algorithm::XChaCha20Poly1305);
+    let workstation = device(Algorithm::Aes256Gcm);
+    let no_conflicts = |conflict: &_| panic!("unexpected conflict: {:?}", conflict);
+    let sync_with_server = |vault: &Vault| {
+        sync(
+            &LocalReplica::new(vault.connection(), "master"),
+            &remote,
+            no_conflicts,
+        )
+        .unwrap()
+    };
+
+    laptop.put(&password("mail", "hunter2")).unwrap();
+    laptop.put(&password("bank", "secret")).unwrap();
+    assert_eq!(sync_with_server(&laptop).to_remote, 2);
+    assert_eq!(sync_with_server(&workstation).to_local, 2);
+    assert_eq!(sync_with_server(&laptop), Default::default());
+
+    // a deletion and edits to different fields of the same entry
+    laptop.delete("bank").unwrap();
+    let mut mail = laptop.get("mail").unwrap().unwrap();
+    mail.notes = Some("from the laptop".to_string());
+    laptop.put(&mail).unwrap();
+    let mut mail = workstation.get("mail").unwrap().unwrap();
+    mail.username = Some("bob".to_string());
+    workstation.put(&mail).unwrap();
+
+    sync_with_server(&laptop);
+    let report = sync_with_server(&workstation);
+    assert_eq!(
+        (report.deleted_local, report.to_local, report.to_remote),
+        (1, 1, 1)
+    );
+    sync_with_server(&laptop);
+    for vault in [&laptop, &workstation] {
+        assert!(vault.get("bank").unwrap().is_none());
+        let mail = vault.get("mail").unwrap().unwrap();
+        assert_eq!(mail.notes.as_deref(), Some("from the laptop"));
+        assert_eq!(mail.username.as_deref(), Some("bob"));
+    }
+    assert!(remote
+        .list()
+        .unwrap()
+        .iter()
+        .all(|entry| entry.name != "bank"));
+
+    // the same field changed on both devices
+    let mut mail = laptop.get("mail").unwrap().unwrap();
+    mail.password = Some("laptop".to_string());
+    laptop.put(&mail).unwrap();
+    sync_with_server(&laptop);
+    // custom field ids are local to each vault, so the workstation edits its own copy
+    let mut mail = workstation.get("mail").unwrap().unwrap();
+    mail.password = Some("workstation".to_string());
+    workstation.put(&mail).unwrap();
+
+    let local = LocalReplica::new(workstation.connection(), "master");
+    let mut plan = plan_sync(&local, &remote).unwrap();
+    assert_eq!(plan.conflicts.len(), 1);
+    plan.conflicts[0].resolution = Some(Resolution::KeepLocal);
+    plan.apply(&local, &remote).unwrap();
+    sync_with_server(&laptop);
+    assert_eq!(
+        laptop.get("mail").unwrap().unwrap().password.as_deref(),
+        Some("workstation")
+    );
+}
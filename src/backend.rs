@@ -1,4 +0,0 @@
-pub mod crypto;
-pub mod db_ops;
-pub mod error;
-pub mod password;
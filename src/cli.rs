@@ -1,158 +1,1654 @@
 // note: i tested the frontend by hand because i wanted to see what things looked like
 // not to mention that it also would be hard to test this frontend because of the cliclack crate
 
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use passman_core::session::DEFAULT_IDLE_TIMEOUT;
+
+/// Command line arguments. Running passman without a subcommand starts the interactive mode.
+#[derive(Parser)]
+#[command(name = "passman", version, about = "A local password manager")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Lock the interactive session after this many idle minutes
+    #[arg(long, global = true, value_name = "MINUTES", default_value_t = DEFAULT_IDLE_TIMEOUT.as_secs() / 60)]
+    pub lock_after: u64,
+    /// The keyfile, for vaults that require one. You're asked for it if it's needed and not given
+    #[arg(long, global = true, value_name = "PATH")]
+    pub keyfile: Option<PathBuf>,
+}
+
+/// Non-interactive subcommands, meant for scripting.
+#[derive(Subcommand)]
+pub enum Command {
+    /// List passwords that are expired or have to be rotated soon
+    Due {
+        /// Print the list as JSON
+        #[arg(long)]
+        json: bool,
+        /// Also list passwords that will be due within this many days
+        #[arg(long, default_value_t = DEFAULT_REMINDER_DAYS)]
+        days: u32,
+    },
+    /// Browse and edit passwords in a full-screen interface
+    Tui,
+    /// Merge changes with another vault file, e.g. a copy used on another device. Both files are updated
+    Sync {
+        /// The other vault file. It has to use the same master password
+        path: PathBuf,
+    },
+    /// Store passwords on a passman-server. They're encrypted before they're sent
+    Remote {
+        /// The URL of the server. Remembered after the first successful use
+        #[arg(long)]
+        server: Option<String>,
+        /// Your username on the server. Remembered after the first successful use
+        #[arg(long)]
+        user: Option<String>,
+        #[command(subcommand)]
+        action: RemoteAction,
+    },
+    /// Keep a copy of the vault in a git repository, one encrypted file per password
+    Git {
+        #[command(subcommand)]
+        action: GitAction,
+    },
+    /// Print the public key others use to add you to a shared vault or share passwords with you
+    Identity,
+    /// Encrypt one password to someone's public key, so they can import it with `passman receive`
+    Share {
+        /// The name of the password to share
+        name: String,
+        /// The recipient's public key, printed by `passman identity`
+        #[arg(long, value_name = "PUBLIC_KEY")]
+        to: String,
+        /// Stop the share from being accepted after this many days
+        #[arg(long, value_name = "DAYS")]
+        expires_in: Option<u32>,
+        /// Where to write the share. Defaults to `<name>.passman-share` in the working directory
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Import a password someone shared with you
+    Receive {
+        /// The share file
+        path: PathBuf,
+    },
+    /// Require a keyfile to unlock the vault, next to the master password
+    Keyfile {
+        #[command(subcommand)]
+        action: KeyfileAction,
+    },
+    /// Manage or open a vault shared with others, who each use their own master password
+    Team {
+        /// The shared vault file
+        path: PathBuf,
+        #[command(subcommand)]
+        action: TeamAction,
+    },
+    /// Check the whole vault for damage or tampering. Exits with 2 if anything is wrong, for use in cron jobs.
+    /// The master password is read from PASSMAN_MASTER if it's set
+    Verify {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        /// Accept the passwords as they are by resealing the vault's manifest first, e.g. after restoring a deleted
+        /// password by hand
+        #[arg(long)]
+        reseal: bool,
+    },
+    /// List or restore the snapshots taken before deleting or re-encrypting anything
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+}
+
+/// What to do with a remote vault.
+#[derive(Subcommand, Clone, Copy, PartialEq, Eq)]
+pub enum RemoteAction {
+    /// Create an account on the server and upload every password
+    Register,
+    /// Upload every password, replacing the server's copies
+    Push,
+    /// Download every password, replacing local copies
+    Pull,
+    /// List the passwords stored on the server
+    List,
+    /// Merge changes made locally and on the server, asking about conflicting changes
+    Sync,
+}
+
+/// What to do with the git repository the vault is kept in.
+#[derive(Subcommand)]
+pub enum GitAction {
+    /// Start keeping the vault in a git repository. An existing clone of a vault is merged with this one
+    Init {
+        /// The directory of the repository. It's created if it doesn't exist
+        path: PathBuf,
+    },
+    /// Commit any changes and push them, passing the rest of the arguments on to git
+    Push {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Pull and merge changes from other clones, passing the rest of the arguments on to git
+    Pull {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Merges two versions of a password file. Run by git during `passman git pull`
+    #[command(hide = true)]
+    MergeDriver {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+        path: String,
+    },
+}
+
+/// What to do with a shared vault.
+#[derive(Subcommand)]
+pub enum TeamAction {
+    /// Create a new shared vault, with you as its first member
+    Create {
+        /// What to call you in the list of members
+        #[arg(long)]
+        name: String,
+    },
+    /// Give someone access to the vault
+    Add {
+        /// What to call them in the list of members
+        name: String,
+        /// Their public key, printed by `passman identity`
+        public_key: String,
+    },
+    /// Take someone's access away. The vault is re-encrypted with a new key
+    Remove {
+        /// The member to remove
+        name: String,
+    },
+    /// List who has access to the vault
+    Members,
+    /// Read and edit the shared passwords interactively
+    Open,
+}
+
+/// What to do with the vault's keyfile.
+#[derive(Subcommand)]
+pub enum KeyfileAction {
+    /// Start requiring a keyfile. The vault is re-encrypted
+    Add {
+        /// The keyfile. Any file that never changes will do
+        path: PathBuf,
+        /// Create a new keyfile of random bytes at `path` first
+        #[arg(long)]
+        generate: bool,
+    },
+    /// Replace the keyfile with another one. The vault is re-encrypted
+    Rotate {
+        /// The new keyfile
+        path: PathBuf,
+        /// Create a new keyfile of random bytes at `path` first
+        #[arg(long)]
+        generate: bool,
+    },
+    /// Stop requiring a keyfile. The vault is re-encrypted
+    Remove,
+}
+
+/// What to do with the vault's snapshots.
+#[derive(Subcommand)]
+pub enum BackupAction {
+    /// List the snapshots, newest first
+    List,
+    /// Take a snapshot now
+    Create,
+    /// Replace the vault with a snapshot. The vault as it is now is snapshotted first
+    Restore {
+        /// The snapshot, as a path or a file name from `passman backup list`
+        snapshot: PathBuf,
+    },
+    /// Show or change how many snapshots are kept. A snapshot is kept if any of the rules keeps it
+    Retention {
+        /// Keep this many of the newest snapshots
+        #[arg(long)]
+        keep_last: Option<u32>,
+        /// Keep the newest snapshot of each of the last this many days
+        #[arg(long)]
+        keep_daily: Option<u32>,
+        /// Keep the newest snapshot of each of the last this many weeks
+        #[arg(long)]
+        keep_weekly: Option<u32>,
+    },
+}
+
+/// How many days ahead passwords are considered "about to expire".
+pub const DEFAULT_REMINDER_DAYS: u32 = 14;
+
 // simple and probably unnecessary enums for control flow in the main application
 
-#[derive(Default, Clone, PartialEq, Eq)]
-pub enum Operation {
-    Insert,
-    Read,
-    Delete,
-    #[default]
-    Exit,
-}
-#[derive(Default, Clone, PartialEq, Eq)]
-pub enum PasswordGeneration {
-    Automatic,
-    Manual,
-    #[default]
-    NoPassword,
-}
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Insert,
+    Read,
+    Edit,
+    Delete,
+    Rename,
+    Duplicate,
+    Browse,
+    CustomFields,
+    ChangeCipher,
+    LoginPolicy,
+    #[default]
+    Exit,
+}
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum PasswordGeneration {
+    Automatic,
+    Manual,
+    #[default]
+    NoPassword,
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum EditAction {
+    Change,
+    Clear,
+    Regenerate,
+    #[default]
+    Exit,
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum CustomFieldOperation {
+    Add,
+    Edit,
+    Remove,
+    #[default]
+    Exit,
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum BrowseOperation {
+    #[default]
+    Tree,
+    List,
+    Tag,
+    Folder,
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum ExpiryOption {
+    #[default]
+    Skip,
+    Date,
+    Rotation,
+}
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub enum LoginOperations {
+    Login,
+    Reset,
+    #[default]
+    Exit,
+}
+// these are the CLI frontend implementations of the CRUD operations
+pub mod crud_operations {
+    use crate::cli::{BrowseOperation, CustomFieldOperation, EditAction};
+    use cliclack::{confirm, input, log, note, select};
+    use colored::Colorize;
+    use passman_core::{
+        db_ops::{
+            crud::{
+                clear_data, delete_entry_field, delete_password_info, duplicate_password_info,
+                insert_data, insert_list_data, list_by_folder, list_by_tag, list_password_info,
+                mark_password_used, read_password_info, rename_password_info, update_entry_field,
+                upsert_password_info,
+            },
+            util::check_password_info_exists,
+        },
+        error::BackendError,
+        password::{
+            normalize_folder, sort_passwords, EntryField, FolderNode, PasswordField, PasswordInfo,
+            SortOrder,
+        },
+    };
+    use rusqlite::Connection;
+
+    use super::utility::{
+        check_password_availability, format_timestamp,
+        password::{auto_password_prompt, confirmed_password, prompt_password},
+        print_password_info, prompt_custom_fields, prompt_expiry, prompt_field, prompt_field_value,
+        prompt_folder, prompt_list_field, render_folder_tree,
+    };
+    /// Series of prompts to insert a new password into the SQLite table `PasswordInfo`.
+    pub fn insert(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let name: String = input("Enter Password name?")
+            .placeholder("My new password")
+            .required(true)
+            .interact()?;
+
+        if !check_password_availability(connection, &name)? {
+            return Ok(());
+        }
+
+        // nothing is written until every prompt has been answered
+        let password_info = PasswordInfo {
+            email: prompt_field(PasswordField::Email, "example@domain.com")?,
+            username: prompt_field(PasswordField::Username, "example_username")?,
+            notes: prompt_field(PasswordField::Notes, "any text here")?,
+            urls: prompt_list_field(
+                PasswordField::Urls,
+                "https://example.com, https://login.example.com",
+            )?,
+            tags: prompt_list_field(PasswordField::Tags, "work, email")?,
+            folder: prompt_folder()?,
+            password: prompt_password()?,
+            fields: prompt_custom_fields()?,
+            expiry: prompt_expiry()?,
+            name,
+            ..Default::default()
+        };
+        upsert_password_info(connection, &password_info, master)?;
+
+        log::success("Successfully inserted a new password!")?;
+        Ok(())
+    }
+
+    /// Series of prompts to read password info. If it finds data given user input, it will print the details of the given password.
+    pub fn read(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let name: String = input("Enter Password name?")
+            .placeholder("My new password")
+            .required(true)
+            .interact()?;
+        let res = read_password_info(connection, &name, master)?;
+        print_password_info(res.as_ref(), false)?;
+        if res.is_some() {
+            match mark_password_used(connection, &name, master) {
+                // reading still works on a vault that hasn't been accepted yet, the use just isn't recorded
+                Ok(_) | Err(BackendError::ManifestMismatch | BackendError::ManifestMissing) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let has_sensitive = res
+            .as_ref()
+            .is_some_and(|info| info.fields.iter().any(|field| field.sensitive));
+        if has_sensitive
+            && confirm("Reveal sensitive fields?")
+                .initial_value(false)
+                .interact()?
+        {
+            print_password_info(res.as_ref(), true)?;
+        }
+        Ok(())
+    }
+    /// Lists the passwords in the vault, either as a folder tree or filtered by a tag or folder.
+    pub fn browse(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let operation = select("How would you like to browse?")
+            .item(BrowseOperation::Tree, "Folder tree", "")
+            .item(BrowseOperation::List, "List all", "sorted by name or date")
+            .item(BrowseOperation::Tag, "By tag", "")
+            .item(BrowseOperation::Folder, "By folder", "includes subfolders")
+            .interact()?;
+
+        let (title, passwords) = match operation {
+            BrowseOperation::List => {
+                let order = select("Sort by")
+                    .item(SortOrder::Name, "Name", "")
+                    .item(SortOrder::Created, "Created", "newest first")
+                    .item(SortOrder::Updated, "Last modified", "newest first")
+                    .item(SortOrder::LastUsed, "Last used", "newest first")
+                    .interact()?;
+                let mut passwords = list_password_info(connection, master)?;
+                sort_passwords(&mut passwords, order);
+
+                let lines = passwords
+                    .iter()
+                    .map(|password| {
+                        let timestamp = match order {
+                            SortOrder::Name => return password.name.clone(),
+                            SortOrder::Created => password.timestamps.created_at,
+                            SortOrder::Updated => password.timestamps.updated_at,
+                            SortOrder::LastUsed => password.timestamps.last_used_at,
+                        };
+                        format!(
+                            "{} {}",
+                            password.name,
+                            format_timestamp(timestamp.as_ref()).dimmed()
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                if lines.is_empty() {
+                    note("Passwords", "No passwords found.")?;
+                } else {
+                    note("Passwords", lines.join("\n"))?;
+                }
+                return Ok(());
+            }
+            BrowseOperation::Tree => (
+                "Passwords".to_string(),
+                list_password_info(connection, master)?,
+            ),
+            BrowseOperation::Tag => {
+                let tag: String = input("Enter a tag").required(true).interact()?;
+                let passwords = list_by_tag(connection, master, &tag)?;
+                (format!("Tagged \"{}\"", tag), passwords)
+            }
+            BrowseOperation::Folder => {
+                let folder: String = input("Enter a folder")
+                    .placeholder("Work/Email")
+                    .required(true)
+                    .interact()?;
+                let passwords = list_by_folder(connection, master, &folder)?;
+                (format!("In \"{}\"", folder), passwords)
+            }
+        };
+
+        if passwords.is_empty() {
+            note(title, "No passwords found.")?;
+        } else {
+            note(
+                title,
+                render_folder_tree(&FolderNode::from_passwords(&passwords)),
+            )?;
+        }
+        Ok(())
+    }
+    /// Series of prompts to add, edit or remove the custom fields of an existing password.
+    pub fn custom_fields(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let name: String = input("Enter Password name?")
+            .placeholder("My new password")
+            .required(true)
+            .interact()?;
+        let Some(password_info) = read_password_info(connection, &name, master)? else {
+            log::warning("No password found with that name.")?;
+            return Ok(());
+        };
+
+        let mut operation = select("What would you like to do?").item(
+            CustomFieldOperation::Add,
+            "Add custom fields",
+            "",
+        );
+        if !password_info.fields.is_empty() {
+            operation = operation
+                .item(CustomFieldOperation::Edit, "Edit a custom field", "")
+                .item(
+                    CustomFieldOperation::Remove,
+                    "Remove a custom field",
+                    "dangerous",
+                );
+        }
+        let operation = operation
+            .item(CustomFieldOperation::Exit, "Exit", "")
+            .interact()?;
+
+        // picks one of the existing fields, by its index in `password_info.fields`
+        let pick_field = |prompt: &str| -> std::io::Result<EntryField> {
+            let mut select = select(prompt);
+            for (index, field) in password_info.fields.iter().enumerate() {
+                select = select.item(index, &field.key, "");
+            }
+            Ok(password_info.fields[select.interact()?].clone())
+        };
+
+        match operation {
+            CustomFieldOperation::Add => {
+                let password_info = PasswordInfo {
+                    name: name.clone(),
+                    fields: prompt_custom_fields()?,
+                    ..Default::default()
+                };
+                upsert_password_info(connection, &password_info, master)?;
+            }
+            CustomFieldOperation::Edit => {
+                let field = pick_field("Select a field to edit")?;
+                let key: String = input("Enter field name")
+                    .default_input(&field.key)
+                    .required(true)
+                    .interact()?;
+                let sensitive = confirm("Is this field sensitive? (it will be masked)")
+                    .initial_value(field.sensitive)
+                    .interact()?;
+                let value = prompt_field_value(sensitive)?;
+                update_entry_field(
+                    connection,
+                    &name,
+                    master,
+                    &EntryField {
+                        key,
+                        value,
+                        sensitive,
+                        ..field
+                    },
+                )?;
+                log::success("Successfully updated field.")?;
+            }
+            CustomFieldOperation::Remove => {
+                let field = pick_field("Select a field to remove")?;
+                let confirm = confirm(format!("Removing field \"{}\"... Continue?", field.key))
+                    .initial_value(false)
+                    .interact()?;
+                if confirm {
+                    delete_entry_field(connection, field.id, master)?;
+                    log::success("Successfully removed field.")?;
+                } else {
+                    log::info("Cancelled.")?;
+                }
+            }
+            CustomFieldOperation::Exit => {}
+        }
+        Ok(())
+    }
+    /// Series of prompts to change, clear or regenerate a single field of an existing password, without re-entering the others.
+    pub fn edit(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let name: String = input("Enter Password name?")
+            .placeholder("My password")
+            .required(true)
+            .interact()?;
+        let Some(password_info) = read_password_info(connection, &name, master)? else {
+            log::warning("No password found with that name.")?;
+            return Ok(());
+        };
+
+        // shows the current value of each field next to its name, with the password masked
+        let current = |field: PasswordField| -> String {
+            let value = match field {
+                PasswordField::Email => password_info.email.clone(),
+                PasswordField::Username => password_info.username.clone(),
+                PasswordField::Notes => password_info.notes.clone(),
+                PasswordField::Password => password_info
+                    .password
+                    .as_ref()
+                    .map(|_| "********".to_string()),
+                PasswordField::Urls => {
+                    Some(password_info.urls.join(", ")).filter(|v| !v.is_empty())
+                }
+                PasswordField::Tags => {
+                    Some(password_info.tags.join(", ")).filter(|v| !v.is_empty())
+                }
+                PasswordField::Folder => password_info.folder.clone(),
+            };
+            value.unwrap_or_else(|| "empty".to_string())
+        };
+        let mut select_field = select("Select a field to edit");
+        for (index, field) in PasswordField::ALL.iter().enumerate() {
+            select_field = select_field.item(index, field, current(*field));
+        }
+        let field = PasswordField::ALL[select_field.interact()?];
+
+        let mut action = select(format!("What would you like to do with {}?", field)).item(
+            EditAction::Change,
+            "Change it",
+            "",
+        );
+        if field == PasswordField::Password {
+            action = action.item(EditAction::Regenerate, "Generate a new password", "");
+        }
+        let action = action
+            .item(EditAction::Clear, "Clear it", "removes the value")
+            .item(EditAction::Exit, "Exit", "")
+            .interact()?;
+
+        match action {
+            EditAction::Change => match field {
+                PasswordField::Password => {
+                    insert_data(connection, &name, master, field, &confirmed_password()?)?;
+                }
+                PasswordField::Urls | PasswordField::Tags => {
+                    let data: String = input(format!("Enter {} (comma separated)", field))
+                        .required(true)
+                        .interact()?;
+                    let values = data.split(',').map(String::from).collect::<Vec<_>>();
+                    insert_list_data(connection, &name, master, field, &values)?;
+                }
+                PasswordField::Folder => {
+                    let data: String = input("Enter folder")
+                        .placeholder("Work/Email")
+                        .required(true)
+                        .interact()?;
+                    match normalize_folder(&data) {
+                        Some(folder) => insert_data(connection, &name, master, field, &folder)?,
+                        None => clear_data(connection, &name, field, master)?,
+                    };
+                }
+                _ => {
+                    let data: String = input(format!("Enter {}", field))
+                        .required(true)
+                        .interact()?;
+                    insert_data(connection, &name, master, field, &data)?;
+                }
+            },
+            EditAction::Regenerate => {
+                insert_data(connection, &name, master, field, &auto_password_prompt()?)?;
+            }
+            EditAction::Clear => {
+                clear_data(connection, &name, field, master)?;
+            }
+            EditAction::Exit => {
+                return Ok(());
+            }
+        }
+        log::success(format!("Successfully updated {}.", field))?;
+        Ok(())
+    }
+    /// Series of prompts to rename a password. All of its fields are re-encrypted under the new name.
+    pub fn rename(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let Some((name, new_name)) = prompt_name_pair(connection, "Enter new name?")? else {
+            return Ok(());
+        };
+        rename_password_info(connection, &name, &new_name, master)?;
+        log::success(format!("Successfully renamed {} to {}.", name, new_name))?;
+        Ok(())
+    }
+    /// Series of prompts to copy a password to a new name.
+    pub fn duplicate(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let Some((name, new_name)) = prompt_name_pair(connection, "Enter name of the copy?")?
+        else {
+            return Ok(());
+        };
+        duplicate_password_info(connection, &name, &new_name, master)?;
+        log::success(format!("Successfully copied {} to {}.", name, new_name))?;
+        Ok(())
+    }
+    /// Prompts for the name of an existing password, and a new, unused name.
+    /// Returns `None` (after telling the user) if the password doesn't exist.
+    fn prompt_name_pair(
+        connection: &Connection,
+        new_name_prompt: &str,
+    ) -> anyhow::Result<Option<(String, String)>> {
+        let name: String = input("Enter Password name?")
+            .placeholder("My password")
+            .required(true)
+            .interact()?;
+        if !check_password_info_exists(connection, &name)? {
+            log::warning("No password found with that name.")?;
+            return Ok(None);
+        }
+        let new_name: String = input(new_name_prompt)
+            .placeholder("My new password")
+            .required(true)
+            .interact()?;
+        if check_password_info_exists(connection, &new_name)? {
+            log::warning("A password already exists with that name.")?;
+            return Ok(None);
+        }
+        Ok(Some((name, new_name)))
+    }
+    /// Series of prompts *and **confirmations*** to delete data from the SQLite table `PasswordInfo`.
+    pub fn delete(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let name: String = input("Enter Password name?")
+            .placeholder("My new password")
+            .required(true)
+            .interact()?;
+
+        let check_exists = check_password_info_exists(connection, &name)?;
+        if !check_exists {
+            log::warning("No password found with that name.")?;
+            return Ok(());
+        }
+        // it's a big deal to delete data so make sure the user understands they're doing some serious shit
+        note(
+        "Password Deletion",
+        "You are about to delete a password. This action is UNDOABLE and your data will be lost FOREVER. There is NO BACKUP or restoration process, so PLEASE SAVE THIS DATA BEFORE YOU DELETE IT. ",
+    )?;
+        let confirm = confirm("Deleting a password... Continue?")
+            .initial_value(false)
+            .interact()?;
+
+        if !confirm {
+            log::info("Cancelled.")?;
+            return Ok(());
+        }
+
+        delete_password_info(connection, &name, master)?;
+        log::success("Successfully deleted password.")?;
+        Ok(())
+    }
+}
+
+// non-interactive subcommands, which print plain text (or JSON) instead of using cliclack
+pub mod commands {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::{bail, Context};
+    use chrono::{Duration, Utc};
+    use cliclack::{confirm, input, log, note, password, select, spinner};
+    use passman_core::{
+        backup::{
+            backup_dir, get_backup_policy, list_snapshots, restore_snapshot, set_backup_policy,
+            take_snapshot,
+        },
+        db_ops::{
+            crud::{get_password_info, list_due, read_password_info},
+            util::{authenticate, get_metadata, get_vault_algorithm, set_metadata},
+        },
+        error::BackendError,
+        git::{GitVault, MASTER_ENV},
+        keyfile::{
+            add_keyfile, composite_key, generate_keyfile, read_keyfile, remove_keyfile,
+            rotate_keyfile,
+        },
+        manifest::reseal_manifest,
+        password::{encode_name, PasswordField},
+        remote::RemoteVault,
+        share::{create_share, import_shared_password, open_share},
+        sync::{
+            plan_sync, reset_vault_id, Conflict, ConflictSide, LocalReplica, Replica, Resolution,
+        },
+        team::{
+            add_member, create_shared_vault, list_members, remove_member, unlock_shared_vault,
+            Identity,
+        },
+        vault::Vault,
+        verify::verify_vault,
+    };
+    use rusqlite::Connection;
+
+    use super::{
+        utility::{format_timestamp, print_password_info, select_cipher, warn_manifest},
+        BackupAction, KeyfileAction, RemoteAction, TeamAction,
+    };
+
+    /// Metadata keys remembering the server and username of the remote vault.
+    const REMOTE_SERVER_METADATA: &str = "remote_server";
+    const REMOTE_USER_METADATA: &str = "remote_user";
+    /// Metadata key remembering the git repository the vault is kept in.
+    const GIT_REPO_METADATA: &str = "git_repo";
+
+    /// Prints passwords that are expired or will be due within `days` days. Doesn't require the master password.
+    pub fn due(connection: &Connection, json: bool, days: u32) -> anyhow::Result<()> {
+        let due = list_due(connection, Utc::now(), Duration::days(days.into()))?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&due)?);
+            return Ok(());
+        }
+        for password in due {
+            let status = if password.expired { "expired" } else { "due" };
+            println!(
+                "{}\t{}\t{}",
+                status,
+                format_timestamp(Some(&password.due_at)),
+                password.name
+            );
+        }
+        Ok(())
+    }
+
+    /// Registers with, uploads to, downloads from or lists a remote vault on a passman-server.
+    /// The server and username are remembered, so they only have to be given once.
+    pub fn remote(
+        connection: &Connection,
+        master: &str,
+        server: Option<String>,
+        user: Option<String>,
+        action: RemoteAction,
+    ) -> anyhow::Result<()> {
+        let server = match server {
+            Some(server) => server,
+            None => get_metadata(connection, REMOTE_SERVER_METADATA)?
+                .context("no server has been used yet; pass one with --server")?,
+        };
+        let user = match user {
+            Some(user) => user,
+            None => get_metadata(connection, REMOTE_USER_METADATA)?
+                .context("no username has been used yet; pass one with --user")?,
+        };
+
+        let mut spinner = spinner();
+        spinner.start(format!("Connecting to {}...", server));
+        let result = (|| {
+            let remote = match action {
+                RemoteAction::Register => {
+                    RemoteVault::register(&server, &user, master, get_vault_algorithm(connection)?)?
+                }
+                _ => RemoteVault::connect(&server, &user, master)?,
+            };
+            set_metadata(connection, REMOTE_SERVER_METADATA, &server)?;
+            set_metadata(connection, REMOTE_USER_METADATA, &user)?;
+            let lines = match action {
+                RemoteAction::Register | RemoteAction::Push => {
+                    let count = remote.push(connection)?;
+                    vec![format!("Uploaded {} password(s).", count)]
+                }
+                RemoteAction::Pull => {
+                    let count = remote.pull(connection)?;
+                    vec![format!("Downloaded {} password(s).", count)]
+                }
+                RemoteAction::List => remote
+                    .list()?
+                    .iter()
+                    .map(|entry| {
+                        format!(
+                            "{} (revision {}, updated {})",
+                            entry.name,
+                            entry.revision,
+                            format_timestamp(Some(&entry.updated_at))
+                        )
+                    })
+                    .collect(),
+                // conflicts are resolved interactively, so this happens once the spinner is gone
+                RemoteAction::Sync => Vec::new(),
+            };
+            Ok::<_, anyhow::Error>((remote, lines))
+        })();
+        let (remote, lines) = match result {
+            Ok(result) => {
+                spinner.stop(format!("Connected to {} as {}.", server, user));
+                result
+            }
+            Err(e) => {
+                spinner.stop("Failed.");
+                return Err(e);
+            }
+        };
+
+        match action {
+            RemoteAction::Sync => sync(&LocalReplica::new(connection, master), &remote)?,
+            RemoteAction::List if lines.is_empty() => {
+                log::info("There are no passwords on the server.")?
+            }
+            RemoteAction::List => note("Remote passwords", lines.join("\n"))?,
+            _ => log::success(lines.join("\n"))?,
+        }
+        Ok(())
+    }
+
+    /// Syncs the vault with another vault file, which has to use the same master password.
+    pub fn sync_file(connection: &Connection, master: &str, path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            bail!("{} doesn't exist", path.display());
+        }
+        let other =
+            Vault::open(path).with_context(|| format!("could not open {}", path.display()))?;
+        if !other.is_initialized()? {
+            bail!("{} isn't a passman vault", path.display());
+        }
+        if !authenticate(other.connection(), master, PasswordField::Password)? {
+            bail!(
+                "{} has a different master password; both vaults have to use the same one",
+                path.display()
+            );
+        }
+        let local = LocalReplica::new(connection, master);
+        let remote = LocalReplica::new(other.connection(), master);
+        if local.id()? == remote.id()? {
+            log::warning(format!(
+                "{} is a copy of this vault, so it's given an id of its own.",
+                path.display()
+            ))?;
+            reset_vault_id(other.connection())?;
+        }
+        sync(&local, &remote)
+    }
+
+    /// Starts keeping the vault in a git repository, and copies every password into it.
+    /// If the repository is a clone of another device's vault, the two are synced.
+    pub fn git_init(connection: &Connection, master: &str, path: &Path) -> anyhow::Result<()> {
+        let repo = GitVault::init(path, master, get_vault_algorithm(connection)?).map_err(
+            |e| match e {
+                BackendError::AesError => anyhow::anyhow!(
+                    "{} holds a vault with a different master password; both have to use the same one",
+                    path.display()
+                ),
+                e => e.into(),
+            },
+        )?;
+        // git runs the command through a shell, hence the quotes
+        let exe = std::env::current_exe().context("couldn't find the passman executable")?;
+        repo.set_merge_driver(&format!("'{}' git merge-driver", exe.display()))?;
+        let path = path.canonicalize()?;
+        set_metadata(connection, GIT_REPO_METADATA, path.to_string_lossy())?;
+        log::info(format!("Keeping the vault in {}.", path.display()))?;
+        sync(&LocalReplica::new(connection, master), &repo)
+    }
+
+    /// Commits any changes, then pushes the repository, passing `args` on to `git push`.
+    pub fn git_push(connection: &Connection, master: &str, args: &[String]) -> anyhow::Result<()> {
+        let repo = open_git_repo(connection, master)?.context(
+            "the vault isn't kept in a git repository yet; set one up with `passman git init`",
+        )?;
+        commit_to_git(&LocalReplica::new(connection, master), &repo)?;
+        let mut spinner = spinner();
+        spinner.start("Pushing...");
+        match repo.push(args) {
+            Ok(_) => spinner.stop("Pushed."),
+            Err(e) => {
+                spinner.stop("Failed.");
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits any changes, then pulls the repository, passing `args` on to `git pull`. Passwords changed on both sides
+    /// are merged, asking about conflicting changes, and the result is copied into the vault.
+    pub fn git_pull(connection: &Connection, master: &str, args: &[String]) -> anyhow::Result<()> {
+        let repo = open_git_repo(connection, master)?.context(
+            "the vault isn't kept in a git repository yet; set one up with `passman git init`",
+        )?;
+        let local = LocalReplica::new(connection, master);
+        commit_to_git(&local, &repo)?;
+
+        let mut spinner = spinner();
+        spinner.start("Pulling...");
+        let mut pending = match repo.pull(args) {
+            Ok(pending) => {
+                spinner.stop("Pulled.");
+                pending
+            }
+            Err(e) => {
+                spinner.stop("Failed.");
+                return Err(e.into());
+            }
+        };
+        if !pending.is_empty() {
+            let total = pending.conflicts.len();
+            for (index, conflict) in pending.conflicts.iter_mut().enumerate() {
+                conflict.resolution = Some(resolve(conflict, index + 1, total)?);
+            }
+            pending.finish(&repo)?;
+        }
+        sync(&local, &repo)
+    }
+
+    /// Merges two versions of a password file, for git. Returns whether they could be merged.
+    /// The master password is passed on by `passman git pull`, so this fails if git was run directly.
+    pub fn git_merge_driver(
+        base: &Path,
+        ours: &Path,
+        theirs: &Path,
+        path: &str,
+    ) -> anyhow::Result<bool> {
+        let master = std::env::var(MASTER_ENV).map_err(|_| {
+            anyhow::anyhow!(
+                "{} was changed on both sides; run `passman git pull` to merge it",
+                path
+            )
+        })?;
+        // git runs merge drivers at the top of the working tree
+        let repo = GitVault::open(Path::new("."), &master)?;
+        Ok(repo.merge_files(base, ours, theirs, path)?)
+    }
+
+    /// Commits changes made to the vault to its git repository, if it's kept in one.
+    pub fn git_commit(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        match open_git_repo(connection, master)? {
+            Some(repo) => commit_to_git(&LocalReplica::new(connection, master), &repo),
+            None => Ok(()),
+        }
+    }
+
+    fn open_git_repo(connection: &Connection, master: &str) -> anyhow::Result<Option<GitVault>> {
+        let Some(path) = get_metadata::<String>(connection, GIT_REPO_METADATA)? else {
+            return Ok(None);
+        };
+        let repo = GitVault::open(Path::new(&path), master)
+            .with_context(|| format!("could not open the git repository at {}", path))?;
+        Ok(Some(repo))
+    }
+
+    /// Copies changes from the vault to the repository, one commit per password.
+    /// The repository is only changed by other clones through `git_pull()`, which syncs straight away, so conflicts are
+    /// unusual; if there are any, they're asked about like any other sync.
+    fn commit_to_git(local: &LocalReplica, repo: &GitVault) -> anyhow::Result<()> {
+        let plan = plan_sync(local, repo)?;
+        if plan.is_empty() {
+            return Ok(());
+        }
+        if !plan.conflicts.is_empty() {
+            return sync(local, repo);
+        }
+        plan.apply(local, repo)?;
+        Ok(())
+    }
+
+    /// Prints the public key of the identity kept in this vault, creating the identity the first time.
+    pub fn identity(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let identity = Identity::load_or_create(connection, master)?;
+        note(
+            "Your public key",
+            format!(
+                "{}\n\nGive it to whoever adds you to a shared vault. It can't be used to open anything by itself.",
+                identity.public_key_hex()
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Adds, rotates or removes the vault's keyfile, re-encrypting the vault.
+    ///
+    /// # Arguments
+    ///
+    /// - `master` - the master password on its own, not combined with the keyfile.
+    /// - `keyfile` - the contents of the current keyfile, if the vault requires one.
+    pub fn keyfile(
+        connection: &Connection,
+        master: &str,
+        keyfile: Option<&[u8]>,
+        action: KeyfileAction,
+    ) -> anyhow::Result<()> {
+        let new_keyfile = |path: &Path, generate: bool| match generate {
+            true => generate_keyfile(path)
+                .with_context(|| format!("could not create {}", path.display())),
+            false => {
+                read_keyfile(path).with_context(|| format!("could not read {}", path.display()))
+            }
+        };
+        let mut spinner = spinner();
+        let result = match (action, keyfile) {
+            (KeyfileAction::Add { path, generate }, None) => {
+                let new_keyfile = new_keyfile(&path, generate)?;
+                spinner.start("Re-encrypting the vault...");
+                add_keyfile(connection, master, &new_keyfile).map(|_| {
+                    format!(
+                        "The vault now requires {} to unlock. Keep a copy of it somewhere safe; it can't be recovered.",
+                        path.display()
+                    )
+                })
+            }
+            (KeyfileAction::Add { .. }, Some(_)) => {
+                bail!("the vault already requires a keyfile; use `passman keyfile rotate` to replace it")
+            }
+            (KeyfileAction::Rotate { path, generate }, Some(keyfile)) => {
+                let new_keyfile = new_keyfile(&path, generate)?;
+                spinner.start("Re-encrypting the vault...");
+                rotate_keyfile(connection, master, keyfile, &new_keyfile).map(|_| {
+                    format!(
+                        "The vault now requires {} to unlock instead of the old keyfile.",
+                        path.display()
+                    )
+                })
+            }
+            (KeyfileAction::Remove, Some(keyfile)) => {
+                spinner.start("Re-encrypting the vault...");
+                remove_keyfile(connection, master, keyfile)
+                    .map(|_| "The vault no longer requires a keyfile.".to_string())
+            }
+            (_, None) => {
+                bail!("the vault doesn't use a keyfile; add one with `passman keyfile add`")
+            }
+        };
+        match result {
+            Ok(message) => {
+                spinner.stop("Re-encrypted.");
+                log::success(message)?;
+            }
+            Err(e) => {
+                spinner.stop("Failed.");
+                return Err(e.into());
+            }
+        }
+        // copies elsewhere are encrypted with the old key
+        if get_metadata::<String>(connection, GIT_REPO_METADATA)?.is_some()
+            || get_metadata::<String>(connection, REMOTE_SERVER_METADATA)?.is_some()
+        {
+            log::warning("The vault's git repository or server copy is still encrypted with the old key; set it up again to keep syncing.")?;
+        }
+        Ok(())
+    }
+
+    /// Writes a share file holding one password, encrypted to the recipient's public key.
+    pub fn share(
+        connection: &Connection,
+        master: &str,
+        name: &str,
+        to: &str,
+        expires_in: Option<u32>,
+        out: Option<PathBuf>,
+    ) -> anyhow::Result<()> {
+        let password = read_password_info(connection, name, master)?
+            .with_context(|| format!("no password named \"{}\"", name))?;
+        let identity = Identity::load_or_create(connection, master)?;
+        let expires_at = expires_in.map(|days| Utc::now() + Duration::days(days.into()));
+        let share = create_share(&password, &identity, to, expires_at)?;
+        let out = out.unwrap_or_else(|| format!("{}.passman-share", encode_name(name)).into());
+        std::fs::write(&out, share)
+            .with_context(|| format!("could not write {}", out.display()))?;
+        log::success(format!(
+            "Wrote {}. Only the owner of that public key can open it{}.",
+            out.display(),
+            expires_at.map_or(String::new(), |expires_at| format!(
+                ", until {}",
+                format_timestamp(Some(&expires_at))
+            ))
+        ))?;
+        Ok(())
+    }
+
+    /// Opens a share file and, once confirmed, imports the password into this vault, under a different name if needed.
+    pub fn receive(connection: &Connection, master: &str, path: &Path) -> anyhow::Result<()> {
+        let data =
+            std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+        let identity = Identity::load_or_create(connection, master)?;
+        let received = open_share(&data, &identity, Utc::now()).map_err(|e| match e {
+            BackendError::AesError => anyhow::anyhow!(
+                "{} wasn't shared with you, or it was changed after it was created",
+                path.display()
+            ),
+            e => e.into(),
+        })?;
+        note(
+            "Shared password",
+            format!(
+                "From: {}\nShared: {}",
+                received.from,
+                format_timestamp(Some(&received.shared_at))
+            ),
+        )?;
+        print_password_info(Some(&received.password), false)?;
+        if !confirm("Import this password?").interact()? {
+            log::info("Cancelled.")?;
+            return Ok(());
+        }
+
+        let mut password = received.password;
+        while get_password_info(connection, &password.name)?.is_some() {
+            password.name = input(format!(
+                "You already have a password named \"{}\". Import it as",
+                password.name
+            ))
+            .required(true)
+            .interact()?;
+        }
+        import_shared_password(connection, &password, master)?;
+        log::success(format!("Imported \"{}\".", password.name))?;
+        Ok(())
+    }
+
+    /// Opens a shared vault with the identity kept in this vault, returning it and its vault key.
+    pub fn open_shared_vault(
+        connection: &Connection,
+        master: &str,
+        path: &Path,
+    ) -> anyhow::Result<(Vault, String)> {
+        if !path.exists() {
+            bail!("{} doesn't exist", path.display());
+        }
+        let shared =
+            Vault::open(path).with_context(|| format!("could not open {}", path.display()))?;
+        let identity = Identity::load_or_create(connection, master)?;
+        let vault_key = unlock_shared_vault(shared.connection(), &identity).map_err(|e| match e {
+            BackendError::NotAMember => anyhow::anyhow!(
+                "you aren't a member of {}; ask a member to add your public key (see `passman identity`)",
+                path.display()
+            ),
+            e => e.into(),
+        })?;
+        warn_manifest(shared.connection(), &vault_key)?;
+        Ok((shared, vault_key))
+    }
+
+    /// Creates a shared vault, or manages who has access to one.
+    pub fn team(
+        connection: &Connection,
+        master: &str,
+        path: &Path,
+        action: TeamAction,
+    ) -> anyhow::Result<()> {
+        if let TeamAction::Create { name } = &action {
+            let shared =
+                Vault::open(path).with_context(|| format!("could not open {}", path.display()))?;
+            let identity = Identity::load_or_create(connection, master)?;
+            let algorithm = select_cipher("Select a cipher for the shared vault")?;
+            create_shared_vault(shared.connection(), name, &identity, algorithm)?;
+            log::success(format!(
+                "Created {}. Add others with `passman team {} add <name> <public key>`.",
+                path.display(),
+                path.display()
+            ))?;
+            return Ok(());
+        }
+
+        let (shared, vault_key) = open_shared_vault(connection, master, path)?;
+        match action {
+            TeamAction::Add { name, public_key } => {
+                add_member(shared.connection(), &vault_key, &name, &public_key)?;
+                log::success(format!("{} can now open {}.", name, path.display()))?;
+            }
+            TeamAction::Remove { name } => {
+                let mut spinner = spinner();
+                spinner.start("Re-encrypting the vault with a new key...");
+                match remove_member(shared.connection(), &vault_key, &name) {
+                    Ok(Some(_)) => {
+                        spinner.stop(format!("Removed {}.", name));
+                        log::warning(format!(
+                            "{} may have kept a copy of the vault, so change the passwords they could see.",
+                            name
+                        ))?;
+                    }
+                    Ok(None) => {
+                        spinner.stop("Failed.");
+                        bail!("{} isn't a member", name);
+                    }
+                    Err(e) => {
+                        spinner.stop("Failed.");
+                        return Err(e.into());
+                    }
+                }
+            }
+            TeamAction::Members => {
+                let members = list_members(shared.connection())?
+                    .iter()
+                    .map(|member| {
+                        format!(
+                            "{} (added {})\n  {}",
+                            member.name,
+                            format_timestamp(Some(&member.added_at)),
+                            member.public_key
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                note("Members", members.join("\n"))?;
+            }
+            TeamAction::Create { .. } | TeamAction::Open => {
+                unreachable!("handled by the caller")
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the whole vault and prints what's wrong with it, if anything. Returns whether the vault is intact.
+    /// If `reseal` is set, the manifest is resealed first, accepting the passwords as they are.
+    pub fn verify(
+        connection: &Connection,
+        master: &str,
+        json: bool,
+        reseal: bool,
+    ) -> anyhow::Result<bool> {
+        if reseal {
+            let revision = reseal_manifest(connection, master)?;
+            log::info(format!("Resealed the manifest at revision {}.", revision))?;
+        }
+        let report = verify_vault(connection, master)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(report.is_ok());
+        }
+        for error in &report.integrity_errors {
+            println!("integrity\t{}", error);
+        }
+        for error in &report.master_record_errors {
+            println!("master record\t{}", error);
+        }
+        for error in &report.metadata_errors {
+            println!("metadata\t{}", error);
+        }
+        for damaged in &report.damaged {
+            println!(
+                "damaged\t{}\t{}\t{}",
+                damaged.password, damaged.field, damaged.error
+            );
+        }
+        match report.is_ok() {
+            true => println!(
+                "ok\t{} password(s) and {} field(s) checked",
+                report.entries, report.fields
+            ),
+            false => println!(
+                "{} of {} password(s) damaged",
+                report.damaged_entries().len(),
+                report.entries
+            ),
+        }
+        Ok(report.is_ok())
+    }
+
+    /// Lists, takes or restores snapshots of the vault, or changes how many are kept.
+    pub fn backup(
+        connection: &Connection,
+        master: &str,
+        keyfile: Option<&[u8]>,
+        action: BackupAction,
+    ) -> anyhow::Result<()> {
+        let dir = backup_dir(connection).context("the vault isn't stored in a file")?;
+        match action {
+            BackupAction::List => {
+                let snapshots = list_snapshots(connection)?
+                    .iter()
+                    .map(|snapshot| {
+                        format!(
+                            "{} (before {})\n  {}",
+                            format_timestamp(Some(&snapshot.taken_at)),
+                            snapshot.reason,
+                            snapshot
+                                .path
+                                .file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                if snapshots.is_empty() {
+                    log::info(format!("There are no snapshots in {}.", dir.display()))?;
+                } else {
+                    note(
+                        format!("Snapshots in {}", dir.display()),
+                        snapshots.join("\n"),
+                    )?;
+                }
+            }
+            BackupAction::Create => match take_snapshot(connection, "manual")? {
+                Some(snapshot) => log::success(format!("Saved {}.", snapshot.path.display()))?,
+                None => {
+                    bail!("snapshots are turned off; turn them on with `passman backup retention`")
+                }
+            },
+            BackupAction::Restore { snapshot } => {
+                let path = match snapshot.exists() {
+                    true => snapshot,
+                    false => dir.join(snapshot),
+                };
+                if !path.exists() {
+                    bail!("no snapshot at {}", path.display());
+                }
+                if !confirm(format!(
+                    "Replace the vault with {}? The vault as it is now is snapshotted first.",
+                    path.display()
+                ))
+                .interact()?
+                {
+                    return Ok(());
+                }
+                // restored through a connection of its own, since the backup API needs exclusive access
+                let mut target = Connection::open(connection.path().unwrap_or_default())?;
+                let restored = match restore_snapshot(&mut target, &path, master) {
+                    Err(BackendError::SnapshotKeyMismatch) => {
+                        log::warning(
+                            "The snapshot was taken with a different master password or keyfile.",
+                        )?;
+                        let old_master: String =
+                            password("Enter the master password it was taken with")
+                                .mask('*')
+                                .interact()?;
+                        // the snapshot may be from before the keyfile was added
+                        let mut result = restore_snapshot(&mut target, &path, &old_master);
+                        if let (Err(BackendError::SnapshotKeyMismatch), Some(keyfile)) =
+                            (&result, keyfile)
+                        {
+                            result = restore_snapshot(
+                                &mut target,
+                                &path,
+                                &composite_key(&old_master, keyfile),
+                            );
+                        }
+                        result?
+                    }
+                    result => result?,
+                };
+                log::success(format!("Restored {} password(s).", restored))?;
+            }
+            BackupAction::Retention {
+                keep_last,
+                keep_daily,
+                keep_weekly,
+            } => {
+                let mut policy = get_backup_policy(connection)?;
+                if keep_last.is_some() || keep_daily.is_some() || keep_weekly.is_some() {
+                    policy.keep_last = keep_last.unwrap_or(policy.keep_last);
+                    policy.keep_daily = keep_daily.unwrap_or(policy.keep_daily);
+                    policy.keep_weekly = keep_weekly.unwrap_or(policy.keep_weekly);
+                    set_backup_policy(connection, &policy)?;
+                }
+                let message = format!(
+                    "Keeping the last {} snapshot(s), one a day for {} day(s) and one a week for {} week(s).",
+                    policy.keep_last, policy.keep_daily, policy.keep_weekly
+                );
+                match policy.is_enabled() {
+                    true => log::info(message)?,
+                    false => log::warning("Snapshots are turned off.")?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Works out what changed on both sides, asks how to resolve any conflicts, and then writes the changes.
+    fn sync(local: &dyn Replica, remote: &dyn Replica) -> anyhow::Result<()> {
+        let mut plan = plan_sync(local, remote)?;
+        if plan.is_empty() {
+            log::info("Everything is in sync already.")?;
+            return Ok(());
+        }
+        let total = plan.conflicts.len();
+        for (index, conflict) in plan.conflicts.iter_mut().enumerate() {
+            conflict.resolution = Some(resolve(conflict, index + 1, total)?);
+        }
 
-#[derive(Default, Clone, PartialEq, Eq)]
-pub enum LoginOperations {
-    Login,
-    Reset,
-    #[default]
-    Exit,
+        let mut spinner = spinner();
+        spinner.start("Syncing...");
+        let report = match plan.apply(local, remote) {
+            Ok(report) => {
+                spinner.stop("Synced.");
+                report
+            }
+            Err(e) => {
+                spinner.stop("Failed.");
+                if let BackendError::ChangedDuringSync(_) = e {
+                    log::warning("Something else changed the vaults while syncing. Sync again to pick up the rest.")?;
+                }
+                return Err(e.into());
+            }
+        };
+        let lines = [
+            (report.to_local, "password(s) updated here"),
+            (report.deleted_local, "password(s) deleted here"),
+            (report.to_remote, "password(s) updated on the other side"),
+            (
+                report.deleted_remote,
+                "password(s) deleted on the other side",
+            ),
+            (report.conflicts, "conflict(s) resolved"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect::<Vec<_>>();
+        match lines.is_empty() {
+            true => log::success("Nothing had to be changed.")?,
+            false => log::success(lines.join("\n"))?,
+        }
+        Ok(())
+    }
+
+    /// Asks which side of a conflict to keep.
+    fn resolve(conflict: &Conflict, number: usize, total: usize) -> anyhow::Result<Resolution> {
+        let describe = |side: &ConflictSide| {
+            let value = match (side.deleted, &side.value) {
+                (true, _) => "deleted".to_string(),
+                (false, Some(value)) => value.clone(),
+                (false, None) => "empty".to_string(),
+            };
+            match side.changed_at {
+                Some(changed_at) => format!(
+                    "{} (changed {})",
+                    value,
+                    format_timestamp(Some(&changed_at))
+                ),
+                None => value,
+            }
+        };
+        let title = match conflict.field {
+            Some(field) => format!(
+                "the {} of \"{}\" was changed on both sides",
+                field, conflict.name
+            ),
+            None => format!(
+                "\"{}\" was deleted on one side and changed on the other",
+                conflict.name
+            ),
+        };
+        Ok(
+            select(format!("Conflict {} of {}: {}", number, total, title))
+                .item(
+                    Resolution::KeepLocal,
+                    "Keep this vault's version",
+                    describe(&conflict.local),
+                )
+                .item(
+                    Resolution::KeepRemote,
+                    "Keep the other version",
+                    describe(&conflict.remote),
+                )
+                .interact()?,
+        )
+    }
 }
-// these are the CLI frontend implementations of the CRUD operations
-pub mod crud_operations {
-    use crate::backend::{
-        db_ops::{
-            crud::{delete_password_info, read_password_info},
-            util::check_password_info_exists,
-        },
-        password::PasswordField,
-    };
-    use cliclack::{confirm, input, note, outro};
-    use colored::Colorize;
-    use rusqlite::Connection;
 
-    use super::utility::{
-        check_password_availability, password::insert_password, print_password_info, prompt_field,
+// operations on the vault as a whole, rather than a single password
+pub mod vault_operations {
+    use cliclack::{confirm, input, log, note, select, spinner};
+    use colored::Colorize;
+    use passman_core::{
+        db_ops::{crud::convert_vault_cipher, util::get_vault_algorithm},
+        login::{get_login_policy, set_login_policy, LockoutAction, LoginPolicy},
     };
-    /// Series of prompts to insert a new password into the SQLite table `PasswordInfo`.
-    pub fn insert(connection: &Connection, master: &str) -> anyhow::Result<()> {
-        let name: String = input("Enter Password name?")
-            .placeholder("My new password")
-            .required(true)
-            .interact()?;
-
-        check_password_availability(connection, &name)?;
+    use rusqlite::Connection;
 
-        prompt_field(
-            connection,
-            master,
-            &name,
-            PasswordField::Email,
-            "example@domain.com",
-        )?;
+    use super::utility::select_cipher;
 
-        prompt_field(
-            connection,
-            master,
-            &name,
-            PasswordField::Username,
-            "example_username",
+    /// Series of prompts to convert every field in the vault to a different cipher.
+    pub fn change_cipher(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let current = get_vault_algorithm(connection)?;
+        note(
+            "Vault Cipher",
+            format!("This vault currently encrypts new data with {}.", current),
         )?;
+        let algorithm = select_cipher("Select a new cipher for this vault")?;
+        if algorithm == current {
+            log::info("The vault already uses this cipher.")?;
+            return Ok(());
+        }
 
-        prompt_field(
-            connection,
-            master,
-            &name,
-            PasswordField::Notes,
-            "any text here",
-        )?;
+        let confirm = confirm(format!(
+            "Every password will be re-encrypted with {}. Continue?",
+            algorithm
+        ))
+        .initial_value(false)
+        .interact()?;
+        if !confirm {
+            log::info("Cancelled.")?;
+            return Ok(());
+        }
 
-        insert_password(connection, &name, master)?;
+        let mut spinner = spinner();
+        spinner.start("Re-encrypting vault...");
+        let converted = convert_vault_cipher(connection, master, algorithm)?;
+        spinner.stop(format!("Re-encrypted {} fields.", converted));
 
-        outro(format!(
-            "Successfully inserted a new password!\n\t{}",
-            "Exiting...".green().bold()
-        ))?;
+        log::success(format!("Vault now uses {}.", algorithm))?;
         Ok(())
     }
 
-    /// Series of prompts to read password info. If it finds data given user input, it will print the details of the given password.
-    pub fn read(connection: &Connection, master: &str) -> anyhow::Result<()> {
-        let name: String = input("Enter Password name?")
-            .placeholder("My new password")
-            .required(true)
-            .interact()?;
-        let res = read_password_info(connection, &name, master)?;
-        print_password_info(res)?;
-        outro("Exiting...".bold())?;
-        Ok(())
-    }
-    /// Series of prompts *and **confirmations*** to delete data from the SQLite table `PasswordInfo`. Only requires an `sqlite::Connection`.
-    pub fn delete(connection: &Connection) -> anyhow::Result<()> {
-        let name: String = input("Enter Password name?")
-            .placeholder("My new password")
-            .required(true)
-            .interact()?;
+    /// Series of prompts to change what happens after too many failed logins.
+    pub fn change_login_policy(connection: &Connection) -> anyhow::Result<()> {
+        let current = get_login_policy(connection)?;
+        let description = match (current.max_attempts, current.action) {
+            (Some(max_attempts), LockoutAction::Lockout { minutes }) => format!(
+                "After {} failed logins, the vault is locked for {} minutes.",
+                max_attempts, minutes
+            ),
+            (Some(max_attempts), LockoutAction::Wipe) => {
+                format!("After {} failed logins, the vault is wiped.", max_attempts)
+            }
+            _ => "Failed logins only cause increasing delays.".to_string(),
+        };
+        note("Login Security", description)?;
 
-        let check_exists = check_password_info_exists(connection, &name)?;
-        if !check_exists {
-            outro("No password found with that name.")?;
-            return Ok(());
-        }
-        // it's a big deal to delete data so make sure the user understands they're doing some serious shit
-        note(
-        "Password Deletion",
-        "You are about to delete a password. This action is UNDOABLE and your data will be lost FOREVER. There is NO BACKUP or restoration process, so PLEASE SAVE THIS DATA BEFORE YOU DELETE IT. ",
-    )?;
-        let confirm = confirm("Deleting a password... Continue?")
-            .initial_value(false)
+        let action = select("What should happen after too many failed logins?")
+            .item(
+                LockoutAction::Nothing,
+                "Nothing",
+                "only wait between attempts",
+            )
+            .item(
+                LockoutAction::Lockout { minutes: 0 },
+                "Lock the vault",
+                "for a number of minutes",
+            )
+            .item(
+                LockoutAction::Wipe,
+                "Wipe the vault",
+                "dangerous - deletes every password".red(),
+            )
             .interact()?;
 
-        if !confirm {
-            outro("Exiting...")?;
-            return Ok(());
-        }
-
-        delete_password_info(connection, &name)?;
-        outro("Successfully deleted password.".bold())?;
+        let number = |prompt: &str, default: &str| -> anyhow::Result<u32> {
+            let value: String = input(prompt)
+                .default_input(default)
+                .validate(|input: &String| match input.parse::<u32>() {
+                    Ok(num) if num > 0 => Ok(()),
+                    _ => Err("Please enter a positive number."),
+                })
+                .interact()?;
+            Ok(value.parse()?)
+        };
+        let policy = match action {
+            LockoutAction::Nothing => LoginPolicy::default(),
+            LockoutAction::Lockout { .. } => LoginPolicy {
+                max_attempts: Some(number("After how many failed logins?", "10")?),
+                action: LockoutAction::Lockout {
+                    minutes: number("Lock the vault for how many minutes?", "60")?,
+                },
+            },
+            LockoutAction::Wipe => {
+                let policy = LoginPolicy {
+                    max_attempts: Some(number("After how many failed logins?", "10")?),
+                    action: LockoutAction::Wipe,
+                };
+                let confirm =
+                    confirm("Anyone can wipe your vault by entering wrong passwords. Continue?")
+                        .initial_value(false)
+                        .interact()?;
+                if !confirm {
+                    log::info("Cancelled.")?;
+                    return Ok(());
+                }
+                policy
+            }
+        };
+        set_login_policy(connection, &policy)?;
+        log::success("Updated login security settings.")?;
         Ok(())
     }
 }
 
 // all of this is just utility functions and refactoring (and abstracting and the like)
 pub mod utility {
-    use crate::backend::{
-        crypto::hash,
+    use anyhow::Context;
+    use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+    use cliclack::{confirm, input, note, outro, password, select, spinner};
+    use colored::Colorize;
+    use passman_core::{
+        backup::take_snapshot,
         db_ops::{
-            crud::{get_password_info, insert_data},
-            util::authenticate,
-            MASTER_KEYWORD,
+            crud::{get_password_info, list_due},
+            util::insert_master_record,
         },
-        password::{PasswordField, PasswordInfo},
+        envelope::Algorithm,
+        error::BackendError,
+        keyfile::{composite_key, read_keyfile, requires_keyfile},
+        login::{attempt_login, next_login_at, start_over, LoginAttempt},
+        manifest::{reseal_manifest, verify_manifest, ManifestStatus},
+        password::{normalize_folder, EntryField, Expiry, FolderNode, PasswordField, PasswordInfo},
     };
-    use cliclack::{confirm, input, note, outro, password, select};
-    use colored::Colorize;
     use rusqlite::Connection;
+    use std::{path::Path, thread};
+
+    /// The environment variable `master_from_env()` reads the master password from.
+    pub const MASTER_ENV: &str = "PASSMAN_MASTER";
 
     pub mod password {
         use cliclack::{input, password, select};
-        use rusqlite::Connection;
         use std::io;
 
-        use crate::{
-            backend::{
-                crypto::generate_password, db_ops::crud::insert_data, password::PasswordField,
-            },
-            cli::PasswordGeneration,
-        };
+        use crate::cli::PasswordGeneration;
+        use passman_core::crypto::generate_password;
 
         /// Prompts the user for a confirmed password, meaning that they must type the same password twice.
         pub fn confirmed_password() -> Result<String, io::Error> {
@@ -171,13 +1667,9 @@ pub mod utility {
         }
 
         /// Prompts a series of inputs to generate a password.
-        ///  A user may either automatically generate a password or manually type one, or not insert a password at all.
-        /// This function inserts a password! As such, it returns ().
-        pub fn insert_password(
-            connection: &Connection,
-            name: &str,
-            master: &str,
-        ) -> anyhow::Result<()> {
+        /// A user may either automatically generate a password or manually type one, or not insert a password at all.
+        /// Nothing is inserted - the password is returned, or `None` if the user doesn't want one.
+        pub fn prompt_password() -> anyhow::Result<Option<String>> {
             let password_type: PasswordGeneration =
                 select("Select password generation type (optional)")
                     .item(
@@ -196,38 +1688,28 @@ pub mod utility {
                         "",
                     )
                     .interact()?;
-            let password: Option<String> = match password_type {
+            Ok(match password_type {
                 PasswordGeneration::Automatic => Some(auto_password_prompt()?),
                 PasswordGeneration::Manual => Some(confirmed_password()?),
                 PasswordGeneration::NoPassword => None,
-            };
-
-            password.map(|password| {
-                insert_data(connection, name, master, PasswordField::Password, &password)
-            });
-            Ok(())
+            })
         }
 
-        pub fn auto_password_prompt() -> Result<String, io::Error> {
+        /// Prompts for a length and generates a password of that length.
+        pub fn auto_password_prompt() -> anyhow::Result<String> {
             let length: String = input("Enter password length")
                 .default_input("12")
                 .placeholder("Your password length")
-                .validate(|input: &String| {
-                    let num = input.parse::<i32>();
-
-                    if num.is_err() {
-                        Err("Please enter a number.")
-                    } else {
-                        Ok(())
-                    }
+                .validate(|input: &String| match input.parse::<usize>() {
+                    Ok(num) if num > 0 => Ok(()),
+                    _ => Err("Please enter a positive number."),
                 })
                 .interact()?;
-            let num = length.parse::<usize>().unwrap();
-            Ok(generate_password(num))
+            Ok(generate_password(length.parse()?))
         }
     }
     use self::password::confirmed_password;
-    use super::LoginOperations;
+    use super::{ExpiryOption, LoginOperations, DEFAULT_REMINDER_DAYS};
     /// Inserts a new master password given a series of prompts and inputs.
     /// The input is a `confirmed_password`, meaning the user must type the same password twice.
     /// The function then hashes the master password and inserts it into the SQLite table `PasswordInfo`.
@@ -242,13 +1724,9 @@ pub mod utility {
         note("Recovery Phrase", "This is the ONLY WAY to change your master password, so DO NOT lose this phrase.\nBetter yet, don't lose your master password.")?;
         let recovery_note: String = input("Enter a recovery phrase.").interact()?;
 
-        let master_password = hex::encode(hash(new_master.as_bytes()));
-        let note = hex::encode(hash(recovery_note.as_bytes()));
+        let algorithm = select_cipher("Select a cipher for this vault")?;
 
-        connection.execute(
-            "insert into PasswordInfo (name, password, notes) values (?1, ?2, ?3)",
-            [MASTER_KEYWORD, &master_password, &note],
-        )?;
+        insert_master_record(connection, &new_master, &recovery_note, algorithm)?;
         outro(format!(
             "Successfully inserted a new master record!\n\t{}",
             "Exiting...".green().bold()
@@ -261,92 +1739,452 @@ pub mod utility {
     /// Utility function for the initial login prompts. Provides the user the ability to:
     ///
     /// 1. Log in using a master password
-    /// 2. Reset a lost/forgotten master password with a recovery phrase (stored as hashed data in notes column)
+    /// 2. Start over after losing/forgetting the master password, deleting every password, with a recovery phrase (stored as hashed data in notes column)
     /// 3. Exit the program immediately.  
     ///
-    /// Returns a master password string for the logic in the program.
-    pub fn login(connection: &Connection) -> anyhow::Result<String> {
+    /// Returns a master password string for the logic in the program,
+    /// or `None` if the user chose to exit or reset their master password.
+    pub fn login(
+        connection: &Connection,
+        keyfile: Option<&Path>,
+    ) -> anyhow::Result<Option<String>> {
         let login_operation: LoginOperations = select("Select a login option.")
             .item(LoginOperations::Login, "Log in", "")
             .item(
                 LoginOperations::Reset,
-                "Reset master password",
-                "use recovery phrase",
+                "Start over",
+                "forgot the master password: delete every password, using the recovery phrase",
             )
             .item(LoginOperations::Exit, "Exit", "")
             .interact()?;
 
-        let master = match login_operation {
-            LoginOperations::Login => {
-                let master = password(format!("Enter {}", "master password:".bright_red().bold()))
-                    .mask('*')
-                    .interact()?;
-                if !(authenticate(connection, &master, PasswordField::Password)?) {
-                    outro("Incorrect password. Exiting...".red().bold())?;
-                    std::process::exit(1);
-                }
-
-                master
-            }
+        match login_operation {
+            LoginOperations::Login => Ok(Some(unlock(connection, keyfile)?)),
             LoginOperations::Reset => {
                 let recovery_phrase =
-                    password(format!("Enter {}", "recovery phrase:".bright_red().bold()))
-                        .mask('*')
-                        .interact()?;
-                if !(authenticate(connection, &recovery_phrase, PasswordField::Notes)?) {
-                    outro("Incorrect recovery phrase. Exiting...".red().bold())?;
-                    std::process::exit(1);
-                }
+                    prompt_secret(connection, "recovery phrase:", PasswordField::Notes, None)?;
 
-                let new_master = hex::encode(hash(confirmed_password()?.as_bytes()));
-                connection.execute(
-                    "update PasswordInfo set password = ?1 where name = ?2",
-                    [&new_master, MASTER_KEYWORD],
-                )?;
+                cliclack::log::warning(format!(
+                    "{}\nEvery password is encrypted with the master password, so none of them can be kept without it. A snapshot is taken first, in case the old master password turns up.",
+                    "Starting over deletes every password in this vault.".red().bold()
+                ))?;
+                if !confirm("Delete every password and set a new master password?")
+                    .initial_value(false)
+                    .interact()?
+                {
+                    outro("Nothing was changed.")?;
+                    return Ok(None);
+                }
 
-                outro("Updated master password!")?;
+                // the keyfile is still needed afterwards
+                let new_master = confirmed_password()?;
+                let new_master = match read_vault_keyfile(connection, keyfile)? {
+                    Some(keyfile) => composite_key(&new_master, &keyfile),
+                    None => new_master,
+                };
+                take_snapshot(connection, "start-over")?;
+                start_over(connection, &recovery_phrase, &new_master)?;
 
-                std::process::exit(1);
+                outro("Deleted every password and set the new master password. Log in again to continue.")?;
+                Ok(None)
             }
             LoginOperations::Exit => {
                 outro("Exiting...".green().bold())?;
-                std::process::exit(1);
+                Ok(None)
             }
+        }
+    }
+
+    /// Prompts for the master password until the correct one is entered. Used to log in, and to unlock a locked session.
+    /// Returns the key to pass to the backend as the master password, which includes the keyfile if the vault requires one.
+    pub fn unlock(connection: &Connection, keyfile: Option<&Path>) -> anyhow::Result<String> {
+        let (master, keyfile) = unlock_credentials(connection, keyfile)?;
+        Ok(match keyfile {
+            Some(keyfile) => composite_key(&master, &keyfile),
+            None => master,
+        })
+    }
+
+    /// Reads the master password from `MASTER_ENV` instead of prompting for it, for commands run by cron jobs.
+    /// Returns `None` if it isn't set. The keyfile has to be given, since there's no one to ask for it.
+    pub fn master_from_env(
+        connection: &Connection,
+        keyfile: Option<&Path>,
+    ) -> anyhow::Result<Option<String>> {
+        let Ok(master) = std::env::var(MASTER_ENV) else {
+            return Ok(None);
         };
-        Ok(master)
+        if !requires_keyfile(connection)? {
+            return Ok(Some(master));
+        }
+        let path = keyfile.context("the vault requires a keyfile; pass it with --keyfile")?;
+        let keyfile = read_keyfile(path)
+            .with_context(|| format!("could not read the keyfile {}", path.display()))?;
+        Ok(Some(composite_key(&master, &keyfile)))
     }
 
-    pub fn prompt_field(
+    /// Like `unlock()`, but returns the master password and the contents of the keyfile (if the vault requires one) separately.
+    pub fn unlock_credentials(
         connection: &Connection,
-        master: &str,
-        name: &str,
+        keyfile: Option<&Path>,
+    ) -> anyhow::Result<(String, Option<Vec<u8>>)> {
+        let keyfile = read_vault_keyfile(connection, keyfile)?;
+        let master = prompt_secret(
+            connection,
+            "master password:",
+            PasswordField::Password,
+            keyfile.as_deref(),
+        )?;
+        let key = match &keyfile {
+            Some(keyfile) => composite_key(&master, keyfile),
+            None => master.clone(),
+        };
+        warn_manifest(connection, &key)?;
+        Ok((master, keyfile))
+    }
+
+    /// Checks the vault against its manifest after unlocking it, and warns if passwords were deleted or rolled back
+    /// outside of passman, or the manifest is damaged. A vault without a manifest is only sealed if the user confirms it.
+    pub fn warn_manifest(connection: &Connection, master: &str) -> anyhow::Result<()> {
+        let accept = "Nothing can be changed until you run `passman verify` to check the vault, and `passman verify --reseal` to accept it as it is.";
+        match verify_manifest(connection, master) {
+            Ok(ManifestStatus::Valid { .. }) => {}
+            Ok(ManifestStatus::Missing) => {
+                cliclack::log::warning(format!(
+                    "{}
+It was made by an older version of passman, or its manifest was removed. Once it's sealed, passwords deleted or rolled back outside of passman are noticed.",
+                    "This vault has no manifest.".red().bold(),
+                ))?;
+                if confirm("Are all your passwords there? Seal the vault as it is?")
+                    .initial_value(false)
+                    .interact()?
+                {
+                    let revision = reseal_manifest(connection, master)?;
+                    cliclack::log::info(format!("Sealed the vault at revision {}.", revision))?;
+                } else {
+                    cliclack::log::info(accept)?;
+                }
+            }
+            Ok(ManifestStatus::Mismatch { .. }) => {
+                cliclack::log::warning(format!(
+                    "{}\nPasswords may have been deleted or rolled back outside of passman, or its manifest removed. {}",
+                    "This vault doesn't match its manifest.".red().bold(),
+                    accept
+                ))?;
+            }
+            // still let the user in, so they can accept the vault
+            Err(e @ BackendError::CorruptMetadata { .. }) => {
+                cliclack::log::warning(format!(
+                    "{}\n{}. {}",
+                    "This vault's manifest is damaged.".red().bold(),
+                    e,
+                    accept
+                ))?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// Reads the keyfile if the vault requires one, asking where it is if `path` is `None`.
+    fn read_vault_keyfile(
+        connection: &Connection,
+        path: Option<&Path>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        if !requires_keyfile(connection)? {
+            if path.is_some() {
+                cliclack::log::warning("This vault doesn't use a keyfile, so it's ignored.")?;
+            }
+            return Ok(None);
+        }
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => input("Enter the path of the keyfile")
+                .required(true)
+                .interact::<String>()?
+                .into(),
+        };
+        let keyfile = read_keyfile(&path)
+            .with_context(|| format!("could not read the keyfile {}", path.display()))?;
+        Ok(Some(keyfile))
+    }
+
+    /// Repeatedly prompts for the master password or recovery phrase until the correct one is entered,
+    /// waiting out the back-off delay between failed attempts. Fails if the vault gets locked out or wiped.
+    /// After a successful attempt, the user is told about any failed attempts since their last login.
+    /// If a keyfile is given, it's combined with the secret before it's checked. The secret itself is returned either way.
+    fn prompt_secret(
+        connection: &Connection,
+        label: &str,
+        column: PasswordField,
+        keyfile: Option<&[u8]>,
+    ) -> anyhow::Result<String> {
+        loop {
+            if let Some(retry_at) = next_login_at(connection, Utc::now())? {
+                let wait = (retry_at - Utc::now()).to_std().unwrap_or_default();
+                let mut spinner = spinner();
+                spinner.start(format!(
+                    "Too many failed attempts. Waiting until {}...",
+                    format_timestamp(Some(&retry_at))
+                ));
+                thread::sleep(wait);
+                spinner.stop("You may try again.");
+            }
+            let secret = password(format!("Enter {}", label.bright_red().bold()))
+                .mask('*')
+                .interact()?;
+
+            let key = match keyfile {
+                Some(keyfile) => composite_key(&secret, keyfile),
+                None => secret.clone(),
+            };
+            match attempt_login(connection, &key, column, Utc::now())? {
+                LoginAttempt::Success {
+                    failed_attempts,
+                    last_failed_at,
+                } => {
+                    if failed_attempts > 0 {
+                        cliclack::log::warning(format!(
+                            "There were {} failed login attempt(s) since your last login, the last one on {}.",
+                            failed_attempts,
+                            format_timestamp(last_failed_at.as_ref())
+                        ))?;
+                    }
+                    return Ok(secret);
+                }
+                LoginAttempt::Failed {
+                    failed_attempts, ..
+                } => {
+                    cliclack::log::error(format!(
+                        "Incorrect {} ({} failed attempt(s))",
+                        label.trim_end_matches(':'),
+                        failed_attempts
+                    ))?;
+                }
+                LoginAttempt::Throttled { .. } => {}
+                LoginAttempt::LockedOut { until } => anyhow::bail!(
+                    "too many failed attempts; the vault is locked until {}",
+                    format_timestamp(Some(&until))
+                ),
+                LoginAttempt::Wiped => {
+                    anyhow::bail!("too many failed attempts; the vault has been wiped")
+                }
+            }
+        }
+    }
+
+    /// Prompts the user to pick one of the supported ciphers.
+    pub fn select_cipher(prompt: &str) -> std::io::Result<Algorithm> {
+        select(prompt)
+            .item(Algorithm::Aes256Gcm, Algorithm::Aes256Gcm, "default")
+            .item(
+                Algorithm::XChaCha20Poly1305,
+                Algorithm::XChaCha20Poly1305,
+                "larger nonces",
+            )
+            .interact()
+    }
+
+    /// Prompts for an optional field. Returns `None` if nothing was entered.
+    pub fn prompt_field(param: PasswordField, placeholder: &str) -> anyhow::Result<Option<String>> {
+        let data = input(format!("Enter {} (optional)", param))
+            .placeholder(placeholder)
+            .default_input("")
+            .interact::<String>()?;
+        Ok(Some(data).filter(|data| !data.is_empty()))
+    }
+    /// Prompts for a comma separated list, e.g. URLs or tags. Returns an empty list if nothing was entered.
+    pub fn prompt_list_field(
         param: PasswordField,
         placeholder: &str,
-    ) -> anyhow::Result<()> {
-        let data = input(format!("Enter {} (optional)", param))
+    ) -> anyhow::Result<Vec<String>> {
+        let data = input(format!("Enter {} (optional, comma separated)", param))
             .placeholder(placeholder)
             .default_input("")
             .interact::<String>()?;
-        if !data.is_empty() {
-            insert_data(connection, name, master, param, &data)?;
+        Ok(data
+            .split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect())
+    }
+
+    /// Prompts for a folder path. Returns `None` if nothing was entered.
+    pub fn prompt_folder() -> anyhow::Result<Option<String>> {
+        let data = input("Enter folder (optional)")
+            .placeholder("Work/Email")
+            .default_input("")
+            .interact::<String>()?;
+        Ok(normalize_folder(&data))
+    }
+
+    /// Prompts for an optional expiry date or rotation interval. Returns `Expiry::default()` if none was chosen.
+    pub fn prompt_expiry() -> anyhow::Result<Expiry> {
+        let option = select("Does this password expire? (optional)")
+            .item(ExpiryOption::Skip, "No expiry", "")
+            .item(ExpiryOption::Date, "Expires on a date", "")
+            .item(
+                ExpiryOption::Rotation,
+                "Rotate every few days",
+                "e.g. every 90 days",
+            )
+            .interact()?;
+        let expiry = match option {
+            ExpiryOption::Skip => Expiry::default(),
+            ExpiryOption::Date => {
+                let date: String = input("Enter expiry date")
+                    .placeholder("YYYY-MM-DD")
+                    .validate(|input: &String| {
+                        NaiveDate::parse_from_str(input, "%Y-%m-%d")
+                            .map(|_| ())
+                            .map_err(|_| "Please enter a date like 2024-12-31.")
+                    })
+                    .interact()?;
+                let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?;
+                Expiry {
+                    expires_at: date.and_hms_opt(0, 0, 0).map(|date| date.and_utc()),
+                    rotation_days: None,
+                }
+            }
+            ExpiryOption::Rotation => {
+                let days: String = input("Rotate every how many days?")
+                    .default_input("90")
+                    .validate(|input: &String| match input.parse::<u32>() {
+                        Ok(days) if days > 0 => Ok(()),
+                        _ => Err("Please enter a positive number."),
+                    })
+                    .interact()?;
+                Expiry {
+                    expires_at: None,
+                    rotation_days: Some(days.parse()?),
+                }
+            }
+        };
+        Ok(expiry)
+    }
+
+    /// Shows a warning listing passwords that are expired or will be due within `DEFAULT_REMINDER_DAYS`.
+    /// Prints nothing if there are none.
+    pub fn print_due_banner(connection: &Connection) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let due = list_due(
+            connection,
+            now,
+            Duration::days(DEFAULT_REMINDER_DAYS.into()),
+        )?;
+        if due.is_empty() {
+            return Ok(());
         }
+        let lines = due
+            .iter()
+            .map(|password| {
+                if password.expired {
+                    format!(
+                        "{} expired on {}",
+                        password.name,
+                        format_timestamp(Some(&password.due_at))
+                    )
+                    .red()
+                    .to_string()
+                } else {
+                    format!(
+                        "{} is due on {}",
+                        password.name,
+                        format_timestamp(Some(&password.due_at))
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+        cliclack::log::warning(format!(
+            "{} password(s) need to be changed:\n{}",
+            due.len(),
+            lines.join("\n")
+        ))?;
         Ok(())
     }
+
+    /// Formats an optional timestamp in local time, for display.
+    pub fn format_timestamp(timestamp: Option<&DateTime<Utc>>) -> String {
+        timestamp.map_or_else(
+            || "never".to_string(),
+            |timestamp| {
+                timestamp
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            },
+        )
+    }
+
+    /// Renders a `FolderNode` as an indented tree, folders first.
+    pub fn render_folder_tree(root: &FolderNode) -> String {
+        fn render(node: &FolderNode, depth: usize, lines: &mut Vec<String>) {
+            let indent = "  ".repeat(depth);
+            for (name, child) in &node.children {
+                lines.push(format!("{}{}/", indent, name.bold()));
+                render(child, depth + 1, lines);
+            }
+            for entry in &node.entries {
+                lines.push(format!("{}{}", indent, entry));
+            }
+        }
+        let mut lines = Vec::new();
+        render(root, 0, &mut lines);
+        lines.join("\n")
+    }
+
+    /// Prompts for the value of a custom field. Sensitive values are masked while typing.
+    pub fn prompt_field_value(sensitive: bool) -> std::io::Result<String> {
+        if sensitive {
+            password("Enter field value").mask('*').interact()
+        } else {
+            input("Enter field value").required(true).interact()
+        }
+    }
+
+    /// Repeatedly prompts the user for custom fields (security questions, PINs, recovery codes...) to add to a password,
+    /// until they choose to stop. Nothing is inserted - the new fields are returned.
+    pub fn prompt_custom_fields() -> anyhow::Result<Vec<EntryField>> {
+        let mut fields = Vec::new();
+        while confirm("Add a custom field?")
+            .initial_value(false)
+            .interact()?
+        {
+            let key: String = input("Enter field name")
+                .placeholder("Security question, PIN, recovery code...")
+                .required(true)
+                .interact()?;
+            let sensitive = confirm("Is this field sensitive? (it will be masked)")
+                .initial_value(true)
+                .interact()?;
+            let value = prompt_field_value(sensitive)?;
+            fields.push(EntryField {
+                key,
+                value,
+                sensitive,
+                ..Default::default()
+            });
+        }
+        Ok(fields)
+    }
     /// Utility function to print the details on the availability/use of a password name when inserting/updating a password.
     /// If a password exists with a given `name`, the user has the option to exit the program and not update the data.
-    pub fn check_password_availability(connection: &Connection, name: &str) -> anyhow::Result<()> {
+    /// Returns `false` if the user chose not to continue.
+    pub fn check_password_availability(
+        connection: &Connection,
+        name: &str,
+    ) -> anyhow::Result<bool> {
         if get_password_info(connection, name)?.is_some() {
             let confirm =
                 confirm("A password already exists with this name. Would you like to update it?")
                     .interact()?;
             if !confirm {
-                // this *should* end the program.
-                outro("Exiting...".green().bold())?;
-                return Ok(());
+                cliclack::log::info("Cancelled.")?;
+                return Ok(false);
             }
             note(
                 "Note",
-                "If you do not messagewish to update a particular field, leave the value empty.",
+                "If you do not wish to update a particular field, leave the value empty. To change or clear a single field, use \"Edit a password\" instead.",
             )?;
         } else {
             note(
@@ -354,13 +2192,17 @@ pub mod utility {
                 "This name is available. Continuing will insert a new password.",
             )?;
         }
-        Ok(())
+        Ok(true)
     }
     // the number of indents on this function scares me.
 
     /// Prints a `cliclack::note()` containing the individual fields of password data, i.e. an instance of `PasswordInfo`.
+    /// Custom fields marked as sensitive are masked unless `reveal` is true.
     /// If no data is found, a specific message will be printed.
-    pub fn print_password_info(password_info: Option<PasswordInfo>) -> anyhow::Result<()> {
+    pub fn print_password_info(
+        password_info: Option<&PasswordInfo>,
+        reveal: bool,
+    ) -> anyhow::Result<()> {
         password_info.map_or_else(
             || -> anyhow::Result<()> {
                 Ok(note("Password Info", "No password found with that name.")?)
@@ -369,12 +2211,12 @@ pub mod utility {
                 // iterating over the important fields. it might be better to refactor this to uh.
                 // NOT use iteration; it could be simpler to just concatenate a string for each field manually.
                 let fields = [
-                    password_info.email,
-                    password_info.username,
-                    password_info.password,
-                    password_info.notes,
+                    &password_info.email,
+                    &password_info.username,
+                    &password_info.password,
+                    &password_info.notes,
                 ];
-                let str = fields
+                let mut lines = fields
                     .iter()
                     .enumerate()
                     .map(|(index, field)| {
@@ -389,12 +2231,77 @@ pub mod utility {
                         // we'll return a string, and print a specific message if nothing is found in a certain field
                         field.as_ref().map_or_else(
                             || format!("No data found for {}", field_name),
-                            |f| format!("{}: {}", field_name, f),
+                            |f| {
+                                let updated = password_info.timestamps.fields.get(field_name);
+                                format!(
+                                    "{}: {} {}",
+                                    field_name,
+                                    f,
+                                    format!("(updated {})", format_timestamp(updated)).dimmed()
+                                )
+                            },
                         )
                     })
-                    .collect::<Vec<String>>()
-                    .join("\n");
-                Ok(note("Password Info", str)?)
+                    .collect::<Vec<String>>();
+
+                if !password_info.urls.is_empty() {
+                    lines.push(format!("urls: {}", password_info.urls.join(", ")));
+                }
+                if !password_info.tags.is_empty() {
+                    lines.push(format!("tags: {}", password_info.tags.join(", ")));
+                }
+                if let Some(folder) = &password_info.folder {
+                    lines.push(format!("folder: {}", folder));
+                }
+
+                lines.extend(password_info.fields.iter().map(|field| {
+                    let updated =
+                        format!("(updated {})", format_timestamp(field.updated_at.as_ref()));
+                    if field.sensitive && !reveal {
+                        format!(
+                            "{}: {} {}",
+                            field.key,
+                            "********".dimmed(),
+                            updated.dimmed()
+                        )
+                    } else {
+                        format!("{}: {} {}", field.key, field.value, updated.dimmed())
+                    }
+                }));
+
+                let due_at = password_info.expiry.due_at(
+                    password_info
+                        .timestamps
+                        .fields
+                        .get("password")
+                        .copied()
+                        .or(password_info.timestamps.updated_at),
+                );
+                if let Some(due_at) = due_at {
+                    let rotation = password_info
+                        .expiry
+                        .rotation_days
+                        .map(|days| format!(" (rotated every {} days)", days))
+                        .unwrap_or_default();
+                    lines.push(format!(
+                        "expires: {}{}",
+                        format_timestamp(Some(&due_at)),
+                        rotation
+                    ));
+                }
+
+                let timestamps = &password_info.timestamps;
+                lines.push(
+                    format!(
+                        "created {} | modified {} | last used {}",
+                        format_timestamp(timestamps.created_at.as_ref()),
+                        format_timestamp(timestamps.updated_at.as_ref()),
+                        format_timestamp(timestamps.last_used_at.as_ref()),
+                    )
+                    .dimmed()
+                    .to_string(),
+                );
+                Ok(note("Password Info", lines.join("\n"))?)
             },
         )
     }
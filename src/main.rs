@@ -1,17 +1,34 @@
-mod backend;
 mod cli;
+mod tui;
 
-use cliclack::{intro, outro, select};
+use std::{
+    process::ExitCode,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use clap::Parser;
+use cliclack::{intro, log, outro, select};
 use colored::Colorize;
+use rusqlite::Connection;
 
-use backend::db_ops::{
-    util::{check_password_info_exists, create_table, establish_connection},
-    MASTER_KEYWORD,
-};
 use cli::{
-    crud_operations::{delete, insert, read},
-    utility::{insert_new_master_info, login},
-    Operation,
+    commands::{
+        backup, due, git_commit, git_init, git_merge_driver, git_pull, git_push, identity, keyfile,
+        open_shared_vault, receive, remote, share, sync_file, team, verify,
+    },
+    crud_operations::{browse, custom_fields, delete, duplicate, edit, insert, read, rename},
+    utility::{
+        insert_new_master_info, login, master_from_env, print_due_banner, unlock_credentials,
+    },
+    vault_operations::{change_cipher, change_login_policy},
+    Cli, Command, GitAction, Operation, TeamAction,
+};
+use passman_core::{
+    keyfile::composite_key,
+    session::{watch_idle, Session},
+    vault::{Vault, DEFAULT_VAULT_PATH},
 };
 
 // TODO:
@@ -24,35 +41,287 @@ use cli::{
 // rework error handling to use a few unwraps / expects where necessary/important.
 
 // very simple main program, yay!
-fn main() -> anyhow::Result<()> {
-    let connection = establish_connection()?;
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{} {:#}", "error:".red().bold(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> anyhow::Result<ExitCode> {
+    passman_core::init()?;
+    let args = Cli::parse();
+    // git runs the merge driver inside the repository, where there's no vault to open
+    if let Some(Command::Git {
+        action:
+            GitAction::MergeDriver {
+                base,
+                ours,
+                theirs,
+                path,
+            },
+    }) = &args.command
+    {
+        return Ok(match git_merge_driver(base, ours, theirs, path)? {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::FAILURE,
+        });
+    }
+    let vault = Vault::open(DEFAULT_VAULT_PATH).context("could not open the vault")?;
+    let connection = vault.connection();
+    let unlock = |connection| cli::utility::unlock(connection, args.keyfile.as_deref());
 
-    create_table(&connection)?;
+    let tui = match args.command {
+        Some(Command::Due { json, days }) => {
+            due(connection, json, days)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Tui) => true,
+        Some(Command::Sync { path }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            sync_file(connection, &master, &path)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Remote {
+            server,
+            user,
+            action,
+        }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            remote(connection, &master, server, user, action)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Git { action }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            match action {
+                GitAction::Init { path } => git_init(connection, &master, &path)?,
+                GitAction::Push { args } => git_push(connection, &master, &args)?,
+                GitAction::Pull { args } => git_pull(connection, &master, &args)?,
+                GitAction::MergeDriver { .. } => unreachable!("handled above"),
+            }
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Identity) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            identity(connection, &master)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Share {
+            name,
+            to,
+            expires_in,
+            out,
+        }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            share(connection, &master, &name, &to, expires_in, out)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Receive { path }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            receive(connection, &master, &path)?;
+            git_commit(connection, &master)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Verify { json, reseal }) => {
+            let master = match master_from_env(connection, args.keyfile.as_deref())? {
+                Some(master) => master,
+                None => {
+                    intro("passman.rs")?;
+                    unlock(connection)?
+                }
+            };
+            return Ok(match verify(connection, &master, json, reseal)? {
+                true => ExitCode::SUCCESS,
+                false => ExitCode::from(2),
+            });
+        }
+        Some(Command::Backup { action }) => {
+            intro("passman.rs")?;
+            let (master, current) = unlock_credentials(connection, args.keyfile.as_deref())?;
+            let master = match &current {
+                Some(keyfile) => composite_key(&master, keyfile),
+                None => master,
+            };
+            backup(connection, &master, current.as_deref(), action)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Keyfile { action }) => {
+            intro("passman.rs")?;
+            let (master, current) = unlock_credentials(connection, args.keyfile.as_deref())?;
+            keyfile(connection, &master, current.as_deref(), action)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Team {
+            path,
+            action: TeamAction::Open,
+        }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            let (shared, vault_key) = open_shared_vault(connection, &master, &path)?;
+            let idle_timeout = Duration::from_secs(args.lock_after.saturating_mul(60));
+            let session = Session::new(vault_key, idle_timeout, Instant::now());
+            // the shared vault is unlocked again through the personal one
+            return interactive(shared.connection(), session, || {
+                let master = unlock(connection)?;
+                Ok(open_shared_vault(connection, &master, &path)?.1)
+            });
+        }
+        Some(Command::Team { path, action }) => {
+            intro("passman.rs")?;
+            let master = unlock(connection)?;
+            team(connection, &master, &path, action)?;
+            outro("Done.".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        None => false,
+    };
 
     intro("passman.rs")?;
 
-    if !check_password_info_exists(&connection, MASTER_KEYWORD)? {
-        insert_new_master_info(&connection)?;
-        return Ok(());
+    if !vault.is_initialized()? {
+        insert_new_master_info(connection)?;
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let master = login(&connection)?;
-
-    let operation = select("What would you like to do?")
-        .item(Operation::Insert, "Insert or Update a password", "")
-        .item(Operation::Read, "Get a password", "")
-        .item(Operation::Delete, "Delete a password", "dangerous")
-        .item(Operation::Exit, "Exit", "")
-        .interact()?;
-
-    match operation {
-        Operation::Insert => insert(&connection, &master)
-            .unwrap_or_else(|f| eprintln!("There was an error updating the database:\n{}", f)),
-        Operation::Read => read(&connection, &master)
-            .unwrap_or_else(|f| eprintln!("There was an error reading the password:\n{}", f)),
-        Operation::Delete => delete(&connection)
-            .unwrap_or_else(|f| eprintln!("There was an error deleting the password:\n{}", f)),
-        Operation::Exit => outro("Exiting...".green().bold())?,
+    let Some(master) = login(connection, args.keyfile.as_deref())? else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    print_due_banner(connection)?;
+
+    let idle_timeout = Duration::from_secs(args.lock_after.saturating_mul(60));
+    let mut session = Session::new(master, idle_timeout, Instant::now());
+
+    if tui {
+        let locked = tui::run(connection, &mut session)?;
+        if let Some(master) = session.master(Instant::now()) {
+            git_commit(connection, master).unwrap_or_else(|f| {
+                eprintln!(
+                    "There was an error committing to the git repository:\n{:#}",
+                    f
+                )
+            });
+        }
+        if locked {
+            outro(format!(
+                "The vault was locked after {} idle minute(s). Exiting...",
+                args.lock_after
+            ))?;
+        } else {
+            outro("Exiting...".green().bold())?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    interactive(connection, session, || unlock(connection))
+}
+
+/// The interactive menu. The session stays unlocked between operations, until the user exits or it's idle for too long,
+/// and then `unlock_again` is used to unlock it.
+/// The session is locked by a background thread, so the master password is wiped even while a prompt is waiting for input.
+fn interactive(
+    connection: &Connection,
+    session: Session,
+    unlock_again: impl Fn() -> anyhow::Result<String>,
+) -> anyhow::Result<ExitCode> {
+    let session = Arc::new(Mutex::new(session));
+    watch_idle(&session);
+    let session = || session.lock().unwrap_or_else(PoisonError::into_inner);
+    loop {
+        let operation = select("What would you like to do?")
+            .item(Operation::Insert, "Insert or Update a password", "")
+            .item(Operation::Read, "Get a password", "")
+            .item(
+                Operation::Edit,
+                "Edit a password",
+                "change, clear or regenerate one field",
+            )
+            .item(Operation::Rename, "Rename a password", "")
+            .item(Operation::Duplicate, "Duplicate a password", "")
+            .item(Operation::Browse, "Browse passwords", "by folder or tag")
+            .item(Operation::CustomFields, "Manage custom fields", "")
+            .item(Operation::Delete, "Delete a password", "dangerous")
+            .item(Operation::ChangeCipher, "Change vault cipher", "")
+            .item(
+                Operation::LoginPolicy,
+                "Login security",
+                "lock or wipe after failed logins",
+            )
+            .item(Operation::Exit, "Exit", "")
+            .interact()?;
+
+        if operation == Operation::Exit {
+            outro("Exiting...".green().bold())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let unlocked = session().master(Instant::now()).map(String::from);
+        let master = match unlocked {
+            Some(master) => master,
+            None => {
+                log::warning(format!(
+                    "The vault was locked after {} idle minute(s).",
+                    session().idle_timeout().as_secs() / 60
+                ))?;
+                let master = unlock_again()?;
+                session().unlock(master.clone(), Instant::now());
+                master
+            }
+        };
+
+        match operation {
+            Operation::Insert => insert(connection, &master).unwrap_or_else(|f| {
+                eprintln!("There was an error updating the database:\n{:#}", f)
+            }),
+            Operation::Read => read(connection, &master)
+                .unwrap_or_else(|f| eprintln!("There was an error reading the password:\n{:#}", f)),
+            Operation::Delete => delete(connection, &master).unwrap_or_else(|f| {
+                eprintln!("There was an error deleting the password:\n{:#}", f)
+            }),
+            Operation::Edit => edit(connection, &master).unwrap_or_else(|f| {
+                eprintln!("There was an error updating the database:\n{:#}", f)
+            }),
+            Operation::Rename => rename(connection, &master).unwrap_or_else(|f| {
+                eprintln!("There was an error renaming the password:\n{:#}", f)
+            }),
+            Operation::Duplicate => duplicate(connection, &master)
+                .unwrap_or_else(|f| eprintln!("There was an error copying the password:\n{:#}", f)),
+            Operation::Browse => browse(connection, &master).unwrap_or_else(|f| {
+                eprintln!("There was an error reading the passwords:\n{:#}", f)
+            }),
+            Operation::CustomFields => custom_fields(connection, &master).unwrap_or_else(|f| {
+                eprintln!("There was an error updating the database:\n{:#}", f)
+            }),
+            Operation::ChangeCipher => change_cipher(connection, &master)
+                .unwrap_or_else(|f| eprintln!("There was an error changing the cipher:\n{:#}", f)),
+            Operation::LoginPolicy => change_login_policy(connection).unwrap_or_else(|f| {
+                eprintln!("There was an error updating the settings:\n{:#}", f)
+            }),
+            Operation::Exit => unreachable!("handled above"),
+        }
+        git_commit(connection, &master).unwrap_or_else(|f| {
+            eprintln!(
+                "There was an error committing to the git repository:\n{:#}",
+                f
+            )
+        });
+        // time spent inside an operation counts as activity
+        session().touch(Instant::now());
     }
-    Ok(())
 }
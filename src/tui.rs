@@ -0,0 +1,748 @@
+// full-screen frontend, for people who'd rather not answer prompts one at a time.
+// unlike the cliclack frontend, this one is split into state (`App`), input handling and drawing,
+// so the rendering can be tested with ratatui's `TestBackend`.
+
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+use rusqlite::Connection;
+
+use passman_core::{
+    crypto::generate_password,
+    db_ops::crud::{clear_data, insert_data, insert_list_data, list_password_info},
+    password::{normalize_folder, PasswordField, PasswordInfo},
+    session::Session,
+};
+
+/// Shown instead of the value of a masked field.
+const MASK: &str = "********";
+/// The length of generated passwords, unless the user changes it.
+const DEFAULT_GENERATED_LENGTH: usize = 16;
+/// How often the event loop wakes up to check whether the session went idle.
+const TICK: Duration = Duration::from_millis(250);
+
+/// What the user is currently doing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    /// Moving through the list.
+    Browse,
+    /// Typing a search query, which filters the list as it's typed.
+    Search,
+    /// Editing one field of the selected password inline.
+    Edit {
+        field: PasswordField,
+        buffer: String,
+    },
+    /// The password generator dialog.
+    Generate { length: usize, password: String },
+}
+
+/// Something the event loop has to do on behalf of the `App`, because it involves the vault or the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Quit,
+    /// Copy a value to the clipboard.
+    Copy(String),
+    /// Write a field of a password. An empty `value` clears the field.
+    Save {
+        name: String,
+        field: PasswordField,
+        value: String,
+    },
+}
+
+/// The state of the TUI: every decrypted password, the search query, the selection and the current `Mode`.
+struct App {
+    passwords: Vec<PasswordInfo>,
+    query: String,
+    selected: usize,
+    mode: Mode,
+    revealed: bool,
+    status: Option<String>,
+}
+
+impl App {
+    fn new(passwords: Vec<PasswordInfo>) -> Self {
+        App {
+            passwords,
+            query: String::new(),
+            selected: 0,
+            mode: Mode::Browse,
+            revealed: false,
+            status: None,
+        }
+    }
+
+    /// The passwords matching the search query. Names, usernames, emails, URLs, tags and folders are searched, ignoring case.
+    fn visible(&self) -> Vec<&PasswordInfo> {
+        let query = self.query.to_lowercase();
+        self.passwords
+            .iter()
+            .filter(|password| {
+                query.is_empty()
+                    || [&password.name]
+                        .into_iter()
+                        .chain(password.username.as_ref())
+                        .chain(password.email.as_ref())
+                        .chain(password.folder.as_ref())
+                        .chain(&password.urls)
+                        .chain(&password.tags)
+                        .any(|value| value.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    fn selected_password(&self) -> Option<&PasswordInfo> {
+        self.visible().get(self.selected).copied()
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let len = self.visible().len();
+        if len == 0 {
+            self.selected = 0;
+            return;
+        }
+        self.selected = self.selected.saturating_add_signed(offset).min(len - 1);
+    }
+
+    /// Replaces the passwords, e.g. after saving, while keeping the same password selected if it still exists.
+    fn reload(&mut self, passwords: Vec<PasswordInfo>) {
+        let name = self
+            .selected_password()
+            .map(|password| password.name.clone());
+        self.passwords = passwords;
+        self.selected = name
+            .and_then(|name| {
+                self.visible()
+                    .iter()
+                    .position(|password| password.name == name)
+            })
+            .unwrap_or(0);
+        self.move_selection(0);
+    }
+
+    /// Updates the state for a key press, returning a `Command` if the event loop has to act on it.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Command> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Command::Quit);
+        }
+        self.status = None;
+        match self.mode.clone() {
+            Mode::Browse => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Some(Command::Quit),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Char('/') => self.mode = Mode::Search,
+                KeyCode::Char('r') => self.revealed = !self.revealed,
+                KeyCode::Char('c') => {
+                    let password = self.selected_password()?;
+                    let Some(value) = password.password.clone() else {
+                        self.status = Some(format!("{} has no password.", password.name));
+                        return None;
+                    };
+                    self.status = Some(format!("Copied the password of {}.", password.name));
+                    return Some(Command::Copy(value));
+                }
+                KeyCode::Char('e') => {
+                    let password = self.selected_password()?;
+                    let field = PasswordField::ALL[0];
+                    self.mode = Mode::Edit {
+                        field,
+                        buffer: field_value(password, field),
+                    };
+                }
+                KeyCode::Char('g') => {
+                    self.selected_password()?;
+                    self.mode = Mode::Generate {
+                        length: DEFAULT_GENERATED_LENGTH,
+                        password: generate_password(DEFAULT_GENERATED_LENGTH),
+                    };
+                }
+                _ => {}
+            },
+            Mode::Search => match key.code {
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Down => self.move_selection(1),
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.selected = 0;
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.selected = 0;
+                }
+                _ => {}
+            },
+            Mode::Edit { field, mut buffer } => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Tab | KeyCode::BackTab => {
+                    let index = PasswordField::ALL.iter().position(|f| *f == field)?;
+                    let len = PasswordField::ALL.len();
+                    let next = if key.code == KeyCode::Tab {
+                        (index + 1) % len
+                    } else {
+                        (index + len - 1) % len
+                    };
+                    let field = PasswordField::ALL[next];
+                    let buffer = field_value(self.selected_password()?, field);
+                    self.mode = Mode::Edit { field, buffer };
+                }
+                KeyCode::Enter => {
+                    self.mode = Mode::Browse;
+                    return Some(Command::Save {
+                        name: self.selected_password()?.name.clone(),
+                        field,
+                        value: buffer,
+                    });
+                }
+                KeyCode::Backspace => {
+                    buffer.pop();
+                    self.mode = Mode::Edit { field, buffer };
+                }
+                KeyCode::Char(c) => {
+                    buffer.push(c);
+                    self.mode = Mode::Edit { field, buffer };
+                }
+                _ => {}
+            },
+            Mode::Generate { length, password } => match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Enter => {
+                    self.mode = Mode::Browse;
+                    return Some(Command::Save {
+                        name: self.selected_password()?.name.clone(),
+                        field: PasswordField::Password,
+                        value: password,
+                    });
+                }
+                KeyCode::Char('+') | KeyCode::Right => {
+                    let length = (length + 1).min(128);
+                    self.mode = Mode::Generate {
+                        length,
+                        password: generate_password(length),
+                    };
+                }
+                KeyCode::Char('-') | KeyCode::Left => {
+                    let length = length.saturating_sub(1).max(4);
+                    self.mode = Mode::Generate {
+                        length,
+                        password: generate_password(length),
+                    };
+                }
+                KeyCode::Char('r') => {
+                    self.mode = Mode::Generate {
+                        length,
+                        password: generate_password(length),
+                    };
+                }
+                _ => {}
+            },
+        }
+        None
+    }
+}
+
+/// The current value of a standard field as editable text. Lists are comma separated.
+fn field_value(password: &PasswordInfo, field: PasswordField) -> String {
+    match field {
+        PasswordField::Email => password.email.clone(),
+        PasswordField::Username => password.username.clone(),
+        PasswordField::Notes => password.notes.clone(),
+        PasswordField::Password => password.password.clone(),
+        PasswordField::Urls => Some(password.urls.join(", ")),
+        PasswordField::Tags => Some(password.tags.join(", ")),
+        PasswordField::Folder => password.folder.clone(),
+    }
+    .unwrap_or_default()
+}
+
+/// Draws the whole TUI: the search bar, the entry list, the detail pane, the key help and any open dialog.
+fn draw(frame: &mut Frame, app: &App) {
+    let [search_area, main_area, help_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [list_area, detail_area] =
+        Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+            .areas(main_area);
+
+    let searching = app.mode == Mode::Search;
+    let search = Paragraph::new(if searching {
+        format!("{}_", app.query)
+    } else {
+        app.query.clone()
+    })
+    .block(Block::bordered().title(" Search (/) "))
+    .style(if searching {
+        Style::new().bold()
+    } else {
+        Style::new()
+    });
+    frame.render_widget(search, search_area);
+
+    let visible = app.visible();
+    let items = visible
+        .iter()
+        .map(|password| ListItem::new(password.name.as_str()))
+        .collect::<Vec<_>>();
+    let title = format!(" Passwords ({}/{}) ", visible.len(), app.passwords.len());
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state =
+        ListState::default().with_selected(Some(app.selected).filter(|_| !visible.is_empty()));
+    frame.render_stateful_widget(list, list_area, &mut state);
+
+    let details = app.selected_password().map_or_else(
+        || vec![Line::from("No password selected.")],
+        |password| detail_lines(app, password),
+    );
+    let detail = Paragraph::new(details)
+        .block(Block::bordered().title(" Details "))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(detail, detail_area);
+
+    let help = match (&app.status, &app.mode) {
+        (Some(status), _) => status.clone(),
+        (None, Mode::Browse) => {
+            "q quit  / search  j/k move  r reveal  c copy  e edit  g generate".to_string()
+        }
+        (None, Mode::Search) => "type to search  enter done  esc clear".to_string(),
+        (None, Mode::Edit { .. }) => {
+            "tab next field  enter save (empty clears)  esc cancel".to_string()
+        }
+        (None, Mode::Generate { .. }) => {
+            "+/- length  r regenerate  enter save  esc cancel".to_string()
+        }
+    };
+    frame.render_widget(Paragraph::new(help).dim(), help_area);
+
+    if let Mode::Generate { length, password } = &app.mode {
+        let area = centered(frame.area(), 44, 5);
+        let dialog = Paragraph::new(vec![
+            Line::from(format!("Length: {}", length)),
+            Line::from(password.as_str()).bold(),
+        ])
+        .block(Block::bordered().title(" Generate password "))
+        .wrap(Wrap { trim: false });
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+    }
+}
+
+/// The lines of the detail pane. Passwords and sensitive custom fields are masked unless the user revealed them.
+fn detail_lines<'a>(app: &App, password: &'a PasswordInfo) -> Vec<Line<'a>> {
+    let row = |label: String, value: String, highlighted: bool| {
+        let label = Span::from(format!("{:<10} ", label)).bold();
+        let value = Span::from(value);
+        if highlighted {
+            Line::from(vec![label, value.reversed()])
+        } else {
+            Line::from(vec![label, value])
+        }
+    };
+    let mut lines = vec![Line::from(password.name.as_str()).bold(), Line::from("")];
+
+    for field in PasswordField::ALL {
+        let (value, editing) = match &app.mode {
+            Mode::Edit {
+                field: editing,
+                buffer,
+            } if *editing == field => {
+                let buffer = if field == PasswordField::Password && !app.revealed {
+                    "*".repeat(buffer.chars().count())
+                } else {
+                    buffer.clone()
+                };
+                (format!("{}_", buffer), true)
+            }
+            _ => {
+                let value = field_value(password, field);
+                if value.is_empty() {
+                    continue;
+                }
+                if field == PasswordField::Password && !app.revealed {
+                    (MASK.to_string(), false)
+                } else {
+                    (value, false)
+                }
+            }
+        };
+        lines.push(row(field.to_string(), value, editing));
+    }
+    for field in &password.fields {
+        let value = if field.sensitive && !app.revealed {
+            MASK.to_string()
+        } else {
+            field.value.clone()
+        };
+        lines.push(row(field.key.clone(), value, false));
+    }
+    // rotation counts from the last change to the password itself, like `passman due`
+    let due_at = password.expiry.due_at(
+        password
+            .timestamps
+            .fields
+            .get(&PasswordField::Password.to_string())
+            .copied()
+            .or(password.timestamps.updated_at)
+            .or(password.timestamps.created_at),
+    );
+    if let Some(due_at) = due_at {
+        lines.push(row(
+            "expires".to_string(),
+            due_at.format("%Y-%m-%d").to_string(),
+            false,
+        ));
+    }
+    lines
+}
+
+/// A `width` by `height` rectangle in the middle of `area`.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+/// Copies `text` to the clipboard with an OSC 52 escape sequence, which most terminals support, including over SSH.
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", BASE64.encode(text))?;
+    stdout.flush()
+}
+
+/// Writes a field the way the interactive `edit` operation does: lists are comma separated, and an empty value clears the field.
+fn save_field(
+    connection: &Connection,
+    master: &str,
+    name: &str,
+    field: PasswordField,
+    value: &str,
+) -> anyhow::Result<()> {
+    // passwords and notes are saved exactly as typed, only the list and metadata fields are trimmed
+    let value = match field {
+        PasswordField::Password | PasswordField::Notes => value,
+        _ => value.trim(),
+    };
+    match field {
+        _ if value.is_empty() => {
+            clear_data(connection, name, field, master)?;
+        }
+        PasswordField::Urls | PasswordField::Tags => {
+            let values = value
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>();
+            insert_list_data(connection, name, master, field, &values)?;
+        }
+        PasswordField::Folder => match normalize_folder(value) {
+            Some(folder) => {
+                insert_data(connection, name, master, field, &folder)?;
+            }
+            None => {
+                clear_data(connection, name, field, master)?;
+            }
+        },
+        _ => {
+            insert_data(connection, name, master, field, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the TUI until the user quits or the session goes idle. Returns `true` if the session was locked for being idle.
+/// # Arguments
+///
+/// - `connection` - a reference to a `rusqlite::Connection`, which may be to a file or in memory.
+/// - `session` - an unlocked `Session`. It's kept alive by key presses.
+///
+pub fn run(connection: &Connection, session: &mut Session) -> anyhow::Result<bool> {
+    let master = session
+        .master(Instant::now())
+        .ok_or_else(|| anyhow::anyhow!("the session is locked"))?;
+    let mut app = App::new(list_password_info(connection, master)?);
+
+    let mut terminal = ratatui::init();
+    let result = (|| -> anyhow::Result<bool> {
+        loop {
+            terminal.draw(|frame| draw(frame, &app))?;
+            if !event::poll(TICK)? {
+                if session.is_idle(Instant::now()) {
+                    session.lock();
+                    return Ok(true);
+                }
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            session.touch(Instant::now());
+            match app.handle_key(key) {
+                Some(Command::Quit) => return Ok(false),
+                Some(Command::Copy(value)) => copy_to_clipboard(&value)?,
+                Some(Command::Save { name, field, value }) => {
+                    // the master password is only borrowed from the session, so locking it wipes the only copy
+                    let Some(master) = session.master(Instant::now()) else {
+                        return Ok(true);
+                    };
+                    match save_field(connection, master, &name, field, &value) {
+                        Ok(()) => {
+                            app.reload(list_password_info(connection, master)?);
+                            app.status = Some(format!("Saved the {} of {}.", field, name));
+                        }
+                        Err(e) => app.status = Some(format!("Couldn't save: {:#}", e)),
+                    }
+                }
+                None => {}
+            }
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{
+        backend::TestBackend,
+        crossterm::event::{KeyCode, KeyEvent},
+        Terminal,
+    };
+
+    use chrono::{Duration, TimeZone, Utc};
+    use passman_core::{
+        db_ops::{
+            crud::read_password_info,
+            util::{create_table, insert_master_record},
+        },
+        envelope::Algorithm,
+        password::{EntryField, Expiry, PasswordField, PasswordInfo},
+    };
+    use rusqlite::Connection;
+
+    use super::{App, Command, Mode};
+
+    fn sample() -> App {
+        App::new(vec![
+            PasswordInfo {
+                name: "bank".to_string(),
+                username: Some("jdoe".to_string()),
+                password: Some("hunter2".to_string()),
+                tags: vec!["finance".to_string()],
+                fields: vec![EntryField {
+                    key: "PIN".to_string(),
+                    value: "1234".to_string(),
+                    sensitive: true,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            PasswordInfo {
+                name: "email".to_string(),
+                email: Some("jdoe@example.com".to_string()),
+                password: Some("correct horse".to_string()),
+                urls: vec!["https://mail.example.com".to_string()],
+                folder: Some("Work".to_string()),
+                ..Default::default()
+            },
+        ])
+    }
+
+    fn render(app: &App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(80, 16)).unwrap();
+        terminal.draw(|frame| super::draw(frame, app)).unwrap();
+        terminal.backend().to_string()
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Option<Command> {
+        app.handle_key(KeyEvent::from(code))
+    }
+
+    #[test]
+    fn browse() {
+        insta::assert_snapshot!(render(&sample()));
+    }
+    #[test]
+    fn reveal() {
+        let mut app = sample();
+        press(&mut app, KeyCode::Char('r'));
+        insta::assert_snapshot!(render(&app));
+    }
+    #[test]
+    fn search() {
+        let mut app = sample();
+        press(&mut app, KeyCode::Char('/'));
+        for c in "work".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        assert_eq!(app.visible().len(), 1);
+        insta::assert_snapshot!(render(&app));
+
+        // escape clears the search
+        press(&mut app, KeyCode::Esc);
+        assert_eq!(app.visible().len(), 2);
+    }
+    #[test]
+    fn edit() {
+        let mut app = sample();
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('e'));
+        press(&mut app, KeyCode::Tab);
+        for c in "jane".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        insta::assert_snapshot!(render(&app));
+
+        assert_eq!(
+            press(&mut app, KeyCode::Enter),
+            Some(Command::Save {
+                name: "email".to_string(),
+                field: PasswordField::Username,
+                value: "jane".to_string()
+            })
+        );
+        assert_eq!(app.mode, Mode::Browse);
+    }
+    #[test]
+    fn generate() {
+        let mut app = sample();
+        press(&mut app, KeyCode::Char('g'));
+        press(&mut app, KeyCode::Char('+'));
+        let Mode::Generate { length, password } = &app.mode else {
+            panic!("expected the generator dialog");
+        };
+        assert_eq!(*length, 17);
+        assert_eq!(password.len(), 17);
+
+        // the generated password is random, so a fixed one is rendered
+        app.mode = Mode::Generate {
+            length: 12,
+            password: "aB3$eF6&hI9(".to_string(),
+        };
+        insta::assert_snapshot!(render(&app));
+        assert!(matches!(
+            press(&mut app, KeyCode::Enter),
+            Some(Command::Save {
+                field: PasswordField::Password,
+                ..
+            })
+        ));
+    }
+    #[test]
+    fn copy() {
+        let mut app = sample();
+        assert_eq!(
+            press(&mut app, KeyCode::Char('c')),
+            Some(Command::Copy("hunter2".to_string()))
+        );
+        assert_eq!(press(&mut app, KeyCode::Char('q')), Some(Command::Quit));
+    }
+    #[test]
+    fn empty() {
+        let mut app = App::new(Vec::new());
+        assert_eq!(press(&mut app, KeyCode::Char('e')), None);
+        assert_eq!(app.mode, Mode::Browse);
+        insta::assert_snapshot!(render(&app));
+    }
+    #[test]
+    fn reload() {
+        let mut app = sample();
+        press(&mut app, KeyCode::Down);
+        let mut passwords = sample().passwords;
+        passwords.insert(
+            0,
+            PasswordInfo {
+                name: "aaa".to_string(),
+                ..Default::default()
+            },
+        );
+        app.reload(passwords);
+        assert_eq!(app.selected_password().unwrap().name, "email");
+    }
+    #[test]
+    fn save_field() {
+        let master = "mymasterpassword";
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        insert_master_record(&connection, master, "recovery", Algorithm::Aes256Gcm).unwrap();
+
+        let save = |field, value| super::save_field(&connection, master, "bank", field, value);
+        save(PasswordField::Password, " pass phrase ").unwrap();
+        save(PasswordField::Username, " jdoe ").unwrap();
+        save(PasswordField::Tags, " finance, , home ").unwrap();
+        let info = read_password_info(&connection, "bank", master)
+            .unwrap()
+            .unwrap();
+        // spaces can be part of a password, but not of a username or a tag
+        assert_eq!(info.password.as_deref(), Some(" pass phrase "));
+        assert_eq!(info.username.as_deref(), Some("jdoe"));
+        assert_eq!(info.tags, ["finance", "home"]);
+    }
+    #[test]
+    fn expires() {
+        let changed = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut password = PasswordInfo {
+            name: "bank".to_string(),
+            expiry: Expiry {
+                expires_at: None,
+                rotation_days: Some(30),
+            },
+            ..Default::default()
+        };
+        password.timestamps.created_at = Some(changed - Duration::days(10));
+        password.timestamps.updated_at = Some(changed + Duration::days(10));
+        password
+            .timestamps
+            .fields
+            .insert(PasswordField::Password.to_string(), changed);
+        let app = App::new(vec![password]);
+        let expires = |app: &App| {
+            let password = app.selected_password().unwrap();
+            super::detail_lines(app, password)
+                .iter()
+                .map(ToString::to_string)
+                .find(|line| line.contains("expires"))
+                .unwrap()
+        };
+        // rotation counts from the last change to the password, not to the entry
+        assert!(expires(&app).contains("2024-01-31"));
+
+        // then from the last change to the entry, then from its creation
+        let mut password = app.passwords[0].clone();
+        password.timestamps.fields.clear();
+        let app = App::new(vec![password.clone()]);
+        assert!(expires(&app).contains("2024-02-10"));
+        password.timestamps.updated_at = None;
+        let app = App::new(vec![password]);
+        assert!(expires(&app).contains("2024-01-21"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Checks that git runs `passman git merge-driver` during a pull, and that it merges passwords changed on both sides.
+
+use std::{path::Path, process::Command};
+
+use passman_core::{
+    envelope::Algorithm,
+    git::GitVault,
+    password::PasswordInfo,
+    sync::{Replica, Resolution},
+};
+
+const MASTER: &str = "master";
+
+fn git(dir: &Path, args: &[&str]) -> std::process::Output {
+    Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        // the machine running the tests may not have an identity to commit with
+        .env("GIT_AUTHOR_NAME", "passman")
+        .env("GIT_AUTHOR_EMAIL", "passman@example.com")
+        .env("GIT_COMMITTER_NAME", "passman")
+        .env("GIT_COMMITTER_EMAIL", "passman@example.com")
+        .output()
+        .unwrap()
+}
+
+fn password(username: &str, password: &str) -> PasswordInfo {
+    PasswordInfo {
+        name: "mail".to_string(),
+        username: Some(username.to_string()),
+        password: Some(password.to_string()),
+        ..Default::default()
+    }
+}
+
+/// A bare repository and two clones of a vault in it, with the merge driver set up in both.
+fn clones(dir: &Path) -> (GitVault, GitVault) {
+    for (key, value) in [
+        ("GIT_AUTHOR_NAME", "passman"),
+        ("GIT_AUTHOR_EMAIL", "passman@example.com"),
+        ("GIT_COMMITTER_NAME", "passman"),
+        ("GIT_COMMITTER_EMAIL", "passman@example.com"),
+    ] {
+        std::env::set_var(key, value);
+    }
+    git(dir, &["init", "-q", "--bare", "vault.git"]);
+    git(dir, &["clone", "-q", "vault.git", "laptop"]);
+    let laptop = GitVault::init(&dir.join("laptop"), MASTER, Algorithm::Aes256Gcm).unwrap();
+    laptop.write(&password("alice", "one"), 0).unwrap();
+    laptop
+        .push(&["-u".to_string(), "origin".to_string(), "HEAD".to_string()])
+        .unwrap();
+    git(dir, &["clone", "-q", "vault.git", "desktop"]);
+    let desktop = GitVault::init(&dir.join("desktop"), MASTER, Algorithm::Aes256Gcm).unwrap();
+
+    let driver = format!("'{}' git merge-driver", env!("CARGO_BIN_EXE_passman-rs"));
+    for vault in [&laptop, &desktop] {
+        vault.set_merge_driver(&driver).unwrap();
+    }
+    (laptop, desktop)
+}
+
+#[test]
+fn merge_driver() {
+    let dir = tempfile::tempdir().unwrap();
+    let (laptop, desktop) = clones(dir.path());
+
+    laptop.write(&password("bob", "one"), 1).unwrap();
+    laptop.push(&[]).unwrap();
+    desktop.write(&password("alice", "two"), 1).unwrap();
+
+    // git merges the file itself, through the driver
+    assert!(desktop.pull(&[]).unwrap().is_empty());
+    let mail = desktop.read("mail").unwrap().unwrap().password;
+    assert_eq!(mail.username.unwrap(), "bob");
+    assert_eq!(mail.password.unwrap(), "two");
+    let log = git(desktop.path(), &["log", "-1", "--format=%P"]);
+    assert_eq!(
+        String::from_utf8_lossy(&log.stdout)
+            .split_whitespace()
+            .count(),
+        2
+    );
+
+    // the same field changed on both sides is left for passman to ask about
+    desktop.push(&[]).unwrap();
+    laptop.pull(&[]).unwrap();
+    laptop.write(&password("carol", "two"), 3).unwrap();
+    laptop.push(&[]).unwrap();
+    desktop.write(&password("dave", "two"), 3).unwrap();
+    let mut pending = desktop.pull(&[]).unwrap();
+    assert_eq!(pending.conflicts.len(), 1);
+    pending.conflicts[0].resolution = Some(Resolution::KeepLocal);
+    pending.finish(&desktop).unwrap();
+    assert_eq!(
+        desktop
+            .read("mail")
+            .unwrap()
+            .unwrap()
+            .password
+            .username
+            .unwrap(),
+        "dave"
+    );
+}
+
+#[test]
+fn plain_git_pull() {
+    let dir = tempfile::tempdir().unwrap();
+    let (laptop, desktop) = clones(dir.path());
+    laptop.write(&password("bob", "one"), 1).unwrap();
+    laptop.push(&[]).unwrap();
+    desktop.write(&password("alice", "two"), 1).unwrap();
+
+    // without the master password the driver can't do anything, and says what to run instead
+    let output = git(desktop.path(), &["pull", "-q", "--no-rebase", "--no-edit"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("passman git pull"));
+
+    // which picks up the merge git left behind
+    let pending = desktop.pull(&[]).unwrap();
+    assert!(!pending.is_empty() && pending.conflicts.is_empty());
+    pending.finish(&desktop).unwrap();
+    let mail = desktop.read("mail").unwrap().unwrap().password;
+    assert_eq!(mail.username.unwrap(), "bob");
+    assert_eq!(mail.password.unwrap(), "two");
+}